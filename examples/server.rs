@@ -94,6 +94,7 @@ fn main() -> Result<()> {
     let handler = FileHandler::new(std::env::current_dir()?);
     let socket = net::TcpListener::bind("127.0.0.1:8080")?;
     let mut server = HttpServer::new(socket, handler);
+    server.set_error_callback(Some(|e| println!("Error {:?}", e)));
     println!("Server started on port 8080");
     server.serve_forever();
 }
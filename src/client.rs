@@ -26,7 +26,7 @@
 //!
 //! fn main() -> Result<()> {
 //!     let url: Url = "http://www.google.com".parse()?;
-//!     let s = TcpStream::connect((url.authority.as_ref(), url.port()?))?;
+//!     let s = TcpStream::connect((url.host(), url.port()?))?;
 //!     let mut response = HttpRequestBuilder::get(url)?.send(s)?.finish()?;
 //!     println!("{:#?}", response.headers);
 //!     io::copy(&mut response.body, &mut io::stdout())?;
@@ -54,24 +54,183 @@
 
 use crate::error::{Error, Result};
 use core2::io;
-#[cfg(feature = "std")]
+use crate::protocol::HttpHeaders;
+#[cfg(any(feature = "std", feature = "websocket"))]
 use crate::protocol::HttpStatus;
-use crate::protocol::{HttpMethod, HttpRequest, OutgoingBody};
+#[cfg(feature = "std")]
+use crate::protocol::{DecodedBody, HttpBody, SharedTrailers, TrailerSlot};
+use crate::protocol::{HttpMethod, HttpRequest, HttpResponse, OutgoingBody};
 #[cfg(feature = "std")]
 use crate::url::Scheme;
 use crate::url::Url;
 #[cfg(not(feature = "std"))]
-use alloc::string::ToString;
+use alloc::{boxed::Box, format, rc::Rc, string::String, string::ToString, vec, vec::Vec};
+#[cfg(feature = "rustls")]
+use core::convert::TryFrom;
 use core::convert::TryInto;
 use core::fmt::Display;
 use core::hash::Hash;
+use core::time::Duration;
 use hashbrown::HashMap;
 #[cfg(feature = "openssl")]
-use openssl::ssl::{SslConnector, SslMethod};
+use openssl::ssl::{NameType, SslConnector, SslMethod, SslSession, SslSessionCacheMode};
+#[cfg(any(feature = "openssl", feature = "rustls"))]
+use std::cell::RefCell;
+#[cfg(feature = "std")]
+use std::rc::Rc;
+#[cfg(feature = "rustls")]
+use std::sync::Arc;
+
+#[cfg(feature = "tokio")]
+mod async_tokio;
+mod body;
+#[cfg(feature = "std")]
+mod cache;
+mod connect;
+mod cookies;
+#[cfg(feature = "std")]
+mod deadline;
+#[cfg(feature = "std")]
+mod digest;
+#[cfg(feature = "embedded-io-async")]
+mod embedded_async;
+#[cfg(feature = "embedded-nal")]
+mod embedded_nal;
+#[cfg(feature = "embedded-tls")]
+mod embedded_tls;
+#[cfg(feature = "gzip")]
+mod gzip;
+#[cfg(feature = "std")]
+mod happy_eyeballs;
+#[cfg(feature = "http2")]
+mod http2;
+#[cfg(feature = "std")]
+mod limit;
+mod middleware;
+mod multipart;
+mod pinning;
+mod progress;
+mod proxy;
+#[cfg(feature = "std")]
+mod range;
+mod resolve;
+mod retry;
+#[cfg(feature = "smoltcp")]
+mod smoltcp;
+#[cfg(feature = "socks5")]
+mod socks5;
+#[cfg(feature = "std")]
+mod sse;
+mod trace;
+
+#[cfg(feature = "tokio")]
+pub use self::async_tokio::{AsyncBody, AsyncHttpResponse};
+pub use self::body::{Body, FnReader, IterReader};
+#[cfg(feature = "std")]
+pub use self::cache::{CacheStorage, CachedResponse, CachingClient, MemoryStorage};
+pub use self::connect::{Connect, Transport};
+pub use self::cookies::CookieJar;
+#[cfg(feature = "std")]
+use self::deadline::DeadlineStream;
+#[cfg(feature = "std")]
+use self::digest::DigestChallenge;
+#[cfg(feature = "embedded-io-async")]
+pub use self::embedded_async::{EmbeddedBody, EmbeddedHttpResponse};
+#[cfg(feature = "embedded-nal")]
+pub use self::embedded_nal::EmbeddedNalStream;
+#[cfg(feature = "embedded-tls")]
+pub use self::embedded_tls::EmbeddedTlsStream;
+#[cfg(feature = "gzip")]
+pub use self::gzip::{DeflateDecoder, GzipDecoder};
+#[cfg(feature = "http2")]
+pub use self::http2::{Http2Body, Http2Connection};
+#[cfg(feature = "std")]
+use self::limit::SizeLimit;
+pub use self::middleware::Middleware;
+pub use self::multipart::Multipart;
+pub use self::pinning::CertificatePins;
+use self::progress::Progress;
+pub use self::proxy::Proxy;
+#[cfg(feature = "std")]
+use self::resolve::DnsCache;
+#[cfg(feature = "std")]
+pub use self::resolve::SystemResolver;
+pub use self::resolve::{Resolve, StaticResolver};
+pub use self::retry::RetryPolicy;
+#[cfg(feature = "smoltcp")]
+pub use self::smoltcp::SmoltcpStream;
+#[cfg(feature = "std")]
+pub use self::sse::{Event, EventStream};
+pub use self::trace::Trace;
+use self::trace::{TracedBody, TracedStream};
+
+/// Decides whether to trust a server given its host and the DER encoded certificate chain it
+/// presented, starting with its own certificate. See `HttpRequestBuilder::certificate_verifier`.
+pub type CertificateVerifier = Rc<dyn Fn(&str, &[&[u8]]) -> bool>;
+
+/// Called with the number of bytes of a body transferred so far, and its length if it is known,
+/// each time more is transferred. See `HttpRequestBuilder::upload_progress`.
+pub type ProgressCallback = Rc<dyn Fn(u64, Option<u64>)>;
+
+/// Called with the method, request target, headers and body of a request just before it is sent,
+/// to add headers signing it. The body is `None` if it is read as it is sent. See
+/// `HttpRequestBuilder::sign_with`.
+pub type RequestSigner = Rc<dyn Fn(HttpMethod, &str, &mut HttpHeaders, Option<&[u8]>)>;
+
+/// Called with the headers of a 103 Early Hints response, which comes before the final response.
+/// See `HttpRequestBuilder::early_hints`.
+pub type EarlyHintsCallback = Rc<dyn Fn(&HttpHeaders)>;
+
+/// The headers every `HttpRequestBuilder` starts with, besides `Host` and `Transfer-Encoding`.
+const BUILDER_DEFAULTS: [(&str, &str); 2] = [("User-Agent", "http_io"), ("Accept", "*/*")];
+
+/// How `HttpRequestBuilder::execute` connects to the server.
+#[derive(Default, Clone)]
+struct ConnectOptions {
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    deadline: Option<Duration>,
+    /// When the deadline passes, from when `execute` was called.
+    #[cfg(feature = "std")]
+    expires: Option<std::time::Instant>,
+    proxy: Option<Proxy>,
+    certificate_pins: Option<CertificatePins>,
+    accept_invalid_certs: bool,
+    certificate_verifier: Option<CertificateVerifier>,
+    #[cfg(feature = "rustls")]
+    rustls_config: Option<Arc<rustls::ClientConfig>>,
+    retry: Option<RetryPolicy>,
+    resolver: Option<Rc<dyn Resolve>>,
+    connector: Option<Rc<dyn Connect>>,
+    upload_progress: Option<ProgressCallback>,
+    download_progress: Option<ProgressCallback>,
+    max_response_size: Option<u64>,
+    max_header_size: Option<usize>,
+    expect_continue: Option<Duration>,
+    early_hints: Option<EarlyHintsCallback>,
+    #[cfg(feature = "gzip")]
+    no_decompression: bool,
+    trace: Option<Rc<dyn Trace>>,
+    digest_auth: Option<(String, String)>,
+    signer: Option<RequestSigner>,
+    random: Option<Random>,
+    #[cfg(feature = "http2")]
+    http2: bool,
+    #[cfg(feature = "http2")]
+    http2_prior_knowledge: bool,
+}
 
 /// A struct for building up an HTTP request.
-pub struct HttpRequestBuilder {
-    request: HttpRequest<io::Empty>,
+pub struct HttpRequestBuilder<B: core2::io::Read = io::Empty> {
+    request: HttpRequest<B>,
+    url: Url,
+    options: ConnectOptions,
+    /// Whether a body was given with `body`, `with_body` or the like, in which case the request
+    /// can only be sent again if it is kept in `replay_body`.
+    has_body: bool,
+    /// A copy of a body held in memory.
+    replay_body: Option<Body>,
 }
 
 impl HttpRequestBuilder {
@@ -107,6 +266,14 @@ impl HttpRequestBuilder {
         HttpRequestBuilder::new(url, HttpMethod::Options)
     }
 
+    /// Create a `HttpRequestBuilder` to build a PATCH request
+    pub fn patch<U: TryInto<Url>>(url: U) -> Result<Self>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        HttpRequestBuilder::new(url, HttpMethod::Patch)
+    }
+
     /// Create a `HttpRequestBuilder` to build a POST request
     pub fn post<U: TryInto<Url>>(url: U) -> Result<Self>
     where
@@ -123,7 +290,8 @@ impl HttpRequestBuilder {
         HttpRequestBuilder::new(url, HttpMethod::Put)
     }
 
-    /// Create a `HttpRequestBuilder`. May fail if the given url does not parse.
+    /// Create a `HttpRequestBuilder` for a request with any method, including ones without a
+    /// helper like `HttpMethod::other("PROPFIND")?`. May fail if the given url does not parse.
     pub fn new<U: TryInto<Url>>(url: U, method: HttpMethod) -> Result<Self>
     where
         <U as TryInto<Url>>::Error: Display,
@@ -133,257 +301,4088 @@ impl HttpRequestBuilder {
             .map_err(|e| Error::ParseError(e.to_string()))?;
         let mut request = HttpRequest::new(method, url.path());
         request.add_header("Host", url.authority.clone());
-        request.add_header("User-Agent", "http_io");
-        request.add_header("Accept", "*/*");
+        for (name, value) in BUILDER_DEFAULTS {
+            request.add_header(name, value);
+        }
         request.add_header("Transfer-Encoding", "chunked");
-        Ok(HttpRequestBuilder { request })
+        Ok(HttpRequestBuilder {
+            request,
+            url,
+            options: ConnectOptions::default(),
+            has_body: false,
+            replay_body: None,
+        })
+    }
+
+    /// Subscribe to Server-Sent Events, by sending the request with `Accept: text/event-stream`
+    /// and returning an iterator over the events of the response. Fails if the first response
+    /// doesn't have status 200 OK. See `EventStream` for how it reconnects.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    #[cfg(feature = "std")]
+    pub fn event_stream(self) -> Result<EventStream> {
+        EventStream::new(
+            self.header("Accept", "text/event-stream")
+                .header("Cache-Control", "no-cache"),
+        )
     }
+}
 
-    /// Send the built request on the given socket
+impl<B: core2::io::Read> HttpRequestBuilder<B> {
+    /// Send the built request on the given socket. If a body was given with `body`, it is sent
+    /// too; otherwise the body can be written to the returned `OutgoingBody`.
     pub fn send<S: core2::io::Read + core2::io::Write>(self, socket: S) -> Result<OutgoingBody<S>> {
-        self.request.serialize(io::BufWriter::new(socket))
+        self.send_with_buffers(socket)
     }
 
-    /// Add a header to the request
-    pub fn add_header<S1: AsRef<str>, S2: AsRef<str>>(mut self, key: S1, value: S2) -> Self {
-        self.request.add_header(key.as_ref(), value.as_ref());
-        self
+    /// Like `send`, but with the given sizes for the buffers the request is written through and
+    /// the response is read through, like `send_with_buffers::<_, 512, 256>(socket)`. See
+    /// `HttpRequest::serialize_with_buffers`.
+    pub fn send_with_buffers<
+        S: core2::io::Read + core2::io::Write,
+        const R: usize,
+        const W: usize,
+    >(
+        mut self,
+        socket: S,
+    ) -> Result<OutgoingBody<S, R, W>> {
+        self.sign();
+        let buffered = self.buffer_body()?;
+        let outgoing = self
+            .request
+            .serialize_with_buffers(io::BufWriter::new(socket))?;
+        self.send_body(outgoing, buffered)
     }
-}
 
-/// Represents the ability to connect an abstract stream to some destination address.
-pub trait StreamConnector {
-    type Stream: core2::io::Read + core2::io::Write;
-    type StreamAddr: Hash + Eq + Clone;
-    fn connect(a: Self::StreamAddr) -> Result<Self::Stream>;
-    fn to_stream_addr(url: Url) -> Result<Self::StreamAddr>;
-}
+    /// HTTP/1.0 has no chunked encoding, so read a body of unknown length into memory to send it
+    /// with its `Content-Length`.
+    fn buffer_body(&mut self) -> Result<Option<Vec<u8>>> {
+        use core2::io::Read as _;
+        if self.request.supports_chunked()
+            || self.request.headers.get("Transfer-Encoding").is_none()
+        {
+            return Ok(None);
+        }
+        self.request.headers.remove("Transfer-Encoding");
+        let mut body = Vec::new();
+        self.request.body.read_to_end(&mut body)?;
+        self.request
+            .add_header("Content-Length", body.len().to_string());
+        Ok(Some(body))
+    }
 
-#[cfg(feature = "std")]
-impl StreamConnector for std::net::TcpStream {
-    type Stream = std::net::TcpStream;
-    type StreamAddr = std::net::SocketAddr;
+    /// Write the body given with `body` or the like, if any, or the one read by `buffer_body`, to
+    /// the request's `OutgoingBody`.
+    fn send_body<S: core2::io::Read + core2::io::Write, const R: usize, const W: usize>(
+        mut self,
+        mut outgoing: OutgoingBody<S, R, W>,
+        buffered: Option<Vec<u8>>,
+    ) -> Result<OutgoingBody<S, R, W>> {
+        let length = self
+            .request
+            .headers
+            .get("Content-Length")
+            .and_then(|length| length.parse().ok());
+        let callback = self.options.upload_progress.take();
+        match buffered {
+            Some(body) => io::copy(
+                &mut Progress::new(body.as_slice(), length, callback),
+                &mut outgoing,
+            )?,
+            None => io::copy(
+                &mut Progress::new(&mut self.request.body, length, callback),
+                &mut outgoing,
+            )?,
+        };
+        Ok(outgoing)
+    }
 
-    fn connect(a: Self::StreamAddr) -> Result<Self::Stream> {
-        Ok(std::net::TcpStream::connect(a)?)
+    /// Add a header to the request. It replaces any header with the same name, ignoring case,
+    /// including the defaults like `User-Agent` and `Accept`. Call it once per header.
+    pub fn header<S1: AsRef<str>, S2: AsRef<str>>(mut self, name: S1, value: S2) -> Self {
+        let name = name.as_ref();
+        self.request.headers.remove(name);
+        self.request.add_header(name, value.as_ref());
+        self
     }
 
-    fn to_stream_addr(url: Url) -> Result<Self::StreamAddr> {
-        let err = || {
-            std::io::Error::new(
-                std::io::ErrorKind::AddrNotAvailable,
-                format!("Failed to lookup {}", &url.authority),
-            )
+    /// Send the request to another url, with its path and query as the request target.
+    fn set_url(&mut self, url: Url) {
+        self.request.uri = url.path();
+        self.request.headers.remove("Host");
+        self.request.add_header("Host", url.authority.clone());
+        self.url = url;
+    }
+
+    /// Whether the request has the header, ignoring case, with a value other than the one it
+    /// starts with.
+    fn has_custom_header(&self, name: &str) -> bool {
+        let default = BUILDER_DEFAULTS
+            .iter()
+            .find(|(default, _)| default.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value);
+        self.request
+            .headers
+            .get_all(name)
+            .any(|value| Some(value) != default)
+    }
+
+    /// Authenticate with the given username and password using HTTP Basic authentication (RFC
+    /// 7617), by sending them base64 encoded in the `Authorization` header.
+    pub fn basic_auth<U: Display, P: Display>(self, username: U, password: Option<P>) -> Self {
+        let credentials = match password {
+            Some(password) => format!("{}:{}", username, password),
+            None => format!("{}:", username),
         };
-        Ok(
-            std::net::ToSocketAddrs::to_socket_addrs(&(url.authority.as_ref(), url.port()?))
-                .map_err(|_| err())?
-                .next()
-                .ok_or_else(err)?,
-        )
+        let value = format!("Basic {}", encode_base64(credentials.as_bytes()));
+        self.header("Authorization", value)
     }
-}
 
-/// An HTTP client that keeps connections open.
-pub struct HttpClient<S: StreamConnector> {
-    streams: HashMap<S::StreamAddr, S::Stream>,
-}
+    /// Authenticate with the given token, like an OAuth 2.0 access token, by sending it in the
+    /// `Authorization` header as `Bearer <token>` (RFC 6750).
+    pub fn bearer_auth<T: Display>(self, token: T) -> Self {
+        self.header("Authorization", format!("Bearer {}", token))
+    }
 
-impl<S: StreamConnector> HttpClient<S> {
-    /// Create an `HTTPClient`
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
-        Self {
-            streams: HashMap::new(),
+    /// Authenticate with the given username and password using HTTP Digest authentication (RFC
+    /// 7616), if the server asks for it. The request is sent without credentials first, and if
+    /// the server answers 401 Unauthorized with a `Digest` challenge in its `WWW-Authenticate`
+    /// header, sent again with an `Authorization` answering it, for MD5 or SHA-256 and
+    /// `qop=auth`. Only requests without a body or with one held in memory, given with
+    /// `with_body`, `json` or `form`, are sent again. Only used by `execute`.
+    pub fn digest_auth<U: Display, P: Display>(mut self, username: U, password: P) -> Self {
+        self.options.digest_auth = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Call `signer` just before the request is sent, once its headers are final, so it can add
+    /// headers signing the request, like an `Authorization` for AWS Signature Version 4 or an
+    /// HMAC of a webhook. It is given the method, the request target, like `/path?query`, the
+    /// headers, including `Host` and `Content-Length`, and the body if it is held in memory.
+    /// Retried requests are signed again.
+    pub fn sign_with<F: Fn(HttpMethod, &str, &mut HttpHeaders, Option<&[u8]>) + 'static>(
+        mut self,
+        signer: F,
+    ) -> Self {
+        self.options.signer = Some(Rc::new(signer));
+        self
+    }
+
+    /// Run the signer given with `sign_with`, if any. It is only run once.
+    fn sign(&mut self) {
+        if let Some(signer) = self.options.signer.take() {
+            let body = match &self.replay_body {
+                Some(body) => body.as_bytes(),
+                None if !self.has_body => Some(&[][..]),
+                None => None,
+            };
+            signer(
+                self.request.method,
+                &self.request.uri,
+                &mut self.request.headers,
+                body,
+            );
         }
     }
 
-    fn get_socket(&mut self, url: Url) -> Result<&mut S::Stream> {
-        let stream_addr = S::to_stream_addr(url)?;
-        if !self.streams.contains_key(&stream_addr) {
-            let stream = S::connect(stream_addr.clone())?;
-            self.streams.insert(stream_addr.clone(), stream);
+    /// Make the request conditional on the resource having changed from the version with the given
+    /// `ETag`, which is passed as the server sent it, quotes included. The server answers with 304
+    /// Not Modified and no body if it hasn't; see `HttpResponse::is_not_modified`.
+    pub fn if_none_match<T: AsRef<str>>(self, etag: T) -> Self {
+        self.header("If-None-Match", etag)
+    }
+
+    /// Make the request conditional on the resource having been modified after the given time, in
+    /// seconds since the unix epoch, like a `Last-Modified` date parsed with
+    /// `date::parse_http_date`. The server answers with 304 Not Modified and no body if it hasn't.
+    pub fn if_modified_since(self, date: u64) -> Self {
+        self.header("If-Modified-Since", crate::date::format_http_date(date))
+    }
+
+    /// Add a header to the request, after any values it already has, for headers which may be
+    /// sent more than once. A default like `User-Agent` is replaced rather than added to. Use
+    /// `header` to replace a header.
+    pub fn add_header<S1: AsRef<str>, S2: AsRef<str>>(mut self, key: S1, value: S2) -> Self {
+        let key = key.as_ref();
+        if !self.has_custom_header(key) {
+            self.request.headers.remove(key);
         }
-        Ok(self.streams.get_mut(&stream_addr).unwrap())
+        self.request.headers.append(key, value.as_ref());
+        self
     }
 
-    /// Execute a GET request. The request isn't completed until `OutgoingBody::finish` is called.
-    pub fn get<U: TryInto<Url>>(&mut self, url: U) -> Result<OutgoingBody<&mut S::Stream>>
-    where
-        <U as TryInto<Url>>::Error: Display,
-    {
-        let url = url
-            .try_into()
-            .map_err(|e| Error::ParseError(e.to_string()))?;
-        Ok(HttpRequestBuilder::get(url.clone())?.send(self.get_socket(url)?)?)
+    /// Append a segment to the path of the request target, percent-encoded on its own, slashes
+    /// included, like `path_segment("a/b c")` for `/a%2fb%20c`. See `Url::push_segment`.
+    pub fn path_segment(mut self, segment: &str) -> Self {
+        self.url.push_segment(segment);
+        let uri = &mut self.request.uri;
+        let rest = uri.find(&['?', '#'][..]).map(|start| uri.split_off(start));
+        if uri.ends_with('/') {
+            uri.pop();
+        }
+        uri.push('/');
+        uri.push_str(&crate::url::percent_encode(segment));
+        uri.extend(rest);
+        self
     }
 
-    /// Execute a PUT request. The request isn't completed until `OutgoingBody::finish` is called.
-    pub fn put<U: TryInto<Url>>(&mut self, url: U) -> Result<OutgoingBody<&mut S::Stream>>
-    where
-        <U as TryInto<Url>>::Error: Display,
-    {
-        let url = url
-            .try_into()
-            .map_err(|e| Error::ParseError(e.to_string()))?;
-        Ok(HttpRequestBuilder::put(url.clone())?.send(self.get_socket(url)?)?)
+    /// Append the parameters to the query of the request target, like
+    /// `query(&[("q", "rust"), ("page", "2")])` for `?q=rust&page=2`. Names and values are
+    /// percent-encoded, with spaces as `+`, and the parameters are added after any the url has.
+    pub fn query<K: AsRef<str>, V: AsRef<str>>(mut self, params: &[(K, V)]) -> Self {
+        if params.is_empty() {
+            return self;
+        }
+        let uri = &mut self.request.uri;
+        let fragment = uri.find('#').map(|start| uri.split_off(start));
+        if !uri.contains('?') {
+            uri.push('?');
+        } else if !uri.ends_with('?') {
+            uri.push('&');
+        }
+        uri.push_str(&crate::url::form_urlencode(params));
+        uri.extend(fragment);
+        self
     }
-}
 
-#[cfg(feature = "std")]
-fn send_request<R: core2::io::Read>(
-    builder: HttpRequestBuilder,
-    url: Url,
-    mut body: R,
-) -> Result<Box<dyn core2::io::Read>> {
-    let stream = std::net::TcpStream::connect((url.authority.as_ref(), url.port()?))?;
-    let (status, body) = match &url.scheme {
-        #[cfg(feature = "openssl")]
-        Scheme::Https => {
-            // XXX I need a front-door way to support self-signed certificates.
-            #[allow(unused_mut)]
-            let mut connector = SslConnector::builder(SslMethod::tls())?;
-            #[cfg(test)]
-            connector.set_verify(openssl::ssl::SslVerifyMode::NONE);
-            let connector = connector.build();
-            let stream = connector.connect(&url.authority, stream)?;
-            let mut request = builder.send(stream)?;
-            io::copy(&mut body, &mut request)?;
-            let response = request.finish()?;
-            (
-                response.status,
-                Box::new(response.body) as Box<dyn core2::io::Read>,
-            )
+    /// Stream the request body from the given reader, which must give `length` bytes. The
+    /// request is sent with `Content-Length` instead of the chunked encoding, and the body is
+    /// copied from the reader after the headers by `send`, without buffering it.
+    pub fn body<R: core2::io::Read>(
+        self,
+        reader: R,
+        length: u64,
+    ) -> HttpRequestBuilder<io::Take<R>> {
+        let mut request = self.request.with_body(reader.take(length));
+        request.headers.remove("Transfer-Encoding");
+        request.add_header("Content-Length", length.to_string());
+        HttpRequestBuilder {
+            request,
+            url: self.url,
+            options: self.options,
+            has_body: true,
+            replay_body: None,
         }
-        Scheme::Http => {
-            let mut request = builder.send(stream)?;
-            io::copy(&mut body, &mut request)?;
-            let response = request.finish()?;
-            (
-                response.status,
-                Box::new(response.body) as Box<dyn core2::io::Read>,
-            )
+    }
+
+    /// Send the given body. A body held in memory is sent with `Content-Length`, and is sent
+    /// again when the request is retried. A body read from a reader is sent using the chunked
+    /// encoding as it is read, so the request can't be retried.
+    pub fn with_body<R: core2::io::Read>(self, body: Body<R>) -> HttpRequestBuilder<Body<R>> {
+        let builder = match body.content_length() {
+            Some(length) => self.header("Content-Length", length.to_string()),
+            None => self.header("Transfer-Encoding", "chunked"),
+        };
+        let replay_body = body.replay();
+        let mut request = builder.request.with_body(body);
+        match replay_body {
+            Some(_) => request.headers.remove("Transfer-Encoding"),
+            None => request.headers.remove("Content-Length"),
+        };
+        HttpRequestBuilder {
+            request,
+            url: builder.url,
+            options: builder.options,
+            has_body: true,
+            replay_body,
         }
-        s => {
-            return Err(Error::UnexpectedScheme(s.to_string()));
+    }
+
+    /// Give up connecting to the server after the given time, failing with `Error::Timeout`.
+    /// Only used by `execute`; by default there is no timeout.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.options.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Give up when a single read from the server takes longer than the given time, failing with
+    /// `Error::Timeout`. Only used by `execute`; by default there is no timeout.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.options.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Give up when a single write to the server takes longer than the given time, failing with
+    /// `Error::Timeout`. Only used by `execute`; by default there is no timeout.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.options.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Give up on the whole request, from connecting to reading the end of the response body,
+    /// once the given time has passed since `execute` was called, failing with `Error::Timeout`
+    /// or reads of the body failing with `io::ErrorKind::TimedOut`. Retries must fit in the same
+    /// time. Only used by `execute`, and not with a `connector`; by default there is no deadline.
+    pub fn deadline(mut self, timeout: Duration) -> Self {
+        self.options.deadline = Some(timeout);
+        self
+    }
+
+    /// Use rustls with the given configuration for https urls. Only used by `execute`. Without
+    /// it, openssl is used if http_io is built with the `"openssl"` feature, otherwise rustls with
+    /// the configuration from `default_rustls_config`. TLS sessions are resumed when connecting to
+    /// a host again, both with openssl and with rustls if requests share the same configuration.
+    ///
+    /// *This function is available if http_io is built with the `"rustls"` feature.*
+    #[cfg(feature = "rustls")]
+    pub fn rustls_config(mut self, config: Arc<rustls::ClientConfig>) -> Self {
+        self.options.rustls_config = Some(config);
+        self
+    }
+
+    /// Stream the request body from the given reader until it ends, for bodies whose length
+    /// isn't known up front. The request is sent with `Transfer-Encoding: chunked`, and the body
+    /// is copied from the reader after the headers by `send`, one chunk per read.
+    pub fn chunked_body<R: core2::io::Read>(self, reader: R) -> HttpRequestBuilder<R> {
+        let builder = self.header("Transfer-Encoding", "chunked");
+        let mut request = builder.request.with_body(reader);
+        request.headers.remove("Content-Length");
+        HttpRequestBuilder {
+            request,
+            url: builder.url,
+            options: builder.options,
+            has_body: true,
+            replay_body: None,
         }
-    };
+    }
 
-    if status != HttpStatus::OK {
-        return Err(Error::UnexpectedStatus(status));
+    /// Stream the request body from the byte slices the iterator yields, like the pieces of a log
+    /// or a reading taken one sensor at a time, for callers without a `Read` implementation. The
+    /// request is sent with `Transfer-Encoding: chunked`; see `chunked_body`.
+    pub fn iter_body<I: IntoIterator>(
+        self,
+        pieces: I,
+    ) -> HttpRequestBuilder<IterReader<I::IntoIter>>
+    where
+        I::Item: AsRef<[u8]>,
+    {
+        self.chunked_body(IterReader::new(pieces.into_iter()))
     }
 
-    Ok(body)
-}
+    /// Stream the request body by calling `fill` with a buffer to write the next piece of it to.
+    /// It returns how many bytes it wrote, and 0 once the body ends. The request is sent with
+    /// `Transfer-Encoding: chunked`; see `chunked_body`.
+    pub fn fn_body<F: FnMut(&mut [u8]) -> usize>(self, fill: F) -> HttpRequestBuilder<FnReader<F>> {
+        self.chunked_body(FnReader::new(fill))
+    }
 
-#[cfg(test)]
-use crate::server::{
-    test_server, test_ssl_server, ExpectedRequest, HttpRequestHandler, HttpServer, Listen,
-};
+    /// Send the form as a `multipart/form-data` request body. Since its length isn't known up
+    /// front, it is sent using the chunked encoding.
+    pub fn multipart<'a>(self, form: Multipart<'a>) -> HttpRequestBuilder<Multipart<'a>> {
+        self.header("Content-Type", form.content_type())
+            .chunked_body(form)
+    }
 
-/// Execute a GET request.
-///
-/// *This function is available if http_io is built with the `"std"` feature.*
-#[cfg(feature = "std")]
-pub fn get<U: TryInto<Url>>(url: U) -> Result<Box<dyn core2::io::Read>>
-where
-    <U as TryInto<Url>>::Error: Display,
-{
-    let url = url
-        .try_into()
-        .map_err(|e| Error::ParseError(e.to_string()))?;
-    let builder = HttpRequestBuilder::get(url.clone())?;
-    Ok(send_request(builder, url, io::empty())?)
-}
+    /// Send the value serialized as JSON as the request body, with `Content-Type:
+    /// application/json`. May fail if the value can't be serialized.
+    ///
+    /// *This function is available if http_io is built with the `"json"` feature.*
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(self, value: &T) -> Result<HttpRequestBuilder<Body>> {
+        let body = serde_json::to_vec(value)?;
+        Ok(self
+            .header("Content-Type", "application/json")
+            .with_body(Body::from(body)))
+    }
 
-#[cfg(test)]
-fn get_test<
-    L: Listen + Send + 'static,
-    T: HttpRequestHandler<L::Stream> + Send + 'static,
-    F: Fn(Vec<ExpectedRequest>) -> Result<(u16, HttpServer<L, T>)>,
->(
-    scheme: Scheme,
-    server_factory: F,
-) -> Result<()> {
-    let (port, mut server) = server_factory(vec![ExpectedRequest {
-        expected_method: HttpMethod::Get,
-        expected_uri: "/".into(),
-        expected_body: "".into(),
-        response_status: HttpStatus::OK,
-        response_body: "hello from server".into(),
-    }])?;
-    let handle = std::thread::spawn(move || server.serve_one());
-    let mut body = get(format!("{}://localhost:{}/", scheme, port).as_ref())?;
-    handle.join().unwrap()?;
+    /// Send the pairs as an `application/x-www-form-urlencoded` request body, like a submitted
+    /// HTML form. Keys and values are percent-encoded.
+    pub fn form<K: AsRef<str>, V: AsRef<str>>(self, pairs: &[(K, V)]) -> HttpRequestBuilder<Body> {
+        let body = crate::url::form_urlencode(pairs);
+        self.header("Content-Type", "application/x-www-form-urlencoded")
+            .with_body(Body::from(body))
+    }
 
-    let mut body_str = String::new();
-    body.read_to_string(&mut body_str)?;
-    assert_eq!(body_str, "hello from server");
-    Ok(())
-}
+    /// Only trust servers presenting a certificate matching the given pins for their host,
+    /// failing with `Error::CertificatePinMismatch` otherwise. Only used by `execute`, for
+    /// https urls.
+    pub fn certificate_pins(mut self, pins: CertificatePins) -> Self {
+        self.options.certificate_pins = Some(pins);
+        self
+    }
 
-#[test]
-fn get_request() -> Result<()> {
-    get_test(Scheme::Http, test_server)
-}
+    /// Don't verify the server's certificate for https urls, trusting any server. This is only
+    /// meant for testing against servers with self-signed certificates: anyone who can intercept
+    /// the connection can read and change the request and response. Certificate pins and a
+    /// verifier given to `certificate_verifier` are still checked. Only used by `execute`.
+    pub fn danger_accept_invalid_certs(mut self) -> Self {
+        self.options.accept_invalid_certs = true;
+        self
+    }
 
-#[test]
-fn get_request_ssl() -> Result<()> {
-    get_test(Scheme::Https, test_ssl_server)
-}
+    /// Decide whether to trust the server with the given function instead of the usual
+    /// certificate verification. It is called with the server's host and the DER encoded
+    /// certificate chain it presented, and the request fails with `Error::UntrustedCertificate`
+    /// if it returns false. Only used by `execute`, for https urls.
+    pub fn certificate_verifier<F: Fn(&str, &[&[u8]]) -> bool + 'static>(
+        mut self,
+        verifier: F,
+    ) -> Self {
+        self.options.certificate_verifier = Some(Rc::new(verifier));
+        self
+    }
 
-/// Execute a PUT request.
-///
-/// *This function is available if http_io is built with the `"std"` feature.*
-#[cfg(feature = "std")]
-pub fn put<U: TryInto<Url>, R: core2::io::Read>(url: U, body: R) -> Result<Box<dyn core2::io::Read>>
-where
-    <U as TryInto<Url>>::Error: Display,
-{
-    let url = url
-        .try_into()
-        .map_err(|e| Error::ParseError(e.to_string()))?;
-    let builder = HttpRequestBuilder::put(url.clone())?;
-    Ok(send_request(builder, url, body)?)
-}
+    /// Call `callback` as the body given with `body` or the like is sent, with the number of bytes
+    /// sent so far and the body's length if it is known.
+    pub fn upload_progress<F: Fn(u64, Option<u64>) + 'static>(mut self, callback: F) -> Self {
+        self.options.upload_progress = Some(Rc::new(callback));
+        self
+    }
 
-#[cfg(test)]
-fn put_test<
-    L: Listen + Send + 'static,
-    T: HttpRequestHandler<L::Stream> + Send + 'static,
-    F: Fn(Vec<ExpectedRequest>) -> Result<(u16, HttpServer<L, T>)>,
->(
-    scheme: Scheme,
-    server_factory: F,
-) -> Result<()> {
-    let (port, mut server) = server_factory(vec![ExpectedRequest {
-        expected_method: HttpMethod::Put,
-        expected_uri: "/".into(),
-        expected_body: "hello from client".into(),
-        response_status: HttpStatus::OK,
-        response_body: "hello from server".into(),
-    }])?;
-    let handle = std::thread::spawn(move || server.serve_one());
+    /// Don't ask for a compressed response body, nor decompress it. By default, `Accept-Encoding:
+    /// gzip, deflate` is sent unless the request has an `Accept-Encoding` of its own, and a
+    /// response body with a `Content-Encoding` of either is decompressed, its `Content-Encoding`
+    /// and `Content-Length` removed. Only used by `execute`.
+    ///
+    /// *This function is available if http_io is built with the `"gzip"` feature.*
+    #[cfg(feature = "gzip")]
+    pub fn no_decompression(mut self) -> Self {
+        self.options.no_decompression = true;
+        self
+    }
 
-    let mut incoming_body = put(
-        format!("{}://localhost:{}/", scheme, port).as_ref(),
-        "hello from client".as_bytes(),
-    )?;
+    /// Call `callback` as the response body is read, with the number of bytes read so far and
+    /// its `Content-Length` if it has one. Bytes are counted as received, before any
+    /// decompression. Only used by `execute`.
+    pub fn download_progress<F: Fn(u64, Option<u64>) + 'static>(mut self, callback: F) -> Self {
+        self.options.download_progress = Some(Rc::new(callback));
+        self
+    }
 
-    handle.join().unwrap()?;
+    /// Fail if the response body is longer than `limit` bytes, after any decompression: with
+    /// `Error::BodyTooLarge` if its `Content-Length` says so, and otherwise with an `io::Error`
+    /// of kind `InvalidData` when reading past the limit. Only used by `execute`.
+    pub fn max_response_size(mut self, limit: u64) -> Self {
+        self.options.max_response_size = Some(limit);
+        self
+    }
 
-    let mut body_str = String::new();
-    incoming_body.read_to_string(&mut body_str)?;
-    assert_eq!(body_str, "hello from server");
-    Ok(())
-}
+    /// Fail with `Error::HeadersTooLarge` if the response's status line and headers are longer
+    /// than `limit` bytes. Only used by `execute`.
+    pub fn max_header_size(mut self, limit: usize) -> Self {
+        self.options.max_header_size = Some(limit);
+        self
+    }
 
-#[test]
-fn put_request() -> Result<()> {
-    put_test(Scheme::Http, test_server)
-}
+    /// Send an `Expect: 100-continue` header, and wait up to `timeout` for the server to answer
+    /// 100 Continue before sending the body, so a server rejecting the request, with 401
+    /// Unauthorized or 413 Payload Too Large for example, does so before a large body is uploaded.
+    /// The body is sent anyway if the server doesn't answer in time, and not at all if it answers
+    /// with a final response, which is returned. Not used with a connector. Only used by
+    /// `execute`.
+    pub fn expect_continue(mut self, timeout: Duration) -> Self {
+        self.options.expect_continue = Some(timeout);
+        self.header("Expect", "100-continue")
+    }
 
-#[test]
-fn put_request_ssl() -> Result<()> {
-    put_test(Scheme::Https, test_ssl_server)
+    /// Call `callback` with the headers of each 103 Early Hints response the server sends before
+    /// the final response, like `Link` headers naming resources worth fetching while the server is
+    /// still working on it. Interim responses are skipped either way. Only used by `execute`.
+    pub fn early_hints<F: Fn(&HttpHeaders) + 'static>(mut self, callback: F) -> Self {
+        self.options.early_hints = Some(Rc::new(callback));
+        self
+    }
+
+    /// Send the request as HTTP/1.0, for old devices and minimal servers which don't understand
+    /// HTTP/1.1. The connection is closed after the response, and as there is no chunked encoding,
+    /// a body of unknown length is read into memory when the request is sent so that it has a
+    /// `Content-Length`; nothing can be written to the `OutgoingBody` `send` returns.
+    pub fn http_1_0(mut self) -> Self {
+        self.request.use_http_1_0();
+        self.header("Connection", "close")
+    }
+
+    /// Resolve the server's host, or the proxy's, with the given resolver instead of the system's.
+    /// Only used by `execute`.
+    pub fn resolver<R: Resolve + 'static>(mut self, resolver: R) -> Self {
+        self.options.resolver = Some(Rc::new(resolver));
+        self
+    }
+
+    /// Connect to the given address instead of resolving the server's host, the proxy's if there
+    /// is one, like a staging server's IP address. The url's host is still sent in the `Host`
+    /// header and used for TLS, so the server is asked for the same site and its certificate is
+    /// checked against it. Only used by `execute`.
+    pub fn connect_to(self, addr: core::net::SocketAddr) -> Self {
+        self.resolver(move |_: &str, _: u16| Ok(vec![addr]))
+    }
+
+    /// Offer HTTP/2 when connecting to https urls, and use it if the server picks it with ALPN,
+    /// falling back to HTTP/1.1 otherwise. The request body and the response body are held in
+    /// memory. Only used by `execute`, with openssl or rustls.
+    ///
+    /// *This function is available if http_io is built with the `"http2"` feature.*
+    #[cfg(feature = "http2")]
+    pub fn http2(mut self) -> Self {
+        self.options.http2 = true;
+        self
+    }
+
+    /// Speak HTTP/2 straight away to http urls, for servers known to support it, instead of
+    /// HTTP/1.1. Requests through a proxy which isn't tunneling still use HTTP/1.1. Only used by
+    /// `execute`.
+    ///
+    /// *This function is available if http_io is built with the `"http2"` feature.*
+    #[cfg(feature = "http2")]
+    pub fn http2_prior_knowledge(mut self) -> Self {
+        self.options.http2_prior_knowledge = true;
+        self
+    }
+
+    /// Report the stages of the request to `trace` as it goes through them, from resolving the
+    /// host to reading the end of the response body. Only used by `execute`.
+    pub fn trace<T: Trace + 'static>(mut self, trace: T) -> Self {
+        self.options.trace = Some(Rc::new(trace));
+        self
+    }
+
+    /// Open the connection with the given connector instead of over TCP. It is given the
+    /// server's scheme, host and port, and does any TLS itself; the proxy, resolver, timeouts and
+    /// TLS options are not used. Only used by `execute`.
+    pub fn connector<C: Connect + 'static>(mut self, connector: C) -> Self {
+        self.options.connector = Some(Rc::new(connector));
+        self
+    }
+
+    /// Send the request through the given proxy. Only used by `execute`; by default requests
+    /// are sent directly to the server.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.options.proxy = Some(proxy);
+        self
+    }
+
+    /// Send the request through the proxy given by the environment, if it gives one. See
+    /// `Proxy::from_env`. Only used by `execute`.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    #[cfg(feature = "std")]
+    pub fn proxy_from_env(mut self) -> Result<Self> {
+        if let Some(proxy) = Proxy::from_env(&self.url)? {
+            self.options.proxy = Some(proxy);
+        }
+        Ok(self)
+    }
+
+    /// Connect to the server in the request's url, send the request and return the response,
+    /// whatever its status. The request is sent with `Connection: close`. Reads from the
+    /// returned body keep the read timeout, but fail with an `io::Error` instead of
+    /// `Error::Timeout`.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    #[cfg(feature = "std")]
+    pub fn execute(mut self) -> Result<HttpResponse<Box<dyn core2::io::Read>>> {
+        if let Some(timeout) = self.options.deadline {
+            self.options.expires = Some(std::time::Instant::now() + timeout);
+        }
+        let policy = match self.options.retry.take() {
+            Some(policy) if self.request.method.is_idempotent() && self.can_replay() => policy,
+            _ => return self.exchange_authenticated(),
+        };
+        let mut attempt = 1;
+        loop {
+            let result = self.replay().exchange_authenticated();
+            if !policy.should_retry(attempt, &result, |response| response.status) {
+                return result;
+            }
+            let response = result.as_ref().ok().map(|r| (r.status, &r.headers));
+            let delay = match policy.retry_delay(attempt, response, crate::date::system_clock()) {
+                Some(delay) => delay,
+                // The server asked to wait longer than the policy allows.
+                None => return result,
+            };
+            if let Some(expires) = self.options.expires {
+                // The next attempt would start too late.
+                if deadline::remaining(expires).map_or(true, |left| left <= delay) {
+                    return result;
+                }
+            }
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    /// Retry the request according to the policy when it fails, or gets a response with a status
+    /// like 503 Service Unavailable. Only requests with idempotent methods, like GET and PUT, and
+    /// no body or one held in memory, given with `with_body`, `json` or `form`, are retried. Only
+    /// used by `execute`.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.options.retry = Some(policy);
+        self
+    }
+
+    /// Resume a download whose first `downloaded` bytes were already written to `sink`, by
+    /// requesting the rest with a `Range` header and appending it to `sink`. Returns the length
+    /// downloaded in total.
+    ///
+    /// A 206 Partial Content response must start at `downloaded`. If the server ignores the range
+    /// and sends the whole body, its first `downloaded` bytes are skipped, and if it answers 416
+    /// Range Not Satisfiable because `downloaded` is the whole length, there is nothing left to
+    /// download. To make sure the rest is of the same version, send the `ETag` or
+    /// `Last-Modified` of the first part in an `If-Range` header.
+    ///
+    /// If it fails, `sink` may have been written to, and the length written to it so far is where
+    /// to resume again.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    #[cfg(feature = "std")]
+    pub fn resume_download<W: core2::io::Write>(
+        self,
+        downloaded: u64,
+        sink: &mut W,
+    ) -> Result<u64> {
+        use self::range::ContentRange;
+        use core2::io::Read as _;
+
+        let mut builder = self;
+        if downloaded > 0 {
+            // A part of a compressed body can't be decoded on its own.
+            if builder.request.headers.get("Accept-Encoding").is_none() {
+                builder = builder.header("Accept-Encoding", "identity");
+            }
+            builder = builder.header("Range", format!("bytes={}-", downloaded));
+        }
+        let mut response = builder.execute()?;
+        let content_range =
+            |headers: &HttpHeaders| ContentRange::parse(headers.get("Content-Range").unwrap_or(""));
+        match response.status {
+            HttpStatus::OK => {
+                let mut skipped = (&mut response.body).take(downloaded);
+                let mut buffer = [0; 1024];
+                while skipped.read(&mut buffer)? > 0 {}
+                if skipped.limit() > 0 {
+                    return Err(Error::UnexpectedEof(format!(
+                        "body shorter than the {} bytes already downloaded",
+                        downloaded
+                    )));
+                }
+            }
+            HttpStatus::PartialContent => {
+                let range = content_range(&response.headers)?;
+                if range.range.map(|(first, _)| first) != Some(downloaded) {
+                    return Err(Error::Other(format!(
+                        "expected a range starting at {}, got {:?}",
+                        downloaded, range
+                    )));
+                }
+            }
+            HttpStatus::RequestedRangeNotSatisfiable if downloaded > 0 => {
+                if content_range(&response.headers)?.length == Some(downloaded) {
+                    return Ok(downloaded);
+                }
+                return Err(Error::UnexpectedStatus(response.status));
+            }
+            status => return Err(Error::UnexpectedStatus(status)),
+        }
+        Ok(downloaded + io::copy(&mut response.body, sink)?)
+    }
+
+    /// Encode the request, with the body read into memory and sent with a `Content-Length`.
+    #[cfg(any(feature = "tokio", feature = "embedded-io-async"))]
+    fn encode(mut self) -> Result<Vec<u8>> {
+        use core2::io::Read as _;
+
+        let mut body = Vec::new();
+        self.request.body.read_to_end(&mut body)?;
+        self.request.headers.remove("Transfer-Encoding");
+        if self.has_body || !body.is_empty() {
+            self = self.header("Content-Length", body.len().to_string());
+        }
+        let mut encoded = Vec::new();
+        self.request.serialize_head(&mut encoded)?;
+        encoded.extend_from_slice(&body);
+        Ok(encoded)
+    }
+
+    /// Use `random` for the `Sec-WebSocket-Key` and the frame masks of `websocket`, and the client
+    /// nonce of `digest_auth`, which must be unpredictable. When http_io is built with the `"std"`
+    /// feature the default is the operating system's secure random number generator; otherwise
+    /// there is no default, and `websocket` fails unless one is given.
+    pub fn random(mut self, random: Random) -> Self {
+        self.options.random = Some(random);
+        self
+    }
+
+    /// The source of random numbers given with `random`, or the default.
+    #[cfg(any(feature = "std", feature = "websocket"))]
+    fn random_source(&self) -> Result<Random> {
+        match self.options.random {
+            Some(random) => Ok(random),
+            #[cfg(feature = "std")]
+            None => Ok(secure_random_u64),
+            #[cfg(not(feature = "std"))]
+            None => Err(Error::Other(
+                "no source of random numbers; see HttpRequestBuilder::random".into(),
+            )),
+        }
+    }
+
+    /// Open a WebSocket connection (RFC 6455) on the given stream, by sending the request as the
+    /// opening handshake and checking that the server switched protocols. The request's body is
+    /// not sent. Messages are read and written with the returned `WebSocket`.
+    ///
+    /// *This function is available if http_io is built with the `"websocket"` feature.*
+    #[cfg(feature = "websocket")]
+    pub fn websocket<S: io::Read + io::Write>(
+        self,
+        mut stream: S,
+    ) -> Result<crate::websocket::WebSocket<S>> {
+        let random = self.random_source()?;
+        let mut nonce = [0; 16];
+        nonce[..8].copy_from_slice(&random().to_le_bytes());
+        nonce[8..].copy_from_slice(&random().to_le_bytes());
+        let key = encode_base64(&nonce);
+        let mut builder = self
+            .header("Upgrade", "websocket")
+            .header("Connection", "Upgrade")
+            .header("Sec-WebSocket-Key", &key)
+            .header("Sec-WebSocket-Version", "13");
+        builder.request.headers.remove("Transfer-Encoding");
+        builder.request.headers.remove("Content-Length");
+        builder.request.serialize_head(&mut stream)?;
+        io::Write::flush(&mut stream)?;
+
+        // The response is read straight from the stream, so no frame is buffered away.
+        let response = HttpResponse::deserialize(&mut stream)?;
+        if response.status != HttpStatus::SwitchingProtocols {
+            return Err(Error::UnexpectedStatus(response.status));
+        }
+        let header = |name: &str| response.headers.get(name);
+        if !header("Upgrade").map_or(false, |value| value.eq_ignore_ascii_case("websocket")) {
+            return Err(Error::WebSocketError("missing Upgrade: websocket".into()));
+        }
+        if header("Sec-WebSocket-Accept") != Some(crate::websocket::accept_key(&key).as_str()) {
+            return Err(Error::WebSocketError("invalid Sec-WebSocket-Accept".into()));
+        }
+        let mut socket = crate::websocket::WebSocket::new(stream, crate::websocket::Role::Client);
+        socket.set_random(random);
+        Ok(socket)
+    }
+
+    /// Whether the request can be sent again, because it has no body or one held in memory.
+    #[cfg(feature = "std")]
+    fn can_replay(&self) -> bool {
+        !self.has_body || self.replay_body.is_some()
+    }
+
+    /// Send the request on a new connection, and again with an `Authorization` answering the
+    /// server's Digest challenge if it asks for credentials and some were given with
+    /// `digest_auth`.
+    #[cfg(feature = "std")]
+    fn exchange_authenticated(self) -> Result<HttpResponse<Box<dyn core2::io::Read>>> {
+        let (username, password) = match &self.options.digest_auth {
+            Some(credentials) if self.can_replay() => credentials.clone(),
+            _ => return exchange(self, io::empty()).map_err(timeout_error),
+        };
+        let response = exchange(self.replay(), io::empty()).map_err(timeout_error)?;
+        if response.status != HttpStatus::Unauthorized {
+            return Ok(response);
+        }
+        // The server may offer other schemes, like Basic, before Digest.
+        let challenge = match response
+            .headers
+            .get_all("WWW-Authenticate")
+            .find_map(DigestChallenge::parse)
+        {
+            Some(challenge) => challenge,
+            None => return Ok(response),
+        };
+        let authorization = challenge.authorization(
+            &username,
+            &password,
+            self.request.method,
+            &self.request.uri,
+            &format!("{:016x}", self.random_source()?()),
+        );
+        let builder = self.replay().header("Authorization", authorization);
+        exchange(builder, io::empty()).map_err(timeout_error)
+    }
+
+    /// A copy of the builder with a copy of the body, or no body if it can't be sent again.
+    #[cfg(feature = "std")]
+    fn replay(&self) -> HttpRequestBuilder<Body> {
+        let copy = self.without_body();
+        let body = self.replay_body.as_ref().and_then(Body::replay);
+        HttpRequestBuilder {
+            request: copy
+                .request
+                .with_body(body.unwrap_or_else(|| Body::bytes(Vec::new()))),
+            url: copy.url,
+            options: copy.options,
+            has_body: self.has_body,
+            replay_body: self.replay_body.as_ref().and_then(Body::replay),
+        }
+    }
+
+    /// A copy of the builder without the body, to send the request again.
+    #[cfg(feature = "std")]
+    fn without_body(&self) -> HttpRequestBuilder {
+        let mut request = HttpRequest::new(self.request.method, self.request.uri.clone());
+        request.headers = self.request.headers.clone();
+        if !self.request.supports_chunked() {
+            request.use_http_1_0();
+        }
+        HttpRequestBuilder {
+            request,
+            url: self.url.clone(),
+            options: self.options.clone(),
+            has_body: false,
+            replay_body: None,
+        }
+    }
+}
+
+#[test]
+fn request_builder_path_segment() -> Result<()> {
+    let builder = HttpRequestBuilder::get("http://localhost/users/")?
+        .path_segment("a/b c")
+        .path_segment("posts")
+        .query(&[("page", "2")]);
+    assert_eq!(builder.request.uri, "/users/a%2fb%20c/posts?page=2");
+    assert_eq!(builder.url.path.to_string(), "/users/a%2fb%20c/posts");
+    Ok(())
+}
+
+#[test]
+fn request_builder_query() -> Result<()> {
+    let builder = HttpRequestBuilder::get("http://localhost/search")?
+        .query(&[("q", "rust lang"), ("a&b", "c=d")])
+        .query(&[("page", "2")]);
+    assert_eq!(
+        builder.request.uri,
+        "/search?q=rust+lang&a%26b=c%3dd&page=2"
+    );
+    let builder = HttpRequestBuilder::get("http://localhost/#top")?.query(&[("q", "x")]);
+    assert_eq!(builder.request.uri, "/?q=x#top");
+    Ok(())
+}
+
+#[test]
+fn request_builder_headers() -> Result<()> {
+    let builder = HttpRequestBuilder::get("http://localhost/")?
+        .header("Authorization", "Bearer token")
+        .header("user-agent", "test")
+        .header("X-Api-Key", "1")
+        .header("X-Api-Key", "2");
+    let mut head = Vec::new();
+    builder.request.serialize_head(&mut head)?;
+    let head = String::from_utf8(head).unwrap();
+    assert!(head.contains("Authorization: Bearer token\r\n"));
+    assert!(head.contains("user-agent: test\r\n"));
+    assert!(!head.contains("User-Agent"));
+    assert!(head.contains("X-Api-Key: 2\r\n"));
+    assert!(!head.contains("X-Api-Key: 1"));
+    Ok(())
+}
+
+#[test]
+fn request_builder_add_header() -> Result<()> {
+    let builder = HttpRequestBuilder::get("http://localhost/")?
+        .add_header("Accept", "text/html")
+        .add_header("accept", "text/plain")
+        .add_header("Cookie", "a=1")
+        .add_header("Cookie", "b=2");
+    let accept: Vec<&str> = builder.request.headers.get_all("Accept").collect();
+    assert_eq!(accept, ["text/html", "text/plain"]);
+    let cookies: Vec<&str> = builder.request.headers.get_all("Cookie").collect();
+    assert_eq!(cookies, ["a=1", "b=2"]);
+    Ok(())
+}
+
+#[test]
+fn request_builder_body() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Put,
+        expected_uri: "/upload".into(),
+        expected_body: "hello from client".into(),
+        response_status: HttpStatus::OK,
+        response_body: "".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let stream = std::net::TcpStream::connect(("localhost", port))?;
+    let response = HttpRequestBuilder::put(format!("http://localhost:{}/upload", port).as_ref())?
+        .header("Connection", "close")
+        .body("hello from client, and more".as_bytes(), 17)
+        .send(stream)?
+        .finish()?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.status, HttpStatus::OK);
+    Ok(())
+}
+
+#[test]
+fn request_builder_chunked_body() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Put,
+        expected_uri: "/log".into(),
+        expected_body: "first line\nsecond line\n".into(),
+        response_status: HttpStatus::OK,
+        response_body: "".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let stream = std::net::TcpStream::connect(("localhost", port))?;
+    let builder = HttpRequestBuilder::put(format!("http://localhost:{}/log", port).as_ref())?
+        .header("Connection", "close")
+        .body(io::empty(), 0)
+        .chunked_body("first line\nsecond line\n".as_bytes());
+    assert_eq!(builder.request.headers.get("Content-Length"), None);
+    assert_eq!(
+        builder.request.headers.get("Transfer-Encoding"),
+        Some("chunked")
+    );
+    let response = builder.send(stream)?.finish()?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.status, HttpStatus::OK);
+    Ok(())
+}
+
+#[test]
+fn request_builder_iter_body() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Post,
+        expected_uri: "/readings".into(),
+        expected_body: "21.5,22.0,21.8".into(),
+        response_status: HttpStatus::OK,
+        response_body: "".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let readings = ["21.5", "22.0", "21.8"];
+    let response =
+        HttpRequestBuilder::post(format!("http://localhost:{}/readings", port).as_ref())?
+            .iter_body(readings.iter().enumerate().map(|(i, reading)| {
+                let separator = if i == 0 { "" } else { "," };
+                format!("{}{}", separator, reading)
+            }))
+            .execute()?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.status, HttpStatus::OK);
+    Ok(())
+}
+
+#[test]
+fn request_builder_form() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Post,
+        expected_uri: "/login".into(),
+        expected_body: "user=jo&password=p%40ss+word".into(),
+        response_status: HttpStatus::OK,
+        response_body: "".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let response = HttpRequestBuilder::post(format!("http://localhost:{}/login", port).as_ref())?
+        .form(&[("user", "jo"), ("password", "p@ss word")])
+        .execute()?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.status, HttpStatus::OK);
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn request_builder_json() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Post,
+        expected_uri: "/api".into(),
+        expected_body: "{\"name\":\"sensor\",\"readings\":[1,2]}".into(),
+        response_status: HttpStatus::OK,
+        response_body: "[3,4]".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let value = serde_json::json!({"name": "sensor", "readings": [1, 2]});
+    let response = HttpRequestBuilder::post(format!("http://localhost:{}/api", port).as_ref())?
+        .json(&value)?
+        .execute()?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.json::<Vec<u32>>(1024)?, vec![3, 4]);
+    Ok(())
+}
+
+#[test]
+fn request_builder_read_timeout() -> Result<()> {
+    // The listener never accepts, so the request is sent but never answered.
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let result = HttpRequestBuilder::get(format!("http://localhost:{}/", port).as_ref())?
+        .connect_timeout(Duration::from_secs(5))
+        .read_timeout(Duration::from_millis(100))
+        .execute();
+    assert!(matches!(result, Err(Error::Timeout)));
+    Ok(())
+}
+
+#[test]
+fn request_builder_retry() -> Result<()> {
+    let unavailable = |method| ExpectedRequest {
+        expected_method: method,
+        expected_uri: "/flaky".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::ServiceUnavailable,
+        response_body: "".into(),
+    };
+    let policy = RetryPolicy::new(3).backoff(Duration::from_millis(1), Duration::from_millis(1));
+
+    let (port, mut server) = test_server(vec![
+        unavailable(HttpMethod::Get),
+        ExpectedRequest {
+            expected_method: HttpMethod::Get,
+            expected_uri: "/flaky".into(),
+            expected_body: "".into(),
+            response_status: HttpStatus::OK,
+            response_body: "hello".into(),
+        },
+    ])?;
+    let handle = std::thread::spawn(move || -> Result<()> {
+        server.serve_one()?;
+        server.serve_one()?;
+        Ok(())
+    });
+    let response = HttpRequestBuilder::get(format!("http://localhost:{}/flaky", port).as_ref())?
+        .retry(policy.clone())
+        .execute()?;
+    handle.join().unwrap()?;
+    assert_eq!(response.status, HttpStatus::OK);
+
+    // POST isn't idempotent, so it is only sent once.
+    let (port, mut server) = test_server(vec![unavailable(HttpMethod::Post)])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let response = HttpRequestBuilder::post(format!("http://localhost:{}/flaky", port).as_ref())?
+        .retry(policy.clone())
+        .execute()?;
+    handle.join().unwrap()?;
+    assert_eq!(response.status, HttpStatus::ServiceUnavailable);
+
+    // A body held in memory is sent again, but one read from a reader can only be sent once.
+    let put = |status| ExpectedRequest {
+        expected_method: HttpMethod::Put,
+        expected_uri: "/flaky".into(),
+        expected_body: "data".into(),
+        response_status: status,
+        response_body: "".into(),
+    };
+    let (port, mut server) = test_server(vec![
+        put(HttpStatus::ServiceUnavailable),
+        put(HttpStatus::OK),
+        put(HttpStatus::ServiceUnavailable),
+    ])?;
+    let handle = std::thread::spawn(move || -> Result<()> {
+        for _ in 0..3 {
+            server.serve_one()?;
+        }
+        Ok(())
+    });
+    let url = format!("http://localhost:{}/flaky", port);
+    let response = HttpRequestBuilder::put(url.as_ref())?
+        .with_body(Body::from("data"))
+        .retry(policy.clone())
+        .execute()?;
+    assert_eq!(response.status, HttpStatus::OK);
+    let response = HttpRequestBuilder::put(url.as_ref())?
+        .with_body(Body::reader("data".as_bytes()))
+        .retry(policy)
+        .execute()?;
+    assert_eq!(response.status, HttpStatus::ServiceUnavailable);
+    handle.join().unwrap()?;
+    Ok(())
+}
+
+#[test]
+fn request_builder_resolver() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let response = HttpRequestBuilder::get(format!("http://example.test:{}/", port).as_ref())?
+        .resolver(|host: &str, port: u16| {
+            assert_eq!(host, "example.test");
+            SystemResolver.resolve("localhost", port)
+        })
+        .execute()?;
+    handle.join().unwrap()?;
+    assert_eq!(response.status, HttpStatus::OK);
+
+    let unresolved = HttpRequestBuilder::get("http://example.test/")?
+        .resolver(StaticResolver::new())
+        .execute();
+    assert!(matches!(unresolved, Err(Error::UnknownHost(h)) if h == "example.test"));
+    Ok(())
+}
+
+#[test]
+fn request_builder_progress() -> Result<()> {
+    use io::Read as _;
+
+    use std::cell::RefCell;
+
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Put,
+        expected_uri: "/upload".into(),
+        expected_body: "hello from client".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let uploaded = Rc::new(RefCell::new(Vec::new()));
+    let downloaded = Rc::new(RefCell::new(Vec::new()));
+    let (up, down) = (uploaded.clone(), downloaded.clone());
+    let mut response =
+        HttpRequestBuilder::put(format!("http://localhost:{}/upload", port).as_ref())?
+            .body(&b"hello from client"[..], 17)
+            .upload_progress(move |sent, total| up.borrow_mut().push((sent, total)))
+            .download_progress(move |read, total| down.borrow_mut().push((read, total)))
+            .execute()?;
+    let mut body = String::new();
+    response.body.read_to_string(&mut body)?;
+    handle.join().unwrap()?;
+
+    assert_eq!(uploaded.borrow().last(), Some(&(17, Some(17))));
+    let length = response
+        .headers
+        .get("Content-Length")
+        .map(|length| length.parse().unwrap());
+    assert_eq!(downloaded.borrow().last(), Some(&(17, length)));
+    Ok(())
+}
+
+#[test]
+fn request_builder_size_limits() -> Result<()> {
+    use io::Read as _;
+
+    let hello = || ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    };
+    let (port, mut server) = test_server(vec![hello(), hello(), hello()])?;
+    let handle = std::thread::spawn(move || -> Result<()> {
+        server.serve_one()?;
+        server.serve_one()?;
+        server.serve_one()?;
+        Ok(())
+    });
+    let url = format!("http://localhost:{}/", port);
+
+    let mut body = String::new();
+    HttpRequestBuilder::get(url.as_ref())?
+        .max_response_size(17)
+        .max_header_size(1024)
+        .execute()?
+        .body
+        .read_to_string(&mut body)?;
+    assert_eq!(body, "hello from server");
+
+    let result = HttpRequestBuilder::get(url.as_ref())?
+        .max_response_size(16)
+        .execute()
+        .and_then(|response| response.bytes(usize::MAX));
+    assert!(result.is_err());
+
+    let result = HttpRequestBuilder::get(url.as_ref())?
+        .max_header_size(10)
+        .execute();
+    assert!(matches!(result, Err(Error::HeadersTooLarge(10))));
+    handle.join().unwrap()?;
+    Ok(())
+}
+
+#[test]
+fn request_builder_connect_to() -> Result<()> {
+    use io::Write as _;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let handle = std::thread::spawn(move || -> Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        let request = HttpRequest::deserialize(&mut stream)?;
+        assert_eq!(request.headers.get("Host"), Some("staging.example.test"));
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")?;
+        Ok(())
+    });
+
+    let response = HttpRequestBuilder::get("http://staging.example.test/")?
+        .connect_to(addr)
+        .execute()?;
+    handle.join().unwrap()?;
+    assert_eq!(response.text(16)?, "ok");
+    Ok(())
+}
+
+#[cfg(feature = "http2")]
+#[test]
+fn request_builder_http2_prior_knowledge() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Post,
+        expected_uri: "/upload".into(),
+        expected_body: "over h2".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one_http2());
+    let response = HttpRequestBuilder::post(format!("http://localhost:{}/upload", port).as_ref())?
+        .http2_prior_knowledge()
+        .body("over h2".as_bytes(), 7)
+        .execute()?;
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.text(64)?, "hello from server");
+    handle.join().unwrap()?;
+    Ok(())
+}
+
+#[cfg(feature = "http2")]
+#[test]
+fn request_builder_http2_limits() -> Result<()> {
+    let hello = || ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    };
+    let (port, mut server) = test_server(vec![hello(), hello()])?;
+    let handle = std::thread::spawn(move || -> Result<()> {
+        // The client gives up on both responses.
+        let _ = server.serve_one_http2();
+        let _ = server.serve_one_http2();
+        Ok(())
+    });
+    let url = format!("http://localhost:{}/", port);
+
+    let result = HttpRequestBuilder::get(url.as_ref())?
+        .http2_prior_knowledge()
+        .max_response_size(16)
+        .execute()
+        .and_then(|response| response.bytes(usize::MAX));
+    assert!(result.is_err());
+
+    let result = HttpRequestBuilder::get(url.as_ref())?
+        .http2_prior_knowledge()
+        .max_header_size(10)
+        .execute();
+    assert!(matches!(result, Err(Error::HeadersTooLarge(10))));
+    handle.join().unwrap()?;
+    Ok(())
+}
+
+#[cfg(feature = "http2")]
+#[test]
+fn request_builder_http2_body_larger_than_window() -> Result<()> {
+    let body = "0123456789".repeat(20_000);
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: body.clone(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one_http2());
+    let response = HttpRequestBuilder::get(format!("http://localhost:{}/", port).as_ref())?
+        .http2_prior_knowledge()
+        .execute()?;
+    assert_eq!(response.text(usize::MAX)?, body);
+    handle.join().unwrap()?;
+    Ok(())
+}
+
+#[test]
+fn request_builder_trace() -> Result<()> {
+    use io::Read as _;
+
+    use std::cell::RefCell;
+
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Trace for Recorder {
+        fn dns_start(&self, host: &str) {
+            self.0.borrow_mut().push(format!("dns start {}", host));
+        }
+
+        fn dns_done(&self, host: &str, _addrs: &[core::net::SocketAddr]) {
+            self.0.borrow_mut().push(format!("dns done {}", host));
+        }
+
+        fn connect_done(
+            &self,
+            _addr: core::net::SocketAddr,
+            result: core::result::Result<(), &Error>,
+        ) {
+            if result.is_ok() {
+                self.0.borrow_mut().push("connected".into());
+            }
+        }
+
+        fn first_byte(&self) {
+            self.0.borrow_mut().push("first byte".into());
+        }
+
+        fn body_done(&self, length: u64) {
+            self.0.borrow_mut().push(format!("body done {}", length));
+        }
+    }
+
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut response = HttpRequestBuilder::get(format!("http://localhost:{}/", port).as_ref())?
+        .trace(Recorder(events.clone()))
+        .execute()?;
+    let mut body = String::new();
+    response.body.read_to_string(&mut body)?;
+    handle.join().unwrap()?;
+
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            "dns start localhost",
+            "dns done localhost",
+            "connected",
+            "first byte",
+            "body done 5"
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn request_builder_connector() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let connected = Rc::new(core::cell::RefCell::new(None));
+    let record = connected.clone();
+    let response = HttpRequestBuilder::get("http://example.invalid:8080/")?
+        .connector(move |scheme: &Scheme, host: &str, url_port: u16| {
+            *record.borrow_mut() = Some((scheme.to_string(), host.to_string(), url_port));
+            let stream = std::net::TcpStream::connect(("localhost", port))?;
+            Ok(Box::new(stream) as Box<dyn Transport>)
+        })
+        .execute()?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.text(1024)?, "hello from server");
+    assert_eq!(
+        connected.borrow().clone(),
+        Some(("http".into(), "example.invalid".into(), 8080))
+    );
+    Ok(())
+}
+
+#[test]
+fn request_builder_resume_download() -> Result<()> {
+    use crate::server::HttpResponder;
+
+    const BODY: &[u8] = b"0123456789";
+
+    /// Serves `BODY`, or the part of it from the start of a `Range: bytes=N-` header.
+    struct RangeResponder;
+
+    impl HttpResponder<std::net::TcpStream> for RangeResponder {
+        type Body = io::Cursor<&'static [u8]>;
+        type Error = Error;
+
+        fn respond(
+            &mut self,
+            request: HttpRequest<&mut std::net::TcpStream>,
+        ) -> Result<HttpResponse<Self::Body>> {
+            let start = match request.headers.get("Range") {
+                Some(range) => range
+                    .trim_start_matches("bytes=")
+                    .trim_end_matches('-')
+                    .parse::<usize>()?,
+                None => 0,
+            };
+            if start >= BODY.len() {
+                let mut response = HttpResponse::new(
+                    HttpStatus::RequestedRangeNotSatisfiable,
+                    io::Cursor::new(&b""[..]),
+                );
+                response.add_header("Content-Range", format!("bytes */{}", BODY.len()));
+                response.add_header("Content-Length", "0");
+                return Ok(response);
+            }
+            let status = if start > 0 {
+                HttpStatus::PartialContent
+            } else {
+                HttpStatus::OK
+            };
+            let mut response = HttpResponse::new(status, io::Cursor::new(&BODY[start..]));
+            if start > 0 {
+                response.add_header(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, BODY.len() - 1, BODY.len()),
+                );
+            }
+            response.add_header("Content-Length", (BODY.len() - start).to_string());
+            Ok(response)
+        }
+    }
+
+    let server_socket = std::net::TcpListener::bind("localhost:0")?;
+    let port = server_socket.local_addr()?.port();
+    let mut server = HttpServer::new(server_socket, RangeResponder);
+    let handle = std::thread::spawn(move || -> Result<()> {
+        server.serve_one()?;
+        server.serve_one()?;
+        Ok(())
+    });
+    let url = format!("http://localhost:{}/firmware.bin", port);
+    let mut sink = b"0123".to_vec();
+    let length = HttpRequestBuilder::get(url.as_ref())?.resume_download(4, &mut sink)?;
+    assert_eq!((length, sink.as_slice()), (10, BODY));
+    let length = HttpRequestBuilder::get(url.as_ref())?.resume_download(10, &mut sink)?;
+    assert_eq!((length, sink.as_slice()), (10, BODY));
+    handle.join().unwrap()?;
+
+    // A server which ignores the range sends the whole body.
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/firmware.bin".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "0123456789".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let mut sink = b"0123".to_vec();
+    let length =
+        HttpRequestBuilder::get(format!("http://localhost:{}/firmware.bin", port).as_ref())?
+            .resume_download(4, &mut sink)?;
+    handle.join().unwrap()?;
+    assert_eq!((length, sink.as_slice()), (10, BODY));
+    Ok(())
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn request_builder_gzip() -> Result<()> {
+    use io::Write as _;
+
+    // "hello from server" compressed by gzip.
+    const BODY: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0x48, 0x2b, 0xca, 0xcf, 0x55, 0x28, 0x4e, 0x2d, 0x2a, 0x4b, 0x2d, 0x02, 0x00, 0x24,
+        0x20, 0xa8, 0x29, 0x11, 0x00, 0x00, 0x00,
+    ];
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = std::thread::spawn(move || -> Result<Option<String>> {
+        let (mut stream, _) = listener.accept()?;
+        let request = HttpRequest::deserialize(io::BufReader::new(&mut stream))?;
+        let mut response = HttpResponse::new(HttpStatus::OK, io::empty());
+        response.add_header("Content-Encoding", "gzip");
+        response.add_header("Content-Length", BODY.len().to_string());
+        response.serialize(&mut stream)?;
+        stream.write_all(BODY)?;
+        Ok(request.headers.get("Accept-Encoding").map(String::from))
+    });
+
+    let response =
+        HttpRequestBuilder::get(format!("http://localhost:{}/", port).as_ref())?.execute()?;
+    let accept_encoding = handle.join().unwrap()?;
+
+    assert_eq!(accept_encoding.as_deref(), Some("gzip, deflate"));
+    assert_eq!(response.headers.get("Content-Encoding"), None);
+    assert_eq!(response.text(1024)?, "hello from server");
+    Ok(())
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn request_builder_deflate() -> Result<()> {
+    // "hello from server" compressed by zlib.
+    const BODY: &[u8] = &[
+        0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0x48, 0x2b, 0xca, 0xcf, 0x55, 0x28, 0x4e,
+        0x2d, 0x2a, 0x4b, 0x2d, 0x02, 0x00, 0x3a, 0xbe, 0x06, 0xa0,
+    ];
+
+    let serve = |no_decompression: bool| -> Result<(Option<String>, HttpResponse<Box<dyn core2::io::Read>>)> {
+        use io::Write as _;
+
+        let listener = std::net::TcpListener::bind("localhost:0")?;
+        let port = listener.local_addr()?.port();
+        let handle = std::thread::spawn(move || -> Result<Option<String>> {
+            let (mut stream, _) = listener.accept()?;
+            let request = HttpRequest::deserialize(io::BufReader::new(&mut stream))?;
+            let mut response = HttpResponse::new(HttpStatus::OK, io::empty());
+            response.add_header("Content-Encoding", "deflate");
+            response.add_header("Content-Length", BODY.len().to_string());
+            response.serialize(&mut stream)?;
+            stream.write_all(BODY)?;
+            Ok(request.headers.get("Accept-Encoding").map(String::from))
+        });
+        let mut builder = HttpRequestBuilder::get(format!("http://localhost:{}/", port).as_ref())?;
+        if no_decompression {
+            builder = builder.no_decompression();
+        }
+        let response = builder.execute()?;
+        Ok((handle.join().unwrap()?, response))
+    };
+
+    let (accept_encoding, response) = serve(false)?;
+    assert_eq!(accept_encoding.as_deref(), Some("gzip, deflate"));
+    assert_eq!(response.text(1024)?, "hello from server");
+
+    let (accept_encoding, mut response) = serve(true)?;
+    assert_eq!(accept_encoding, None);
+    assert_eq!(response.headers.get("Content-Encoding"), Some("deflate"));
+    let mut body = Vec::new();
+    response.body.read_to_end(&mut body)?;
+    assert_eq!(body, BODY);
+    Ok(())
+}
+
+#[test]
+fn request_builder_event_stream() -> Result<()> {
+    use io::{Read as _, Write as _};
+
+    const RESPONSES: [&str; 3] = [
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n\
+         retry: 10\nid: 1\ndata: one\n\ndata: cut",
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n\
+         event: update\ndata: two\n\n",
+        "HTTP/1.1 204 No Content\r\n\r\n",
+    ];
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = std::thread::spawn(move || -> Result<Vec<Option<String>>> {
+        let mut last_event_ids = Vec::new();
+        for response in RESPONSES {
+            let (mut stream, _) = listener.accept()?;
+            {
+                let mut request =
+                    HttpRequest::deserialize(io::BufReader::<_, 1024>::new(&mut stream))?;
+                assert_eq!(request.headers.get("Accept"), Some("text/event-stream"));
+                last_event_ids.push(request.headers.get("Last-Event-ID").map(String::from));
+                request.body.read_to_end(&mut Vec::new())?;
+            }
+            stream.write_all(response.as_bytes())?;
+        }
+        Ok(last_event_ids)
+    });
+
+    let events = HttpRequestBuilder::get(format!("http://localhost:{}/events", port).as_ref())?
+        .event_stream()?
+        .collect::<Result<Vec<_>>>()?;
+    let last_event_ids = handle.join().unwrap()?;
+
+    let data: Vec<_> = events
+        .iter()
+        .map(|event| {
+            (
+                event.event.as_str(),
+                event.data.as_str(),
+                event.id.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        data,
+        [("message", "one", Some("1")), ("update", "two", Some("1"))]
+    );
+    assert_eq!(last_event_ids, [None, Some("1".into()), Some("1".into())]);
+    Ok(())
+}
+
+#[test]
+fn request_builder_http_1_0() -> Result<()> {
+    use io::{Read as _, Write as _};
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = std::thread::spawn(move || -> Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        {
+            let mut request = HttpRequest::deserialize(&mut stream)?;
+            assert!(!request.supports_chunked());
+            assert_eq!(request.headers.get("Connection"), Some("close"));
+            assert_eq!(request.headers.get("Transfer-Encoding"), None);
+            assert_eq!(request.headers.get("Content-Length"), Some("5"));
+            let mut body = Vec::new();
+            request.body.read_to_end(&mut body)?;
+            assert_eq!(body, b"hello");
+        }
+        stream.write_all(b"HTTP/1.0 200 OK\r\n\r\nold")?;
+        Ok(())
+    });
+
+    let response = HttpRequestBuilder::post(format!("http://localhost:{}/", port).as_ref())?
+        .http_1_0()
+        .chunked_body("hello".as_bytes())
+        .execute()?;
+    handle.join().unwrap()?;
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.text(16)?, "old");
+    Ok(())
+}
+
+#[test]
+fn request_builder_trailers() -> Result<()> {
+    use io::{Read as _, Write as _};
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = std::thread::spawn(move || -> Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        HttpRequest::deserialize(&mut stream)?;
+        stream.write_all(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nTrailer: Checksum\r\n\r\n\
+              5\r\nhello\r\n0\r\nChecksum: abc\r\n\r\n",
+        )?;
+        Ok(())
+    });
+
+    let mut response =
+        HttpRequestBuilder::get(format!("http://localhost:{}/", port).as_ref())?.execute()?;
+    handle.join().unwrap()?;
+    assert!(response.body.trailers().is_none());
+    let mut body = Vec::new();
+    response.body.read_to_end(&mut body)?;
+    assert_eq!(body, b"hello");
+    let trailers = response.body.trailers().unwrap();
+    assert_eq!(trailers.get("Checksum"), Some("abc"));
+    Ok(())
+}
+
+#[test]
+fn request_builder_expect_continue() -> Result<()> {
+    use io::{Read as _, Write as _};
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = std::thread::spawn(move || -> Result<()> {
+        // Answers 100 Continue, then reads the body.
+        let (mut stream, _) = listener.accept()?;
+        let mut writer = stream.try_clone()?;
+        let mut request = HttpRequest::deserialize(&mut stream)?;
+        assert_eq!(request.headers.get("Expect"), Some("100-continue"));
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        let mut body = Vec::new();
+        request.body.read_to_end(&mut body)?;
+        assert_eq!(body, b"data");
+        writer.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")?;
+
+        // Rejects the request, so the body is never sent.
+        let (mut stream, _) = listener.accept()?;
+        HttpRequest::deserialize(&mut stream)?;
+        stream.write_all(b"HTTP/1.1 413 Request Entity Too Large\r\nContent-Length: 0\r\n\r\n")?;
+        stream.set_read_timeout(Some(Duration::from_millis(200)))?;
+        assert!(matches!(stream.read(&mut [0]), Ok(0) | Err(_)));
+
+        // Doesn't answer, so the body is sent after the wait.
+        let (mut stream, _) = listener.accept()?;
+        let mut writer = stream.try_clone()?;
+        let mut request = HttpRequest::deserialize(&mut stream)?;
+        let mut body = Vec::new();
+        request.body.read_to_end(&mut body)?;
+        assert_eq!(body, b"data");
+        writer.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")?;
+        Ok(())
+    });
+
+    let url = format!("http://localhost:{}/upload", port);
+    let put = |wait| -> Result<_> {
+        HttpRequestBuilder::put(url.as_ref())?
+            .with_body(Body::from("data"))
+            .expect_continue(wait)
+            .execute()
+    };
+    let response = put(Duration::from_secs(10))?;
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.text(16)?, "ok");
+    let response = put(Duration::from_secs(10))?;
+    assert_eq!(response.status, HttpStatus::RequestEntityTooLarge);
+    let response = put(Duration::from_millis(50))?;
+    assert_eq!(response.status, HttpStatus::OK);
+    handle.join().unwrap()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn request_builder_early_hints() -> Result<()> {
+    use io::Write as _;
+    use std::cell::RefCell;
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = std::thread::spawn(move || -> Result<()> {
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept()?;
+            let mut writer = stream.try_clone()?;
+            HttpRequest::deserialize(&mut stream)?;
+            writer.write_all(
+                b"HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload\r\n\r\n\
+                  HTTP/1.1 102 Processing\r\n\r\n\
+                  HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+            )?;
+        }
+        Ok(())
+    });
+
+    let url = format!("http://localhost:{}/", port);
+    let hints = Rc::new(RefCell::new(Vec::new()));
+    let recorded = hints.clone();
+    let response = HttpRequestBuilder::get(url.as_ref())?
+        .early_hints(move |headers| {
+            recorded
+                .borrow_mut()
+                .push(headers.get("Link").unwrap().to_owned())
+        })
+        .execute()?;
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.text(16)?, "ok");
+    assert_eq!(*hints.borrow(), ["</style.css>; rel=preload"]);
+
+    // The hints come while waiting for 100 Continue too.
+    let response = HttpRequestBuilder::put(url.as_ref())?
+        .with_body(Body::from("data"))
+        .expect_continue(Duration::from_secs(10))
+        .execute()?;
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(hints.borrow().len(), 2);
+    handle.join().unwrap()?;
+    Ok(())
+}
+
+#[cfg(all(feature = "std", feature = "websocket"))]
+#[test]
+fn request_builder_websocket() -> Result<()> {
+    use crate::websocket::{accept_key, Message, Role, WebSocket};
+    use io::Write as _;
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = std::thread::spawn(move || -> Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        let request = HttpRequest::deserialize(&mut stream)?;
+        let key = request.headers.get("Sec-WebSocket-Key").unwrap_or("");
+        assert_eq!(request.headers.get("Upgrade"), Some("websocket"));
+        assert_eq!(request.headers.get("Sec-WebSocket-Version"), Some("13"));
+        write!(
+            stream,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        )?;
+
+        // Echo messages until the client closes.
+        let mut socket = WebSocket::new(stream, Role::Server);
+        loop {
+            match socket.read_message()? {
+                Message::Close(_) => return Ok(()),
+                message => socket.write_message(&message)?,
+            }
+        }
+    });
+
+    let stream = std::net::TcpStream::connect(("localhost", port))?;
+    let mut socket = HttpRequestBuilder::get(format!("http://localhost:{}/echo", port).as_ref())?
+        .websocket(stream)?;
+    socket.write_message(&Message::Text("hello".into()))?;
+    assert_eq!(socket.read_message()?, Message::Text("hello".into()));
+    socket.write_message(&Message::Binary(vec![0; 1000]))?;
+    assert_eq!(socket.read_message()?, Message::Binary(vec![0; 1000]));
+    socket.close(1000, "done")?;
+    assert_eq!(
+        socket.read_message()?,
+        Message::Close(Some((1000, "".into())))
+    );
+    handle.join().unwrap()?;
+
+    // A server which doesn't switch protocols.
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/echo".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let stream = std::net::TcpStream::connect(("localhost", port))?;
+    let result = HttpRequestBuilder::get(format!("http://localhost:{}/echo", port).as_ref())?
+        .websocket(stream);
+    assert!(matches!(
+        result,
+        Err(Error::UnexpectedStatus(HttpStatus::OK))
+    ));
+    handle.join().unwrap()?;
+    Ok(())
+}
+
+/// A source of random numbers which must be unpredictable, for WebSocket keys and masks and
+/// Digest client nonces. See `HttpRequestBuilder::random`.
+pub type Random = fn() -> u64;
+
+/// The default `Random` when http_io is built with the `"std"` feature: a number from the
+/// operating system's secure random number generator. Panics if the generator fails, which
+/// `Random` has no way to report.
+#[cfg(feature = "std")]
+pub(crate) fn secure_random_u64() -> u64 {
+    let mut bytes = [0; 8];
+    getrandom::getrandom(&mut bytes).expect("the system's random number generator failed");
+    u64::from_ne_bytes(bytes)
+}
+
+/// A random number for multipart boundaries. Without the `"std"` feature, the number is only a
+/// counter mixed with a stack address, which is fine for boundaries but predictable. Not fit for
+/// cryptography, see `secure_random_u64`.
+pub(crate) fn random_u64() -> u64 {
+    #[cfg(feature = "std")]
+    let seed = {
+        use std::collections::hash_map::RandomState;
+        use std::hash::{BuildHasher, Hasher};
+        RandomState::new().build_hasher().finish()
+    };
+    #[cfg(not(feature = "std"))]
+    let seed = {
+        use core::sync::atomic::{AtomicU64, Ordering};
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let local = 0u8;
+        COUNTER.fetch_add(1, Ordering::Relaxed) ^ (&local as *const u8 as u64)
+    };
+
+    // splitmix64, so that similar seeds give unrelated numbers.
+    let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Encode the data as base64 with padding (RFC 4648).
+pub(crate) fn encode_base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | ((b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+#[test]
+fn base64() {
+    assert_eq!(encode_base64(b""), "");
+    assert_eq!(encode_base64(b"f"), "Zg==");
+    assert_eq!(encode_base64(b"fo"), "Zm8=");
+    assert_eq!(encode_base64(b"foo"), "Zm9v");
+    assert_eq!(encode_base64(b"foobar"), "Zm9vYmFy");
+    assert_eq!(encode_base64(&[0xfb, 0xff]), "+/8=");
+}
+
+#[test]
+fn request_builder_basic_auth() -> Result<()> {
+    let builder =
+        HttpRequestBuilder::get("http://localhost/")?.basic_auth("Aladdin", Some("open sesame"));
+    assert_eq!(
+        builder.request.headers.get("Authorization"),
+        Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")
+    );
+    let builder = HttpRequestBuilder::get("http://localhost/")?.basic_auth("user", None::<&str>);
+    assert_eq!(
+        builder.request.headers.get("Authorization"),
+        Some("Basic dXNlcjo=")
+    );
+    Ok(())
+}
+
+#[test]
+fn request_builder_bearer_auth() -> Result<()> {
+    let builder = HttpRequestBuilder::get("http://localhost/")?
+        .basic_auth("user", Some("pass"))
+        .bearer_auth("mF_9.B5f-4.1JqM");
+    assert_eq!(
+        builder.request.headers.get("Authorization"),
+        Some("Bearer mF_9.B5f-4.1JqM")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+fn digest_auth_test(basic_first: bool) -> Result<()> {
+    use crate::server::HttpResponder;
+
+    /// Asks for Digest credentials until it gets an answer to its challenge, offering Basic first
+    /// if `basic_first` is set.
+    struct DigestResponder {
+        basic_first: bool,
+    }
+
+    impl HttpResponder<std::net::TcpStream> for DigestResponder {
+        type Body = io::Cursor<Vec<u8>>;
+        type Error = Error;
+
+        fn respond(
+            &mut self,
+            request: HttpRequest<&mut std::net::TcpStream>,
+        ) -> Result<HttpResponse<Self::Body>> {
+            let authorization = request.headers.get("Authorization").unwrap_or("");
+            if authorization.starts_with("Digest username=\"Mufasa\"")
+                && authorization.contains("nonce=\"n0nce\"")
+                && authorization.contains("qop=auth,")
+                && authorization.contains("cnonce=\"0000000000000abc\"")
+            {
+                return Ok(HttpResponse::new(
+                    HttpStatus::OK,
+                    io::Cursor::new(b"welcome".to_vec()),
+                ));
+            }
+            let mut response =
+                HttpResponse::new(HttpStatus::Unauthorized, io::Cursor::new(Vec::new()));
+            if self.basic_first {
+                response
+                    .headers
+                    .append("WWW-Authenticate", "Basic realm=\"test\"");
+            }
+            response.headers.append(
+                "WWW-Authenticate",
+                "Digest realm=\"test\", qop=\"auth\", algorithm=SHA-256, nonce=\"n0nce\"",
+            );
+            Ok(response)
+        }
+    }
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let mut server = HttpServer::new(listener, DigestResponder { basic_first });
+    let handle = std::thread::spawn(move || -> Result<()> {
+        server.serve_one()?;
+        server.serve_one()?;
+        Ok(())
+    });
+
+    let response = HttpRequestBuilder::get(format!("http://localhost:{}/", port).as_ref())?
+        .digest_auth("Mufasa", "Circle of Life")
+        .random(|| 0xabc)
+        .execute()?;
+    handle.join().unwrap()?;
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.text(16)?, "welcome");
+    Ok(())
+}
+
+#[test]
+fn request_builder_digest_auth() -> Result<()> {
+    digest_auth_test(false)
+}
+
+#[test]
+fn request_builder_digest_auth_after_basic() -> Result<()> {
+    digest_auth_test(true)
+}
+
+#[test]
+fn request_builder_sign_with() -> Result<()> {
+    use io::{Read as _, Write as _};
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = std::thread::spawn(move || -> Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        {
+            let mut request = HttpRequest::deserialize(&mut stream)?;
+            assert_eq!(
+                request.headers.get("X-Signature"),
+                Some("POST /hook?a=b 5 hello")
+            );
+            let mut body = Vec::new();
+            request.body.read_to_end(&mut body)?;
+        }
+        stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")?;
+        Ok(())
+    });
+
+    HttpRequestBuilder::post(format!("http://localhost:{}/hook", port).as_ref())?
+        .query(&[("a", "b")])
+        .with_body(Body::bytes("hello"))
+        .sign_with(|method, target, headers, body| {
+            let signature = format!(
+                "{} {} {} {}",
+                method,
+                target,
+                headers.get("Content-Length").unwrap_or(""),
+                String::from_utf8_lossy(body.unwrap_or(b"")),
+            );
+            headers.insert("X-Signature", signature);
+        })
+        .execute()?;
+    handle.join().unwrap()?;
+    Ok(())
+}
+
+#[test]
+fn request_builder_other_method() -> Result<()> {
+    use io::Write as _;
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = std::thread::spawn(move || -> Result<String> {
+        let (mut stream, _) = listener.accept()?;
+        let mut head = Vec::new();
+        let mut byte = [0];
+        while !head.ends_with(b"\r\n\r\n") {
+            io::Read::read_exact(&mut stream, &mut byte)?;
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8_lossy(&head).into_owned();
+        if head.contains("Transfer-Encoding: chunked") {
+            io::Read::read_exact(&mut stream, &mut [0; 5])?;
+        }
+        stream.write_all(b"HTTP/1.1 207 Multi-Status\r\nContent-Length: 0\r\n\r\n")?;
+        Ok(head)
+    });
+
+    let response = HttpRequestBuilder::new(
+        format!("http://localhost:{}/calendars/", port).as_ref(),
+        HttpMethod::other("PROPFIND")?,
+    )?
+    .header("Depth", "1")
+    .execute()?;
+    let head = handle.join().unwrap()?;
+    assert!(head.starts_with("PROPFIND /calendars/ HTTP/1.1\r\n"));
+    assert_eq!(response.status, HttpStatus::MultiStatus);
+    Ok(())
+}
+
+#[test]
+fn request_builder_deadline() -> Result<()> {
+    // The listener never accepts, so the request is sent but never answered.
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let started = std::time::Instant::now();
+    let result = HttpRequestBuilder::get(format!("http://localhost:{}/", port).as_ref())?
+        .read_timeout(Duration::from_secs(5))
+        .deadline(Duration::from_millis(200))
+        .execute();
+    assert!(matches!(result, Err(Error::Timeout)));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    Ok(())
+}
+
+#[test]
+fn request_builder_conditional() -> Result<()> {
+    let builder = HttpRequestBuilder::get("http://localhost/")?
+        .if_none_match("\"33a64df5\"")
+        .if_modified_since(784111777);
+    assert_eq!(
+        builder.request.headers.get("If-None-Match"),
+        Some("\"33a64df5\"")
+    );
+    assert_eq!(
+        builder.request.headers.get("If-Modified-Since"),
+        Some("Sun, 06 Nov 1994 08:49:37 GMT")
+    );
+    Ok(())
+}
+
+/// Represents the ability to connect an abstract stream to some destination address.
+pub trait StreamConnector {
+    type Stream: core2::io::Read + core2::io::Write;
+    type StreamAddr: Hash + Eq + Clone;
+    fn connect(a: Self::StreamAddr) -> Result<Self::Stream>;
+    fn to_stream_addr(url: Url) -> Result<Self::StreamAddr>;
+
+    /// Like `to_stream_addr`, but resolving the url's host with the given resolver. Streams
+    /// whose addresses aren't resolved from host names ignore it.
+    fn resolve_stream_addr(url: Url, _resolver: &dyn Resolve) -> Result<Self::StreamAddr> {
+        Self::to_stream_addr(url)
+    }
+
+    /// Connect, giving up after the given time. Streams which can't time out connect without
+    /// one.
+    fn connect_timeout(a: Self::StreamAddr, _timeout: Duration) -> Result<Self::Stream> {
+        Self::connect(a)
+    }
+
+    /// Make reads and writes on the stream give up after the given times, or never if `None`.
+    /// Streams which can't time out ignore them.
+    fn set_timeouts(
+        _stream: &Self::Stream,
+        _read: Option<Duration>,
+        _write: Option<Duration>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl StreamConnector for std::net::TcpStream {
+    type Stream = std::net::TcpStream;
+    type StreamAddr = std::net::SocketAddr;
+
+    fn connect(a: Self::StreamAddr) -> Result<Self::Stream> {
+        Ok(std::net::TcpStream::connect(a)?)
+    }
+
+    fn connect_timeout(a: Self::StreamAddr, timeout: Duration) -> Result<Self::Stream> {
+        Ok(std::net::TcpStream::connect_timeout(&a, timeout)?)
+    }
+
+    fn resolve_stream_addr(url: Url, resolver: &dyn Resolve) -> Result<Self::StreamAddr> {
+        let addrs = resolver.resolve(url.host(), url.port()?)?;
+        addrs
+            .into_iter()
+            .next()
+            .ok_or(Error::UnknownHost(url.authority))
+    }
+
+    fn set_timeouts(
+        stream: &Self::Stream,
+        read: Option<Duration>,
+        write: Option<Duration>,
+    ) -> Result<()> {
+        stream.set_read_timeout(read)?;
+        stream.set_write_timeout(write)?;
+        Ok(())
+    }
+
+    fn to_stream_addr(url: Url) -> Result<Self::StreamAddr> {
+        let err = || {
+            std::io::Error::new(
+                std::io::ErrorKind::AddrNotAvailable,
+                format!("Failed to lookup {}", url.host()),
+            )
+        };
+        Ok(
+            std::net::ToSocketAddrs::to_socket_addrs(&(url.host(), url.port()?))
+                .map_err(|_| err())?
+                .next()
+                .ok_or_else(err)?,
+        )
+    }
+}
+
+/// An HTTP client that keeps connections open.
+///
+/// Requests are written through a buffer of `W` bytes and responses read through a buffer of `R`
+/// bytes, 1024 by default; `HttpClient::<std::net::TcpStream, 512, 256>::new()` uses smaller ones.
+pub struct HttpClient<S: StreamConnector, const R: usize = 1024, const W: usize = 1024> {
+    streams: HashMap<S::StreamAddr, S::Stream>,
+    cookie_jar: Option<CookieJar>,
+    resolver: Option<Box<dyn Resolve>>,
+    #[cfg(feature = "std")]
+    dns_cache: DnsCache<S::StreamAddr>,
+    default_headers: HttpHeaders,
+    middleware: Vec<Box<dyn Middleware>>,
+    /// The options builders made by `request` start with.
+    options: ConnectOptions,
+}
+
+impl<S: StreamConnector, const R: usize, const W: usize> HttpClient<S, R, W> {
+    /// Create an `HTTPClient`
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            streams: HashMap::new(),
+            cookie_jar: None,
+            resolver: None,
+            #[cfg(feature = "std")]
+            dns_cache: DnsCache::new(Duration::from_secs(60)),
+            default_headers: HttpHeaders::new(),
+            middleware: Vec::new(),
+            options: ConnectOptions::default(),
+        }
+    }
+
+    /// Create a builder for a request with the given method, starting with the client's
+    /// defaults: the default headers, and the timeouts, proxy and TLS configuration set on the
+    /// client. The builder's own methods override them. Send it with `send`, or with `execute`
+    /// for a connection of its own, which is where the proxy and TLS configuration apply.
+    pub fn request<U: TryInto<Url>>(&self, method: HttpMethod, url: U) -> Result<HttpRequestBuilder>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let mut builder = HttpRequestBuilder::new(url, method)?;
+        builder.options = self.options.clone();
+        Ok(self.add_default_headers(builder))
+    }
+
+    /// Give up connecting after the given time, or never if `None`, which is the default.
+    /// Applies to the connections the client keeps open and to builders made by `request`.
+    pub fn set_connect_timeout(&mut self, timeout: Option<Duration>) {
+        self.options.connect_timeout = timeout;
+    }
+
+    /// Give up when a single read takes longer than the given time, or never if `None`, which is
+    /// the default. Applies to connections opened from now on and to builders made by `request`.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.options.read_timeout = timeout;
+    }
+
+    /// Give up when a single write takes longer than the given time, or never if `None`, which is
+    /// the default. Applies to connections opened from now on and to builders made by `request`.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.options.write_timeout = timeout;
+    }
+
+    /// Send the requests of builders made by `request` through the proxy when they are executed,
+    /// or not if `None`, which is the default. See `HttpRequestBuilder::proxy`.
+    pub fn set_proxy(&mut self, proxy: Option<Proxy>) {
+        self.options.proxy = proxy;
+    }
+
+    /// Use rustls with the given configuration for https urls of builders made by `request`, or
+    /// the default if `None`. See `HttpRequestBuilder::rustls_config`.
+    ///
+    /// *This function is available if http_io is built with the `"rustls"` feature.*
+    #[cfg(feature = "rustls")]
+    pub fn set_rustls_config(&mut self, config: Option<Arc<rustls::ClientConfig>>) {
+        self.options.rustls_config = config;
+    }
+
+    /// Send the given `User-Agent` with every request, instead of `http_io`.
+    pub fn set_user_agent<U: Into<String>>(&mut self, user_agent: U) {
+        self.default_headers.insert("User-Agent", user_agent);
+    }
+
+    /// The headers sent with every request, like an `Authorization` or `Accept-Language`, which
+    /// are empty to begin with. A header of a builder given to `send` takes precedence over the
+    /// default with the same name, unless it has the value every builder starts with.
+    pub fn default_headers(&mut self) -> &mut HttpHeaders {
+        &mut self.default_headers
+    }
+
+    /// Resolve hosts with the given resolver, or with `StreamConnector::to_stream_addr` if `None`,
+    /// which is the default.
+    pub fn set_resolver(&mut self, resolver: Option<Box<dyn Resolve>>) {
+        self.resolver = resolver;
+        #[cfg(feature = "std")]
+        self.dns_cache.clear();
+    }
+
+    /// Keep the address each host resolves to for the given time, so that requests to the same
+    /// host within it don't resolve the host again. The default is 60 seconds, and zero turns the
+    /// cache off. An address is forgotten early if connecting to it fails.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    #[cfg(feature = "std")]
+    pub fn set_dns_ttl(&mut self, ttl: Duration) {
+        self.dns_cache.ttl = ttl;
+        self.dns_cache.clear();
+    }
+
+    /// Forget the addresses hosts resolved to, so that the next request to each resolves it
+    /// again, like after the network changed. See `set_dns_ttl`.
+    ///
+    /// *This function is available if http_io is built with the `"std"` feature.*
+    #[cfg(feature = "std")]
+    pub fn flush_dns_cache(&mut self) {
+        self.dns_cache.clear();
+    }
+
+    /// Keep cookies in the given jar, or don't keep them if `None`, which is the default. Cookies
+    /// set by responses to `send` are stored in the jar, and sent with every request to the hosts
+    /// and paths they apply to.
+    pub fn set_cookie_jar(&mut self, cookie_jar: Option<CookieJar>) {
+        self.cookie_jar = cookie_jar;
+    }
+
+    /// The jar cookies are kept in, if any. See `set_cookie_jar`.
+    pub fn cookie_jar(&mut self) -> Option<&mut CookieJar> {
+        self.cookie_jar.as_mut()
+    }
+
+    /// Run the middleware's hooks around every request, after those of the middleware added
+    /// before it.
+    pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) {
+        self.middleware.push(Box::new(middleware));
+    }
+
+    /// Resolve the url's host, unless its address is cached, and connect to it unless a
+    /// connection is already open, returning the address the connection is kept under.
+    fn open_stream(&mut self, url: &Url) -> Result<S::StreamAddr> {
+        #[cfg(feature = "std")]
+        let cached = self.dns_cache.get(url.host(), url.port()?);
+        #[cfg(not(feature = "std"))]
+        let cached = None;
+        let stream_addr = match cached {
+            Some(stream_addr) => stream_addr,
+            None => {
+                let stream_addr = match self.resolver.as_deref() {
+                    Some(resolver) => S::resolve_stream_addr(url.clone(), resolver)?,
+                    None => S::to_stream_addr(url.clone())?,
+                };
+                #[cfg(feature = "std")]
+                self.dns_cache
+                    .insert(url.host(), url.port()?, stream_addr.clone());
+                stream_addr
+            }
+        };
+        if !self.streams.contains_key(&stream_addr) {
+            let stream = match self.options.connect_timeout {
+                Some(timeout) => S::connect_timeout(stream_addr.clone(), timeout),
+                None => S::connect(stream_addr.clone()),
+            };
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    #[cfg(feature = "std")]
+                    self.dns_cache.remove(url.host(), url.port()?);
+                    return Err(e);
+                }
+            };
+            S::set_timeouts(
+                &stream,
+                self.options.read_timeout,
+                self.options.write_timeout,
+            )?;
+            self.streams.insert(stream_addr.clone(), stream);
+        }
+        Ok(stream_addr)
+    }
+
+    /// Add the default headers the builder doesn't override.
+    fn add_default_headers<B: core2::io::Read>(
+        &self,
+        mut builder: HttpRequestBuilder<B>,
+    ) -> HttpRequestBuilder<B> {
+        for name in self.default_headers.keys() {
+            if !builder.has_custom_header(name) {
+                builder = builder.header(name, self.default_headers.get(name).unwrap_or(""));
+            }
+        }
+        builder
+    }
+
+    /// Add the default headers, and the cookies for the builder's url from the jar if there is
+    /// one, then run the middleware's `before_request`.
+    fn prepare<B: core2::io::Read>(
+        &mut self,
+        builder: HttpRequestBuilder<B>,
+    ) -> HttpRequestBuilder<B> {
+        let builder = self.add_default_headers(builder);
+        let url = builder.url.clone();
+        let mut builder = match self
+            .cookie_jar
+            .as_mut()
+            .and_then(|jar| jar.cookie_header(&url))
+        {
+            Some(cookies) => builder.header("Cookie", cookies),
+            None => builder,
+        };
+        let mut url = url;
+        for middleware in &mut self.middleware {
+            middleware.before_request(
+                builder.request.method,
+                &mut url,
+                &mut builder.request.headers,
+            );
+        }
+        if url != builder.url {
+            builder.set_url(url);
+        }
+        builder
+    }
+
+    /// Send the built request on a connection kept open by the client, returning the response.
+    /// Its body must be read before the next request to the same server. Unlike `get` and `put`,
+    /// the cookies the response sets are stored in the cookie jar.
+    pub fn send<B: core2::io::Read>(
+        &mut self,
+        builder: HttpRequestBuilder<B>,
+    ) -> Result<HttpResponse<io::BufReader<&mut S::Stream, R>>> {
+        let builder = self.prepare(builder);
+        let url = builder.url.clone();
+        let stream_addr = self.open_stream(&url)?;
+        let socket = self.streams.get_mut(&stream_addr).unwrap();
+        let response = builder.send_with_buffers::<_, R, W>(socket)?.finish()?;
+        if let Some(jar) = &mut self.cookie_jar {
+            jar.store(&url, &response.headers);
+        }
+        for middleware in &mut self.middleware {
+            middleware.after_response(&url, response.status, &response.headers);
+        }
+        Ok(response)
+    }
+
+    /// Execute a GET request. The request isn't completed until `OutgoingBody::finish` is called.
+    pub fn get<U: TryInto<Url>>(&mut self, url: U) -> Result<OutgoingBody<&mut S::Stream, R, W>>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let url = url
+            .try_into()
+            .map_err(|e| Error::ParseError(e.to_string()))?;
+        let builder = self.prepare(HttpRequestBuilder::get(url)?);
+        let stream_addr = self.open_stream(&builder.url)?;
+        Ok(builder.send_with_buffers(self.streams.get_mut(&stream_addr).unwrap())?)
+    }
+
+    /// Execute a PUT request. The request isn't completed until `OutgoingBody::finish` is called.
+    pub fn put<U: TryInto<Url>>(&mut self, url: U) -> Result<OutgoingBody<&mut S::Stream, R, W>>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let url = url
+            .try_into()
+            .map_err(|e| Error::ParseError(e.to_string()))?;
+        let builder = self.prepare(HttpRequestBuilder::put(url)?);
+        let stream_addr = self.open_stream(&builder.url)?;
+        Ok(builder.send_with_buffers(self.streams.get_mut(&stream_addr).unwrap())?)
+    }
+}
+
+#[test]
+fn http_client_default_headers() -> Result<()> {
+    use crate::server::HttpResponder;
+    use io::Read as _;
+
+    /// Responds with the `User-Agent` and `Accept-Language` it was sent.
+    struct HeaderResponder;
+
+    impl HttpResponder<std::net::TcpStream> for HeaderResponder {
+        type Body = io::Cursor<Vec<u8>>;
+        type Error = Error;
+
+        fn respond(
+            &mut self,
+            request: HttpRequest<&mut std::net::TcpStream>,
+        ) -> Result<HttpResponse<Self::Body>> {
+            let body = format!(
+                "{} {}",
+                request.headers.get("User-Agent").unwrap_or(""),
+                request.headers.get("Accept-Language").unwrap_or("")
+            )
+            .into_bytes();
+            let length = body.len();
+            let mut response = HttpResponse::new(HttpStatus::OK, io::Cursor::new(body));
+            response.add_header("Content-Length", length.to_string());
+            Ok(response)
+        }
+    }
+
+    let server_socket = std::net::TcpListener::bind("localhost:0")?;
+    let port = server_socket.local_addr()?.port();
+    let mut server = HttpServer::new(server_socket, HeaderResponder);
+    let handle = std::thread::spawn(move || -> Result<()> {
+        server.serve_one()?;
+        server.serve_one()?;
+        Ok(())
+    });
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    client.set_user_agent("firmware-updater/1.2");
+    client.default_headers().insert("Accept-Language", "en");
+    let mut body = String::new();
+    client
+        .get(format!("http://localhost:{}/", port).as_ref())?
+        .finish()?
+        .body
+        .read_to_string(&mut body)?;
+    assert_eq!(body, "firmware-updater/1.2 en");
+
+    let builder = HttpRequestBuilder::get(format!("http://localhost:{}/", port).as_ref())?
+        .header("Accept-Language", "fr");
+    let mut body = String::new();
+    client.send(builder)?.body.read_to_string(&mut body)?;
+    handle.join().unwrap()?;
+    assert_eq!(body, "firmware-updater/1.2 fr");
+    Ok(())
+}
+
+#[test]
+fn http_client_request_defaults() -> Result<()> {
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    client.set_user_agent("firmware-updater/1.2");
+    client.set_read_timeout(Some(Duration::from_secs(3)));
+    client.set_proxy(Some(Proxy::new("http://proxy.example.test:3128")?));
+
+    let builder = client.request(HttpMethod::Get, "http://example.test/")?;
+    assert_eq!(
+        builder.request.headers.get("User-Agent"),
+        Some("firmware-updater/1.2")
+    );
+    assert_eq!(builder.options.read_timeout, Some(Duration::from_secs(3)));
+    assert!(builder.options.proxy.is_some());
+
+    let builder = client
+        .request(HttpMethod::Get, "http://example.test/")?
+        .header("User-Agent", "probe")
+        .read_timeout(Duration::from_secs(1));
+    assert_eq!(builder.request.headers.get("User-Agent"), Some("probe"));
+    assert_eq!(builder.options.read_timeout, Some(Duration::from_secs(1)));
+    Ok(())
+}
+
+#[test]
+fn http_client_dns_cache() -> Result<()> {
+    use io::Read as _;
+    use std::cell::Cell;
+
+    let (port, mut server) = test_server(
+        (0..3)
+            .map(|_| ExpectedRequest {
+                expected_method: HttpMethod::Get,
+                expected_uri: "/".into(),
+                expected_body: "".into(),
+                response_status: HttpStatus::OK,
+                response_body: "".into(),
+            })
+            .collect(),
+    )?;
+    let handle = std::thread::spawn(move || -> Result<()> {
+        for _ in 0..3 {
+            server.serve_one()?;
+        }
+        Ok(())
+    });
+
+    let lookups = Rc::new(Cell::new(0));
+    let counter = lookups.clone();
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    client.set_resolver(Some(Box::new(move |_: &str, port: u16| {
+        counter.set(counter.get() + 1);
+        Ok(vec![std::net::SocketAddr::from(([127, 0, 0, 1], port))])
+    })));
+    let url = format!("http://cached.example.test:{}/", port);
+    for _ in 0..2 {
+        let builder = HttpRequestBuilder::get(url.as_ref())?;
+        client.send(builder)?.body.read_to_end(&mut Vec::new())?;
+    }
+    assert_eq!(lookups.get(), 1);
+
+    client.flush_dns_cache();
+    let builder = HttpRequestBuilder::get(url.as_ref())?;
+    client.send(builder)?.body.read_to_end(&mut Vec::new())?;
+    handle.join().unwrap()?;
+    assert_eq!(lookups.get(), 2);
+    Ok(())
+}
+
+#[test]
+fn http_client_middleware() -> Result<()> {
+    use io::Read as _;
+
+    use crate::server::HttpResponder;
+    use std::cell::RefCell;
+
+    /// Responds with the path and `Authorization` header it was sent.
+    struct AuthResponder;
+
+    impl HttpResponder<std::net::TcpStream> for AuthResponder {
+        type Body = io::Cursor<Vec<u8>>;
+        type Error = Error;
+
+        fn respond(
+            &mut self,
+            request: HttpRequest<&mut std::net::TcpStream>,
+        ) -> Result<HttpResponse<Self::Body>> {
+            let body = format!(
+                "{} {}",
+                request.uri,
+                request.headers.get("Authorization").unwrap_or("")
+            )
+            .into_bytes();
+            let length = body.len();
+            let mut response = HttpResponse::new(HttpStatus::OK, io::Cursor::new(body));
+            response.add_header("Content-Length", length.to_string());
+            Ok(response)
+        }
+    }
+
+    /// Authenticates requests, moves them under `/v2` and records the statuses of the responses.
+    struct Recorder(Rc<RefCell<Vec<HttpStatus>>>);
+
+    impl Middleware for Recorder {
+        fn before_request(&mut self, _: HttpMethod, url: &mut Url, headers: &mut HttpHeaders) {
+            headers.insert("Authorization", "Bearer token");
+            url.path = format!("/v2{}", url.path).parse().unwrap();
+        }
+
+        fn after_response(&mut self, _: &Url, status: HttpStatus, _: &HttpHeaders) {
+            self.0.borrow_mut().push(status);
+        }
+    }
+
+    let server_socket = std::net::TcpListener::bind("localhost:0")?;
+    let port = server_socket.local_addr()?.port();
+    let mut server = HttpServer::new(server_socket, AuthResponder);
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let statuses = Rc::new(RefCell::new(Vec::new()));
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    client.add_middleware(Recorder(statuses.clone()));
+    let builder = HttpRequestBuilder::get(format!("http://localhost:{}/items", port).as_ref())?;
+    let mut body = String::new();
+    client.send(builder)?.body.read_to_string(&mut body)?;
+    handle.join().unwrap()?;
+    assert_eq!(body, "/v2/items Bearer token");
+    assert_eq!(*statuses.borrow(), [HttpStatus::OK]);
+    Ok(())
+}
+
+#[test]
+fn http_client_cookies() -> Result<()> {
+    use io::Read as _;
+
+    use crate::server::HttpResponder;
+
+    /// Sets a cookie on `/login`, and responds with the cookies it was sent.
+    struct CookieResponder;
+
+    impl HttpResponder<std::net::TcpStream> for CookieResponder {
+        type Body = io::Cursor<Vec<u8>>;
+        type Error = Error;
+
+        fn respond(
+            &mut self,
+            request: HttpRequest<&mut std::net::TcpStream>,
+        ) -> Result<HttpResponse<Self::Body>> {
+            let body = request
+                .headers
+                .get("Cookie")
+                .unwrap_or("")
+                .as_bytes()
+                .to_vec();
+            let length = body.len();
+            let mut response = HttpResponse::new(HttpStatus::OK, io::Cursor::new(body));
+            response.add_header("Content-Length", length.to_string());
+            if request.uri == "/login" {
+                response.add_header("Set-Cookie", "session=abc; Path=/; HttpOnly");
+            }
+            Ok(response)
+        }
+    }
+
+    let server_socket = std::net::TcpListener::bind("localhost:0")?;
+    let port = server_socket.local_addr()?.port();
+    let mut server = HttpServer::new(server_socket, CookieResponder);
+    let handle = std::thread::spawn(move || -> Result<()> {
+        server.serve_one()?;
+        server.serve_one()?;
+        Ok(())
+    });
+
+    let mut client = HttpClient::<std::net::TcpStream>::new();
+    client.set_cookie_jar(Some(CookieJar::new()));
+    let login = HttpRequestBuilder::get(format!("http://localhost:{}/login", port).as_ref())?;
+    let mut body = String::new();
+    client.send(login)?.body.read_to_string(&mut body)?;
+    assert_eq!(body, "");
+
+    let mut response = client
+        .get(format!("http://localhost:{}/home", port).as_ref())?
+        .finish()?;
+    response.body.read_to_string(&mut body)?;
+    handle.join().unwrap()?;
+
+    assert_eq!(body, "session=abc");
+    Ok(())
+}
+
+/// Report connects, reads and writes which ran out of time as `Error::Timeout`. Depending on
+/// the platform, a read or write timeout fails with `WouldBlock` or `TimedOut`.
+#[cfg(feature = "std")]
+fn timeout_error(error: Error) -> Error {
+    match &error {
+        Error::IoError(e)
+            if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock =>
+        {
+            Error::Timeout
+        }
+        _ => error,
+    }
+}
+
+/// Connect to the server in the builder's url, or its proxy if it has one, applying the
+/// builder's timeouts.
+#[cfg(feature = "std")]
+fn connect<B: core2::io::Read>(builder: &HttpRequestBuilder<B>) -> Result<std::net::TcpStream> {
+    use std::net::TcpStream;
+
+    let options = &builder.options;
+    let url = options.proxy.as_ref().map_or(&builder.url, Proxy::url);
+    let trace = options.trace.as_deref();
+    if let Some(trace) = trace {
+        trace.dns_start(url.host());
+    }
+    let addrs = match &options.resolver {
+        Some(resolver) => resolver.resolve(url.host(), url.port()?)?,
+        None => SystemResolver.resolve(url.host(), url.port()?)?,
+    };
+    if let Some(trace) = trace {
+        trace.dns_done(url.host(), &addrs);
+    }
+
+    let mut timeout = options.connect_timeout;
+    if let Some(expires) = options.expires {
+        let left = deadline::remaining(expires)?;
+        timeout = Some(timeout.map_or(left, |timeout| timeout.min(left)));
+    }
+    let stream = happy_eyeballs::connect(&url.authority, addrs, timeout, trace)?;
+    <TcpStream as StreamConnector>::set_timeouts(
+        &stream,
+        options.read_timeout,
+        options.write_timeout,
+    )?;
+    Ok(stream)
+}
+
+/// Accepts any certificate, for when verification is skipped or done after the handshake.
+#[cfg(feature = "rustls")]
+struct AcceptAnyCertificate;
+
+#[cfg(feature = "rustls")]
+impl rustls::client::ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: std::time::SystemTime,
+    ) -> core::result::Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+impl ConnectOptions {
+    /// Whether the TLS implementation's own certificate verification is skipped.
+    #[cfg(any(feature = "openssl", feature = "rustls"))]
+    fn skip_verification(&self) -> bool {
+        self.accept_invalid_certs || self.certificate_verifier.is_some()
+    }
+
+    /// Check the certificate chain presented by the host after the handshake with the certificate
+    /// verifier, and the chain built when verifying it with the pins. Without a verified chain
+    /// only the host's own certificate, the first presented, is checked against the pins.
+    #[cfg(any(feature = "openssl", feature = "rustls"))]
+    fn check_certificates(
+        &self,
+        host: &str,
+        presented: &[&[u8]],
+        verified: Option<&[&[u8]]>,
+    ) -> Result<()> {
+        if let Some(verifier) = &self.certificate_verifier {
+            if !verifier(host, presented) {
+                return Err(Error::UntrustedCertificate(host.into()));
+            }
+        }
+        if let Some(pins) = &self.certificate_pins {
+            let pinned = verified.unwrap_or_else(|| &presented[..presented.len().min(1)]);
+            pins.verify(host, pinned.iter().copied())?;
+        }
+        Ok(())
+    }
+}
+
+/// The status, reason phrase, headers and body of a response received by `exchange`.
+#[cfg(feature = "std")]
+type Exchanged = (
+    HttpStatus,
+    Option<String>,
+    HttpHeaders,
+    Box<dyn core2::io::Read>,
+);
+
+/// Send the request on the stream followed by the body, returning the response's status, reason
+/// phrase, headers and body, after any interim responses. With `expect_continue`, the body is only
+/// sent once the server answers 100 Continue or the wait is up, which `set_read_timeout` is used
+/// for.
+#[cfg(feature = "std")]
+fn send_and_receive<
+    B: core2::io::Read,
+    R: core2::io::Read,
+    S: core2::io::Read + core2::io::Write + 'static,
+>(
+    mut builder: HttpRequestBuilder<B>,
+    mut stream: S,
+    mut body: R,
+    header_limit: usize,
+    trailers: &TrailerSlot,
+    set_read_timeout: &dyn Fn(Option<Duration>) -> std::io::Result<()>,
+) -> Result<Exchanged> {
+    use core2::io::{Read as _, Write as _};
+    let mut stream = TracedStream::new(stream, builder.options.trace.clone());
+    let early_hints = builder.options.early_hints.clone();
+    let on_early_hints = |headers: &HttpHeaders| {
+        if let Some(callback) = &early_hints {
+            callback(headers);
+        }
+    };
+    let mut request = match builder.options.expect_continue {
+        Some(wait) => {
+            let buffered = builder.buffer_body()?;
+            let mut head = Vec::new();
+            builder.request.serialize_head(&mut head)?;
+            stream.write_all(&head)?;
+            stream.flush()?;
+
+            set_read_timeout(Some(wait))?;
+            let mut first = [0];
+            let read = stream.read(&mut first);
+            set_read_timeout(builder.options.read_timeout)?;
+            match read {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "connection closed before the response",
+                    )
+                    .into())
+                }
+                Ok(_) => {
+                    // The byte read while waiting starts the first response.
+                    let mut start = &first[..];
+                    loop {
+                        let (status, reason, headers) = {
+                            let response = HttpResponse::deserialize_with_header_limit(
+                                start.chain(&mut stream),
+                                header_limit,
+                            )?;
+                            let reason = response.reason_phrase().map(String::from);
+                            (response.status, reason, response.headers)
+                        };
+                        start = &[];
+                        if status == HttpStatus::EarlyHints {
+                            on_early_hints(&headers);
+                        }
+                        if status == HttpStatus::Continue {
+                            break;
+                        }
+                        if !status.is_interim() {
+                            // The server rejected the request without reading the body.
+                            let body = HttpBody::from_headers(
+                                &headers,
+                                io::BufReader::<_, 1024>::new(stream),
+                            )?;
+                            let body = SharedTrailers::new(body, trailers.clone());
+                            return Ok((status, reason, headers, Box::new(body)));
+                        }
+                    }
+                }
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(e.into()),
+            }
+            let outgoing = builder.request.body_writer(io::BufWriter::new(stream));
+            builder.send_body(outgoing, buffered)?
+        }
+        None => builder.send(stream)?,
+    };
+    io::copy(&mut body, &mut request)?;
+    let response = request.finish_with_early_hints(header_limit, on_early_hints)?;
+    let reason = response.reason_phrase().map(String::from);
+    let body = SharedTrailers::new(response.body, trailers.clone());
+    Ok((response.status, reason, response.headers, Box::new(body)))
+}
+
+/// Connect to the server over TCP, through the proxy if there is one, and send the request
+/// followed by the body, returning the response's status, reason phrase, headers and body.
+#[cfg(feature = "std")]
+fn exchange_tcp<B: core2::io::Read, R: core2::io::Read>(
+    mut builder: HttpRequestBuilder<B>,
+    url: &Url,
+    body: R,
+    header_limit: usize,
+    trailers: &TrailerSlot,
+) -> Result<Exchanged> {
+    let stream = connect(&builder)?;
+    // A handle on the TCP stream, for waiting on 100 Continue once it is wrapped in TLS.
+    let socket = match builder.options.expect_continue {
+        Some(_) => Some(stream.try_clone()?),
+        None => None,
+    };
+    let set_read_timeout = |timeout| match &socket {
+        Some(socket) => socket.set_read_timeout(timeout),
+        None => Ok(()),
+    };
+    let mut stream = DeadlineStream::new(stream, builder.options.expires);
+    if let Some(proxy) = builder.options.proxy.take() {
+        if proxy.tunnels(url) {
+            proxy.tunnel(&mut stream, url)?;
+        } else {
+            // The proxy speaks HTTP/1.1 for the server.
+            #[cfg(feature = "http2")]
+            {
+                builder.options.http2_prior_knowledge = false;
+            }
+            builder.request.uri = proxy::absolute_form(url, &builder.request.uri);
+            if let Some(authorization) = proxy.authorization_header() {
+                builder = builder.header("Proxy-Authorization", authorization);
+            }
+        }
+    }
+    #[cfg(feature = "rustls")]
+    let use_rustls = builder.options.rustls_config.is_some() || cfg!(not(feature = "openssl"));
+    let response = match &url.scheme {
+        #[cfg(feature = "rustls")]
+        Scheme::Https if use_rustls => {
+            let mut config = match builder.options.rustls_config.take() {
+                Some(config) => config,
+                None => shared_rustls_config()?,
+            };
+            if builder.options.skip_verification() {
+                let mut insecure = (*config).clone();
+                insecure
+                    .dangerous()
+                    .set_certificate_verifier(Arc::new(AcceptAnyCertificate));
+                // Sessions of connections whose certificates weren't verified aren't resumed.
+                insecure.resumption = rustls::client::Resumption::disabled();
+                config = Arc::new(insecure);
+            }
+            #[cfg(feature = "http2")]
+            if builder.options.http2 {
+                let mut offering_h2 = (*config).clone();
+                offering_h2.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+                config = Arc::new(offering_h2);
+            }
+            let server_name = rustls::ServerName::try_from(url.host())
+                .map_err(|e| Error::TlsError(e.to_string()))?;
+            let mut connection = rustls::ClientConnection::new(config, server_name)?;
+            if let Some(trace) = &builder.options.trace {
+                trace.tls_start(url.host());
+            }
+            while connection.is_handshaking() {
+                connection.complete_io(&mut stream)?;
+            }
+            if let Some(trace) = &builder.options.trace {
+                trace.tls_done(url.host());
+            }
+            let chain: Vec<&[u8]> = connection
+                .peer_certificates()
+                .unwrap_or(&[])
+                .iter()
+                .map(|c| c.0.as_slice())
+                .collect();
+            // rustls doesn't say which chain it verified, so only the host's certificate is
+            // pinned.
+            builder
+                .options
+                .check_certificates(url.host(), &chain, None)?;
+            #[cfg(feature = "http2")]
+            let use_h2 = connection.alpn_protocol() == Some(&b"h2"[..]);
+            let stream = rustls::StreamOwned::new(connection, stream);
+            #[cfg(feature = "http2")]
+            if use_h2 {
+                return exchange_http2(builder, stream, body, Scheme::Https, trailers);
+            }
+            send_and_receive(
+                builder,
+                stream,
+                body,
+                header_limit,
+                trailers,
+                &set_read_timeout,
+            )?
+        }
+        #[cfg(feature = "openssl")]
+        Scheme::Https => {
+            let verify = !builder.options.skip_verification();
+            let mut configuration = openssl_connector(verify)?.configure()?;
+            let session = OPENSSL_SESSIONS.with(|sessions| {
+                sessions
+                    .borrow()
+                    .get(&(verify, url.host().to_string()))
+                    .cloned()
+            });
+            if let Some(session) = session {
+                // Safe as the session was made by a connection from the same connector.
+                unsafe { configuration.set_session(&session)? };
+            }
+            #[cfg(feature = "http2")]
+            if builder.options.http2 {
+                configuration.set_alpn_protos(b"\x02h2\x08http/1.1")?;
+            }
+            if let Some(trace) = &builder.options.trace {
+                trace.tls_start(url.host());
+            }
+            let stream = configuration.connect(url.host(), stream)?;
+            if let Some(trace) = &builder.options.trace {
+                trace.tls_done(url.host());
+            }
+            let to_der = |chain: Option<&openssl::stack::StackRef<openssl::x509::X509>>| {
+                chain
+                    .into_iter()
+                    .flatten()
+                    .map(|c| c.to_der())
+                    .collect::<core::result::Result<Vec<_>, _>>()
+            };
+            let presented = to_der(stream.ssl().peer_cert_chain())?;
+            let presented: Vec<&[u8]> = presented.iter().map(Vec::as_slice).collect();
+            let verified = if verify {
+                Some(to_der(stream.ssl().verified_chain())?)
+            } else {
+                None
+            };
+            let verified: Option<Vec<&[u8]>> = verified
+                .as_ref()
+                .map(|chain| chain.iter().map(Vec::as_slice).collect());
+            builder
+                .options
+                .check_certificates(url.host(), &presented, verified.as_deref())?;
+            #[cfg(feature = "http2")]
+            if stream.ssl().selected_alpn_protocol() == Some(&b"h2"[..]) {
+                return exchange_http2(builder, stream, body, Scheme::Https, trailers);
+            }
+            send_and_receive(
+                builder,
+                stream,
+                body,
+                header_limit,
+                trailers,
+                &set_read_timeout,
+            )?
+        }
+        #[cfg(feature = "http2")]
+        Scheme::Http if builder.options.http2_prior_knowledge => {
+            exchange_http2(builder, stream, body, Scheme::Http, trailers)?
+        }
+        Scheme::Http => send_and_receive(
+            builder,
+            stream,
+            body,
+            header_limit,
+            trailers,
+            &set_read_timeout,
+        )?,
+        s => {
+            return Err(Error::UnexpectedScheme(s.to_string()));
+        }
+    };
+    Ok(response)
+}
+
+/// Send the request followed by the body over HTTP/2 on the stream, returning the response's
+/// status, reason phrase, headers and body.
+#[cfg(feature = "http2")]
+fn exchange_http2<
+    B: core2::io::Read,
+    R: core2::io::Read,
+    S: core2::io::Read + core2::io::Write + 'static,
+>(
+    builder: HttpRequestBuilder<B>,
+    stream: S,
+    mut body: R,
+    scheme: Scheme,
+    trailers: &TrailerSlot,
+) -> Result<Exchanged> {
+    use core2::io::Read as _;
+    let stream = TracedStream::new(stream, builder.options.trace.clone());
+    let mut request = builder.request;
+    // There is nothing to wait for, as the whole body is sent at once.
+    request.headers.remove("Expect");
+    let mut data = Vec::new();
+    request.body.read_to_end(&mut data)?;
+    body.read_to_end(&mut data)?;
+    let request = request.with_body(io::Cursor::new(data));
+    let mut connection = Box::new(Http2Connection::new(stream, scheme)?);
+    if let Some(limit) = builder.options.max_header_size {
+        connection.set_max_header_size(limit);
+    }
+    if let Some(limit) = builder.options.max_response_size {
+        connection.set_max_response_size(limit);
+    }
+    let (status, headers) = connection.start_request(request)?;
+    let body = Http2Body::new(connection).with_trailer_slot(trailers.clone());
+    // HTTP/2 has no reason phrases.
+    Ok((status, None, headers, Box::new(body)))
+}
+
+/// Send the request on a new connection followed by the body, returning the response.
+#[cfg(feature = "std")]
+fn exchange<B: core2::io::Read, R: core2::io::Read>(
+    builder: HttpRequestBuilder<B>,
+    body: R,
+) -> Result<HttpResponse<Box<dyn core2::io::Read>>> {
+    let url = builder.url.clone();
+    let is_head = builder.request.method == HttpMethod::Head;
+    // Each request gets its own connection, so let the server know it won't be reused.
+    let mut builder = builder.header("Connection", "close");
+    // Only decode the body if the caller didn't ask for an encoding themselves.
+    #[cfg(feature = "gzip")]
+    let decompress = !builder.options.no_decompression
+        && builder.request.headers.get("Accept-Encoding").is_none();
+    #[cfg(feature = "gzip")]
+    if decompress {
+        builder = builder.header("Accept-Encoding", "gzip, deflate");
+    }
+    // Before a proxy changes the request target.
+    builder.sign();
+    let download_progress = builder.options.download_progress.take();
+    let trace = builder.options.trace.clone();
+    let max_response_size = builder.options.max_response_size;
+    let header_limit = builder.options.max_header_size.unwrap_or(usize::MAX);
+    let trailers = TrailerSlot::default();
+    let (status, reason, headers, body) = match builder.options.connector.take() {
+        Some(connector) => {
+            let stream = connector.connect(&url.scheme, url.host(), url.port()?)?;
+            // There is no way to wait on a custom stream with a timeout.
+            builder.options.expect_continue = None;
+            builder.request.headers.remove("Expect");
+            send_and_receive(builder, stream, body, header_limit, &trailers, &|_| Ok(()))?
+        }
+        None => exchange_tcp(builder, &url, body, header_limit, &trailers)?,
+    };
+    // The headers of a response to HEAD describe the body a GET would get, but it has none.
+    let body = if is_head {
+        Box::new(io::empty()) as Box<dyn core2::io::Read>
+    } else {
+        body
+    };
+    let body = match download_progress {
+        Some(callback) => {
+            let length = headers
+                .get("Content-Length")
+                .and_then(|length| length.parse().ok());
+            Box::new(Progress::new(body, length, Some(callback))) as Box<dyn core2::io::Read>
+        }
+        None => body,
+    };
+
+    #[cfg(feature = "gzip")]
+    let (headers, body) = {
+        // They describe the encoded body, not the one given to the caller.
+        let decoded = |mut headers: HttpHeaders| {
+            headers.remove("Content-Encoding");
+            headers.remove("Content-Length");
+            headers
+        };
+        let encoding = headers
+            .get("Content-Encoding")
+            .map(|encoding| encoding.trim().to_ascii_lowercase());
+        match encoding.as_deref() {
+            Some("gzip") if decompress => (
+                decoded(headers),
+                Box::new(GzipDecoder::new(body)) as Box<dyn core2::io::Read>,
+            ),
+            Some("deflate") if decompress => (
+                decoded(headers),
+                Box::new(DeflateDecoder::new(body)) as Box<dyn core2::io::Read>,
+            ),
+            _ => (headers, body),
+        }
+    };
+
+    let body = match max_response_size {
+        Some(limit) => {
+            let length = headers
+                .get("Content-Length")
+                .and_then(|length| length.parse::<u64>().ok());
+            if length.map_or(false, |length| length > limit) {
+                return Err(Error::BodyTooLarge(limit.try_into().unwrap_or(usize::MAX)));
+            }
+            Box::new(SizeLimit::new(body, limit)) as Box<dyn core2::io::Read>
+        }
+        None => body,
+    };
+    let body = match trace {
+        Some(trace) => Box::new(TracedBody::new(body, trace)) as Box<dyn core2::io::Read>,
+        None => body,
+    };
+
+    let mut response = HttpResponse::new(status, Box::new(io::empty()) as Box<dyn core2::io::Read>);
+    response.headers = headers;
+    if let Some(reason) = reason {
+        response.set_reason_phrase(reason);
+    }
+    // The trailers of a chunked body are made available once the caller has read it all.
+    response.body = HttpBody::Decoded(DecodedBody::new(body, trailers));
+    Ok(response)
+}
+
+#[cfg(feature = "openssl")]
+thread_local! {
+    /// The connectors for https urls, by whether they verify certificates. They are kept so that
+    /// the TLS sessions they make can be resumed.
+    static OPENSSL_CONNECTORS: RefCell<HashMap<bool, SslConnector>> = RefCell::new(HashMap::new());
+
+    /// The last TLS session with each host, by whether the connector verified certificates.
+    static OPENSSL_SESSIONS: RefCell<HashMap<(bool, String), SslSession>> =
+        RefCell::new(HashMap::new());
+}
+
+/// The connector for https urls, which keeps the TLS sessions it makes so that connecting to the
+/// same host again resumes the session instead of doing a full handshake.
+#[cfg(feature = "openssl")]
+fn openssl_connector(verify: bool) -> Result<SslConnector> {
+    let cached = OPENSSL_CONNECTORS.with(|connectors| connectors.borrow().get(&verify).cloned());
+    if let Some(connector) = cached {
+        return Ok(connector);
+    }
+    let mut connector = SslConnector::builder(SslMethod::tls())?;
+    if !verify {
+        connector.set_verify(openssl::ssl::SslVerifyMode::NONE);
+    }
+    connector.set_session_cache_mode(SslSessionCacheMode::CLIENT);
+    connector.set_new_session_callback(move |ssl, session| {
+        if let Some(host) = ssl.servername(NameType::HOST_NAME) {
+            let key = (verify, host.to_string());
+            OPENSSL_SESSIONS.with(|sessions| sessions.borrow_mut().insert(key, session));
+        }
+    });
+    let connector = connector.build();
+    OPENSSL_CONNECTORS.with(|connectors| connectors.borrow_mut().insert(verify, connector.clone()));
+    Ok(connector)
+}
+
+#[cfg(feature = "rustls")]
+thread_local! {
+    /// The `default_rustls_config`, made once so that its session store is shared by all the
+    /// connections using it.
+    static DEFAULT_RUSTLS_CONFIG: RefCell<Option<Arc<rustls::ClientConfig>>> = RefCell::new(None);
+}
+
+/// The `default_rustls_config`, shared by the requests made without one so that connecting to
+/// the same host again resumes the TLS session instead of doing a full handshake.
+#[cfg(feature = "rustls")]
+fn shared_rustls_config() -> Result<Arc<rustls::ClientConfig>> {
+    if let Some(config) = DEFAULT_RUSTLS_CONFIG.with(|config| config.borrow().clone()) {
+        return Ok(config);
+    }
+    let config = default_rustls_config()?;
+    DEFAULT_RUSTLS_CONFIG.with(|shared| *shared.borrow_mut() = Some(config.clone()));
+    Ok(config)
+}
+
+/// The rustls configuration used for https urls when none is given with
+/// `HttpRequestBuilder::rustls_config`. It has the default protocol settings and trusts the root
+/// certificates from webpki-roots with the `"webpki-roots"` feature, and those of the platform
+/// with the `"rustls-native-certs"` feature. With neither, no certificates are trusted.
+///
+/// *This function is available if http_io is built with the `"rustls"` feature.*
+#[cfg(feature = "rustls")]
+pub fn default_rustls_config() -> Result<Arc<rustls::ClientConfig>> {
+    #[allow(unused_mut)]
+    let mut roots = rustls::RootCertStore::empty();
+    #[cfg(feature = "webpki-roots")]
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    #[cfg(feature = "rustls-native-certs")]
+    {
+        let certificates: Vec<Vec<u8>> = rustls_native_certs::load_native_certs()?
+            .into_iter()
+            .map(|certificate| certificate.0)
+            .collect();
+        roots.add_parsable_certificates(&certificates);
+    }
+
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Send the request on a new connection, returning the response if it has status 200 OK.
+#[cfg(feature = "std")]
+fn send_expecting_ok<B: core2::io::Read, R: core2::io::Read>(
+    builder: HttpRequestBuilder<B>,
+    body: R,
+) -> Result<HttpResponse<Box<dyn core2::io::Read>>> {
+    let response = exchange(builder, body).map_err(timeout_error)?;
+    if response.status != HttpStatus::OK {
+        return Err(Error::UnexpectedStatus(response.status));
+    }
+    Ok(response)
+}
+
+/// Send a request on a stream already connected to the server, and read the response, without
+/// any of the resolving, connecting, TLS or header defaults of `HttpRequestBuilder`. It is for
+/// callers managing their own connections, or using transports this crate doesn't know about.
+///
+/// The request is sent as it is, with its body, so it should have a `Host` header. A body is sent
+/// with the chunked encoding if the request has a `Transfer-Encoding` header, and otherwise is
+/// read into memory first to send its `Content-Length`, unless the request has one already. The
+/// response body is read from the stream, which can be used for another request once it was read
+/// to the end, if the server keeps the connection open.
+pub fn send_request<S: core2::io::Read + core2::io::Write, B: core2::io::Read>(
+    stream: S,
+    request: HttpRequest<B>,
+) -> Result<HttpResponse<io::BufReader<S, 1024>>> {
+    send_request_with_buffers::<S, B, 1024, 1024>(stream, request)
+}
+
+/// Like `send_request`, but with the given sizes for the buffers the request is written through
+/// and the response is read through, like `send_request_with_buffers::<_, _, 512, 256>`. See
+/// `HttpRequest::serialize_with_buffers`.
+pub fn send_request_with_buffers<
+    S: core2::io::Read + core2::io::Write,
+    B: core2::io::Read,
+    const R: usize,
+    const W: usize,
+>(
+    stream: S,
+    mut request: HttpRequest<B>,
+) -> Result<HttpResponse<io::BufReader<S, R>>> {
+    use core2::io::Read as _;
+    let has_length = request.headers.get("Content-Length").is_some();
+    let chunked = request.headers.get("Transfer-Encoding").is_some();
+    if !has_length && !chunked {
+        let mut body = Vec::new();
+        request.body.read_to_end(&mut body)?;
+        request.add_header("Content-Length", body.len().to_string());
+        let mut outgoing = request.serialize_with_buffers::<_, R, W>(io::BufWriter::new(stream))?;
+        io::copy(&mut body.as_slice(), &mut outgoing)?;
+        return outgoing.finish();
+    }
+    let mut outgoing = request.serialize_with_buffers::<_, R, W>(io::BufWriter::new(stream))?;
+    io::copy(&mut request.body, &mut outgoing)?;
+    outgoing.finish()
+}
+
+#[test]
+fn send_request_on_stream() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Put,
+        expected_uri: "/file".into(),
+        expected_body: "hello".into(),
+        response_status: HttpStatus::OK,
+        response_body: "stored".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let stream = std::net::TcpStream::connect(("localhost", port))?;
+    let mut request = HttpRequest::new(HttpMethod::Put, "/file").with_body("hello".as_bytes());
+    request.add_header("Host", format!("localhost:{}", port));
+    let response = send_request(stream, request)?;
+    handle.join().unwrap()?;
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.text(16)?, "stored");
+    Ok(())
+}
+
+#[test]
+fn send_request_with_small_buffers() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Put,
+        expected_uri: "/file".into(),
+        expected_body: "a body longer than the buffers".into(),
+        response_status: HttpStatus::OK,
+        response_body: "a response longer than the buffers".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let stream = std::net::TcpStream::connect(("localhost", port))?;
+    let mut request = HttpRequest::new(HttpMethod::Put, "/file")
+        .with_body("a body longer than the buffers".as_bytes());
+    request.add_header("Host", format!("localhost:{}", port));
+    let response = send_request_with_buffers::<_, _, 16, 8>(stream, request)?;
+    handle.join().unwrap()?;
+    assert_eq!(response.text(64)?, "a response longer than the buffers");
+    Ok(())
+}
+
+#[test]
+fn send_request_reuses_connection() -> Result<()> {
+    use io::{Read as _, Write as _};
+
+    let listener = std::net::TcpListener::bind("localhost:0")?;
+    let port = listener.local_addr()?.port();
+    let handle = std::thread::spawn(move || -> Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        for body in &["one", "two"] {
+            HttpRequest::deserialize(&mut stream)?;
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n{}", body);
+            stream.write_all(response.as_bytes())?;
+        }
+        Ok(())
+    });
+
+    let mut stream = std::net::TcpStream::connect(("localhost", port))?;
+    for expected in &["one", "two"] {
+        let mut request = HttpRequest::new(HttpMethod::Get, "/");
+        request.add_header("Host", format!("localhost:{}", port));
+        let mut response = send_request(stream, request)?;
+        let mut body = String::new();
+        response.body.read_to_string(&mut body)?;
+        assert_eq!(body, *expected);
+        stream = response.into_inner().unwrap().into_inner();
+    }
+    handle.join().unwrap()?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "rustls"))]
+use crate::server::test_rustls_server;
+#[cfg(test)]
+use crate::server::{
+    test_server, test_sni_server, test_ssl_server, test_ssl_server_with_extra_chain,
+    ExpectedRequest, ForwardProxy, HttpRequestHandler, HttpServer, Listen,
+};
+
+/// Execute a GET request, returning the response body. Fails with `Error::UnexpectedStatus`
+/// unless the response has status 200 OK; use `get_response` to handle other statuses.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn get<U: TryInto<Url>>(url: U) -> Result<Box<dyn core2::io::Read>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    let builder = HttpRequestBuilder::get(url)?;
+    Ok(Box::new(send_expecting_ok(builder, io::empty())?.body))
+}
+
+/// Execute a GET request, returning the response whatever its status.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn get_response<U: TryInto<Url>>(url: U) -> Result<HttpResponse<Box<dyn core2::io::Read>>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    HttpRequestBuilder::get(url)?.execute()
+}
+
+/// Subscribe to the Server-Sent Events at the url. See `HttpRequestBuilder::event_stream`.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn events<U: TryInto<Url>>(url: U) -> Result<EventStream>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    HttpRequestBuilder::get(url)?.event_stream()
+}
+
+#[test]
+fn get_response_not_found() -> Result<()> {
+    use io::Read as _;
+
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/missing".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::NotFound,
+        response_body: "not here".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let mut response = get_response(format!("http://localhost:{}/missing", port).as_ref())?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.status, HttpStatus::NotFound);
+    assert!(response.get_header("Server").is_some());
+    let mut body_str = String::new();
+    response.body.read_to_string(&mut body_str)?;
+    assert_eq!(body_str, "not here");
+    Ok(())
+}
+
+#[cfg(test)]
+fn get_test<
+    L: Listen + Send + 'static,
+    T: HttpRequestHandler<L::Stream> + Send + 'static,
+    F: Fn(Vec<ExpectedRequest>) -> Result<(u16, HttpServer<L, T>)>,
+>(
+    scheme: Scheme,
+    server_factory: F,
+) -> Result<()>
+where
+    L::Stream: Send,
+{
+    let (port, mut server) = server_factory(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    // The test certificate isn't trusted, or valid for localhost.
+    let builder = HttpRequestBuilder::get(format!("{}://localhost:{}/", scheme, port).as_ref())?
+        .danger_accept_invalid_certs();
+    let mut body = send_expecting_ok(builder, io::empty())?.body;
+    handle.join().unwrap()?;
+
+    let mut body_str = String::new();
+    body.read_to_string(&mut body_str)?;
+    assert_eq!(body_str, "hello from server");
+    Ok(())
+}
+
+#[test]
+fn get_request() -> Result<()> {
+    get_test(Scheme::Http, test_server)
+}
+
+#[test]
+fn get_request_ssl() -> Result<()> {
+    get_test(Scheme::Https, test_ssl_server)
+}
+
+#[test]
+fn get_request_ssl_sni() -> Result<()> {
+    get_test(Scheme::Https, test_sni_server)
+}
+
+#[cfg(feature = "rustls")]
+#[test]
+fn get_request_ssl_rustls_listener() -> Result<()> {
+    get_test(Scheme::Https, test_rustls_server)
+}
+
+#[test]
+fn get_request_ssl_untrusted() -> Result<()> {
+    let (port, mut server) = test_ssl_server(vec![])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let result = get(format!("https://localhost:{}/", port).as_ref());
+    // The server fails too, since the client gives up during the handshake.
+    let _ = handle.join().unwrap();
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[cfg(test)]
+fn proxy_test<
+    L: Listen + Send + 'static,
+    T: HttpRequestHandler<L::Stream> + Send + 'static,
+    F: Fn(Vec<ExpectedRequest>) -> Result<(u16, HttpServer<L, T>)>,
+>(
+    scheme: Scheme,
+    server_factory: F,
+) -> Result<()>
+where
+    L::Stream: Send,
+{
+    use io::Read as _;
+
+    let (port, mut server) = server_factory(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let proxy_socket = std::net::TcpListener::bind("localhost:0")?;
+    let proxy_port = proxy_socket.local_addr()?.port();
+    let mut forward_proxy = ForwardProxy::new(proxy_socket);
+    let proxy_handle = std::thread::spawn(move || forward_proxy.serve_one());
+
+    let proxy = Proxy::new(format!("http://localhost:{}", proxy_port).as_ref())?;
+    let mut response =
+        HttpRequestBuilder::get(format!("{}://localhost:{}/", scheme, port).as_ref())?
+            .proxy(proxy)
+            .danger_accept_invalid_certs()
+            .execute()?;
+    proxy_handle.join().unwrap()?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.status, HttpStatus::OK);
+    let mut body_str = String::new();
+    response.body.read_to_string(&mut body_str)?;
+    assert_eq!(body_str, "hello from server");
+    Ok(())
+}
+
+#[cfg(feature = "rustls")]
+#[test]
+fn get_request_rustls() -> Result<()> {
+    let (port, mut server) = test_rustls_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let mut response = HttpRequestBuilder::get(format!("https://localhost:{}/", port).as_ref())?
+        .rustls_config(default_rustls_config()?)
+        .danger_accept_invalid_certs()
+        .execute()?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.status, HttpStatus::OK);
+    let mut body_str = String::new();
+    response.body.read_to_string(&mut body_str)?;
+    assert_eq!(body_str, "hello from server");
+    Ok(())
+}
+
+#[cfg(test)]
+fn pinning_test(pins: CertificatePins) -> Result<()> {
+    pinning_test_with_extra_chain(pins, vec![])
+}
+
+#[cfg(test)]
+fn pinning_test_with_extra_chain(
+    pins: CertificatePins,
+    extra_chain: Vec<openssl::x509::X509>,
+) -> Result<()> {
+    let script = vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }];
+    let (port, mut server) = test_ssl_server_with_extra_chain(script, extra_chain)?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let result = HttpRequestBuilder::get(format!("https://localhost:{}/", port).as_ref())?
+        .certificate_pins(pins)
+        .danger_accept_invalid_certs()
+        .execute();
+    // The server expects a request, so it fails when the connection is dropped instead.
+    let served = handle.join().unwrap();
+    result?;
+    served?;
+    Ok(())
+}
+
+#[cfg(test)]
+fn from_hex(hex: &str) -> [u8; 32] {
+    let mut hash = [0; 32];
+    for (i, b) in hash.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    hash
+}
+
+#[test]
+fn certificate_pinning() -> Result<()> {
+    // The SHA-256 hashes of test_cert.pem and its public key.
+    let certificate = "23f362b72949a12c287699835380e51a8c12f7444897f0039ee103950d9a8aa0";
+    let public_key = "9473ca472f73bf74007254106958f7e2182df2a8ca94ec3c83f0ba0db7737ccd";
+    let wrong = "0000000000000000000000000000000000000000000000000000000000000000";
+
+    let mut pins = CertificatePins::new();
+    pins.add_certificate_sha256("localhost", from_hex(certificate));
+    pinning_test(pins)?;
+
+    let mut pins = CertificatePins::new();
+    pins.add_public_key_sha256("localhost", from_hex(public_key));
+    pins.add_public_key_sha256("localhost", from_hex(wrong));
+    pinning_test(pins)?;
+
+    let mut pins = CertificatePins::new();
+    pins.add_public_key_sha256("example.com", from_hex(wrong));
+    pinning_test(pins)?;
+
+    let mut pins = CertificatePins::new();
+    pins.add_certificate_sha256("localhost", from_hex(wrong));
+    assert!(matches!(
+        pinning_test(pins),
+        Err(Error::CertificatePinMismatch(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn certificate_pin_appended_to_chain() -> Result<()> {
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    // A certificate with nothing to do with the server, which it presents after its own.
+    let key = PKey::from_rsa(Rsa::generate(2048)?)?;
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", "pinned")?;
+    let name = name.build();
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&key)?;
+    builder.set_not_before(&*Asn1Time::days_from_now(0)?)?;
+    builder.set_not_after(&*Asn1Time::days_from_now(1)?)?;
+    builder.sign(&key, MessageDigest::sha256())?;
+    let pinned = builder.build();
+
+    let mut sha256 = [0; 32];
+    sha256.copy_from_slice(&pinned.digest(MessageDigest::sha256())?);
+    let mut pins = CertificatePins::new();
+    pins.add_certificate_sha256("localhost", sha256);
+    assert!(matches!(
+        pinning_test_with_extra_chain(pins, vec![pinned]),
+        Err(Error::CertificatePinMismatch(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn certificate_verifier() -> Result<()> {
+    let verifier = |host: &str, chain: &[&[u8]]| {
+        assert_eq!(host, "localhost");
+        assert!(!chain.is_empty());
+        true
+    };
+    let (port, mut server) = test_ssl_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Get,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let response = HttpRequestBuilder::get(format!("https://localhost:{}/", port).as_ref())?
+        .certificate_verifier(verifier)
+        .execute()?;
+    handle.join().unwrap()?;
+    assert_eq!(response.status, HttpStatus::OK);
+
+    let (port, mut server) = test_ssl_server(vec![])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let result = HttpRequestBuilder::get(format!("https://localhost:{}/", port).as_ref())?
+        .certificate_verifier(|_: &str, _: &[&[u8]]| false)
+        .execute();
+    let _ = handle.join().unwrap();
+    assert!(matches!(result, Err(Error::UntrustedCertificate(_))));
+    Ok(())
+}
+
+#[test]
+fn get_request_proxy() -> Result<()> {
+    proxy_test(Scheme::Http, test_server)
+}
+
+#[test]
+fn get_request_proxy_ssl() -> Result<()> {
+    proxy_test(Scheme::Https, test_ssl_server)
+}
+
+/// Execute a PUT request.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn put<U: TryInto<Url>, R: core2::io::Read>(url: U, body: R) -> Result<Box<dyn core2::io::Read>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    let builder = HttpRequestBuilder::put(url)?;
+    Ok(Box::new(send_expecting_ok(builder, body)?.body))
+}
+
+/// Execute a PUT request, returning the response whatever its status.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn put_response<U: TryInto<Url>, R: core2::io::Read>(
+    url: U,
+    body: R,
+) -> Result<HttpResponse<Box<dyn core2::io::Read>>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    let builder = HttpRequestBuilder::put(url)?;
+    exchange(builder, body).map_err(timeout_error)
+}
+
+#[cfg(test)]
+fn put_test<
+    L: Listen + Send + 'static,
+    T: HttpRequestHandler<L::Stream> + Send + 'static,
+    F: Fn(Vec<ExpectedRequest>) -> Result<(u16, HttpServer<L, T>)>,
+>(
+    scheme: Scheme,
+    server_factory: F,
+) -> Result<()>
+where
+    L::Stream: Send,
+{
+    let (port, mut server) = server_factory(vec![ExpectedRequest {
+        expected_method: HttpMethod::Put,
+        expected_uri: "/".into(),
+        expected_body: "hello from client".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let builder = HttpRequestBuilder::put(format!("{}://localhost:{}/", scheme, port).as_ref())?
+        .danger_accept_invalid_certs();
+    let mut incoming_body = send_expecting_ok(builder, "hello from client".as_bytes())?.body;
+
+    handle.join().unwrap()?;
+
+    let mut body_str = String::new();
+    incoming_body.read_to_string(&mut body_str)?;
+    assert_eq!(body_str, "hello from server");
+    Ok(())
+}
+
+#[test]
+fn put_request() -> Result<()> {
+    put_test(Scheme::Http, test_server)
+}
+
+#[test]
+fn put_request_ssl() -> Result<()> {
+    put_test(Scheme::Https, test_ssl_server)
+}
+
+/// Execute a DELETE request.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn delete<U: TryInto<Url>>(url: U) -> Result<Box<dyn core2::io::Read>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    let builder = HttpRequestBuilder::delete(url)?;
+    Ok(Box::new(send_expecting_ok(builder, io::empty())?.body))
+}
+
+/// Execute a DELETE request, returning the response whatever its status.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn delete_response<U: TryInto<Url>>(url: U) -> Result<HttpResponse<Box<dyn core2::io::Read>>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    HttpRequestBuilder::delete(url)?.execute()
+}
+
+#[test]
+fn delete_request() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Delete,
+        expected_uri: "/a".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "deleted".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let mut body = delete(format!("http://localhost:{}/a", port).as_ref())?;
+    handle.join().unwrap()?;
+
+    let mut body_str = String::new();
+    body.read_to_string(&mut body_str)?;
+    assert_eq!(body_str, "deleted");
+    Ok(())
+}
+
+/// Execute a HEAD request, returning the response status and headers whatever the status, to
+/// check whether a resource exists, or its `Content-Length` or `Last-Modified`, without
+/// downloading it. The returned body is always empty. No compression is asked for, so the
+/// `Content-Length` is the length of the resource itself.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn head<U: TryInto<Url>>(url: U) -> Result<HttpResponse<io::Empty>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    let response = HttpRequestBuilder::head(url)?
+        .header("Accept-Encoding", "identity")
+        .execute()?;
+    let mut head = HttpResponse::new(response.status, io::empty());
+    head.headers = response.headers;
+    Ok(head)
+}
+
+#[test]
+fn head_request() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Head,
+        expected_uri: "/".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let response = head(format!("http://localhost:{}/", port).as_ref())?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.status, HttpStatus::OK);
+    assert_eq!(response.headers.get("Server"), Some("http_io"));
+    assert_eq!(response.text(1024)?, "");
+    Ok(())
+}
+
+#[test]
+fn head_request_not_found() -> Result<()> {
+    let (port, mut server) = test_server(vec![ExpectedRequest {
+        expected_method: HttpMethod::Head,
+        expected_uri: "/missing".into(),
+        expected_body: "".into(),
+        response_status: HttpStatus::NotFound,
+        response_body: "not found".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+    let response = head(format!("http://localhost:{}/missing", port).as_ref())?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.status, HttpStatus::NotFound);
+    Ok(())
+}
+
+/// Execute an OPTIONS request, returning the response whatever its status. The methods the server
+/// supports for the url are in its `Allow` header, see `HttpResponse::allowed_methods`.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn options<U: TryInto<Url>>(url: U) -> Result<HttpResponse<Box<dyn core2::io::Read>>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    HttpRequestBuilder::options(url)?.execute()
+}
+
+#[test]
+fn options_request() -> Result<()> {
+    use crate::server::HttpResponder;
+
+    struct AllowResponder;
+
+    impl HttpResponder<std::net::TcpStream> for AllowResponder {
+        type Body = io::Empty;
+        type Error = Error;
+
+        fn respond(
+            &mut self,
+            request: HttpRequest<&mut std::net::TcpStream>,
+        ) -> Result<HttpResponse<Self::Body>> {
+            assert_eq!(request.method, HttpMethod::Options);
+            let mut response = HttpResponse::new(HttpStatus::NoContent, io::empty());
+            response.add_header("Allow", "GET, HEAD, OPTIONS, PATCH");
+            Ok(response)
+        }
+    }
+
+    let server_socket = std::net::TcpListener::bind("localhost:0")?;
+    let port = server_socket.local_addr()?.port();
+    let mut server = HttpServer::new(server_socket, AllowResponder);
+    let handle = std::thread::spawn(move || server.serve_one());
+    let response = options(format!("http://localhost:{}/resource", port).as_ref())?;
+    handle.join().unwrap()?;
+
+    assert_eq!(response.status, HttpStatus::NoContent);
+    assert_eq!(
+        response.headers.get("Allow"),
+        Some("GET, HEAD, OPTIONS, PATCH")
+    );
+    assert_eq!(
+        response.allowed_methods(),
+        [
+            HttpMethod::Get,
+            HttpMethod::Head,
+            HttpMethod::Options,
+            HttpMethod::Patch
+        ]
+    );
+    Ok(())
+}
+
+/// Execute a POST request, sending the given body as `application/octet-stream`.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn post<U: TryInto<Url>, B: AsRef<[u8]>>(url: U, body: B) -> Result<Box<dyn core2::io::Read>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    let body = body.as_ref();
+    post_reader(url, body, body.len() as u64)
+}
+
+/// Execute a POST request, sending `length` bytes read from `body` as
+/// `application/octet-stream`.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn post_reader<U: TryInto<Url>, R: core2::io::Read>(
+    url: U,
+    body: R,
+    length: u64,
+) -> Result<Box<dyn core2::io::Read>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    let builder = HttpRequestBuilder::post(url)?
+        .header("Content-Type", "application/octet-stream")
+        .body(body, length);
+    Ok(Box::new(send_expecting_ok(builder, io::empty())?.body))
+}
+
+/// Execute a POST request, sending the given body as `application/octet-stream` and returning
+/// the response whatever its status.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn post_response<U: TryInto<Url>, B: AsRef<[u8]>>(
+    url: U,
+    body: B,
+) -> Result<HttpResponse<Box<dyn core2::io::Read>>>
+where
+    <U as TryInto<Url>>::Error: Display,
+{
+    let body = body.as_ref();
+    HttpRequestBuilder::post(url)?
+        .header("Content-Type", "application/octet-stream")
+        .body(body, body.len() as u64)
+        .execute()
+}
+
+#[cfg(test)]
+fn post_test<
+    L: Listen + Send + 'static,
+    T: HttpRequestHandler<L::Stream> + Send + 'static,
+    F: Fn(Vec<ExpectedRequest>) -> Result<(u16, HttpServer<L, T>)>,
+>(
+    scheme: Scheme,
+    server_factory: F,
+) -> Result<()>
+where
+    L::Stream: Send,
+{
+    let (port, mut server) = server_factory(vec![ExpectedRequest {
+        expected_method: HttpMethod::Post,
+        expected_uri: "/".into(),
+        expected_body: "hello from client".into(),
+        response_status: HttpStatus::OK,
+        response_body: "hello from server".into(),
+    }])?;
+    let handle = std::thread::spawn(move || server.serve_one());
+
+    let body = "hello from client".as_bytes();
+    let builder = HttpRequestBuilder::post(format!("{}://localhost:{}/", scheme, port).as_ref())?
+        .header("Content-Type", "application/octet-stream")
+        .body(body, body.len() as u64)
+        .danger_accept_invalid_certs();
+    let mut incoming_body = send_expecting_ok(builder, io::empty())?.body;
+
+    handle.join().unwrap()?;
+
+    let mut body_str = String::new();
+    incoming_body.read_to_string(&mut body_str)?;
+    assert_eq!(body_str, "hello from server");
+    Ok(())
+}
+
+#[test]
+fn post_request() -> Result<()> {
+    post_test(Scheme::Http, test_server)
+}
+
+#[test]
+fn post_request_ssl() -> Result<()> {
+    post_test(Scheme::Https, test_ssl_server)
 }
@@ -0,0 +1,394 @@
+//! Sending requests on tokio streams, without blocking the runtime.
+
+use super::HttpRequestBuilder;
+use crate::error::{Error, Result};
+use crate::protocol::{HttpHeaders, HttpMethod, HttpResponse, HttpStatus};
+use crate::url::Scheme;
+use core::convert::TryFrom;
+use core::future::Future;
+use core::pin::Pin;
+use core::str;
+use core::task::{ready, Context, Poll};
+use std::io;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf,
+};
+use tokio::net::TcpStream;
+
+/// The longest chunk size or trailer line accepted in a chunked body.
+const MAX_LINE: usize = 4096;
+
+/// A response received by `HttpRequestBuilder::send_async` or `execute_async`, whose body is
+/// read asynchronously.
+///
+/// *This struct is available if http_io is built with the `"tokio"` feature.*
+pub struct AsyncHttpResponse<S> {
+    pub status: HttpStatus,
+    pub headers: HttpHeaders,
+    pub body: AsyncBody<S>,
+}
+
+impl<S: AsyncRead + Unpin> AsyncHttpResponse<S> {
+    /// Read the whole body. Fails with `Error::BodyTooLarge` if it is longer than `limit` bytes.
+    pub async fn bytes(mut self, limit: usize) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        (&mut self.body)
+            .take(limit as u64 + 1)
+            .read_to_end(&mut body)
+            .await?;
+        if body.len() > limit {
+            return Err(Error::BodyTooLarge(limit));
+        }
+        Ok(body)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// This many bytes of the body are left.
+    Length(u64),
+    /// The body ends when the stream does.
+    UntilClose,
+    ChunkSize,
+    /// This many bytes of the current chunk are left.
+    ChunkData(u64),
+    /// The line ending after a chunk's data.
+    ChunkEnd,
+    Trailers,
+    Done,
+}
+
+/// The body of an `AsyncHttpResponse`, decoded from its `Content-Length` or chunked framing.
+///
+/// *This struct is available if http_io is built with the `"tokio"` feature.*
+pub struct AsyncBody<S> {
+    stream: tokio::io::BufReader<S>,
+    state: State,
+    line: Vec<u8>,
+}
+
+impl<S> AsyncBody<S> {
+    fn new(stream: tokio::io::BufReader<S>, state: State) -> Self {
+        AsyncBody {
+            stream,
+            state,
+            line: Vec::new(),
+        }
+    }
+
+    /// Return the stream, once the body has been read.
+    pub fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<S: AsyncRead + Unpin> AsyncBody<S> {
+    /// Read a line into `self.line`, without its line ending.
+    fn poll_line(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            let available = ready!(Pin::new(&mut self.stream).poll_fill_buf(cx))?;
+            if available.is_empty() {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            let (used, found) = match available.iter().position(|&b| b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            self.line.extend_from_slice(&available[..used]);
+            Pin::new(&mut self.stream).consume(used);
+            if self.line.len() > MAX_LINE {
+                return Poll::Ready(Err(invalid_data("chunked body line too long")));
+            }
+            if found {
+                while matches!(self.line.last(), Some(b'\n') | Some(b'\r')) {
+                    self.line.pop();
+                }
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+
+    /// Copy up to `limit` bytes of the stream into `buf`, returning how many were copied.
+    fn poll_copy(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+        limit: u64,
+    ) -> Poll<io::Result<usize>> {
+        let available = ready!(Pin::new(&mut self.stream).poll_fill_buf(cx))?;
+        let length = available
+            .len()
+            .min(buf.remaining())
+            .min(usize::try_from(limit).unwrap_or(usize::MAX));
+        buf.put_slice(&available[..length]);
+        Pin::new(&mut self.stream).consume(length);
+        Poll::Ready(Ok(length))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for AsyncBody<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            match this.state {
+                State::Done => return Poll::Ready(Ok(())),
+                State::Length(0) => this.state = State::Done,
+                State::Length(remaining) => {
+                    let copied = ready!(this.poll_copy(cx, buf, remaining))?;
+                    if copied == 0 {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    this.state = State::Length(remaining - copied as u64);
+                    return Poll::Ready(Ok(()));
+                }
+                State::UntilClose => {
+                    if ready!(this.poll_copy(cx, buf, u64::MAX))? == 0 {
+                        this.state = State::Done;
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+                State::ChunkSize => {
+                    ready!(this.poll_line(cx))?;
+                    let size = str::from_utf8(&this.line)
+                        .ok()
+                        .and_then(|line| line.split(';').next())
+                        .and_then(|size| u64::from_str_radix(size.trim(), 16).ok())
+                        .ok_or_else(|| invalid_data("invalid chunk size"))?;
+                    this.line.clear();
+                    this.state = match size {
+                        0 => State::Trailers,
+                        size => State::ChunkData(size),
+                    };
+                }
+                State::ChunkData(remaining) => {
+                    let copied = ready!(this.poll_copy(cx, buf, remaining))? as u64;
+                    if copied == 0 {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    this.state = match remaining - copied {
+                        0 => State::ChunkEnd,
+                        remaining => State::ChunkData(remaining),
+                    };
+                    return Poll::Ready(Ok(()));
+                }
+                State::ChunkEnd => {
+                    ready!(this.poll_line(cx))?;
+                    if !this.line.is_empty() {
+                        return Poll::Ready(Err(invalid_data("chunk longer than its size")));
+                    }
+                    this.state = State::ChunkSize;
+                }
+                State::Trailers => {
+                    ready!(this.poll_line(cx))?;
+                    if this.line.is_empty() {
+                        this.state = State::Done;
+                    }
+                    this.line.clear();
+                }
+            }
+        }
+    }
+}
+
+/// Read the status line and headers of a response. Fails with `Error::HeadersTooLarge` if they
+/// are longer than `limit` bytes.
+async fn read_head<S: AsyncRead + Unpin>(
+    stream: &mut tokio::io::BufReader<S>,
+    limit: usize,
+) -> Result<(HttpStatus, HttpHeaders)> {
+    let mut head = Vec::new();
+    loop {
+        let start = head.len();
+        let remaining = limit.saturating_sub(start) as u64;
+        let read = (&mut *stream)
+            .take(remaining.saturating_add(1))
+            .read_until(b'\n', &mut head)
+            .await?;
+        if head.len() > limit {
+            return Err(Error::HeadersTooLarge(limit));
+        }
+        if read == 0 {
+            return Err(Error::UnexpectedEof("Expected \\r\\n".into()));
+        }
+        if start > 0 && head[start..] == *b"\r\n" {
+            break;
+        }
+    }
+    let response = HttpResponse::deserialize(&head[..])?;
+    Ok((response.status, response.headers))
+}
+
+/// How the body of a response to a request with the given method is framed.
+fn body_state(method: HttpMethod, status: HttpStatus, headers: &HttpHeaders) -> Result<State> {
+    if method == HttpMethod::Head
+        || status == HttpStatus::NoContent
+        || status == HttpStatus::NotModified
+    {
+        Ok(State::Done)
+    } else if headers.is_chunked() {
+        Ok(State::ChunkSize)
+    } else if headers.get("Transfer-Encoding").is_some() {
+        Ok(State::UntilClose)
+    } else if let Some(length) = headers.content_length()? {
+        Ok(State::Length(length))
+    } else {
+        Ok(State::UntilClose)
+    }
+}
+
+/// Write the encoded request to the stream and read the response's head, skipping interim
+/// responses.
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    request: Result<Vec<u8>>,
+    method: HttpMethod,
+    header_limit: usize,
+) -> Result<AsyncHttpResponse<S>> {
+    stream.write_all(&request?).await?;
+    stream.flush().await?;
+    let mut stream = tokio::io::BufReader::new(stream);
+    let (status, headers) = loop {
+        let (status, headers) = read_head(&mut stream, header_limit).await?;
+        if !status.is_interim() {
+            break (status, headers);
+        }
+    };
+    let state = body_state(method, status, &headers)?;
+    Ok(AsyncHttpResponse {
+        status,
+        headers,
+        body: AsyncBody::new(stream, state),
+    })
+}
+
+impl<B: core2::io::Read> HttpRequestBuilder<B> {
+    /// Send the request on the given tokio stream, returning the response once its headers have
+    /// been read. The body given with `body` or the like is read into memory first, so it
+    /// shouldn't block, and is sent with a `Content-Length`.
+    ///
+    /// The returned future doesn't hold on to the builder, so it can be spawned on a
+    /// multi-threaded runtime when the stream can.
+    ///
+    /// *This function is available if http_io is built with the `"tokio"` feature.*
+    pub fn send_async<S: AsyncRead + AsyncWrite + Unpin>(
+        self,
+        stream: S,
+    ) -> impl Future<Output = Result<AsyncHttpResponse<S>>> {
+        let method = self.request.method;
+        let header_limit = self.options.max_header_size.unwrap_or(usize::MAX);
+        exchange(stream, self.encode(), method, header_limit)
+    }
+
+    /// Like `execute`, but connecting and sending the request with tokio. The connect timeout
+    /// and header size limit apply; wrap the future in `tokio::time::timeout` to limit the rest.
+    ///
+    /// Only http urls are supported, and https urls fail with `Error::UnexpectedScheme`.
+    /// Proxies aren't supported either, and retries, resolvers and progress callbacks are
+    /// ignored.
+    ///
+    /// *This function is available if http_io is built with the `"tokio"` feature.*
+    pub fn execute_async(self) -> impl Future<Output = Result<AsyncHttpResponse<TcpStream>>> {
+        let scheme = self.url.scheme.clone();
+        let host = self.url.host().to_owned();
+        let port = self.url.port();
+        let proxied = self.options.proxy.is_some();
+        let connect_timeout = self.options.connect_timeout;
+        let method = self.request.method;
+        let header_limit = self.options.max_header_size.unwrap_or(usize::MAX);
+        // Each request gets its own connection, so let the server know it won't be reused.
+        let request = self.header("Connection", "close").encode();
+        async move {
+            if scheme != Scheme::Http {
+                return Err(Error::UnexpectedScheme(scheme.to_string()));
+            }
+            if proxied {
+                return Err(Error::Other("execute_async doesn't support proxies".into()));
+            }
+            let connect = TcpStream::connect((host.as_str(), port?));
+            let stream = match connect_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, connect)
+                    .await
+                    .map_err(|_| Error::Timeout)??,
+                None => connect.await?,
+            };
+            exchange(stream, request, method, header_limit).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncBody, State};
+    use crate::client::HttpRequestBuilder;
+    use crate::error::Result;
+    use crate::protocol::{HttpMethod, HttpStatus};
+    use crate::server::{test_server, ExpectedRequest};
+    use std::future::Future;
+    use tokio::io::{AsyncReadExt, BufReader};
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn chunked_body() {
+        let input = &b"5\r\nhello\r\n6;name=value\r\n world\r\n0\r\nExpires: never\r\n\r\nnext"[..];
+        let mut body = AsyncBody::new(BufReader::new(input), State::ChunkSize);
+        let mut decoded = String::new();
+        block_on(body.read_to_string(&mut decoded)).unwrap();
+        assert_eq!(decoded, "hello world");
+
+        let mut rest = String::new();
+        block_on(body.into_inner().read_to_string(&mut rest)).unwrap();
+        assert_eq!(rest, "next");
+    }
+
+    #[test]
+    fn truncated_body() {
+        let mut body = AsyncBody::new(BufReader::new(&b"hello"[..]), State::Length(10));
+        let mut decoded = Vec::new();
+        assert!(block_on(body.read_to_end(&mut decoded)).is_err());
+
+        let mut body = AsyncBody::new(BufReader::new(&b"a\r\nhello"[..]), State::ChunkSize);
+        assert!(block_on(body.read_to_end(&mut decoded)).is_err());
+    }
+
+    #[test]
+    fn execute_async() -> Result<()> {
+        let (port, mut server) = test_server(vec![ExpectedRequest {
+            expected_method: HttpMethod::Post,
+            expected_uri: "/upload".into(),
+            expected_body: "hello from client".into(),
+            response_status: HttpStatus::OK,
+            response_body: "hello from server".into(),
+        }])?;
+        let handle = std::thread::spawn(move || server.serve_one());
+        let body = block_on(async {
+            let response =
+                HttpRequestBuilder::post(format!("http://localhost:{}/upload", port).as_ref())?
+                    .body(&b"hello from client"[..], 17)
+                    .execute_async()
+                    .await?;
+            assert_eq!(response.status, HttpStatus::OK);
+            response.bytes(1024).await
+        })?;
+        handle.join().unwrap()?;
+        assert_eq!(body, b"hello from server");
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+//! Request bodies which can or can't be sent again.
+
+#[cfg(not(feature = "std"))]
+use alloc::{rc::Rc, string::String, vec::Vec};
+use core2::io;
+#[cfg(feature = "std")]
+use std::rc::Rc;
+
+enum Inner<R> {
+    Bytes { data: Rc<[u8]>, position: usize },
+    Reader(R),
+}
+
+/// A request body, given to `HttpRequestBuilder::with_body`. It is either held in memory, so it
+/// can be sent again when the request is retried, or read from a reader as it is sent, so it can
+/// only be sent once.
+pub struct Body<R = io::Empty> {
+    inner: Inner<R>,
+}
+
+impl Body {
+    /// A body held in memory.
+    pub fn bytes<D: Into<Vec<u8>>>(data: D) -> Self {
+        Body {
+            inner: Inner::Bytes {
+                data: Rc::from(data.into()),
+                position: 0,
+            },
+        }
+    }
+}
+
+impl<R> Body<R> {
+    /// A body read from the reader until it ends, as it is sent.
+    pub fn reader(reader: R) -> Self {
+        Body {
+            inner: Inner::Reader(reader),
+        }
+    }
+
+    /// Whether the body is held in memory, so the request can be sent again.
+    pub fn is_replayable(&self) -> bool {
+        matches!(self.inner, Inner::Bytes { .. })
+    }
+
+    /// The length of a body held in memory, sent as its `Content-Length`.
+    pub fn content_length(&self) -> Option<u64> {
+        match &self.inner {
+            Inner::Bytes { data, .. } => Some(data.len() as u64),
+            Inner::Reader(_) => None,
+        }
+    }
+
+    /// The data of a body held in memory.
+    pub(super) fn as_bytes(&self) -> Option<&[u8]> {
+        match &self.inner {
+            Inner::Bytes { data, .. } => Some(&data[..]),
+            Inner::Reader(_) => None,
+        }
+    }
+
+    /// A copy of a body held in memory, from its start.
+    pub(super) fn replay<R2>(&self) -> Option<Body<R2>> {
+        match &self.inner {
+            Inner::Bytes { data, .. } => Some(Body {
+                inner: Inner::Bytes {
+                    data: data.clone(),
+                    position: 0,
+                },
+            }),
+            Inner::Reader(_) => None,
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for Body<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Inner::Bytes { data, position } => {
+                let rest = &data[*position..];
+                let read = rest.len().min(buf.len());
+                buf[..read].copy_from_slice(&rest[..read]);
+                *position += read;
+                Ok(read)
+            }
+            Inner::Reader(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(data: Vec<u8>) -> Self {
+        Body::bytes(data)
+    }
+}
+
+impl From<&[u8]> for Body {
+    fn from(data: &[u8]) -> Self {
+        Body::bytes(data)
+    }
+}
+
+impl From<String> for Body {
+    fn from(data: String) -> Self {
+        Body::bytes(data)
+    }
+}
+
+impl From<&str> for Body {
+    fn from(data: &str) -> Self {
+        Body::bytes(data)
+    }
+}
+
+/// Reads the byte slices an iterator yields, one after the other, for sending bodies built up in
+/// pieces without a `Read` implementation. See `HttpRequestBuilder::iter_body`.
+pub struct IterReader<I: Iterator> {
+    iter: I,
+    current: Option<I::Item>,
+    position: usize,
+}
+
+impl<I: Iterator> IterReader<I>
+where
+    I::Item: AsRef<[u8]>,
+{
+    pub fn new(iter: I) -> Self {
+        IterReader {
+            iter,
+            current: None,
+            position: 0,
+        }
+    }
+}
+
+impl<I: Iterator> io::Read for IterReader<I>
+where
+    I::Item: AsRef<[u8]>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(slice) = &self.current {
+                let rest = &slice.as_ref()[self.position..];
+                // Empty slices are skipped, as reading nothing means the end of the body.
+                if !rest.is_empty() {
+                    let read = rest.len().min(buf.len());
+                    buf[..read].copy_from_slice(&rest[..read]);
+                    self.position += read;
+                    return Ok(read);
+                }
+            }
+            self.current = self.iter.next();
+            self.position = 0;
+            if self.current.is_none() {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+/// Reads by calling a function with the buffer to fill, which returns how much it filled, until
+/// it returns 0. See `HttpRequestBuilder::fn_body`.
+pub struct FnReader<F> {
+    fill: F,
+}
+
+impl<F: FnMut(&mut [u8]) -> usize> FnReader<F> {
+    pub fn new(fill: F) -> Self {
+        FnReader { fill }
+    }
+}
+
+impl<F: FnMut(&mut [u8]) -> usize> io::Read for FnReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        Ok((self.fill)(buf).min(buf.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Body, FnReader, IterReader};
+    use core2::io::{self, Read};
+
+    #[test]
+    fn replay() {
+        let mut body = Body::bytes("hello");
+        let mut read = [0; 3];
+        body.read_exact(&mut read).unwrap();
+        assert_eq!(&read, b"hel");
+
+        let mut replayed: Body = body.replay().unwrap();
+        let mut all = Vec::new();
+        replayed.read_to_end(&mut all).unwrap();
+        assert_eq!(all, b"hello");
+        assert_eq!(body.content_length(), Some(5));
+
+        let body = Body::reader(io::empty());
+        assert!(!body.is_replayable());
+        assert!(body.replay::<io::Empty>().is_none());
+        assert_eq!(body.content_length(), None);
+    }
+
+    #[test]
+    fn iter_reader() {
+        let pieces: [&[u8]; 4] = [b"hello", b"", b" ", b"world"];
+        let mut reader = IterReader::new(pieces.iter());
+        let mut read = [0; 3];
+        assert_eq!(reader.read(&mut read).unwrap(), 3);
+        assert_eq!(&read, b"hel");
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"lo world");
+    }
+
+    #[test]
+    fn fn_reader() {
+        let mut left = 5;
+        let mut reader = FnReader::new(|buf: &mut [u8]| {
+            let n = left.min(buf.len());
+            buf[..n].fill(b'a');
+            left -= n;
+            n
+        });
+        let mut all = Vec::new();
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all, b"aaaaa");
+    }
+}
@@ -0,0 +1,416 @@
+//! A client which caches responses, honoring `Cache-Control` and revalidating with ETags.
+
+use super::HttpRequestBuilder;
+use crate::date::{self, Clock};
+use crate::error::Result;
+use crate::protocol::{HttpHeaders, HttpMethod, HttpResponse, HttpStatus};
+use core2::io::{self, Read};
+use hashbrown::HashMap;
+
+/// A response kept by a `CachingClient`.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub status: HttpStatus,
+    pub headers: HttpHeaders,
+    pub body: Vec<u8>,
+    /// When the response was received, or last revalidated, in seconds since the unix epoch.
+    pub stored_at: u64,
+    /// When the response stops being fresh and must be revalidated before it is used again.
+    pub expires_at: u64,
+    /// The request headers named by the response's `Vary` header, with their values in the
+    /// request the response was for. The response is only used for requests with the same values.
+    pub varied: Vec<(String, Option<String>)>,
+}
+
+impl CachedResponse {
+    fn to_response(&self, now: u64) -> HttpResponse<Box<dyn Read>> {
+        let body = Box::new(io::Cursor::new(self.body.clone())) as Box<dyn Read>;
+        let mut response = HttpResponse::new(self.status, body);
+        response.headers = self.headers.clone();
+        response.add_header("Age", now.saturating_sub(self.stored_at).to_string());
+        response
+    }
+
+    /// Whether the server can tell if the response is still current, with an `ETag` or a
+    /// `Last-Modified` date.
+    fn has_validator(&self) -> bool {
+        self.headers.get("ETag").is_some() || self.headers.get("Last-Modified").is_some()
+    }
+
+    /// Whether the response was for a request with the same values of the varied headers.
+    fn matches(&self, request: &HttpHeaders) -> bool {
+        self.varied
+            .iter()
+            .all(|(name, value)| request.get(name) == value.as_deref())
+    }
+}
+
+/// Where a `CachingClient` keeps responses, by url. Implement it to keep them on disk or in flash
+/// storage for example; `MemoryStorage` keeps them in memory.
+pub trait CacheStorage {
+    /// The response stored for the url, if any.
+    fn get(&self, url: &str) -> Option<CachedResponse>;
+
+    /// Store the response for the url, replacing any other.
+    fn put(&mut self, url: &str, response: CachedResponse);
+
+    /// Remove the response stored for the url, if any.
+    fn remove(&mut self, url: &str);
+}
+
+/// Keeps the responses of a `CachingClient` in memory, without a limit.
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: HashMap<String, CachedResponse>,
+}
+
+impl CacheStorage for MemoryStorage {
+    fn get(&self, url: &str) -> Option<CachedResponse> {
+        self.entries.get(url).cloned()
+    }
+
+    fn put(&mut self, url: &str, response: CachedResponse) {
+        self.entries.insert(url.into(), response);
+    }
+
+    fn remove(&mut self, url: &str) {
+        self.entries.remove(url);
+    }
+}
+
+/// How long a response received at `now` may be used without revalidating it according to its
+/// `Cache-Control` headers, or its `Expires` header without `max-age`, 0 without either. `None`
+/// if it may not be stored.
+fn cache_lifetime(headers: &HttpHeaders, now: u64) -> Option<u64> {
+    let mut max_age = None;
+    let mut no_cache = false;
+    let directives = headers
+        .get_all("Cache-Control")
+        .flat_map(|value| value.split(','));
+    for directive in directives {
+        let mut parts = directive.trim().splitn(2, '=');
+        let name = parts.next().unwrap_or("").to_ascii_lowercase();
+        let value = parts.next().map(|v| v.trim_matches('"'));
+        match name.as_ref() {
+            "no-store" => return None,
+            "no-cache" => no_cache = true,
+            "max-age" => max_age = Some(value.and_then(|v| v.parse().ok()).unwrap_or(0)),
+            _ => {}
+        }
+    }
+    if no_cache {
+        return Some(0);
+    }
+    if let Some(max_age) = max_age {
+        return Some(max_age);
+    }
+
+    // An invalid date, like `0`, means the response has already expired.
+    let expires = match headers.get("Expires") {
+        Some(expires) => date::parse_http_date(expires).unwrap_or(0),
+        None => return Some(0),
+    };
+    // Measured from the server's `Date` if it gave one, so the clocks don't need to agree.
+    let sent = headers
+        .get("Date")
+        .and_then(date::parse_http_date)
+        .unwrap_or(now);
+    Some(expires.saturating_sub(sent))
+}
+
+/// The values of the request headers named by the response's `Vary` headers. `None` if the
+/// response varies on something other than request headers, so it can't be reused.
+fn varied_headers(
+    response: &HttpHeaders,
+    request: &HttpHeaders,
+) -> Option<Vec<(String, Option<String>)>> {
+    let mut varied = Vec::new();
+    for name in response.get_all("Vary").flat_map(|value| value.split(',')) {
+        let name = name.trim();
+        if name == "*" {
+            return None;
+        }
+        if !name.is_empty() {
+            varied.push((name.into(), request.get(name).map(Into::into)));
+        }
+    }
+    Some(varied)
+}
+
+/// The key the response to a request is stored under: its url, with the query of the request
+/// target. The url of the builder isn't enough, as parameters added with `query` are only in the
+/// request target.
+fn cache_key<B: Read>(builder: &HttpRequestBuilder<B>) -> String {
+    let url = &builder.url;
+    let port = url
+        .port
+        .map(|port| format!(":{}", port))
+        .unwrap_or_default();
+    format!(
+        "{}://{}{}{}",
+        url.scheme, url.authority, port, builder.request.uri
+    )
+}
+
+/// Sends requests with `execute`, answering GET requests from its storage while their responses
+/// are fresh according to their `Cache-Control` or `Expires` header, without any network I/O. A
+/// stored response is only used for requests with the same values of the headers named by its
+/// `Vary` header.
+///
+/// A stale response with an `ETag` or `Last-Modified` header is revalidated by sending the request
+/// with `If-None-Match` or `If-Modified-Since`, and a 304 Not Modified answer is turned into the
+/// stored response, with the headers the server sent. Responses with status 200 OK are stored
+/// unless they are marked `no-store`, and a PUT, POST or DELETE to a url removes its response.
+///
+/// Stored response bodies are read into memory, so this is best suited to small responses.
+///
+/// *This struct is available if http_io is built with the `"std"` feature.*
+pub struct CachingClient<C: CacheStorage = MemoryStorage> {
+    storage: C,
+    clock: Clock,
+}
+
+impl<C: CacheStorage> CachingClient<C> {
+    /// Create a `CachingClient` keeping responses in the given storage. `clock` gives the current
+    /// time, e.g. `date::system_clock`.
+    pub fn new(storage: C, clock: Clock) -> Self {
+        CachingClient { storage, clock }
+    }
+
+    /// The storage the responses are kept in.
+    pub fn storage(&mut self) -> &mut C {
+        &mut self.storage
+    }
+
+    /// Send the request, or answer it from the storage.
+    pub fn execute(
+        &mut self,
+        mut builder: HttpRequestBuilder,
+    ) -> Result<HttpResponse<Box<dyn Read>>> {
+        let url = cache_key(&builder);
+        match builder.request.method {
+            HttpMethod::Get => {}
+            HttpMethod::Put | HttpMethod::Post | HttpMethod::Patch | HttpMethod::Delete => {
+                self.storage.remove(&url);
+                return builder.execute();
+            }
+            _ => return builder.execute(),
+        }
+
+        let now = (self.clock)();
+        let request_headers = builder.request.headers.clone();
+        let stored = self
+            .storage
+            .get(&url)
+            .filter(|entry| entry.matches(&request_headers));
+        if let Some(entry) = &stored {
+            if entry.expires_at > now {
+                return Ok(entry.to_response(now));
+            }
+            if let Some(etag) = entry.headers.get("ETag") {
+                builder = builder.if_none_match(etag);
+            }
+            if let Some(date) = entry.headers.get("Last-Modified") {
+                builder = builder.header("If-Modified-Since", date);
+            }
+        }
+
+        let mut response = builder.execute()?;
+        let now = (self.clock)();
+        match (response.status, stored) {
+            (HttpStatus::NotModified, Some(mut entry)) => {
+                // The 304 gives the current values of headers like `Cache-Control` and `ETag`.
+                for name in response.headers.keys() {
+                    // Those describe the empty body of the 304.
+                    if name.eq_ignore_ascii_case("Content-Length")
+                        || name.eq_ignore_ascii_case("Transfer-Encoding")
+                    {
+                        continue;
+                    }
+                    let mut values = response.headers.get_all(name);
+                    if let Some(first) = values.next() {
+                        entry.headers.insert(name, first);
+                    }
+                    for value in values {
+                        entry.headers.append(name, value);
+                    }
+                }
+                entry.stored_at = now;
+                let lifetime = cache_lifetime(&entry.headers, now).unwrap_or(0);
+                entry.expires_at = now.saturating_add(lifetime);
+                let response = entry.to_response(now);
+                self.storage.put(&url, entry);
+                Ok(response)
+            }
+            (HttpStatus::OK, _) => {
+                let lifetime = cache_lifetime(&response.headers, now);
+                let varied = varied_headers(&response.headers, &request_headers);
+                let (lifetime, varied) = match (lifetime, varied) {
+                    (Some(lifetime), Some(varied)) => (lifetime, varied),
+                    _ => {
+                        self.storage.remove(&url);
+                        return Ok(response);
+                    }
+                };
+                let mut body = Vec::new();
+                response.body.read_to_end(&mut body)?;
+                // The stored body is replayed from memory, not framed the way it was sent.
+                let mut headers = response.headers;
+                headers.remove("Content-Length");
+                headers.remove("Transfer-Encoding");
+                let entry = CachedResponse {
+                    status: response.status,
+                    headers,
+                    body,
+                    stored_at: now,
+                    expires_at: now.saturating_add(lifetime),
+                    varied,
+                };
+                let response = entry.to_response(now);
+                if lifetime > 0 || entry.has_validator() {
+                    self.storage.put(&url, entry);
+                }
+                Ok(response)
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cache_key, cache_lifetime, varied_headers, CachingClient, MemoryStorage};
+    use crate::client::HttpRequestBuilder;
+    use crate::error::{Error, Result};
+    use crate::protocol::{HttpHeaders, HttpRequest, HttpResponse, HttpStatus};
+    use crate::server::{HttpResponder, HttpServer};
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use core2::io::{self, Read};
+
+    /// Serves version 1 of a document, counting the requests which weren't answered with 304.
+    struct DocumentResponder {
+        sent: usize,
+    }
+
+    impl HttpResponder<std::net::TcpStream> for DocumentResponder {
+        type Body = io::Cursor<Vec<u8>>;
+        type Error = Error;
+
+        fn respond(
+            &mut self,
+            request: HttpRequest<&mut std::net::TcpStream>,
+        ) -> Result<HttpResponse<Self::Body>> {
+            let mut response = if request.headers.get("If-None-Match") == Some("\"v1\"") {
+                HttpResponse::new(HttpStatus::NotModified, io::Cursor::new(Vec::new()))
+            } else {
+                self.sent += 1;
+                let body = format!("document {}", self.sent).into_bytes();
+                let mut response = HttpResponse::new(HttpStatus::OK, io::Cursor::new(body));
+                response.add_header("Content-Length", "10");
+                response
+            };
+            response.add_header("ETag", "\"v1\"");
+            response.add_header("Cache-Control", "max-age=60");
+            Ok(response)
+        }
+    }
+
+    #[test]
+    fn key_includes_query() -> Result<()> {
+        let a = HttpRequestBuilder::get("http://localhost:8080/search")?.query(&[("q", "a")]);
+        let b = HttpRequestBuilder::get("http://localhost:8080/search")?.query(&[("q", "b")]);
+        assert_eq!(cache_key(&a), "http://localhost:8080/search?q=a");
+        assert_ne!(cache_key(&a), cache_key(&b));
+        Ok(())
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> HttpHeaders {
+        let mut headers = HttpHeaders::new();
+        for (name, value) in pairs {
+            headers.append(*name, *value);
+        }
+        headers
+    }
+
+    #[test]
+    fn lifetime_from_every_cache_control() {
+        let stored = headers(&[
+            ("Cache-Control", "max-age=60"),
+            ("Cache-Control", "no-store"),
+        ]);
+        assert_eq!(cache_lifetime(&stored, 0), None);
+        let cached = headers(&[
+            ("Cache-Control", "max-age=60"),
+            ("Cache-Control", "no-cache"),
+        ]);
+        assert_eq!(cache_lifetime(&cached, 0), Some(0));
+    }
+
+    #[test]
+    fn lifetime_from_expires() {
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        let expires = "Sun, 06 Nov 1994 08:50:37 GMT";
+        let dated = headers(&[("Date", date), ("Expires", expires)]);
+        assert_eq!(cache_lifetime(&dated, 0), Some(60));
+        let undated = headers(&[("Expires", expires)]);
+        assert_eq!(cache_lifetime(&undated, 784_111_767), Some(70));
+        assert_eq!(cache_lifetime(&headers(&[("Expires", "0")]), 0), Some(0));
+
+        let max_age = headers(&[("Expires", expires), ("Cache-Control", "max-age=5")]);
+        assert_eq!(cache_lifetime(&max_age, 0), Some(5));
+    }
+
+    #[test]
+    fn vary() {
+        let request = headers(&[("Accept-Language", "en")]);
+        let response = headers(&[("Vary", "Accept-Language, Accept-Encoding")]);
+        assert_eq!(
+            varied_headers(&response, &request),
+            Some(vec![
+                ("Accept-Language".into(), Some("en".into())),
+                ("Accept-Encoding".into(), None)
+            ])
+        );
+        assert_eq!(varied_headers(&headers(&[("Vary", "*")]), &request), None);
+    }
+
+    static NOW: AtomicU64 = AtomicU64::new(1000);
+
+    fn test_clock() -> u64 {
+        NOW.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn serves_fresh_and_revalidates_stale() -> Result<()> {
+        let listener = std::net::TcpListener::bind("localhost:0")?;
+        let port = listener.local_addr()?.port();
+        let mut server = HttpServer::new(listener, DocumentResponder { sent: 0 });
+        let handle = std::thread::spawn(move || -> Result<()> {
+            server.serve_one()?;
+            server.serve_one()?;
+            Ok(())
+        });
+
+        let url = format!("http://localhost:{}/document", port);
+        let mut client = CachingClient::new(MemoryStorage::default(), test_clock);
+        let get = |client: &mut CachingClient| -> Result<(HttpStatus, String)> {
+            let mut response = client.execute(HttpRequestBuilder::get(url.as_ref())?)?;
+            let mut body = String::new();
+            response.body.read_to_string(&mut body)?;
+            Ok((response.status, body))
+        };
+
+        let first = get(&mut client)?;
+        assert_eq!(first, (HttpStatus::OK, "document 1".into()));
+        // Fresh, so the server isn't asked.
+        assert_eq!(get(&mut client)?, first);
+
+        // Stale, so it is revalidated, and the server answers 304.
+        NOW.store(1060, Ordering::SeqCst);
+        assert_eq!(get(&mut client)?, first);
+        handle.join().unwrap()?;
+        // Fresh again.
+        assert_eq!(get(&mut client)?, first);
+        Ok(())
+    }
+}
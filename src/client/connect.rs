@@ -0,0 +1,28 @@
+//! Pluggable transports the client sends requests over.
+
+use crate::error::Result;
+use crate::url::Scheme;
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+
+/// A stream a `Connect` returns. It is implemented for every readable and writable stream.
+pub trait Transport: core2::io::Read + core2::io::Write {}
+
+impl<T: core2::io::Read + core2::io::Write> Transport for T {}
+
+/// Opens the streams requests are sent over, in place of TCP connections, like unix sockets,
+/// in-memory pipes for tests or other TLS implementations. See `HttpRequestBuilder::connector`.
+///
+/// It is implemented for functions and closures taking the scheme, host and port and returning a
+/// boxed stream, like `|_: &Scheme, _: &str, _: u16| Ok(Box::new(UnixStream::connect(path)?) as _)`.
+pub trait Connect {
+    /// Connect to the server of a url with the given scheme, host and port. The stream is used
+    /// as is, so for `https` it must be encrypted already.
+    fn connect(&self, scheme: &Scheme, host: &str, port: u16) -> Result<Box<dyn Transport>>;
+}
+
+impl<F: Fn(&Scheme, &str, u16) -> Result<Box<dyn Transport>>> Connect for F {
+    fn connect(&self, scheme: &Scheme, host: &str, port: u16) -> Result<Box<dyn Transport>> {
+        self(scheme, host, port)
+    }
+}
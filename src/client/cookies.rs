@@ -0,0 +1,390 @@
+//! Storing cookies from responses and sending them with later requests (RFC 6265).
+
+use crate::date::{self, Clock};
+use crate::protocol::HttpHeaders;
+use crate::url::{Scheme, Url};
+#[cfg(not(feature = "std"))]
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    /// Set when the cookie had no `Domain` attribute, so it is only sent to the exact host that
+    /// set it.
+    host_only: bool,
+    path: String,
+    /// When the cookie expires, in seconds since the unix epoch. Session cookies never do.
+    expires: Option<u64>,
+    secure: bool,
+}
+
+/// The cookies received by an `HttpClient`, which it sends back with later requests to the hosts
+/// and paths they apply to. Cookies are kept until they expire or the jar is dropped.
+///
+/// See `HttpClient::set_cookie_jar`.
+#[derive(Debug, Clone)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+    clock: Option<Clock>,
+}
+
+impl Default for CookieJar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The default path of a cookie set by a response to the given request path: its directory.
+fn default_path(request_path: &str) -> String {
+    match request_path.rfind('/') {
+        Some(0) | None => "/".into(),
+        Some(i) => request_path[..i].into(),
+    }
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && host.parse::<core::net::IpAddr>().is_err())
+}
+
+/// Whether a `Domain` attribute names a public suffix, like `com` or `co.uk`, under which anyone
+/// can register a domain, according to the Public Suffix List.
+#[cfg(feature = "psl")]
+fn is_public_suffix(domain: &str) -> bool {
+    psl::suffix_str(domain) == Some(domain)
+}
+
+/// Whether a `Domain` attribute names a public suffix, like `com`, under which anyone can register
+/// a domain. Without the Public Suffix List only domains without an embedded dot are recognized,
+/// so a host like `a.example.co.uk` can set cookies for every domain under `co.uk`. Build http_io
+/// with the `"psl"` feature to check against the list.
+#[cfg(not(feature = "psl"))]
+fn is_public_suffix(domain: &str) -> bool {
+    !domain.contains('.')
+}
+
+fn path_matches(request_path: &str, path: &str) -> bool {
+    request_path == path
+        || (request_path.starts_with(path)
+            && (path.ends_with('/') || request_path[path.len()..].starts_with('/')))
+}
+
+impl CookieJar {
+    /// Create an empty `CookieJar`. When http_io is built with the `"std"` feature it uses
+    /// `date::system_clock` to expire cookies, otherwise cookies only expire once a clock is given
+    /// with `set_clock`.
+    pub fn new() -> Self {
+        CookieJar {
+            cookies: Vec::new(),
+            #[cfg(feature = "std")]
+            clock: Some(date::system_clock),
+            #[cfg(not(feature = "std"))]
+            clock: None,
+        }
+    }
+
+    /// Set the clock used to expire cookies, or `None` to only expire them when a server asks to
+    /// remove them.
+    pub fn set_clock(&mut self, clock: Option<Clock>) {
+        self.clock = clock;
+    }
+
+    /// Remove all the cookies.
+    pub fn clear(&mut self) {
+        self.cookies.clear();
+    }
+
+    /// The number of cookies stored, including any which have expired but haven't been removed.
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.clock.map(|clock| clock())
+    }
+
+    /// Store the cookie set by the `Set-Cookie` header in a response from the given url. Cookies
+    /// which are invalid, or whose `Domain` doesn't include the url's host, are ignored.
+    pub fn set_cookie(&mut self, url: &Url, set_cookie: &str) {
+        let mut attributes = set_cookie.split(';');
+        let (name, value) = match attributes.next().and_then(|pair| pair.split_once('=')) {
+            Some((name, value)) if !name.trim().is_empty() => (name.trim(), value.trim()),
+            _ => return,
+        };
+
+        let host = url.authority.to_ascii_lowercase();
+        let mut cookie = Cookie {
+            name: name.into(),
+            value: value.into(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(&url.path.to_string()),
+            expires: None,
+            secure: false,
+        };
+        let mut max_age = None;
+        let mut expired = false;
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let (key, value) = (key.trim(), value.trim());
+            if key.eq_ignore_ascii_case("Expires") {
+                if let Some(expires) = date::parse_http_date(value) {
+                    cookie.expires = Some(expires);
+                }
+            } else if key.eq_ignore_ascii_case("Max-Age") {
+                max_age = value.parse::<i64>().ok();
+            } else if key.eq_ignore_ascii_case("Domain") && !value.is_empty() {
+                let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                if !domain_matches(&host, &domain) {
+                    return;
+                }
+                // A cookie for a public suffix would be sent to every domain under it, so it is
+                // only kept for the host which set it (RFC 6265 section 5.3 step 5).
+                if is_public_suffix(&domain) {
+                    if domain != host {
+                        return;
+                    }
+                    continue;
+                }
+                cookie.domain = domain;
+                cookie.host_only = false;
+            } else if key.eq_ignore_ascii_case("Path") && value.starts_with('/') {
+                cookie.path = value.into();
+            } else if key.eq_ignore_ascii_case("Secure") {
+                cookie.secure = true;
+            }
+        }
+
+        // Max-Age takes precedence over Expires.
+        match max_age {
+            Some(age) if age <= 0 => expired = true,
+            Some(age) => cookie.expires = self.now().map(|now| now.saturating_add(age as u64)),
+            None => {}
+        }
+        if let (Some(expires), Some(now)) = (cookie.expires, self.now()) {
+            expired |= expires <= now;
+        }
+
+        self.cookies.retain(|c| {
+            !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+        });
+        if !expired {
+            self.cookies.push(cookie);
+        }
+    }
+
+    /// Store the cookies set by a response from the given url.
+    pub fn store(&mut self, url: &Url, headers: &HttpHeaders) {
+        for set_cookie in headers.get_all("Set-Cookie") {
+            self.set_cookie(url, set_cookie);
+        }
+    }
+
+    /// The `Cookie` header value to send with a request to the given url, or `None` if no
+    /// cookies apply to it. Expired cookies are removed.
+    pub fn cookie_header(&mut self, url: &Url) -> Option<String> {
+        if let Some(now) = self.now() {
+            self.cookies
+                .retain(|c| c.expires.map_or(true, |expires| expires > now));
+        }
+
+        let host = url.authority.to_ascii_lowercase();
+        let path = url.path.to_string();
+        let path = if path.is_empty() { "/".into() } else { path };
+        let mut cookies: Vec<&Cookie> = self
+            .cookies
+            .iter()
+            .filter(|c| {
+                if c.host_only {
+                    host == c.domain
+                } else {
+                    domain_matches(&host, &c.domain)
+                }
+            })
+            .filter(|c| path_matches(&path, &c.path))
+            .filter(|c| !c.secure || url.scheme == Scheme::Https)
+            .collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        // Cookies with longer paths are sent first.
+        cookies.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+        Some(
+            cookies
+                .iter()
+                .map(|c| format!("{}={}", c.name, c.value))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_path, CookieJar};
+    use crate::protocol::HttpHeaders;
+    use crate::url::Url;
+
+    fn url(s: &str) -> Url {
+        s.parse().unwrap()
+    }
+
+    fn jar() -> CookieJar {
+        let mut jar = CookieJar::new();
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        jar.set_clock(Some(|| 784_111_777));
+        jar
+    }
+
+    #[test]
+    fn default_paths() {
+        assert_eq!(default_path(""), "/");
+        assert_eq!(default_path("/"), "/");
+        assert_eq!(default_path("/login"), "/");
+        assert_eq!(default_path("/account/login"), "/account");
+    }
+
+    #[test]
+    fn host_only() {
+        let mut jar = jar();
+        jar.set_cookie(&url("http://example.com/login"), "session=abc; HttpOnly");
+        assert_eq!(
+            jar.cookie_header(&url("http://example.com/home"))
+                .as_deref(),
+            Some("session=abc")
+        );
+        assert_eq!(jar.cookie_header(&url("http://www.example.com/")), None);
+    }
+
+    #[test]
+    fn store() {
+        let mut jar = jar();
+        let mut headers = HttpHeaders::new();
+        headers.append("Set-Cookie", "a=1");
+        headers.append("set-cookie", "b=2");
+        jar.store(&url("http://example.com/"), &headers);
+        assert_eq!(
+            jar.cookie_header(&url("http://example.com/")).as_deref(),
+            Some("a=1; b=2")
+        );
+    }
+
+    #[test]
+    fn domain() {
+        let mut jar = jar();
+        jar.set_cookie(&url("http://www.example.com/"), "a=1; Domain=.Example.com");
+        jar.set_cookie(&url("http://www.example.com/"), "b=2; Domain=other.com");
+        assert_eq!(
+            jar.cookie_header(&url("http://api.example.com/"))
+                .as_deref(),
+            Some("a=1")
+        );
+        assert_eq!(jar.cookie_header(&url("http://badexample.com/")), None);
+    }
+
+    #[test]
+    fn public_suffix_domain() {
+        let mut jar = jar();
+        jar.set_cookie(&url("http://example.com/"), "a=1; Domain=com");
+        assert!(jar.is_empty());
+
+        jar.set_cookie(&url("http://localhost/"), "b=2; Domain=localhost");
+        assert_eq!(
+            jar.cookie_header(&url("http://localhost/")).as_deref(),
+            Some("b=2")
+        );
+        assert_eq!(jar.cookie_header(&url("http://a.localhost/")), None);
+    }
+
+    #[cfg(feature = "psl")]
+    #[test]
+    fn public_suffix_list() {
+        let mut jar = jar();
+        jar.set_cookie(&url("http://example.co.uk/"), "a=1; Domain=co.uk");
+        assert!(jar.is_empty());
+
+        let www = url("http://www.example.co.uk/");
+        jar.set_cookie(&www, "b=2; Domain=example.co.uk");
+        assert_eq!(
+            jar.cookie_header(&url("http://example.co.uk/")).as_deref(),
+            Some("b=2")
+        );
+    }
+
+    #[test]
+    fn path() {
+        let mut jar = jar();
+        jar.set_cookie(&url("http://example.com/"), "a=1; Path=/docs");
+        jar.set_cookie(&url("http://example.com/"), "b=2");
+        assert_eq!(
+            jar.cookie_header(&url("http://example.com/docs/web"))
+                .as_deref(),
+            Some("a=1; b=2")
+        );
+        assert_eq!(
+            jar.cookie_header(&url("http://example.com/docsets"))
+                .as_deref(),
+            Some("b=2")
+        );
+    }
+
+    #[test]
+    fn secure() {
+        let mut jar = jar();
+        jar.set_cookie(&url("https://example.com/"), "a=1; Secure");
+        assert_eq!(jar.cookie_header(&url("http://example.com/")), None);
+        assert_eq!(
+            jar.cookie_header(&url("https://example.com/")).as_deref(),
+            Some("a=1")
+        );
+    }
+
+    #[test]
+    fn expiry() {
+        let mut jar = jar();
+        let site = url("http://example.com/");
+        jar.set_cookie(&site, "a=1; Expires=Sun, 06-Nov-1994 08:49:38 GMT");
+        jar.set_cookie(&site, "b=2; Expires=Sun, 06 Nov 1994 08:49:37 GMT");
+        jar.set_cookie(
+            &site,
+            "c=3; Max-Age=60; Expires=Sun, 06 Nov 1994 08:49:37 GMT",
+        );
+        assert_eq!(jar.cookie_header(&site).as_deref(), Some("a=1; c=3"));
+
+        jar.set_clock(Some(|| 784_111_778));
+        assert_eq!(jar.cookie_header(&site).as_deref(), Some("c=3"));
+        assert_eq!(jar.len(), 1);
+    }
+
+    #[test]
+    fn replace_and_remove() {
+        let mut jar = jar();
+        let site = url("http://example.com/");
+        jar.set_cookie(&site, "a=1");
+        jar.set_cookie(&site, "a=2");
+        assert_eq!(jar.cookie_header(&site).as_deref(), Some("a=2"));
+        jar.set_cookie(&site, "a=; Max-Age=0");
+        assert_eq!(jar.cookie_header(&site), None);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut jar = jar();
+        let site = url("http://example.com/");
+        jar.set_cookie(&site, "no equals sign");
+        jar.set_cookie(&site, "=value");
+        assert!(jar.is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+//! Enforcing `HttpRequestBuilder::deadline` on every read and write of a connection.
+
+use core::cmp;
+use core::time::Duration;
+use core2::io;
+use std::net::TcpStream;
+use std::time::Instant;
+
+/// The time left until the deadline, failing with `io::ErrorKind::TimedOut` once it has passed.
+pub(super) fn remaining(deadline: Instant) -> io::Result<Duration> {
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(left) if !left.is_zero() => Ok(left),
+        _ => Err(io::Error::new(io::ErrorKind::TimedOut, "deadline passed")),
+    }
+}
+
+/// A TCP stream whose reads and writes give up at the deadline, if there is one, by lowering the
+/// socket's timeouts to the time left before each of them.
+#[derive(Debug)]
+pub(super) struct DeadlineStream {
+    inner: TcpStream,
+    deadline: Option<Instant>,
+}
+
+impl DeadlineStream {
+    pub fn new(inner: TcpStream, deadline: Option<Instant>) -> Self {
+        DeadlineStream { inner, deadline }
+    }
+}
+
+/// The shorter of the time left and the timeout the socket had, which `None` means is infinite.
+fn shortest(left: Duration, timeout: Option<Duration>) -> Option<Duration> {
+    Some(timeout.map_or(left, |timeout| cmp::min(left, timeout)))
+}
+
+impl io::Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let timeout = shortest(remaining(deadline)?, self.inner.read_timeout()?);
+            self.inner.set_read_timeout(timeout)?;
+        }
+        io::Read::read(&mut self.inner, buf)
+    }
+}
+
+impl io::Write for DeadlineStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let timeout = shortest(remaining(deadline)?, self.inner.write_timeout()?);
+            self.inner.set_write_timeout(timeout)?;
+        }
+        io::Write::write(&mut self.inner, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(&mut self.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeadlineStream;
+    use core::time::Duration;
+    use core2::io::{self, Read};
+    use std::time::Instant;
+
+    #[test]
+    fn read_gives_up_at_deadline() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let _server = listener.accept().unwrap();
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(100);
+        let mut stream = DeadlineStream::new(stream, Some(deadline));
+        let error = stream.read(&mut [0; 16]).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+        ));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let error = stream.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+}
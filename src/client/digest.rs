@@ -0,0 +1,349 @@
+//! HTTP Digest authentication (RFC 7616).
+
+use super::pinning::sha256;
+use crate::protocol::HttpMethod;
+use core::fmt::Write as _;
+
+/// The hash function a challenge asks for, and whether the `-sess` variant is used, which mixes
+/// the nonces into the hash of the credentials.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Algorithm {
+    Md5 { session: bool },
+    Sha256 { session: bool },
+}
+
+impl Algorithm {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "MD5" => Some(Algorithm::Md5 { session: false }),
+            "MD5-SESS" => Some(Algorithm::Md5 { session: true }),
+            "SHA-256" => Some(Algorithm::Sha256 { session: false }),
+            "SHA-256-SESS" => Some(Algorithm::Sha256 { session: true }),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Md5 { session: false } => "MD5",
+            Algorithm::Md5 { session: true } => "MD5-sess",
+            Algorithm::Sha256 { session: false } => "SHA-256",
+            Algorithm::Sha256 { session: true } => "SHA-256-sess",
+        }
+    }
+
+    fn session(self) -> bool {
+        match self {
+            Algorithm::Md5 { session } | Algorithm::Sha256 { session } => session,
+        }
+    }
+
+    /// The hash of the data in lowercase hex.
+    fn hash(self, data: &str) -> String {
+        match self {
+            Algorithm::Md5 { .. } => hex(&md5(data.as_bytes())),
+            Algorithm::Sha256 { .. } => hex(&sha256(data.as_bytes())),
+        }
+    }
+}
+
+/// A `Digest` challenge from the `WWW-Authenticate` header of a 401 Unauthorized response.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Algorithm,
+    /// Whether the server accepts `qop=auth`. Without a `qop` at all, the response is computed the
+    /// way RFC 2069 did.
+    qop_auth: bool,
+}
+
+impl DigestChallenge {
+    /// The `Digest` challenge in the value of a `WWW-Authenticate` header, which may hold others
+    /// as well. `None` if there is none, or it needs something unsupported, like an unknown
+    /// algorithm or only `qop=auth-int`.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut params = Vec::new();
+        let mut in_digest = false;
+        let mut rest = header;
+        loop {
+            rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+            if rest.is_empty() {
+                break;
+            }
+            let end = rest
+                .find(|c: char| c == '=' || c == ',' || c.is_ascii_whitespace())
+                .unwrap_or(rest.len());
+            let (token, after) = rest.split_at(end);
+            let after = after.trim_start();
+            if let Some(value) = after.strip_prefix('=') {
+                let (value, after) = parse_value(value.trim_start())?;
+                if in_digest {
+                    params.push((token.to_ascii_lowercase(), value));
+                }
+                rest = after;
+            } else {
+                // The name of the next challenge's scheme.
+                if in_digest {
+                    break;
+                }
+                in_digest = token.eq_ignore_ascii_case("Digest");
+                rest = after;
+            }
+        }
+
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value.clone())
+        };
+        let algorithm = match param("algorithm") {
+            Some(name) => Algorithm::parse(&name)?,
+            None => Algorithm::Md5 { session: false },
+        };
+        let qop_auth = match param("qop") {
+            Some(qop) => {
+                if !qop
+                    .split(',')
+                    .any(|q| q.trim().eq_ignore_ascii_case("auth"))
+                {
+                    return None;
+                }
+                true
+            }
+            None => false,
+        };
+        Some(DigestChallenge {
+            realm: param("realm")?,
+            nonce: param("nonce")?,
+            opaque: param("opaque"),
+            algorithm,
+            qop_auth,
+        })
+    }
+
+    /// The value of the `Authorization` header answering the challenge for a request with the
+    /// given method and target, using `cnonce` as the client's nonce.
+    pub fn authorization(
+        &self,
+        username: &str,
+        password: &str,
+        method: HttpMethod,
+        uri: &str,
+        cnonce: &str,
+    ) -> String {
+        let algorithm = self.algorithm;
+        let nc = "00000001";
+        let mut ha1 = algorithm.hash(&format!("{}:{}:{}", username, self.realm, password));
+        if algorithm.session() {
+            ha1 = algorithm.hash(&format!("{}:{}:{}", ha1, self.nonce, cnonce));
+        }
+        let ha2 = algorithm.hash(&format!("{}:{}", method, uri));
+        let response = if self.qop_auth {
+            algorithm.hash(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, self.nonce, nc, cnonce, ha2
+            ))
+        } else {
+            algorithm.hash(&format!("{}:{}:{}", ha1, self.nonce, ha2))
+        };
+
+        let mut value = format!(
+            "Digest username={}, realm={}, uri={}, algorithm={}, nonce={}",
+            quote(username),
+            quote(&self.realm),
+            quote(uri),
+            algorithm.name(),
+            quote(&self.nonce)
+        );
+        if self.qop_auth {
+            let _ = write!(value, ", nc={}, cnonce={}, qop=auth", nc, quote(cnonce));
+        }
+        let _ = write!(value, ", response={}", quote(&response));
+        if let Some(opaque) = &self.opaque {
+            let _ = write!(value, ", opaque={}", quote(opaque));
+        }
+        value
+    }
+}
+
+/// Split an auth-param value, a token or a quoted string, from what follows it.
+fn parse_value(input: &str) -> Option<(String, &str)> {
+    let quoted = match input.strip_prefix('"') {
+        Some(quoted) => quoted,
+        None => {
+            let end = input.find(',').unwrap_or(input.len());
+            return Some((input[..end].trim_end().into(), &input[end..]));
+        }
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &quoted[i + 1..])),
+            '\\' => value.push(chars.next()?.1),
+            c => value.push(c),
+        }
+    }
+    // The closing quote is missing.
+    None
+}
+
+/// The value as a quoted string.
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The per-round shift amounts of MD5.
+const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+/// The MD5 constants, the integer parts of `abs(sin(i + 1)) * 2^32`.
+const SINES: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// The MD5 digest of the data. MD5 is broken, but Digest authentication still mostly uses it.
+fn md5(data: &[u8]) -> [u8; 16] {
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64).wrapping_mul(8).to_le_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut m = [0u32; 16];
+        for (word, bytes) in m.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(SINES[i])
+                .wrapping_add(m[g])
+                .rotate_left(SHIFTS[i / 16 * 4 + i % 4]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 16];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hex, md5, Algorithm, DigestChallenge};
+    use crate::protocol::HttpMethod;
+
+    #[test]
+    fn md5_vectors() {
+        assert_eq!(hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(&md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            hex(&md5(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            )),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+    }
+
+    /// The challenge and credentials of the examples in RFC 7616 section 3.9.1.
+    fn rfc_example(algorithm: &str) -> String {
+        let header = format!(
+            "Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", algorithm={}, \
+             nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"",
+            algorithm
+        );
+        DigestChallenge::parse(&header).unwrap().authorization(
+            "Mufasa",
+            "Circle of Life",
+            HttpMethod::Get,
+            "/dir/index.html",
+            "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ",
+        )
+    }
+
+    #[test]
+    fn rfc_7616_examples() {
+        assert_eq!(
+            rfc_example("MD5"),
+            "Digest username=\"Mufasa\", realm=\"http-auth@example.org\", \
+             uri=\"/dir/index.html\", algorithm=MD5, \
+             nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", nc=00000001, \
+             cnonce=\"f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ\", qop=auth, \
+             response=\"8ca523f5e9506fed4657c9700eebdbec\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\""
+        );
+        assert!(rfc_example("SHA-256").contains(
+            "response=\"753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1\""
+        ));
+    }
+
+    #[test]
+    fn parse_among_other_challenges() {
+        let challenge = DigestChallenge::parse(
+            "Basic realm=\"simple\", DIGEST nonce=abc, realm=\"a \\\"b\\\"\", \
+             algorithm=md5-sess, Bearer realm=\"other\"",
+        )
+        .unwrap();
+        assert_eq!(
+            challenge,
+            DigestChallenge {
+                realm: "a \"b\"".into(),
+                nonce: "abc".into(),
+                opaque: None,
+                algorithm: Algorithm::Md5 { session: true },
+                qop_auth: false,
+            }
+        );
+
+        assert_eq!(DigestChallenge::parse("Basic realm=\"simple\""), None);
+        assert_eq!(
+            DigestChallenge::parse("Digest realm=\"a\", nonce=\"b\", algorithm=SHA-512-256"),
+            None
+        );
+        assert_eq!(
+            DigestChallenge::parse("Digest realm=\"a\", nonce=\"b\", qop=\"auth-int\""),
+            None
+        );
+    }
+}
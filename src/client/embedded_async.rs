@@ -0,0 +1,430 @@
+//! Sending requests on `embedded-io-async` streams, for async no_std firmware like embassy tasks.
+
+use super::HttpRequestBuilder;
+use crate::error::{Error, Result};
+use crate::protocol::{HttpHeaders, HttpMethod, HttpResponse, HttpStatus};
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+use core::convert::TryFrom;
+use core::str;
+use core2::io;
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+/// The longest chunk size or trailer line accepted in a chunked body.
+const MAX_LINE: usize = 4096;
+
+fn io_kind(kind: ErrorKind) -> io::ErrorKind {
+    match kind {
+        ErrorKind::NotFound => io::ErrorKind::NotFound,
+        ErrorKind::PermissionDenied => io::ErrorKind::PermissionDenied,
+        ErrorKind::ConnectionRefused => io::ErrorKind::ConnectionRefused,
+        ErrorKind::ConnectionReset => io::ErrorKind::ConnectionReset,
+        ErrorKind::ConnectionAborted => io::ErrorKind::ConnectionAborted,
+        ErrorKind::NotConnected => io::ErrorKind::NotConnected,
+        ErrorKind::BrokenPipe => io::ErrorKind::BrokenPipe,
+        ErrorKind::InvalidInput => io::ErrorKind::InvalidInput,
+        ErrorKind::InvalidData => io::ErrorKind::InvalidData,
+        ErrorKind::TimedOut => io::ErrorKind::TimedOut,
+        ErrorKind::Interrupted => io::ErrorKind::Interrupted,
+        ErrorKind::WriteZero => io::ErrorKind::WriteZero,
+        _ => io::ErrorKind::Other,
+    }
+}
+
+fn io_error<E: embedded_io_async::Error>(error: E) -> Error {
+    io::Error::new(io_kind(error.kind()), "I/O error").into()
+}
+
+fn error_kind(error: &Error) -> ErrorKind {
+    match error {
+        Error::IoError(e) => match e.kind() {
+            io::ErrorKind::ConnectionReset => ErrorKind::ConnectionReset,
+            io::ErrorKind::ConnectionAborted => ErrorKind::ConnectionAborted,
+            io::ErrorKind::BrokenPipe => ErrorKind::BrokenPipe,
+            io::ErrorKind::TimedOut => ErrorKind::TimedOut,
+            io::ErrorKind::Interrupted => ErrorKind::Interrupted,
+            _ => ErrorKind::Other,
+        },
+        Error::Timeout => ErrorKind::TimedOut,
+        _ => ErrorKind::InvalidData,
+    }
+}
+
+/// Reads from a stream through a buffer.
+struct Buffered<S> {
+    stream: S,
+    buffer: Vec<u8>,
+    position: usize,
+    end: usize,
+}
+
+impl<S: Read> Buffered<S> {
+    /// The buffered data, reading more if it has all been used. It is empty at the end of the
+    /// stream.
+    async fn fill(&mut self) -> Result<&[u8]> {
+        if self.position == self.end {
+            self.position = 0;
+            self.end = self.stream.read(&mut self.buffer).await.map_err(io_error)?;
+        }
+        Ok(&self.buffer[self.position..self.end])
+    }
+
+    /// Add a line to `line`, without its line ending. Returns false if `line` grows longer than
+    /// `limit` bytes first.
+    async fn read_line(&mut self, line: &mut Vec<u8>, limit: usize) -> Result<bool> {
+        loop {
+            let available = self.fill().await?;
+            if available.is_empty() {
+                return Err(Error::UnexpectedEof("Expected \\r\\n".into()));
+            }
+            let (used, found) = match available.iter().position(|&b| b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            line.extend_from_slice(&available[..used]);
+            self.position += used;
+            if line.len() > limit {
+                return Ok(false);
+            }
+            if found {
+                while matches!(line.last(), Some(b'\n') | Some(b'\r')) {
+                    line.pop();
+                }
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Copy up to `limit` bytes into `buf`, returning how many were copied.
+    async fn copy(&mut self, buf: &mut [u8], limit: u64) -> Result<usize> {
+        let available = self.fill().await?;
+        let length = available
+            .len()
+            .min(buf.len())
+            .min(usize::try_from(limit).unwrap_or(usize::MAX));
+        buf[..length].copy_from_slice(&available[..length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+/// A response received by `HttpRequestBuilder::send_embedded`, whose body is read
+/// asynchronously.
+///
+/// *This struct is available if http_io is built with the `"embedded-io-async"` feature.*
+pub struct EmbeddedHttpResponse<S> {
+    pub status: HttpStatus,
+    pub headers: HttpHeaders,
+    pub body: EmbeddedBody<S>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// This many bytes of the body are left.
+    Length(u64),
+    /// The body ends when the stream does.
+    UntilClose,
+    ChunkSize,
+    /// This many bytes of the current chunk are left.
+    ChunkData(u64),
+    /// The line ending after a chunk's data.
+    ChunkEnd,
+    Trailers,
+    Done,
+}
+
+/// The body of an `EmbeddedHttpResponse`, decoded from its `Content-Length` or chunked framing.
+/// It implements `embedded_io_async::Read`, reporting errors by their kind; the inherent `read`
+/// gives the full `Error`.
+///
+/// *This struct is available if http_io is built with the `"embedded-io-async"` feature.*
+pub struct EmbeddedBody<S> {
+    stream: Buffered<S>,
+    state: State,
+}
+
+impl<S: Read> EmbeddedBody<S> {
+    /// Read some of the body into `buf`, returning 0 at its end.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut line = Vec::new();
+        loop {
+            match self.state {
+                State::Done => return Ok(0),
+                State::Length(0) => self.state = State::Done,
+                State::Length(remaining) => {
+                    let copied = self.stream.copy(buf, remaining).await?;
+                    if copied == 0 {
+                        return Err(Error::UnexpectedEof(
+                            "body shorter than Content-Length".into(),
+                        ));
+                    }
+                    self.state = State::Length(remaining - copied as u64);
+                    return Ok(copied);
+                }
+                State::UntilClose => {
+                    let copied = self.stream.copy(buf, u64::MAX).await?;
+                    if copied == 0 {
+                        self.state = State::Done;
+                    }
+                    return Ok(copied);
+                }
+                State::ChunkSize => {
+                    self.read_chunk_line(&mut line).await?;
+                    let size = str::from_utf8(&line)?.split(';').next().unwrap_or("");
+                    self.state = match u64::from_str_radix(size.trim(), 16)? {
+                        0 => State::Trailers,
+                        size => State::ChunkData(size),
+                    };
+                }
+                State::ChunkData(remaining) => {
+                    let copied = self.stream.copy(buf, remaining).await?;
+                    if copied == 0 {
+                        return Err(Error::UnexpectedEof("chunk shorter than its size".into()));
+                    }
+                    self.state = match remaining - copied as u64 {
+                        0 => State::ChunkEnd,
+                        remaining => State::ChunkData(remaining),
+                    };
+                    return Ok(copied);
+                }
+                State::ChunkEnd => {
+                    self.read_chunk_line(&mut line).await?;
+                    if !line.is_empty() {
+                        return Err(Error::ParseError("chunk longer than its size".into()));
+                    }
+                    self.state = State::ChunkSize;
+                }
+                State::Trailers => {
+                    self.read_chunk_line(&mut line).await?;
+                    if line.is_empty() {
+                        self.state = State::Done;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn read_chunk_line(&mut self, line: &mut Vec<u8>) -> Result<()> {
+        line.clear();
+        if !self.stream.read_line(line, MAX_LINE).await? {
+            return Err(Error::ParseError("chunked body line too long".into()));
+        }
+        Ok(())
+    }
+
+    /// Return the stream, once the body has been read.
+    pub fn into_inner(self) -> S {
+        self.stream.stream
+    }
+}
+
+impl<S> ErrorType for EmbeddedBody<S> {
+    type Error = ErrorKind;
+}
+
+impl<S: Read> Read for EmbeddedBody<S> {
+    async fn read(&mut self, buf: &mut [u8]) -> core::result::Result<usize, ErrorKind> {
+        EmbeddedBody::read(self, buf)
+            .await
+            .map_err(|e| error_kind(&e))
+    }
+}
+
+/// How the body of a response to a request with the given method is framed.
+fn body_state(method: HttpMethod, status: HttpStatus, headers: &HttpHeaders) -> Result<State> {
+    if method == HttpMethod::Head
+        || status == HttpStatus::NoContent
+        || status == HttpStatus::NotModified
+    {
+        Ok(State::Done)
+    } else if headers.is_chunked() {
+        Ok(State::ChunkSize)
+    } else if headers.get("Transfer-Encoding").is_some() {
+        Ok(State::UntilClose)
+    } else if let Some(length) = headers.content_length()? {
+        Ok(State::Length(length))
+    } else {
+        Ok(State::UntilClose)
+    }
+}
+
+impl<B: core2::io::Read> HttpRequestBuilder<B> {
+    /// Send the request on the given `embedded-io-async` stream, like an embassy-net
+    /// `TcpSocket`, returning the response once its headers have been read. The body given with
+    /// `body` or the like is read into memory first, so it shouldn't block, and is sent with a
+    /// `Content-Length`. The header size limit applies.
+    ///
+    /// *This function is available if http_io is built with the `"embedded-io-async"` feature.*
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// use http_io::client::HttpRequestBuilder;
+    ///
+    /// let mut socket = embassy_net::tcp::TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+    /// socket.connect(address).await?;
+    /// let mut response = HttpRequestBuilder::get("http://192.168.1.10/status")?
+    ///     .send_embedded(&mut socket)
+    ///     .await?;
+    /// let read = response.body.read(&mut buffer).await?;
+    /// ```
+    pub async fn send_embedded<S: Read + Write>(
+        self,
+        mut stream: S,
+    ) -> Result<EmbeddedHttpResponse<S>> {
+        let method = self.request.method;
+        let header_limit = self.options.max_header_size.unwrap_or(usize::MAX);
+        let request = self.encode()?;
+        stream.write_all(&request).await.map_err(io_error)?;
+        stream.flush().await.map_err(io_error)?;
+
+        let mut stream = Buffered {
+            stream,
+            buffer: vec![0; 1024],
+            position: 0,
+            end: 0,
+        };
+        let response = loop {
+            let mut head = Vec::new();
+            loop {
+                let start = head.len();
+                if !stream.read_line(&mut head, header_limit).await? {
+                    return Err(Error::HeadersTooLarge(header_limit));
+                }
+                // Put back the line ending, which the parser needs.
+                head.extend_from_slice(b"\r\n");
+                if head.len() == start + 2 && start > 0 {
+                    break;
+                }
+            }
+            let response = HttpResponse::deserialize(&head[..])?;
+            // Interim responses like 103 Early Hints come before the final one.
+            if !response.status.is_interim() {
+                break response;
+            }
+        };
+        let state = body_state(method, response.status, &response.headers)?;
+        Ok(EmbeddedHttpResponse {
+            status: response.status,
+            headers: response.headers,
+            body: EmbeddedBody { stream, state },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::HttpRequestBuilder;
+    use crate::error::Result;
+    use crate::protocol::HttpStatus;
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+    use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+    /// Run a future which never waits, as the test streams are always ready.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        fn raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+        let waker = unsafe { Waker::from_raw(raw_waker()) };
+        let mut future = pin!(future);
+        match future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("test stream blocked"),
+        }
+    }
+
+    /// Responds with `input`, recording what is written in `output`. Reads return at most 7
+    /// bytes, to exercise reads split across the buffer.
+    struct TestStream {
+        input: &'static [u8],
+        output: Vec<u8>,
+    }
+
+    impl ErrorType for TestStream {
+        type Error = ErrorKind;
+    }
+
+    impl Read for TestStream {
+        async fn read(&mut self, buf: &mut [u8]) -> core::result::Result<usize, ErrorKind> {
+            let length = buf.len().min(self.input.len()).min(7);
+            buf[..length].copy_from_slice(&self.input[..length]);
+            self.input = &self.input[length..];
+            Ok(length)
+        }
+    }
+
+    impl Write for TestStream {
+        async fn write(&mut self, buf: &[u8]) -> core::result::Result<usize, ErrorKind> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    fn read_body<S: Read>(body: &mut super::EmbeddedBody<S>) -> Result<String> {
+        let mut decoded = Vec::new();
+        let mut buffer = [0; 5];
+        loop {
+            match block_on(body.read(&mut buffer))? {
+                0 => return Ok(String::from_utf8(decoded).unwrap()),
+                read => decoded.extend_from_slice(&buffer[..read]),
+            }
+        }
+    }
+
+    #[test]
+    fn send_embedded_chunked() -> Result<()> {
+        let mut stream = TestStream {
+            input: b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                     5\r\nhello\r\n6;name=value\r\n world\r\n0\r\nExpires: never\r\n\r\n",
+            output: Vec::new(),
+        };
+        let mut response = block_on(
+            HttpRequestBuilder::post("http://device.local/upload")?
+                .body(&b"hello from client"[..], 17)
+                .send_embedded(&mut stream),
+        )?;
+        assert_eq!(response.status, HttpStatus::OK);
+        assert_eq!(read_body(&mut response.body)?, "hello world");
+
+        let request = String::from_utf8(stream.output).unwrap();
+        assert!(request.starts_with("POST /upload HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Length: 17\r\n"));
+        assert!(!request.contains("Transfer-Encoding"));
+        assert!(request.ends_with("\r\n\r\nhello from client"));
+        Ok(())
+    }
+
+    #[test]
+    fn send_embedded_content_length() -> Result<()> {
+        let mut stream = TestStream {
+            input: b"HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nnot here!extra",
+            output: Vec::new(),
+        };
+        let mut response =
+            block_on(HttpRequestBuilder::get("http://device.local/")?.send_embedded(&mut stream))?;
+        assert_eq!(response.status, HttpStatus::NotFound);
+        assert_eq!(read_body(&mut response.body)?, "not here!");
+        Ok(())
+    }
+
+    #[test]
+    fn send_embedded_truncated() -> Result<()> {
+        let mut stream = TestStream {
+            input: b"HTTP/1.1 200 OK\r\nContent-Length: 20\r\n\r\nshort",
+            output: Vec::new(),
+        };
+        let mut response =
+            block_on(HttpRequestBuilder::get("http://device.local/")?.send_embedded(&mut stream))?;
+        assert!(read_body(&mut response.body).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! Client streams over embedded-nal network stacks.
+
+use crate::error::{Error, Result};
+use ::embedded_nal::nb;
+use ::embedded_nal::{AddrType, Dns, TcpClientStack, TcpError, TcpErrorKind};
+#[cfg(not(feature = "std"))]
+use alloc::string::ToString;
+use core::net::{IpAddr, SocketAddr};
+use core2::io;
+
+fn io_error<E: TcpError>(error: E, closed: io::ErrorKind) -> io::Error {
+    match error.kind() {
+        TcpErrorKind::PipeClosed => io::Error::new(closed, "connection closed"),
+        _ => io::Error::new(io::ErrorKind::Other, "network stack error"),
+    }
+}
+
+/// A `Read + Write` stream over a TCP socket of an embedded-nal `TcpClientStack`, like the
+/// drivers of W5500 chips, ESP-AT and cellular modems. Pass it to `HttpRequestBuilder::send`.
+///
+/// The stack's non-blocking calls are retried until they complete, so reads and writes block,
+/// without a timeout. The socket is closed when the stream is dropped.
+///
+/// *This struct is available if http_io is built with the `"embedded-nal"` feature.*
+///
+/// # Example
+/// ```rust,ignore
+/// use http_io::client::{EmbeddedNalStream, HttpRequestBuilder};
+///
+/// let stream = EmbeddedNalStream::connect_host(&mut stack, "example.com", 80)?;
+/// let response = HttpRequestBuilder::get("http://example.com/")?.send(stream)?.finish()?;
+/// ```
+pub struct EmbeddedNalStream<'a, T: TcpClientStack> {
+    stack: &'a mut T,
+    socket: Option<T::TcpSocket>,
+}
+
+impl<'a, T: TcpClientStack> EmbeddedNalStream<'a, T> {
+    /// Open a socket on the stack and connect it to the server at `remote`.
+    pub fn connect(stack: &'a mut T, remote: SocketAddr) -> Result<Self> {
+        let mut socket = stack
+            .socket()
+            .map_err(|e| io_error(e, io::ErrorKind::NotConnected))?;
+        if let Err(e) = nb::block!(stack.connect(&mut socket, remote)) {
+            let _ = stack.close(socket);
+            return Err(io_error(e, io::ErrorKind::ConnectionRefused).into());
+        }
+        Ok(EmbeddedNalStream {
+            stack,
+            socket: Some(socket),
+        })
+    }
+
+    /// Close the socket.
+    pub fn close(mut self) -> Result<()> {
+        match self.socket.take() {
+            Some(socket) => self
+                .stack
+                .close(socket)
+                .map_err(|e| io_error(e, io::ErrorKind::NotConnected).into()),
+            None => Ok(()),
+        }
+    }
+
+    fn socket(&mut self) -> io::Result<(&mut T, &mut T::TcpSocket)> {
+        match &mut self.socket {
+            Some(socket) => Ok((&mut *self.stack, socket)),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "socket closed")),
+        }
+    }
+}
+
+impl<'a, T: TcpClientStack + Dns> EmbeddedNalStream<'a, T> {
+    /// Resolve the host with the stack's DNS, unless it is an IP address, and connect to it.
+    pub fn connect_host(stack: &'a mut T, host: &str, port: u16) -> Result<Self> {
+        let name = host.trim_start_matches('[').trim_end_matches(']');
+        let ip = match name.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => nb::block!(stack.get_host_by_name(name, AddrType::Either))
+                .map_err(|_| Error::UnknownHost(host.to_string()))?,
+        };
+        Self::connect(stack, SocketAddr::new(ip, port))
+    }
+}
+
+impl<'a, T: TcpClientStack> io::Read for EmbeddedNalStream<'a, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (stack, socket) = self.socket()?;
+        match nb::block!(stack.receive(socket, buf)) {
+            Ok(read) => Ok(read),
+            // The server closed the connection, which ends the body of some responses.
+            Err(e) if matches!(e.kind(), TcpErrorKind::PipeClosed) => Ok(0),
+            Err(e) => Err(io_error(e, io::ErrorKind::ConnectionAborted)),
+        }
+    }
+}
+
+impl<'a, T: TcpClientStack> io::Write for EmbeddedNalStream<'a, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (stack, socket) = self.socket()?;
+        nb::block!(stack.send(socket, buf)).map_err(|e| io_error(e, io::ErrorKind::BrokenPipe))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, T: TcpClientStack> Drop for EmbeddedNalStream<'a, T> {
+    fn drop(&mut self) {
+        if let Some(socket) = self.socket.take() {
+            let _ = self.stack.close(socket);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! TLS for no_std clients using embedded-tls.
+
+use crate::error::{Error, Result};
+use ::embedded_tls::blocking::TlsConnection;
+use ::embedded_tls::{CryptoProvider, TlsCipherSuite, TlsConfig, TlsContext, TlsError};
+#[cfg(not(feature = "std"))]
+use alloc::format;
+use core2::io;
+use embedded_io::ErrorKind;
+
+/// Lets embedded-tls use a `core2::io` stream.
+struct Socket<S>(S);
+
+fn error_kind(kind: io::ErrorKind) -> ErrorKind {
+    match kind {
+        io::ErrorKind::NotFound => ErrorKind::NotFound,
+        io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+        io::ErrorKind::ConnectionRefused => ErrorKind::ConnectionRefused,
+        io::ErrorKind::ConnectionReset => ErrorKind::ConnectionReset,
+        io::ErrorKind::ConnectionAborted => ErrorKind::ConnectionAborted,
+        io::ErrorKind::NotConnected => ErrorKind::NotConnected,
+        io::ErrorKind::AddrInUse => ErrorKind::AddrInUse,
+        io::ErrorKind::AddrNotAvailable => ErrorKind::AddrNotAvailable,
+        io::ErrorKind::BrokenPipe => ErrorKind::BrokenPipe,
+        io::ErrorKind::AlreadyExists => ErrorKind::AlreadyExists,
+        io::ErrorKind::InvalidInput => ErrorKind::InvalidInput,
+        io::ErrorKind::InvalidData => ErrorKind::InvalidData,
+        io::ErrorKind::TimedOut => ErrorKind::TimedOut,
+        io::ErrorKind::Interrupted => ErrorKind::Interrupted,
+        io::ErrorKind::WriteZero => ErrorKind::WriteZero,
+        _ => ErrorKind::Other,
+    }
+}
+
+impl<S> embedded_io::ErrorType for Socket<S> {
+    type Error = ErrorKind;
+}
+
+impl<S: io::Read> embedded_io::Read for Socket<S> {
+    fn read(&mut self, buf: &mut [u8]) -> core::result::Result<usize, ErrorKind> {
+        self.0.read(buf).map_err(|e| error_kind(e.kind()))
+    }
+}
+
+impl<S: io::Write> embedded_io::Write for Socket<S> {
+    fn write(&mut self, buf: &[u8]) -> core::result::Result<usize, ErrorKind> {
+        self.0.write(buf).map_err(|e| error_kind(e.kind()))
+    }
+
+    fn flush(&mut self) -> core::result::Result<(), ErrorKind> {
+        self.0.flush().map_err(|e| error_kind(e.kind()))
+    }
+}
+
+fn io_error(error: TlsError) -> io::Error {
+    match error {
+        TlsError::Io(ErrorKind::TimedOut) => io::Error::new(io::ErrorKind::TimedOut, "timed out"),
+        TlsError::Io(_) => io::Error::new(io::ErrorKind::Other, "I/O error under TLS"),
+        TlsError::ConnectionClosed => {
+            io::Error::new(io::ErrorKind::ConnectionAborted, "TLS connection closed")
+        }
+        _ => io::Error::new(io::ErrorKind::InvalidData, "TLS error"),
+    }
+}
+
+/// A TLS connection over any `Read + Write` stream using embedded-tls, for HTTPS without std.
+/// Pass it to `HttpRequestBuilder::send`.
+///
+/// Only TLS 1.3 is supported. The record buffers must be large enough for the records the server
+/// sends, up to 16640 bytes.
+///
+/// *This struct is available if http_io is built with the `"embedded-tls"` feature.*
+///
+/// # Example
+/// ```rust,ignore
+/// use embedded_tls::{Aes128GcmSha256, UnsecureProvider};
+/// use http_io::client::{EmbeddedTlsStream, HttpRequestBuilder};
+///
+/// let mut read_buffer = [0; 16640];
+/// let mut write_buffer = [0; 16640];
+/// let provider = UnsecureProvider::new::<Aes128GcmSha256>(rng);
+/// let tls = EmbeddedTlsStream::connect(
+///     socket, "example.com", &mut read_buffer, &mut write_buffer, provider,
+/// )?;
+/// let response = HttpRequestBuilder::get("https://example.com/")?.send(tls)?.finish()?;
+/// ```
+pub struct EmbeddedTlsStream<'a, S: io::Read + io::Write, C: TlsCipherSuite + 'static> {
+    connection: TlsConnection<'a, Socket<S>, C>,
+}
+
+impl<'a, S: io::Read + io::Write, C: TlsCipherSuite + 'static> EmbeddedTlsStream<'a, S, C> {
+    /// Perform the TLS handshake with the server on the other end of `stream`. `server_name` is
+    /// sent using SNI. The provider supplies the random number generator and verifies the
+    /// server's certificate.
+    pub fn connect<P: CryptoProvider<CipherSuite = C>>(
+        stream: S,
+        server_name: &str,
+        read_buffer: &'a mut [u8],
+        write_buffer: &'a mut [u8],
+        provider: P,
+    ) -> Result<Self> {
+        let config = TlsConfig::new().with_server_name(server_name);
+        let mut connection = TlsConnection::new(Socket(stream), read_buffer, write_buffer);
+        connection
+            .open(TlsContext::new(&config, provider))
+            .map_err(|e| Error::TlsError(format!("{:?}", e)))?;
+        Ok(EmbeddedTlsStream { connection })
+    }
+
+    /// Close the TLS connection, returning the underlying stream.
+    pub fn close(self) -> Result<S> {
+        match self.connection.close() {
+            Ok(socket) => Ok(socket.0),
+            Err((_, e)) => Err(Error::TlsError(format!("{:?}", e))),
+        }
+    }
+}
+
+impl<'a, S: io::Read + io::Write, C: TlsCipherSuite + 'static> io::Read
+    for EmbeddedTlsStream<'a, S, C>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.connection.read(buf).map_err(io_error)
+    }
+}
+
+impl<'a, S: io::Read + io::Write, C: TlsCipherSuite + 'static> io::Write
+    for EmbeddedTlsStream<'a, S, C>
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.connection.write(buf).map_err(io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.connection.flush().map_err(io_error)
+    }
+}
@@ -0,0 +1,360 @@
+//! Decoding gzip (RFC 1952) and deflate (RFC 1950) response bodies.
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, vec, vec::Vec};
+use core2::io;
+use miniz_oxide::inflate::stream::{inflate, InflateState};
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+
+const ID: [u8; 2] = [0x1f, 0x8b];
+const DEFLATE: u8 = 8;
+const FLAG_HEADER_CRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated gzip stream")
+}
+
+/// Update a CRC-32 (as used by gzip) with the data.
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+    !crc
+}
+
+/// Decodes a gzip stream read from the inner reader. `HttpRequestBuilder::execute` uses it for
+/// responses with `Content-Encoding: gzip`; it can wrap the body of responses received with
+/// `HttpRequestBuilder::send` too.
+///
+/// An empty stream decodes as empty, since responses without a body may still claim to be gzip
+/// encoded.
+///
+/// *This struct is available if http_io is built with the `"gzip"` feature.*
+pub struct GzipDecoder<R: io::Read> {
+    inner: R,
+    input: Vec<u8>,
+    position: usize,
+    end: usize,
+    state: Box<InflateState>,
+    started: bool,
+    finished: bool,
+    crc: u32,
+    size: u32,
+}
+
+impl<R: io::Read> GzipDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        GzipDecoder {
+            inner,
+            input: vec![0; 8192],
+            position: 0,
+            end: 0,
+            state: InflateState::new_boxed(DataFormat::Raw),
+            started: false,
+            finished: false,
+            crc: 0,
+            size: 0,
+        }
+    }
+
+    /// Return the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read more input if it has all been used, returning false at the end of the stream.
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.position == self.end {
+            self.position = 0;
+            self.end = self.inner.read(&mut self.input)?;
+        }
+        Ok(self.position < self.end)
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        if !self.fill()? {
+            return Err(truncated());
+        }
+        self.position += 1;
+        Ok(self.input[self.position - 1])
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        for b in &mut bytes {
+            *b = self.read_byte()?;
+        }
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn skip_zero_terminated(&mut self) -> io::Result<()> {
+        while self.read_byte()? != 0 {}
+        Ok(())
+    }
+
+    /// Read the gzip header, returning false if the stream is empty.
+    fn read_header(&mut self) -> io::Result<bool> {
+        if !self.fill()? {
+            return Ok(false);
+        }
+        let mut header = [0; 10];
+        for b in &mut header {
+            *b = self.read_byte()?;
+        }
+        if header[..2] != ID {
+            return Err(invalid_data("not a gzip stream"));
+        }
+        if header[2] != DEFLATE {
+            return Err(invalid_data("unknown gzip compression method"));
+        }
+        let flags = header[3];
+        if flags & FLAG_EXTRA != 0 {
+            let length = u16::from_le_bytes([self.read_byte()?, self.read_byte()?]);
+            for _ in 0..length {
+                self.read_byte()?;
+            }
+        }
+        if flags & FLAG_NAME != 0 {
+            self.skip_zero_terminated()?;
+        }
+        if flags & FLAG_COMMENT != 0 {
+            self.skip_zero_terminated()?;
+        }
+        if flags & FLAG_HEADER_CRC != 0 {
+            self.read_byte()?;
+            self.read_byte()?;
+        }
+        Ok(true)
+    }
+
+    fn read_trailer(&mut self) -> io::Result<()> {
+        if self.read_u32()? != self.crc {
+            return Err(invalid_data("gzip checksum mismatch"));
+        }
+        if self.read_u32()? != self.size {
+            return Err(invalid_data("gzip length mismatch"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: io::Read> io::Read for GzipDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.finished || buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.started {
+            self.started = true;
+            if !self.read_header()? {
+                self.finished = true;
+                return Ok(0);
+            }
+        }
+
+        loop {
+            let more = self.fill()?;
+            let result = inflate(
+                &mut self.state,
+                &self.input[self.position..self.end],
+                buf,
+                MZFlush::None,
+            );
+            self.position += result.bytes_consumed;
+            let written = result.bytes_written;
+            self.crc = crc32(self.crc, &buf[..written]);
+            self.size = self.size.wrapping_add(written as u32);
+
+            match result.status {
+                Ok(MZStatus::StreamEnd) => {
+                    self.read_trailer()?;
+                    self.finished = true;
+                    return Ok(written);
+                }
+                Ok(_) | Err(MZError::Buf) if written > 0 => return Ok(written),
+                Ok(_) | Err(MZError::Buf) if more => {}
+                Ok(_) | Err(MZError::Buf) => return Err(truncated()),
+                Err(_) => return Err(invalid_data("invalid deflate data")),
+            }
+        }
+    }
+}
+
+/// Decodes a deflate stream, as HTTP's `deflate` content coding means: compressed data in the
+/// zlib format (RFC 1950). Streams without the zlib wrapper, which some servers send instead,
+/// are decoded too. `HttpRequestBuilder::execute` uses it for responses with
+/// `Content-Encoding: deflate`.
+///
+/// *This struct is available if http_io is built with the `"gzip"` feature.*
+pub struct DeflateDecoder<R: io::Read> {
+    inner: R,
+    input: Vec<u8>,
+    position: usize,
+    end: usize,
+    state: Option<Box<InflateState>>,
+    finished: bool,
+}
+
+impl<R: io::Read> DeflateDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        DeflateDecoder {
+            inner,
+            input: vec![0; 8192],
+            position: 0,
+            end: 0,
+            state: None,
+            finished: false,
+        }
+    }
+
+    /// Return the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read more input if it has all been used, returning false at the end of the stream.
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.position == self.end {
+            self.position = 0;
+            self.end = self.inner.read(&mut self.input)?;
+        }
+        Ok(self.position < self.end)
+    }
+
+    /// Whether the input starts with a zlib header: deflate compression, and a check value
+    /// making the first two bytes a multiple of 31.
+    fn is_zlib(&self) -> bool {
+        match &self.input[self.position..self.end] {
+            [cmf, flg, ..] => cmf & 0x0f == DEFLATE && u16::from_be_bytes([*cmf, *flg]) % 31 == 0,
+            _ => false,
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for DeflateDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.finished || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.state.is_none() {
+            if !self.fill()? {
+                self.finished = true;
+                return Ok(0);
+            }
+            let format = if self.is_zlib() {
+                DataFormat::Zlib
+            } else {
+                DataFormat::Raw
+            };
+            self.state = Some(InflateState::new_boxed(format));
+        }
+
+        loop {
+            let more = self.fill()?;
+            let state = self.state.as_mut().unwrap();
+            let result = inflate(
+                state,
+                &self.input[self.position..self.end],
+                buf,
+                MZFlush::None,
+            );
+            self.position += result.bytes_consumed;
+            let written = result.bytes_written;
+            match result.status {
+                Ok(MZStatus::StreamEnd) => {
+                    self.finished = true;
+                    return Ok(written);
+                }
+                Ok(_) | Err(MZError::Buf) if written > 0 => return Ok(written),
+                Ok(_) | Err(MZError::Buf) if more => {}
+                Ok(_) | Err(MZError::Buf) => return Err(truncated()),
+                Err(_) => return Err(invalid_data("invalid deflate data")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, DeflateDecoder, GzipDecoder};
+    use core2::io::{self, Read};
+
+    /// "hello hello hello hello\n" compressed by gzip, with the file name "hello.txt".
+    const HELLO: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+        0x2e, 0x74, 0x78, 0x74, 0x00, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0xb9,
+        0x00, 0x00, 0x88, 0x59, 0x0b, 0x18, 0x00, 0x00, 0x00,
+    ];
+
+    fn decode(input: &[u8]) -> io::Result<String> {
+        let mut output = String::new();
+        GzipDecoder::new(input).read_to_string(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(crc32(0, b"12345"), b"6789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn decode_hello() {
+        assert_eq!(decode(HELLO).unwrap(), "hello hello hello hello\n");
+    }
+
+    #[test]
+    fn decode_empty() {
+        assert_eq!(decode(b"").unwrap(), "");
+    }
+
+    #[test]
+    fn decode_truncated() {
+        assert!(decode(&HELLO[..HELLO.len() - 6]).is_err());
+    }
+
+    #[test]
+    fn decode_corrupt_checksum() {
+        let mut corrupt = HELLO.to_vec();
+        let index = corrupt.len() - 8;
+        corrupt[index] ^= 1;
+        assert!(decode(&corrupt).is_err());
+    }
+
+    #[test]
+    fn not_gzip() {
+        assert!(decode(b"hello").is_err());
+    }
+
+    /// "hello hello hello hello\n" compressed by zlib.
+    const HELLO_ZLIB: &[u8] = &[
+        0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0xb9, 0x00, 0x70, 0xbe,
+        0x08, 0xbb,
+    ];
+
+    fn inflate(input: &[u8]) -> io::Result<String> {
+        let mut output = String::new();
+        DeflateDecoder::new(input).read_to_string(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn deflate() {
+        assert_eq!(inflate(HELLO_ZLIB).unwrap(), "hello hello hello hello\n");
+        // The same data without the zlib header and checksum.
+        let raw = &HELLO_ZLIB[2..HELLO_ZLIB.len() - 4];
+        assert_eq!(inflate(raw).unwrap(), "hello hello hello hello\n");
+        assert_eq!(inflate(b"").unwrap(), "");
+        assert!(inflate(&HELLO_ZLIB[..8]).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Connecting to hosts with both IPv6 and IPv4 addresses, racing them as in RFC 8305 ("Happy
+//! Eyeballs") so that a broken route for one family doesn't hold up the connection.
+
+use super::Trace;
+use crate::error::{Error, Result};
+use core::net::SocketAddr;
+use core::time::Duration;
+use std::net::TcpStream;
+use std::sync::mpsc;
+
+/// How long to wait on an attempt before starting the next one alongside it.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// The addresses in the order to try them: alternating between families, starting with the
+/// family of the first one, which the resolver prefers.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_ipv6 = addrs.first().map_or(false, SocketAddr::is_ipv6);
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_is_ipv6);
+    let mut other = other.into_iter();
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    for addr in preferred {
+        ordered.push(addr);
+        ordered.extend(other.next());
+    }
+    ordered.extend(other);
+    ordered
+}
+
+/// Connect to the first of the addresses to accept. Attempts start `ATTEMPT_DELAY` apart, or as
+/// soon as the previous one fails, and run alongside each other until one succeeds. Each gives up
+/// after `timeout`, if given. Fails with the last error if no address works, or
+/// `Error::UnknownHost` with the host if there are none.
+pub(super) fn connect(
+    host: &str,
+    addrs: Vec<SocketAddr>,
+    timeout: Option<Duration>,
+    trace: Option<&dyn Trace>,
+) -> Result<TcpStream> {
+    let (sender, receiver) = mpsc::channel();
+    let mut addrs = interleave(addrs).into_iter().peekable();
+    let mut pending = 0;
+    let mut error = Error::UnknownHost(host.into());
+    loop {
+        if let Some(addr) = addrs.next() {
+            if let Some(trace) = trace {
+                trace.connect_start(addr);
+            }
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                let stream = match timeout {
+                    Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                    None => TcpStream::connect(addr),
+                };
+                // The receiver is gone if another attempt won, and this stream is dropped.
+                let _ = sender.send((addr, stream));
+            });
+            pending += 1;
+        }
+        if pending == 0 {
+            return Err(error);
+        }
+        let received = if addrs.peek().is_some() {
+            receiver.recv_timeout(ATTEMPT_DELAY).ok()
+        } else {
+            receiver.recv().ok()
+        };
+        let (addr, stream) = match received {
+            Some(result) => result,
+            // Start the next attempt.
+            None => continue,
+        };
+        pending -= 1;
+        let stream = stream.map_err(Error::from);
+        if let Some(trace) = trace {
+            trace.connect_done(addr, stream.as_ref().map(|_| ()));
+        }
+        match stream {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{connect, interleave};
+    use core::net::SocketAddr;
+
+    fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn interleaves_families() {
+        assert_eq!(
+            interleave(addrs(&[
+                "[::1]:80",
+                "[::2]:80",
+                "[::3]:80",
+                "1.1.1.1:80",
+                "2.2.2.2:80"
+            ])),
+            addrs(&[
+                "[::1]:80",
+                "1.1.1.1:80",
+                "[::2]:80",
+                "2.2.2.2:80",
+                "[::3]:80"
+            ])
+        );
+        assert_eq!(
+            interleave(addrs(&["1.1.1.1:80", "[::1]:80", "[::2]:80", "[::3]:80"])),
+            addrs(&["1.1.1.1:80", "[::1]:80", "[::2]:80", "[::3]:80"])
+        );
+    }
+
+    #[test]
+    fn connects_past_unreachable_address() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let local = listener.local_addr().unwrap();
+        // Reserved for documentation, so nothing answers there.
+        let unreachable: SocketAddr = "192.0.2.1:80".parse().unwrap();
+        let started = std::time::Instant::now();
+        let stream = connect("localhost", vec![unreachable, local], None, None).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), local);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,591 @@
+//! HTTP/2 for the client. See `Http2Connection`.
+
+use crate::error::{Error, Result};
+use crate::protocol::{HttpHeaders, HttpRequest, HttpResponse, HttpStatus, TrailerSlot};
+use crate::server::http2::{
+    canonical_name, header_fields, read_exact_or_eof, ACK, CONTINUATION, DATA,
+    DEFAULT_MAX_FRAME_SIZE, DEFAULT_WINDOW_SIZE, END_HEADERS, END_STREAM, GOAWAY, HEADERS,
+    MAX_WINDOW_SIZE, PADDED, PING, PREFACE, PRIORITY_FLAG, PUSH_PROMISE, RST_STREAM, SETTINGS,
+    SETTINGS_INITIAL_WINDOW_SIZE, SETTINGS_MAX_FRAME_SIZE, WINDOW_UPDATE,
+};
+use crate::url::Scheme;
+use core2::io::{self, Read, Write};
+use std::convert::TryInto;
+use std::ops::DerefMut;
+use std::{cmp, mem};
+
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const CANCEL: u32 = 0x8;
+
+fn protocol_error(reason: &str) -> Error {
+    Error::Http2Error(reason.into())
+}
+
+/// The error for reading a body, whose message has to be static.
+fn read_error(error: Error) -> io::Error {
+    match error {
+        Error::IoError(error) => error,
+        Error::UnexpectedEof(_) => {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")
+        }
+        Error::BodyTooLarge(_) => io::Error::new(io::ErrorKind::InvalidData, "body too large"),
+        _ => io::Error::new(io::ErrorKind::InvalidData, "HTTP/2 protocol error"),
+    }
+}
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    /// The payload without any padding.
+    fn unpadded(&self) -> Result<&[u8]> {
+        if self.flags & PADDED == 0 {
+            return Ok(&self.payload);
+        }
+        let (&padding, rest) = self
+            .payload
+            .split_first()
+            .ok_or_else(|| protocol_error("missing padding length"))?;
+        rest.len()
+            .checked_sub(padding as usize)
+            .map(|len| &rest[..len])
+            .ok_or_else(|| protocol_error("too much padding"))
+    }
+}
+
+/// The response being received on the current stream.
+#[derive(Default)]
+struct Exchange {
+    id: u32,
+    /// The header block being received, until the last piece of it arrives.
+    header_block: Option<Vec<u8>>,
+    ends_after_headers: bool,
+    status: Option<HttpStatus>,
+    headers: HttpHeaders,
+    /// The DATA received but not read yet.
+    body: Vec<u8>,
+    /// How much of the body was received, without padding.
+    received: u64,
+    trailers: HttpHeaders,
+    complete: bool,
+    /// How much DATA may be sent on the stream.
+    send_window: i64,
+    /// How much DATA the server may send on the stream.
+    receive_window: i64,
+    /// How much of the stream's window was read but not given back to the server yet.
+    released: i64,
+}
+
+/// An HTTP/2 connection to a server, on a stream which was either negotiated to speak HTTP/2
+/// with ALPN, or connects to a server known to support it. Requests are sent one at a time, each
+/// on its own stream. Their bodies are held in memory, while those of their responses are read
+/// from the connection as the server sends them.
+///
+/// `HttpRequestBuilder::http2` uses it for single requests; keep one to send several requests on
+/// the same connection.
+///
+/// *This struct is available if http_io is built with the `"http2"` feature.*
+pub struct Http2Connection<S> {
+    stream: S,
+    scheme: Scheme,
+    encoder: hpack::Encoder<'static>,
+    decoder: hpack::Decoder<'static>,
+    next_stream_id: u32,
+    send_window: i64,
+    peer_initial_window: i64,
+    peer_max_frame_size: usize,
+    receive_window: i64,
+    released: i64,
+    max_header_size: Option<usize>,
+    max_response_size: Option<u64>,
+    /// The last stream the server will answer, once it sent GOAWAY.
+    last_stream_id: Option<u32>,
+    exchange: Exchange,
+}
+
+impl<S: Read + Write> Http2Connection<S> {
+    /// Start speaking HTTP/2 on the stream, for urls with the given scheme, by sending the
+    /// connection preface.
+    pub fn new(mut stream: S, scheme: Scheme) -> Result<Self> {
+        stream.write_all(PREFACE)?;
+        let mut connection = Http2Connection {
+            stream,
+            scheme,
+            encoder: hpack::Encoder::new(),
+            decoder: hpack::Decoder::new(),
+            next_stream_id: 1,
+            send_window: DEFAULT_WINDOW_SIZE,
+            peer_initial_window: DEFAULT_WINDOW_SIZE,
+            peer_max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            receive_window: DEFAULT_WINDOW_SIZE,
+            released: 0,
+            max_header_size: None,
+            max_response_size: None,
+            last_stream_id: None,
+            exchange: Exchange::default(),
+        };
+        let mut settings = Vec::new();
+        settings.extend_from_slice(&SETTINGS_ENABLE_PUSH.to_be_bytes());
+        settings.extend_from_slice(&0u32.to_be_bytes());
+        connection.write_frame(SETTINGS, 0, 0, &settings)?;
+        Ok(connection)
+    }
+
+    /// Fail with `Error::HeadersTooLarge` if a block of response headers or trailers is longer
+    /// than `limit` bytes, either as received or once decompressed.
+    pub fn set_max_header_size(&mut self, limit: usize) {
+        self.max_header_size = Some(limit);
+    }
+
+    /// Fail with `Error::BodyTooLarge` when reading a response body longer than `limit` bytes.
+    pub fn set_max_response_size(&mut self, limit: u64) {
+        self.max_response_size = Some(limit);
+    }
+
+    /// Send the request, with its `Host` header as the `:authority`, and read the response's
+    /// headers. The request body is read into memory first. Interim 1xx responses are skipped.
+    ///
+    /// The response body is read from the connection, which the server may only send more of as
+    /// it is read. Trailers sent after it are available from `Http2Body::trailers` once it was
+    /// read to the end. Sending another request before then cancels this one.
+    pub fn send_request<B: Read>(
+        &mut self,
+        request: HttpRequest<B>,
+    ) -> Result<HttpResponse<Http2Body<&mut Self>>> {
+        let (status, headers) = self.start_request(request)?;
+        let mut response = HttpResponse::new(status, Http2Body::new(self));
+        response.headers = headers;
+        Ok(response)
+    }
+
+    /// Send the request and read the response up to the end of its headers, for `Http2Body` to
+    /// read its body.
+    pub(super) fn start_request<B: Read>(
+        &mut self,
+        mut request: HttpRequest<B>,
+    ) -> Result<(HttpStatus, HttpHeaders)> {
+        let id = self.next_stream_id;
+        if self.last_stream_id.map_or(false, |last| id > last) {
+            return Err(protocol_error("the server is closing the connection"));
+        }
+        if self.exchange.id != 0 && !self.exchange.complete {
+            let previous = self.exchange.id;
+            self.write_frame(RST_STREAM, 0, previous, &CANCEL.to_be_bytes())?;
+        }
+        // Whatever was left unread of the previous body is given back to the connection.
+        let unread = self.exchange.body.len();
+        self.release(unread, false)?;
+        self.next_stream_id += 2;
+        self.exchange = Exchange {
+            id,
+            send_window: self.peer_initial_window,
+            receive_window: DEFAULT_WINDOW_SIZE,
+            ..Exchange::default()
+        };
+
+        let mut body = Vec::new();
+        request.body.read_to_end(&mut body)?;
+        let authority = request.headers.get("Host").unwrap_or("").to_string();
+        let mut fields = vec![
+            (b":method".to_vec(), request.method.to_string().into_bytes()),
+            (b":scheme".to_vec(), self.scheme.to_string().into_bytes()),
+            (b":authority".to_vec(), authority.into_bytes()),
+            (b":path".to_vec(), request.uri.clone().into_bytes()),
+        ];
+        fields.extend(
+            header_fields(&request.headers).filter(|(name, _)| name != b"host" && name != b"te"),
+        );
+        self.write_header_block(id, &fields, body.is_empty())?;
+        if !body.is_empty() {
+            self.send_body(&body)?;
+        }
+
+        while self.exchange.status.is_none() && !self.exchange.complete {
+            self.receive_frame()?;
+        }
+        let status = self
+            .exchange
+            .status
+            .ok_or_else(|| protocol_error("response without a status"))?;
+        Ok((status, mem::take(&mut self.exchange.headers)))
+    }
+
+    /// Read the body of the current response, waiting for the server to send more of it.
+    fn read_body(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        while self.exchange.body.is_empty() && !self.exchange.complete {
+            self.receive_frame()?;
+        }
+        let len = cmp::min(buffer.len(), self.exchange.body.len());
+        buffer[..len].copy_from_slice(&self.exchange.body[..len]);
+        self.exchange.body.drain(..len);
+        self.release(len, true)?;
+        Ok(len)
+    }
+
+    /// Give back `len` bytes of the connection's window, and of the stream's if `on_stream`, once
+    /// they were read. WINDOW_UPDATE is only sent once half a window was read, so that the server
+    /// can't send much more than the caller reads.
+    fn release(&mut self, len: usize, on_stream: bool) -> Result<()> {
+        self.released += len as i64;
+        if self.released >= DEFAULT_WINDOW_SIZE / 2 {
+            let increment = mem::take(&mut self.released);
+            self.receive_window += increment;
+            self.write_frame(WINDOW_UPDATE, 0, 0, &(increment as u32).to_be_bytes())?;
+        }
+        if !on_stream || self.exchange.complete {
+            return Ok(());
+        }
+        self.exchange.released += len as i64;
+        if self.exchange.released >= DEFAULT_WINDOW_SIZE / 2 {
+            let increment = mem::take(&mut self.exchange.released);
+            self.exchange.receive_window += increment;
+            let id = self.exchange.id;
+            self.write_frame(WINDOW_UPDATE, 0, id, &(increment as u32).to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn receive_frame(&mut self) -> Result<()> {
+        let frame = self
+            .read_frame()?
+            .ok_or_else(|| Error::UnexpectedEof("connection closed mid-response".into()))?;
+        self.process_frame(frame)
+    }
+
+    /// Send the body in DATA frames as the flow control windows allow, ending the stream.
+    fn send_body(&mut self, body: &[u8]) -> Result<()> {
+        let mut sent = 0;
+        while sent < body.len() {
+            // The server may answer before reading the whole body.
+            if self.exchange.complete {
+                return Ok(());
+            }
+            let window = cmp::min(self.send_window, self.exchange.send_window);
+            if window <= 0 {
+                let frame = self
+                    .read_frame()?
+                    .ok_or_else(|| Error::UnexpectedEof("connection closed mid-request".into()))?;
+                self.process_frame(frame)?;
+                continue;
+            }
+            let len = cmp::min(
+                cmp::min(window as usize, body.len() - sent),
+                self.peer_max_frame_size,
+            );
+            let flags = if sent + len == body.len() {
+                END_STREAM
+            } else {
+                0
+            };
+            let id = self.exchange.id;
+            self.write_frame(DATA, flags, id, &body[sent..sent + len])?;
+            self.send_window -= len as i64;
+            self.exchange.send_window -= len as i64;
+            sent += len;
+        }
+        Ok(())
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame>> {
+        let mut header = [0; 9];
+        if !read_exact_or_eof(&mut self.stream, &mut header)? {
+            return Ok(None);
+        }
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        if length > DEFAULT_MAX_FRAME_SIZE {
+            return Err(protocol_error("frame too large"));
+        }
+        let mut payload = vec![0; length];
+        self.stream.read_exact(&mut payload)?;
+        Ok(Some(Frame {
+            kind: header[3],
+            flags: header[4],
+            stream_id: u32::from_be_bytes([header[5], header[6], header[7], header[8]])
+                & 0x7fff_ffff,
+            payload,
+        }))
+    }
+
+    fn write_frame(&mut self, kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Result<()> {
+        let mut header = [0; 9];
+        header[..3].copy_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+        header[3] = kind;
+        header[4] = flags;
+        header[5..].copy_from_slice(&stream_id.to_be_bytes());
+        self.stream.write_all(&header)?;
+        self.stream.write_all(payload)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    fn write_header_block(
+        &mut self,
+        id: u32,
+        fields: &[(Vec<u8>, Vec<u8>)],
+        end_stream: bool,
+    ) -> Result<()> {
+        let block = self
+            .encoder
+            .encode(fields.iter().map(|(n, v)| (&n[..], &v[..])));
+        let max_frame_size = self.peer_max_frame_size;
+        let mut pieces = block.chunks(max_frame_size).peekable();
+        let mut kind = HEADERS;
+        loop {
+            let piece = pieces.next().unwrap_or(&[][..]);
+            let mut flags = if pieces.peek().is_none() {
+                END_HEADERS
+            } else {
+                0
+            };
+            if kind == HEADERS && end_stream {
+                flags |= END_STREAM;
+            }
+            self.write_frame(kind, flags, id, piece)?;
+            if flags & END_HEADERS != 0 {
+                return Ok(());
+            }
+            kind = CONTINUATION;
+        }
+    }
+
+    fn process_frame(&mut self, frame: Frame) -> Result<()> {
+        if self.exchange.header_block.is_some() && frame.kind != CONTINUATION {
+            return Err(protocol_error("expected CONTINUATION"));
+        }
+        let current = frame.stream_id == self.exchange.id && !self.exchange.complete;
+        match frame.kind {
+            DATA if current => self.receive_data(frame),
+            // The rest of a body that wasn't read still counts against the connection's window.
+            DATA => {
+                self.take_from_window(frame.payload.len(), false)?;
+                self.release(frame.payload.len(), false)
+            }
+            HEADERS | CONTINUATION if current => self.receive_headers(frame),
+            RST_STREAM if current => Err(protocol_error("the server reset the stream")),
+            SETTINGS if frame.flags & ACK == 0 => {
+                if frame.payload.len() % 6 != 0 {
+                    return Err(protocol_error("bad SETTINGS length"));
+                }
+                self.apply_settings(&frame.payload)?;
+                self.write_frame(SETTINGS, ACK, 0, &[])
+            }
+            PING if frame.flags & ACK == 0 => self.write_frame(PING, ACK, 0, &frame.payload),
+            GOAWAY => {
+                let p = &frame.payload;
+                if p.len() < 8 {
+                    return Err(protocol_error("bad GOAWAY length"));
+                }
+                let last = u32::from_be_bytes([p[0], p[1], p[2], p[3]]) & 0x7fff_ffff;
+                self.last_stream_id = Some(last);
+                if self.exchange.id > last && !self.exchange.complete {
+                    return Err(protocol_error("the server closed the connection"));
+                }
+                Ok(())
+            }
+            WINDOW_UPDATE => self.receive_window_update(frame),
+            PUSH_PROMISE => Err(protocol_error("push was disabled")),
+            // Frames of earlier streams, PRIORITY, acknowledgements and unknown frame types need
+            // nothing doing.
+            _ => Ok(()),
+        }
+    }
+
+    fn apply_settings(&mut self, payload: &[u8]) -> Result<()> {
+        for setting in payload.chunks_exact(6) {
+            let id = u16::from_be_bytes([setting[0], setting[1]]);
+            let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+            match id {
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    let value = i64::from(value);
+                    if value > MAX_WINDOW_SIZE {
+                        return Err(protocol_error("window too large"));
+                    }
+                    self.exchange.send_window += value - self.peer_initial_window;
+                    self.peer_initial_window = value;
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(16_384..=16_777_215).contains(&value) {
+                        return Err(protocol_error("bad max frame size"));
+                    }
+                    self.peer_max_frame_size = value as usize;
+                }
+                // The encoder never adds to the dynamic table, so the table size doesn't matter.
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn receive_window_update(&mut self, frame: Frame) -> Result<()> {
+        let p = &frame.payload;
+        if p.len() != 4 {
+            return Err(protocol_error("bad WINDOW_UPDATE length"));
+        }
+        let increment = i64::from(u32::from_be_bytes([p[0], p[1], p[2], p[3]]) & 0x7fff_ffff);
+        let window = if frame.stream_id == 0 {
+            &mut self.send_window
+        } else if frame.stream_id == self.exchange.id {
+            &mut self.exchange.send_window
+        } else {
+            return Ok(());
+        };
+        *window += increment;
+        if *window > MAX_WINDOW_SIZE {
+            return Err(protocol_error("window too large"));
+        }
+        Ok(())
+    }
+
+    fn receive_headers(&mut self, frame: Frame) -> Result<()> {
+        if frame.kind == HEADERS {
+            let mut block = frame.unpadded()?;
+            if frame.flags & PRIORITY_FLAG != 0 {
+                if block.len() < 5 {
+                    return Err(protocol_error("bad HEADERS length"));
+                }
+                block = &block[5..];
+            }
+            self.exchange.header_block = Some(block.to_vec());
+            self.exchange.ends_after_headers = frame.flags & END_STREAM != 0;
+        } else {
+            self.exchange
+                .header_block
+                .as_mut()
+                .ok_or_else(|| protocol_error("unexpected CONTINUATION"))?
+                .extend_from_slice(&frame.payload);
+        }
+        let received = self.exchange.header_block.as_ref().map_or(0, Vec::len);
+        self.check_header_size(received)?;
+        if frame.flags & END_HEADERS == 0 {
+            return Ok(());
+        }
+
+        let block = self.exchange.header_block.take().unwrap_or_default();
+        let fields = self
+            .decoder
+            .decode(&block)
+            .map_err(|_| protocol_error("bad header block"))?;
+        // Like for HTTP/1, as `name: value` lines.
+        self.check_header_size(fields.iter().map(|(n, v)| n.len() + v.len() + 4).sum())?;
+        let mut status = None;
+        let mut headers = HttpHeaders::new();
+        for (name, value) in fields {
+            let name = String::from_utf8(name).map_err(|_| protocol_error("bad header name"))?;
+            let value = String::from_utf8(value).map_err(|_| protocol_error("bad header value"))?;
+            if name == ":status" {
+                let code: u16 = value.parse().map_err(|_| protocol_error("bad :status"))?;
+                status = Some(HttpStatus::from_u16(code));
+                continue;
+            }
+            headers.append(canonical_name(&name), value);
+        }
+
+        match (self.exchange.status, status) {
+            // An interim response, like 103 Early Hints.
+            (None, Some(status)) if status.as_u16() < 200 => {}
+            (None, Some(status)) => {
+                self.exchange.status = Some(status);
+                self.exchange.headers = headers;
+            }
+            (None, None) => return Err(protocol_error("response without a status")),
+            (Some(_), _) => self.exchange.trailers = headers,
+        }
+        if self.exchange.ends_after_headers {
+            self.exchange.complete = true;
+        }
+        Ok(())
+    }
+
+    fn check_header_size(&self, size: usize) -> Result<()> {
+        match self.max_header_size {
+            Some(limit) if size > limit => Err(Error::HeadersTooLarge(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Count a DATA payload against the connection's window, and the stream's if `on_stream`.
+    fn take_from_window(&mut self, len: usize, on_stream: bool) -> Result<()> {
+        let len = len as i64;
+        if len > self.receive_window || (on_stream && len > self.exchange.receive_window) {
+            return Err(protocol_error("flow control window exceeded"));
+        }
+        self.receive_window -= len;
+        if on_stream {
+            self.exchange.receive_window -= len;
+        }
+        Ok(())
+    }
+
+    fn receive_data(&mut self, frame: Frame) -> Result<()> {
+        if self.exchange.status.is_none() {
+            return Err(protocol_error("DATA before the response headers"));
+        }
+        // Flow control counts the whole payload, including padding.
+        self.take_from_window(frame.payload.len(), true)?;
+        let data = frame.unpadded()?;
+        self.exchange.received += data.len() as u64;
+        if let Some(limit) = self.max_response_size {
+            if self.exchange.received > limit {
+                return Err(Error::BodyTooLarge(limit.try_into().unwrap_or(usize::MAX)));
+            }
+        }
+        self.exchange.body.extend_from_slice(data);
+        let padding = frame.payload.len() - data.len();
+        if frame.flags & END_STREAM != 0 {
+            self.exchange.complete = true;
+        }
+        // Padding is never read, so it is given back right away.
+        self.release(padding, true)
+    }
+}
+
+/// The body of a response from `Http2Connection::send_request`, read from the connection as the
+/// server sends it. No more than a flow control window of it is held in memory, as the server
+/// may only send more of it as it is read.
+///
+/// *This struct is available if http_io is built with the `"http2"` feature.*
+pub struct Http2Body<C> {
+    connection: C,
+    trailers: Option<HttpHeaders>,
+    slot: Option<TrailerSlot>,
+}
+
+impl<C> Http2Body<C> {
+    pub(super) fn new(connection: C) -> Self {
+        Http2Body {
+            connection,
+            trailers: None,
+            slot: None,
+        }
+    }
+
+    /// Also leave the trailers in the slot once the body ends.
+    pub(super) fn with_trailer_slot(mut self, slot: TrailerSlot) -> Self {
+        self.slot = Some(slot);
+        self
+    }
+
+    /// The trailers sent after the body, once it was read to the end. They are empty if the
+    /// server didn't send any.
+    pub fn trailers(&self) -> Option<&HttpHeaders> {
+        self.trailers.as_ref()
+    }
+}
+
+impl<S: Read + Write, C: DerefMut<Target = Http2Connection<S>>> Read for Http2Body<C> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.connection.read_body(buffer).map_err(read_error)?;
+        if read == 0 && !buffer.is_empty() && self.trailers.is_none() {
+            let trailers = mem::take(&mut self.connection.exchange.trailers);
+            if let Some(slot) = &self.slot {
+                *slot.borrow_mut() = Some(trailers.clone());
+            }
+            self.trailers = Some(trailers);
+        }
+        Ok(read)
+    }
+}
@@ -0,0 +1,65 @@
+//! Limiting the size of response bodies.
+
+use core::convert::TryFrom;
+use core2::io;
+
+/// Reads from the inner reader, failing with an `io::Error` of kind `InvalidData` once it has
+/// returned more than `limit` bytes.
+pub(super) struct SizeLimit<R> {
+    inner: R,
+    read: u64,
+    limit: u64,
+}
+
+impl<R: io::Read> SizeLimit<R> {
+    pub fn new(inner: R, limit: u64) -> Self {
+        SizeLimit {
+            inner,
+            read: 0,
+            limit,
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for SizeLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Read at most one byte past the limit, to tell whether there is any more.
+        let allowed = self.limit.saturating_sub(self.read).saturating_add(1);
+        let length = buf
+            .len()
+            .min(usize::try_from(allowed).unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..length])?;
+        self.read += read as u64;
+        if self.read > self.limit {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "response body longer than the limit",
+            ));
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SizeLimit;
+    use core2::io::{self, Read};
+
+    #[test]
+    fn within_limit() {
+        let mut body = String::new();
+        SizeLimit::new(io::Cursor::new(&b"hello"[..]), 5)
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "hello");
+    }
+
+    #[test]
+    fn over_limit() {
+        let mut body = String::new();
+        let error = SizeLimit::new(io::Cursor::new(&b"hello!"[..]), 5)
+            .read_to_string(&mut body)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,20 @@
+//! Hooks run around the requests an `HttpClient` sends.
+
+use crate::protocol::{HttpHeaders, HttpMethod, HttpStatus};
+use crate::url::Url;
+
+/// Hooks an `HttpClient` runs around every request it sends, to add headers like an
+/// `Authorization`, record timings or rewrite urls in one place. See `HttpClient::add_middleware`.
+///
+/// Both methods do nothing by default, so implement only the ones needed.
+pub trait Middleware {
+    /// Called before the request is sent, once the client's default headers and cookies were
+    /// added. Changing the url sends the request to it instead, with its path and query as the
+    /// request target.
+    fn before_request(&mut self, _method: HttpMethod, _url: &mut Url, _headers: &mut HttpHeaders) {}
+
+    /// Called once the status and headers of the response were read, before the body. It is only
+    /// called for requests made with `HttpClient::send`, as the responses to `get` and `put` are
+    /// read by the caller.
+    fn after_response(&mut self, _url: &Url, _status: HttpStatus, _headers: &HttpHeaders) {}
+}
@@ -0,0 +1,190 @@
+//! `multipart/form-data` request bodies (RFC 7578).
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core2::io;
+
+/// A `multipart/form-data` body made of text fields and files, like a submitted HTML form with
+/// file inputs. It reads as the encoded body, streaming the files from their readers as it goes.
+///
+/// See `HttpRequestBuilder::multipart`.
+///
+/// # Example
+/// ```rust,ignore
+/// use http_io::client::{HttpRequestBuilder, Multipart};
+///
+/// let form = Multipart::new()
+///     .text("description", "holiday")
+///     .file("photo", "beach.jpg", "image/jpeg", std::fs::File::open("beach.jpg")?);
+/// let response = HttpRequestBuilder::post("http://example.com/upload")?
+///     .multipart(form)
+///     .execute()?;
+/// ```
+pub struct Multipart<'a> {
+    boundary: String,
+    readers: Vec<Box<dyn io::Read + 'a>>,
+    current: usize,
+    finished: bool,
+}
+
+/// A boundary which is very unlikely to appear in any of the parts.
+fn generate_boundary() -> String {
+    format!(
+        "http_io-boundary-{:016x}{:016x}",
+        super::random_u64(),
+        super::random_u64()
+    )
+}
+
+/// Quote a name or file name for the `Content-Disposition` header, percent-encoding the
+/// characters which would end the quoted string or the header like browsers do.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("%22"),
+            '\r' => quoted.push_str("%0D"),
+            '\n' => quoted.push_str("%0A"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl<'a> Default for Multipart<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Multipart<'a> {
+    /// Create an empty `Multipart` with a randomly generated boundary.
+    pub fn new() -> Self {
+        Multipart {
+            boundary: generate_boundary(),
+            readers: Vec::new(),
+            current: 0,
+            finished: false,
+        }
+    }
+
+    /// Add a text field.
+    pub fn text<N: AsRef<str>, V: Into<String>>(self, name: N, value: V) -> Self {
+        let headers = format!(
+            "Content-Disposition: form-data; name={}",
+            quote(name.as_ref())
+        );
+        self.part(headers, io::Cursor::new(value.into().into_bytes()))
+    }
+
+    /// Add a file, read from `reader` when the body is sent.
+    pub fn file<N: AsRef<str>, F: AsRef<str>, R: io::Read + 'a>(
+        self,
+        name: N,
+        file_name: F,
+        content_type: &str,
+        reader: R,
+    ) -> Self {
+        let headers = format!(
+            "Content-Disposition: form-data; name={}; filename={}\r\nContent-Type: {}",
+            quote(name.as_ref()),
+            quote(file_name.as_ref()),
+            content_type
+        );
+        self.part(headers, reader)
+    }
+
+    fn part<R: io::Read + 'a>(mut self, headers: String, body: R) -> Self {
+        let head = format!("--{}\r\n{}\r\n\r\n", self.boundary, headers);
+        self.readers
+            .push(Box::new(io::Cursor::new(head.into_bytes())));
+        self.readers.push(Box::new(body));
+        self.readers.push(Box::new(io::Cursor::new(&b"\r\n"[..])));
+        self
+    }
+
+    /// The boundary separating the parts.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// The `Content-Type` header value to send with the body.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+}
+
+impl<'a> io::Read for Multipart<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.current == self.readers.len() {
+                if self.finished {
+                    return Ok(0);
+                }
+                let close = format!("--{}--\r\n", self.boundary);
+                self.readers
+                    .push(Box::new(io::Cursor::new(close.into_bytes())));
+                self.finished = true;
+            }
+            let read = self.readers[self.current].read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            self.current += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Multipart;
+    use core2::io::{self, Read};
+
+    fn encode(mut form: Multipart) -> String {
+        form.boundary = "XyZ".into();
+        let mut output = String::new();
+        form.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn text_and_file() {
+        let form = Multipart::new().text("title", "Notes").file(
+            "upload",
+            "notes.txt",
+            "text/plain",
+            io::Cursor::new(&b"line 1\nline 2"[..]),
+        );
+        assert_eq!(
+            encode(form),
+            "--XyZ\r\n\
+             Content-Disposition: form-data; name=\"title\"\r\n\
+             \r\n\
+             Notes\r\n\
+             --XyZ\r\n\
+             Content-Disposition: form-data; name=\"upload\"; filename=\"notes.txt\"\r\n\
+             Content-Type: text/plain\r\n\
+             \r\n\
+             line 1\nline 2\r\n\
+             --XyZ--\r\n"
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(encode(Multipart::new()), "--XyZ--\r\n");
+    }
+
+    #[test]
+    fn names_quoted() {
+        let form = Multipart::new().text("a\"b\r\nc", "");
+        assert!(encode(form).contains("name=\"a%22b%0D%0Ac\"\r\n"));
+    }
+
+    #[test]
+    fn boundaries_differ() {
+        assert_ne!(Multipart::new().boundary(), Multipart::new().boundary());
+    }
+}
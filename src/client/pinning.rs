@@ -0,0 +1,246 @@
+//! Certificate pinning for HTTPS requests.
+
+use crate::error::{Error, Result};
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PinKind {
+    Certificate,
+    PublicKey,
+}
+
+#[derive(Debug, Clone)]
+struct Pin {
+    host: String,
+    kind: PinKind,
+    sha256: [u8; 32],
+}
+
+/// The certificates or public keys servers are expected to present, by host.
+///
+/// A host with pins is only trusted if its certificate, or another certificate in the chain built
+/// when verifying it, matches one of its pins. Other certificates the host presents aren't
+/// considered, since it can present any certificate it likes. When verification is skipped, and
+/// with rustls, which doesn't give the chain it built, only the host's certificate is considered.
+/// Hosts without pins aren't affected. Pinning is checked in addition to the usual certificate
+/// verification.
+///
+/// See `HttpRequestBuilder::certificate_pins`.
+#[derive(Debug, Clone, Default)]
+pub struct CertificatePins {
+    pins: Vec<Pin>,
+}
+
+impl CertificatePins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pin a certificate for the host by the SHA-256 hash of its DER encoding.
+    pub fn add_certificate_sha256<S: Into<String>>(&mut self, host: S, sha256: [u8; 32]) {
+        self.add(host.into(), PinKind::Certificate, sha256);
+    }
+
+    /// Pin a public key for the host by the SHA-256 hash of its DER encoded SubjectPublicKeyInfo,
+    /// like `pin-sha256` in HTTP Public Key Pinning. Unlike certificate pins, these keep matching
+    /// when a certificate is renewed with the same key.
+    pub fn add_public_key_sha256<S: Into<String>>(&mut self, host: S, sha256: [u8; 32]) {
+        self.add(host.into(), PinKind::PublicKey, sha256);
+    }
+
+    fn add(&mut self, host: String, kind: PinKind, sha256: [u8; 32]) {
+        self.pins.push(Pin { host, kind, sha256 });
+    }
+
+    /// Check the host's DER encoded certificates against its pins: its own certificate followed by
+    /// the rest of the chain built when verifying it, or only its own certificate if the chain
+    /// wasn't verified. This is done for requests sent using openssl or rustls; it can be called
+    /// from a custom verifier for other TLS implementations.
+    pub fn verify<'c, I: IntoIterator<Item = &'c [u8]>>(&self, host: &str, chain: I) -> Result<()> {
+        let pins: Vec<&Pin> = self
+            .pins
+            .iter()
+            .filter(|pin| pin.host.eq_ignore_ascii_case(host))
+            .collect();
+        if pins.is_empty() {
+            return Ok(());
+        }
+
+        for certificate in chain {
+            let certificate_hash = sha256(certificate);
+            let public_key_hash = subject_public_key_info(certificate).map(sha256);
+            let matches = pins.iter().any(|pin| match pin.kind {
+                PinKind::Certificate => pin.sha256 == certificate_hash,
+                PinKind::PublicKey => Some(pin.sha256) == public_key_hash,
+            });
+            if matches {
+                return Ok(());
+            }
+        }
+        Err(Error::CertificatePinMismatch(host.into()))
+    }
+}
+
+/// Split the DER element at the start of the input into its tag, its whole encoding and its
+/// contents, returning them with the rest of the input.
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8], &[u8])> {
+    let tag = *input.first()?;
+    let first = *input.get(1)? as usize;
+    let (header, length) = if first < 0x80 {
+        (2, first)
+    } else {
+        let count = first & 0x7f;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let bytes = input.get(2..2 + count)?;
+        let length = bytes.iter().fold(0, |length, &b| length << 8 | b as usize);
+        (2 + count, length)
+    };
+    let end = header.checked_add(length)?;
+    let whole = input.get(..end)?;
+    Some((tag, whole, &whole[header..], &input[end..]))
+}
+
+/// The DER encoded SubjectPublicKeyInfo of a DER encoded X.509 certificate.
+fn subject_public_key_info(certificate: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION: u8 = 0xa0;
+
+    let (tag, _, certificate, _) = der_element(certificate)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (tag, _, mut fields, _) = der_element(certificate)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    if *fields.first()? == VERSION {
+        fields = der_element(fields)?.3;
+    }
+    // Skip the serial number, signature algorithm, issuer, validity and subject.
+    for _ in 0..5 {
+        fields = der_element(fields)?.3;
+    }
+    let (tag, spki, _, _) = der_element(fields)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    Some(spki)
+}
+
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// The SHA-256 hash of the data.
+pub(super) fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(ROUND_CONSTANTS[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    let mut hash = [0; 32];
+    for (i, word) in state.iter().enumerate() {
+        hash[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{der_element, sha256};
+
+    fn hex(hash: [u8; 32]) -> String {
+        hash.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn sha256_vectors() {
+        assert_eq!(
+            hex(sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn der_lengths() {
+        let (tag, whole, contents, rest) = der_element(&[0x02, 0x01, 0x05, 0xff]).unwrap();
+        assert_eq!(
+            (tag, whole, contents, rest),
+            (2, &[2, 1, 5][..], &[5][..], &[0xff][..])
+        );
+
+        let mut long = vec![0x04, 0x81, 0x80];
+        long.extend_from_slice(&[0; 0x80]);
+        let (_, _, contents, rest) = der_element(&long).unwrap();
+        assert_eq!((contents.len(), rest.len()), (0x80, 0));
+
+        assert!(der_element(&[0x04, 0x05, 0x00]).is_none());
+    }
+}
@@ -0,0 +1,66 @@
+//! Reporting the progress of request and response bodies.
+
+use super::ProgressCallback;
+use core2::io;
+
+/// Reads from the inner reader, calling the callback with the number of bytes read so far after
+/// each read which returns some.
+pub(super) struct Progress<R> {
+    inner: R,
+    transferred: u64,
+    total: Option<u64>,
+    callback: Option<ProgressCallback>,
+}
+
+impl<R: io::Read> Progress<R> {
+    /// Report reads from `inner`, of which `total` bytes are expected if known, to `callback` if
+    /// there is one.
+    pub fn new(inner: R, total: Option<u64>, callback: Option<ProgressCallback>) -> Self {
+        Progress {
+            inner,
+            transferred: 0,
+            total,
+            callback,
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for Progress<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            self.transferred += read as u64;
+            if let Some(callback) = &self.callback {
+                callback(self.transferred, self.total);
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Progress;
+    use core2::io::{self, Read};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn reports_each_read() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let recorded = calls.clone();
+        let mut reader = Progress::new(
+            io::Cursor::new(&b"hello world"[..]),
+            Some(11),
+            Some(Rc::new(move |transferred, total| {
+                recorded.borrow_mut().push((transferred, total))
+            })),
+        );
+        let mut buffer = [0; 4];
+        while reader.read(&mut buffer).unwrap() > 0 {}
+        assert_eq!(
+            *calls.borrow(),
+            vec![(4, Some(11)), (8, Some(11)), (11, Some(11))]
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Resuming downloads with range requests (RFC 7233).
+
+use crate::error::{Error, Result};
+#[cfg(not(feature = "std"))]
+use alloc::format;
+
+/// A `Content-Range` header value of a 206 Partial Content or 416 Range Not Satisfiable response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ContentRange {
+    /// The first and last byte positions of the part sent, if any.
+    pub range: Option<(u64, u64)>,
+    /// The length of the whole representation, if the server knows it.
+    pub length: Option<u64>,
+}
+
+impl ContentRange {
+    /// Parse a value like `bytes 100-199/1000`, `bytes 100-199/*` or `bytes */1000`.
+    pub fn parse(value: &str) -> Result<Self> {
+        let invalid = || Error::ParseError(format!("invalid Content-Range '{}'", value));
+        let rest = value.trim().strip_prefix("bytes ").ok_or_else(invalid)?;
+        let (range, length) = rest.split_once('/').ok_or_else(invalid)?;
+        let length = match length {
+            "*" => None,
+            length => Some(length.parse::<u64>().map_err(|_| invalid())?),
+        };
+        let range = match range {
+            "*" => None,
+            range => {
+                let (first, last) = range.split_once('-').ok_or_else(invalid)?;
+                let first = first.parse::<u64>().map_err(|_| invalid())?;
+                let last = last.parse::<u64>().map_err(|_| invalid())?;
+                if last < first || length.map_or(false, |length| last >= length) {
+                    return Err(invalid());
+                }
+                Some((first, last))
+            }
+        };
+        if range.is_none() && length.is_none() {
+            return Err(invalid());
+        }
+        Ok(ContentRange { range, length })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContentRange;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            ContentRange::parse("bytes 100-199/1000").unwrap(),
+            ContentRange {
+                range: Some((100, 199)),
+                length: Some(1000)
+            }
+        );
+        assert_eq!(
+            ContentRange::parse("bytes 100-199/*").unwrap(),
+            ContentRange {
+                range: Some((100, 199)),
+                length: None
+            }
+        );
+        assert_eq!(
+            ContentRange::parse("bytes */1000").unwrap(),
+            ContentRange {
+                range: None,
+                length: Some(1000)
+            }
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for value in [
+            "",
+            "bytes",
+            "items 1-2/3",
+            "bytes 1-2",
+            "bytes */*",
+            "bytes 2-1/3",
+            "bytes 1-3/3",
+            "bytes a-b/3",
+        ] {
+            assert!(ContentRange::parse(value).is_err(), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Resolving host names to the addresses the client connects to.
+
+use crate::error::{Error, Result};
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::net::{IpAddr, SocketAddr};
+#[cfg(feature = "std")]
+use core::time::Duration;
+use hashbrown::HashMap;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// Resolves host names to socket addresses. See `HttpRequestBuilder::resolver` and
+/// `HttpClient::set_resolver`.
+///
+/// It is implemented for functions and closures taking the host and port, like
+/// `|host: &str, port: u16| Ok(vec![SocketAddr::new(ip, port)])`.
+pub trait Resolve {
+    /// The addresses of the host, to try in order. Fails with `Error::UnknownHost` if it has
+    /// none.
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>>;
+}
+
+impl<F: Fn(&str, u16) -> Result<Vec<SocketAddr>>> Resolve for F {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        self(host, port)
+    }
+}
+
+/// Resolves hosts using the operating system, like `std::net::ToSocketAddrs`. IP address literals
+/// resolve to themselves. This is the default.
+///
+/// *This struct is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+#[cfg(feature = "std")]
+impl Resolve for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        let name = host.trim_start_matches('[').trim_end_matches(']');
+        let addrs: Vec<SocketAddr> =
+            std::net::ToSocketAddrs::to_socket_addrs(&(name, port))?.collect();
+        if addrs.is_empty() {
+            return Err(Error::UnknownHost(host.into()));
+        }
+        Ok(addrs)
+    }
+}
+
+/// Resolves hosts using a fixed table of addresses, for split-horizon setups, tests or devices
+/// without DNS. Hosts missing from the table are given to the fallback resolver if there is one.
+/// IP address literals resolve to themselves.
+#[derive(Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+    fallback: Option<Box<dyn Resolve>>,
+}
+
+impl StaticResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve the host to the given address, in addition to any it was already given. Host
+    /// names are compared ignoring case.
+    pub fn add<S: AsRef<str>>(mut self, host: S, addr: IpAddr) -> Self {
+        self.hosts
+            .entry(host.as_ref().to_ascii_lowercase())
+            .or_insert_with(Vec::new)
+            .push(addr);
+        self
+    }
+
+    /// Resolve hosts missing from the table with the given resolver.
+    pub fn fallback<R: Resolve + 'static>(mut self, resolver: R) -> Self {
+        self.fallback = Some(Box::new(resolver));
+        self
+    }
+}
+
+impl Resolve for StaticResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = literal.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+        match (self.hosts.get(&host.to_ascii_lowercase()), &self.fallback) {
+            (Some(ips), _) => Ok(ips.iter().map(|&ip| SocketAddr::new(ip, port)).collect()),
+            (None, Some(fallback)) => fallback.resolve(host, port),
+            (None, None) => Err(Error::UnknownHost(host.into())),
+        }
+    }
+}
+
+/// The addresses `HttpClient` resolved hosts and ports to, kept until they are `ttl` old so that
+/// requests to the same host don't resolve it again.
+#[cfg(feature = "std")]
+pub(super) struct DnsCache<A> {
+    pub ttl: Duration,
+    entries: HashMap<(String, u16), (Instant, A)>,
+}
+
+#[cfg(feature = "std")]
+impl<A: Clone> DnsCache<A> {
+    pub fn new(ttl: Duration) -> Self {
+        DnsCache {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// The address the host and port resolved to, unless it has expired.
+    pub fn get(&mut self, host: &str, port: u16) -> Option<A> {
+        let key = (host.to_ascii_lowercase(), port);
+        match self.entries.get(&key) {
+            Some((resolved, addr)) if resolved.elapsed() < self.ttl => Some(addr.clone()),
+            Some(_) => {
+                self.entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&mut self, host: &str, port: u16, addr: A) {
+        if !self.ttl.is_zero() {
+            let key = (host.to_ascii_lowercase(), port);
+            self.entries.insert(key, (Instant::now(), addr));
+        }
+    }
+
+    pub fn remove(&mut self, host: &str, port: u16) {
+        self.entries.remove(&(host.to_ascii_lowercase(), port));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Resolve, StaticResolver};
+    use crate::error::{Error, Result};
+    use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    const INTERNAL: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7));
+
+    #[test]
+    fn static_table() {
+        let resolver = StaticResolver::new()
+            .add("api.example.com", INTERNAL)
+            .add("API.example.com", IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(
+            resolver.resolve("Api.Example.com", 443).unwrap(),
+            vec![
+                SocketAddr::new(INTERNAL, 443),
+                SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 443)
+            ]
+        );
+        assert!(matches!(
+            resolver.resolve("example.com", 80),
+            Err(Error::UnknownHost(h)) if h == "example.com"
+        ));
+    }
+
+    #[test]
+    fn literals() {
+        let resolver = StaticResolver::new();
+        assert_eq!(
+            resolver.resolve("127.0.0.1", 80).unwrap(),
+            vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 80)]
+        );
+        assert_eq!(
+            resolver.resolve("[::1]", 80).unwrap(),
+            vec![SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 80)]
+        );
+    }
+
+    #[test]
+    fn fallback() {
+        let resolver = StaticResolver::new()
+            .add("api.example.com", INTERNAL)
+            .fallback(|_: &str, port: u16| -> Result<Vec<SocketAddr>> {
+                Ok(vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port)])
+            });
+        assert_eq!(
+            resolver.resolve("example.com", 8080).unwrap(),
+            vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8080)]
+        );
+        assert_eq!(
+            resolver.resolve("api.example.com", 8080).unwrap(),
+            vec![SocketAddr::new(INTERNAL, 8080)]
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn dns_cache() {
+        use super::DnsCache;
+        use core::time::Duration;
+
+        let mut cache = DnsCache::new(Duration::from_millis(50));
+        cache.insert("Example.com", 80, INTERNAL);
+        assert_eq!(cache.get("example.com", 80), Some(INTERNAL));
+        assert_eq!(cache.get("example.com", 443), None);
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.get("example.com", 80), None);
+
+        cache.insert("example.com", 80, INTERNAL);
+        cache.clear();
+        assert_eq!(cache.get("example.com", 80), None);
+
+        cache.ttl = Duration::ZERO;
+        cache.insert("example.com", 80, INTERNAL);
+        assert_eq!(cache.get("example.com", 80), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! Retrying requests which failed for reasons likely to be temporary.
+
+#[cfg(feature = "std")]
+use crate::date;
+use crate::error::Error;
+#[cfg(feature = "std")]
+use crate::protocol::HttpHeaders;
+use crate::protocol::HttpStatus;
+#[cfg(not(feature = "std"))]
+use alloc::{vec, vec::Vec};
+use core::time::Duration;
+
+/// Whether the error is likely to be temporary: the connection failing or timing out.
+fn is_transient(error: &Error) -> bool {
+    match error {
+        Error::Timeout => true,
+        #[cfg(feature = "std")]
+        Error::IoError(e) => matches!(
+            e.kind(),
+            std::io::ErrorKind::ConnectionRefused
+                | std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+                | std::io::ErrorKind::BrokenPipe
+                | std::io::ErrorKind::UnexpectedEof
+                | std::io::ErrorKind::TimedOut
+        ),
+        _ => false,
+    }
+}
+
+/// How long a 429 Too Many Requests or 503 Service Unavailable response asks to wait before
+/// trying again with its `Retry-After` header, either a number of seconds or an HTTP date, given
+/// the current time in seconds since the unix epoch.
+#[cfg(feature = "std")]
+fn retry_after(status: HttpStatus, headers: &HttpHeaders, now: u64) -> Option<Duration> {
+    if status != HttpStatus::TooManyRequests && status != HttpStatus::ServiceUnavailable {
+        return None;
+    }
+    let value = headers.get("Retry-After")?.trim();
+    let seconds = match value.parse::<u64>() {
+        Ok(seconds) => seconds,
+        Err(_) => date::parse_http_date(value)?.saturating_sub(now),
+    };
+    Some(Duration::from_secs(seconds))
+}
+
+/// When and how often to retry a request. See `HttpRequestBuilder::retry`.
+///
+/// Retries wait with exponential backoff: the first waits the initial backoff, and each one after
+/// waits twice as long as the last, up to the maximum backoff. When a 429 Too Many Requests or
+/// 503 Service Unavailable response has a `Retry-After` header, the retry waits as long as it
+/// asks instead.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_retry_after: Duration,
+    statuses: Vec<HttpStatus>,
+    retry_error: fn(&Error) -> bool,
+}
+
+impl RetryPolicy {
+    /// Create a `RetryPolicy` making up to `max_attempts` attempts in total, including the first.
+    ///
+    /// By default it waits 100ms before the first retry and at most 10s between retries, unless
+    /// `Retry-After` asks for up to a minute, and retries connection failures, timeouts and the
+    /// statuses 429, 502, 503 and 504.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_retry_after: Duration::from_secs(60),
+            statuses: vec![
+                HttpStatus::TooManyRequests,
+                HttpStatus::BadGateway,
+                HttpStatus::ServiceUnavailable,
+                HttpStatus::GatewayTimeout,
+            ],
+            retry_error: is_transient,
+        }
+    }
+
+    /// Wait `initial` before the first retry, doubling each time up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Give up and return the response instead of retrying when its `Retry-After` header asks to
+    /// wait longer than `max`.
+    pub fn max_retry_after(mut self, max: Duration) -> Self {
+        self.max_retry_after = max;
+        self
+    }
+
+    /// Retry responses with these statuses, instead of the defaults.
+    pub fn statuses<I: IntoIterator<Item = HttpStatus>>(mut self, statuses: I) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Retry the errors for which the function returns true, instead of connection failures and
+    /// timeouts.
+    pub fn errors(mut self, retry_error: fn(&Error) -> bool) -> Self {
+        self.retry_error = retry_error;
+        self
+    }
+
+    /// Whether to make another attempt after the given one, counting from 1, ended with a
+    /// response with the given status or an error.
+    #[cfg(feature = "std")]
+    pub(super) fn should_retry<T>(
+        &self,
+        attempt: u32,
+        result: &crate::error::Result<T>,
+        status: impl FnOnce(&T) -> HttpStatus,
+    ) -> bool {
+        if attempt >= self.max_attempts {
+            return false;
+        }
+        match result {
+            Ok(response) => self.statuses.contains(&status(response)),
+            Err(error) => (self.retry_error)(error),
+        }
+    }
+
+    /// How long to wait before the retry after the given attempt, counting from 1, which ended
+    /// with a response with the given status and headers or an error. `None` if its
+    /// `Retry-After` asks to wait too long to retry. `now` is the current time in seconds since
+    /// the unix epoch.
+    #[cfg(feature = "std")]
+    pub(super) fn retry_delay(
+        &self,
+        attempt: u32,
+        response: Option<(HttpStatus, &HttpHeaders)>,
+        now: u64,
+    ) -> Option<Duration> {
+        match response.and_then(|(status, headers)| retry_after(status, headers, now)) {
+            Some(wait) if wait > self.max_retry_after => None,
+            Some(wait) => Some(wait),
+            None => Some(self.delay(attempt)),
+        }
+    }
+
+    /// How long to wait before the retry after the given attempt, counting from 1.
+    #[cfg(feature = "std")]
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use crate::error::{Error, Result};
+    use crate::protocol::{HttpHeaders, HttpStatus};
+    use core::time::Duration;
+
+    #[test]
+    fn delays() {
+        let policy =
+            RetryPolicy::new(10).backoff(Duration::from_millis(100), Duration::from_secs(1));
+        let delays: Vec<u128> = (1..7).map(|a| policy.delay(a).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(policy.delay(100), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn attempts() {
+        let policy = RetryPolicy::new(3);
+        let timeout: Result<HttpStatus> = Err(Error::Timeout);
+        assert!(policy.should_retry(1, &timeout, |s| *s));
+        assert!(policy.should_retry(2, &timeout, |s| *s));
+        assert!(!policy.should_retry(3, &timeout, |s| *s));
+    }
+
+    #[test]
+    fn statuses_and_errors() {
+        let policy = RetryPolicy::new(3).statuses(vec![HttpStatus::InternalServerError]);
+        assert!(policy.should_retry(1, &Ok(HttpStatus::InternalServerError), |s| *s));
+        assert!(!policy.should_retry(1, &Ok(HttpStatus::ServiceUnavailable), |s| *s));
+        assert!(!policy.should_retry(1, &Ok(HttpStatus::OK), |s| *s));
+
+        let bad_request: Result<HttpStatus> = Err(Error::BadRequest("no".into()));
+        assert!(!policy.should_retry(1, &bad_request, |s| *s));
+        let policy = policy.errors(|_| true);
+        assert!(policy.should_retry(1, &bad_request, |s| *s));
+    }
+
+    #[test]
+    fn retry_after() {
+        let policy =
+            RetryPolicy::new(3).backoff(Duration::from_millis(100), Duration::from_secs(1));
+        let now = 784_111_777; // Sun, 06 Nov 1994 08:49:37 GMT
+        let mut headers = HttpHeaders::new();
+        headers.insert("Retry-After", "5");
+        let retry_delay =
+            |status, headers: &HttpHeaders| policy.retry_delay(1, Some((status, headers)), now);
+        assert_eq!(
+            retry_delay(HttpStatus::ServiceUnavailable, &headers),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            retry_delay(HttpStatus::TooManyRequests, &headers),
+            Some(Duration::from_secs(5))
+        );
+        // Only 429 and 503 responses are asked about.
+        assert_eq!(
+            retry_delay(HttpStatus::BadGateway, &headers),
+            Some(Duration::from_millis(100))
+        );
+
+        headers.insert("Retry-After", "Sun, 06 Nov 1994 08:50:07 GMT");
+        assert_eq!(
+            retry_delay(HttpStatus::ServiceUnavailable, &headers),
+            Some(Duration::from_secs(30))
+        );
+        headers.insert("Retry-After", "Sun, 06 Nov 1994 08:00:00 GMT");
+        assert_eq!(
+            retry_delay(HttpStatus::ServiceUnavailable, &headers),
+            Some(Duration::ZERO)
+        );
+        headers.insert("Retry-After", "soon");
+        assert_eq!(
+            retry_delay(HttpStatus::ServiceUnavailable, &headers),
+            Some(Duration::from_millis(100))
+        );
+        headers.insert("Retry-After", "3600");
+        assert_eq!(retry_delay(HttpStatus::ServiceUnavailable, &headers), None);
+        assert_eq!(
+            policy.retry_delay(2, None, now),
+            Some(Duration::from_millis(200))
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Client streams over smoltcp TCP sockets, for bare-metal devices.
+
+use ::smoltcp::iface::{Interface, SocketHandle, SocketSet};
+use ::smoltcp::phy::Device;
+use ::smoltcp::socket::tcp;
+use ::smoltcp::time::{Duration, Instant};
+use ::smoltcp::wire::IpEndpoint;
+use core2::io;
+
+/// A `Read + Write` stream over a smoltcp TCP socket. Pass it to `HttpRequestBuilder::send`.
+///
+/// smoltcp sockets only make progress when the interface is polled, so the stream borrows the
+/// interface, the device and the socket set, and polls them itself while it waits for a
+/// connection, for data to read or for room in the send buffer. Reads and writes busy-wait until
+/// they can progress or the timeout set with `set_timeout` is up. Other sockets in the set are
+/// serviced by the same polls. `clock` gives the current time, as for `Interface::poll`.
+///
+/// *This struct is available if http_io is built with the `"smoltcp"` feature.*
+///
+/// # Example
+/// ```rust,ignore
+/// use http_io::client::{HttpRequestBuilder, SmoltcpStream};
+/// use smoltcp::wire::{IpAddress, IpEndpoint};
+///
+/// let handle = sockets.add(tcp::Socket::new(rx_buffer, tx_buffer));
+/// let mut stream = SmoltcpStream::new(&mut iface, &mut device, &mut sockets, handle, now);
+/// stream.set_timeout(Some(Duration::from_secs(10)));
+/// stream.connect(IpEndpoint::new(IpAddress::v4(192, 168, 1, 10), 80), 49152)?;
+/// let response = HttpRequestBuilder::get("http://192.168.1.10/")?.send(stream)?.finish()?;
+/// ```
+pub struct SmoltcpStream<'a, 'b, D: Device> {
+    iface: &'a mut Interface,
+    device: &'a mut D,
+    sockets: &'a mut SocketSet<'b>,
+    handle: SocketHandle,
+    clock: fn() -> Instant,
+    timeout: Option<Duration>,
+}
+
+impl<'a, 'b, D: Device> SmoltcpStream<'a, 'b, D> {
+    /// Use the TCP socket with the given handle in `sockets`. It may be connected already, or be
+    /// connected with `connect`.
+    pub fn new(
+        iface: &'a mut Interface,
+        device: &'a mut D,
+        sockets: &'a mut SocketSet<'b>,
+        handle: SocketHandle,
+        clock: fn() -> Instant,
+    ) -> Self {
+        SmoltcpStream {
+            iface,
+            device,
+            sockets,
+            handle,
+            clock,
+            timeout: None,
+        }
+    }
+
+    /// Make connecting, reads, writes and closing give up with `ErrorKind::TimedOut` after the
+    /// given time, or never if `None`, which is the default.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Connect the socket to the server at `remote` from the given local port, waiting until the
+    /// connection is established.
+    pub fn connect(&mut self, remote: IpEndpoint, local_port: u16) -> io::Result<()> {
+        self.sockets
+            .get_mut::<tcp::Socket>(self.handle)
+            .connect(self.iface.context(), remote, local_port)
+            .map_err(|e| match e {
+                tcp::ConnectError::InvalidState => {
+                    io::Error::new(io::ErrorKind::AlreadyExists, "socket already open")
+                }
+                tcp::ConnectError::Unaddressable => {
+                    io::Error::new(io::ErrorKind::AddrNotAvailable, "address unusable")
+                }
+            })?;
+        self.poll_until(|socket| {
+            if socket.may_send() {
+                Some(Ok(()))
+            } else if !socket.is_open() {
+                Some(Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    "connection refused",
+                )))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Close the connection, waiting until the data sent was acknowledged and the server closed
+    /// its side too.
+    pub fn close(mut self) -> io::Result<()> {
+        self.sockets.get_mut::<tcp::Socket>(self.handle).close();
+        let closed = self.poll_until(|socket| match socket.state() {
+            tcp::State::Closed | tcp::State::TimeWait => Some(Ok(())),
+            _ => None,
+        });
+        if closed.is_err() {
+            self.sockets.get_mut::<tcp::Socket>(self.handle).abort();
+            self.poll();
+        }
+        closed
+    }
+
+    fn poll(&mut self) {
+        self.iface
+            .poll((self.clock)(), &mut *self.device, &mut *self.sockets);
+    }
+
+    /// Poll the interface until `ready` gives a result for the socket, or the timeout is up.
+    fn poll_until<T>(
+        &mut self,
+        mut ready: impl FnMut(&mut tcp::Socket<'b>) -> Option<io::Result<T>>,
+    ) -> io::Result<T> {
+        let deadline = self.timeout.map(|timeout| (self.clock)() + timeout);
+        loop {
+            self.poll();
+            if let Some(result) = ready(self.sockets.get_mut::<tcp::Socket>(self.handle)) {
+                self.poll();
+                return result;
+            }
+            if deadline.map_or(false, |deadline| (self.clock)() >= deadline) {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+            }
+        }
+    }
+}
+
+impl<'a, 'b, D: Device> io::Read for SmoltcpStream<'a, 'b, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.poll_until(|socket| {
+            if socket.can_recv() {
+                Some(socket.recv_slice(buf).map_err(|e| match e {
+                    tcp::RecvError::Finished => {
+                        io::Error::new(io::ErrorKind::UnexpectedEof, "connection finished")
+                    }
+                    tcp::RecvError::InvalidState => {
+                        io::Error::new(io::ErrorKind::NotConnected, "socket not connected")
+                    }
+                }))
+            } else if !socket.may_recv() {
+                // The server closed its side, or the connection is gone.
+                Some(Ok(0))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl<'a, 'b, D: Device> io::Write for SmoltcpStream<'a, 'b, D> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.poll_until(|socket| {
+            if socket.can_send() {
+                Some(socket.send_slice(buf).map_err(|_| {
+                    io::Error::new(io::ErrorKind::NotConnected, "socket not connected")
+                }))
+            } else if !socket.may_send() {
+                Some(Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "connection closed",
+                )))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Wait until all the data written was sent and acknowledged.
+    fn flush(&mut self) -> io::Result<()> {
+        self.poll_until(|socket| {
+            if socket.send_queue() == 0 {
+                Some(Ok(()))
+            } else if !socket.may_send() {
+                Some(Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "connection closed",
+                )))
+            } else {
+                None
+            }
+        })
+    }
+}
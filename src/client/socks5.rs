@@ -0,0 +1,226 @@
+//! The client side of the SOCKS5 protocol (RFC 1928), with username/password authentication
+//! (RFC 1929).
+
+use crate::error::{Error, Result};
+#[cfg(not(feature = "std"))]
+use alloc::{format, string::String, vec, vec::Vec};
+use core::net::{Ipv4Addr, Ipv6Addr};
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT: u8 = 0x01;
+const ADDRESS_IPV4: u8 = 0x01;
+const ADDRESS_DOMAIN: u8 = 0x03;
+const ADDRESS_IPV6: u8 = 0x04;
+
+fn socks_error<S: Into<String>>(message: S) -> Error {
+    Error::Socks5Error(message.into())
+}
+
+fn reply_message(reply: u8) -> &'static str {
+    match reply {
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown failure",
+    }
+}
+
+fn read_byte<S: core2::io::Read>(stream: &mut S) -> Result<u8> {
+    let mut byte = [0; 1];
+    stream.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn authenticate<S: core2::io::Read + core2::io::Write>(
+    stream: &mut S,
+    username: &str,
+    password: &str,
+) -> Result<()> {
+    if username.len() > 255 || password.len() > 255 {
+        return Err(socks_error("username or password too long"));
+    }
+    let mut request = Vec::with_capacity(3 + username.len() + password.len());
+    request.push(1);
+    request.push(username.len() as u8);
+    request.extend_from_slice(username.as_bytes());
+    request.push(password.len() as u8);
+    request.extend_from_slice(password.as_bytes());
+    stream.write_all(&request)?;
+    stream.flush()?;
+
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(socks_error("authentication failed"));
+    }
+    Ok(())
+}
+
+/// Ask the SOCKS5 server on the other end of the stream to connect to the given host and port.
+/// Once this returns, the stream is connected to the host.
+pub(super) fn connect<S: core2::io::Read + core2::io::Write>(
+    mut stream: S,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<()> {
+    let method = if credentials.is_some() {
+        USERNAME_PASSWORD
+    } else {
+        NO_AUTHENTICATION
+    };
+    stream.write_all(&[VERSION, 1, method])?;
+    stream.flush()?;
+
+    let mut choice = [0; 2];
+    stream.read_exact(&mut choice)?;
+    if choice[0] != VERSION {
+        return Err(socks_error("not a SOCKS5 server"));
+    }
+    match (choice[1], credentials) {
+        (NO_AUTHENTICATION, None) => {}
+        (USERNAME_PASSWORD, Some((username, password))) => {
+            authenticate(&mut stream, username, password)?
+        }
+        (NO_ACCEPTABLE_METHODS, _) => {
+            return Err(socks_error("no acceptable authentication methods"))
+        }
+        (method, _) => {
+            return Err(socks_error(format!(
+                "unexpected authentication method {}",
+                method
+            )))
+        }
+    }
+
+    let mut request = vec![VERSION, CONNECT, 0];
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        request.push(ADDRESS_IPV4);
+        request.extend_from_slice(&ip.octets());
+    } else if let Ok(ip) = host.parse::<Ipv6Addr>() {
+        request.push(ADDRESS_IPV6);
+        request.extend_from_slice(&ip.octets());
+    } else if host.len() <= 255 {
+        request.push(ADDRESS_DOMAIN);
+        request.push(host.len() as u8);
+        request.extend_from_slice(host.as_bytes());
+    } else {
+        return Err(socks_error("host name too long"));
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+    stream.flush()?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != VERSION {
+        return Err(socks_error("not a SOCKS5 server"));
+    }
+    if reply[1] != 0 {
+        return Err(socks_error(reply_message(reply[1])));
+    }
+
+    // The reply ends with the address the server connected from, which isn't needed.
+    let address_length = match reply[3] {
+        ADDRESS_IPV4 => 4,
+        ADDRESS_IPV6 => 16,
+        ADDRESS_DOMAIN => read_byte(&mut stream)? as usize,
+        t => return Err(socks_error(format!("unknown address type {}", t))),
+    };
+    let mut bound_address = vec![0; address_length + 2];
+    stream.read_exact(&mut bound_address)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::connect;
+    use crate::error::Error;
+    use core2::io::{self, Read, Write};
+
+    /// A stream which reads from a script and records what is written to it.
+    struct ScriptedStream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl ScriptedStream {
+        fn new(input: &[u8]) -> Self {
+            ScriptedStream {
+                input: io::Cursor::new(input.to_vec()),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl io::Read for ScriptedStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl io::Write for ScriptedStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const SUCCESS: &[u8] = &[5, 0, 0, 1, 127, 0, 0, 1, 0x1f, 0x90];
+
+    #[test]
+    fn connect_domain() {
+        let mut input = vec![5, 0];
+        input.extend_from_slice(SUCCESS);
+        let mut stream = ScriptedStream::new(&input);
+        connect(&mut stream, "example.com", 80, None).unwrap();
+
+        let mut expected = vec![5, 1, 0, 5, 1, 0, 3, 11];
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&[0, 80]);
+        assert_eq!(stream.output, expected);
+    }
+
+    #[test]
+    fn connect_ip_with_credentials() {
+        let mut input = vec![5, 2, 1, 0];
+        input.extend_from_slice(SUCCESS);
+        let mut stream = ScriptedStream::new(&input);
+        connect(&mut stream, "10.0.0.1", 443, Some(("user", "pw"))).unwrap();
+
+        let mut expected = vec![5, 1, 2, 1, 4];
+        expected.extend_from_slice(b"user");
+        expected.push(2);
+        expected.extend_from_slice(b"pw");
+        expected.extend_from_slice(&[5, 1, 0, 1, 10, 0, 0, 1, 1, 187]);
+        assert_eq!(stream.output, expected);
+    }
+
+    #[test]
+    fn authentication_failed() {
+        let mut stream = ScriptedStream::new(&[5, 2, 1, 1]);
+        let result = connect(&mut stream, "example.com", 80, Some(("user", "wrong")));
+        assert!(matches!(result, Err(Error::Socks5Error(_))));
+    }
+
+    #[test]
+    fn connection_refused() {
+        let mut stream = ScriptedStream::new(&[5, 0, 5, 5, 0, 1, 0, 0, 0, 0, 0, 0]);
+        match connect(&mut stream, "example.com", 80, None) {
+            Err(Error::Socks5Error(message)) => assert_eq!(message, "connection refused"),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
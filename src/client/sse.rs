@@ -0,0 +1,287 @@
+//! Server-Sent Events, the `text/event-stream` format of the HTML standard.
+
+use super::HttpRequestBuilder;
+use crate::error::{Error, Result};
+use crate::protocol::{HttpBody, HttpStatus};
+use core::time::Duration;
+use core2::io::{self, BufRead};
+
+/// An event received from an `EventStream`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The event type, `message` unless the server gave another in an `event` field.
+    pub event: String,
+    /// The `data` fields of the event, joined by newlines.
+    pub data: String,
+    /// The last event ID the server sent, in this event or an earlier one.
+    pub id: Option<String>,
+    /// The reconnection time the server sent with this event, if any.
+    pub retry: Option<Duration>,
+}
+
+/// Parses the lines of an event stream into events.
+#[derive(Debug)]
+struct EventParser {
+    event: String,
+    data: String,
+    retry: Option<Duration>,
+    last_event_id: String,
+    reconnection_time: Duration,
+}
+
+impl EventParser {
+    fn new() -> Self {
+        EventParser {
+            event: String::new(),
+            data: String::new(),
+            retry: None,
+            last_event_id: String::new(),
+            reconnection_time: Duration::from_secs(3),
+        }
+    }
+
+    /// Forget the fields of an event which was cut off.
+    fn reset(&mut self) {
+        self.event.clear();
+        self.data.clear();
+        self.retry = None;
+    }
+
+    /// Process a line without its line ending, returning the event it ends if any.
+    fn line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            if self.data.is_empty() {
+                self.reset();
+                return None;
+            }
+            self.data.pop();
+            let event = Event {
+                event: match self.event.as_str() {
+                    "" => "message".into(),
+                    event => event.into(),
+                },
+                data: core::mem::take(&mut self.data),
+                id: Some(self.last_event_id.clone()).filter(|id| !id.is_empty()),
+                retry: self.retry,
+            };
+            self.reset();
+            return Some(event);
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = value.into(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = value.into(),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(millis) = value.parse() {
+                    self.reconnection_time = Duration::from_millis(millis);
+                    self.retry = Some(self.reconnection_time);
+                }
+            }
+            // Comments start with a colon, and unknown fields are ignored.
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Read a line ending with LF, CRLF or CR into `line`, without the ending. Returns false at the
+/// end of the stream.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut Vec<u8>, after_cr: &mut bool) -> Result<bool> {
+    line.clear();
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(false);
+        }
+        if *after_cr && buf[0] == b'\n' {
+            reader.consume(1);
+            *after_cr = false;
+            continue;
+        }
+        *after_cr = false;
+        match buf.iter().position(|&b| b == b'\n' || b == b'\r') {
+            Some(end) => {
+                line.extend_from_slice(&buf[..end]);
+                *after_cr = buf[end] == b'\r';
+                reader.consume(end + 1);
+                return Ok(true);
+            }
+            None => {
+                let length = buf.len();
+                line.extend_from_slice(buf);
+                reader.consume(length);
+            }
+        }
+    }
+}
+
+/// An iterator over the events of a `text/event-stream` response. Create it with
+/// `HttpRequestBuilder::event_stream` or `client::events`.
+///
+/// When the connection is closed or lost, it waits the reconnection time, 3 seconds unless the
+/// server sent another in a `retry` field, and sends the request again with the last event ID in
+/// a `Last-Event-ID` header. An error reconnecting is returned, and the next call tries again. The
+/// iterator ends when the server answers 204 No Content, and after a response with any status
+/// other than 200 OK, which is returned as `Error::UnexpectedStatus`.
+///
+/// *This struct is available if http_io is built with the `"std"` feature.*
+pub struct EventStream {
+    builder: HttpRequestBuilder,
+    body: Option<io::BufReader<HttpBody<Box<dyn io::Read>>, 1024>>,
+    parser: EventParser,
+    line: Vec<u8>,
+    after_cr: bool,
+    done: bool,
+}
+
+impl EventStream {
+    pub(super) fn new(builder: HttpRequestBuilder) -> Result<Self> {
+        let mut stream = EventStream {
+            builder,
+            body: None,
+            parser: EventParser::new(),
+            line: Vec::new(),
+            after_cr: false,
+            done: false,
+        };
+        stream.connect()?;
+        Ok(stream)
+    }
+
+    /// The ID of the last event received, sent in the `Last-Event-ID` header when reconnecting.
+    pub fn last_event_id(&self) -> Option<&str> {
+        Some(self.parser.last_event_id.as_str()).filter(|id| !id.is_empty())
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        let mut builder = self.builder.without_body();
+        if let Some(id) = self.last_event_id() {
+            builder = builder.header("Last-Event-ID", id);
+        }
+        let response = builder.execute()?;
+        match response.status {
+            HttpStatus::OK => {
+                self.body = Some(io::BufReader::new(response.body));
+                self.after_cr = false;
+            }
+            HttpStatus::NoContent => self.done = true,
+            status => {
+                self.done = true;
+                return Err(Error::UnexpectedStatus(status));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for EventStream {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.done {
+                return None;
+            }
+            let body = match &mut self.body {
+                Some(body) => body,
+                None => {
+                    std::thread::sleep(self.parser.reconnection_time);
+                    if let Err(e) = self.connect() {
+                        return Some(Err(e));
+                    }
+                    continue;
+                }
+            };
+            match read_line(body, &mut self.line, &mut self.after_cr) {
+                Ok(true) => {
+                    let line = String::from_utf8_lossy(&self.line);
+                    let line = line.strip_prefix('\u{feff}').unwrap_or(&line);
+                    if let Some(event) = self.parser.line(line) {
+                        return Some(Ok(event));
+                    }
+                }
+                // The connection was closed or lost.
+                Ok(false) | Err(_) => {
+                    self.body = None;
+                    self.parser.reset();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_line, Event, EventParser};
+    use core::time::Duration;
+    use core2::io;
+
+    fn parse(stream: &str) -> Vec<Event> {
+        let mut parser = EventParser::new();
+        let mut reader = io::BufReader::<_, 4>::new(stream.as_bytes());
+        let mut line = Vec::new();
+        let mut after_cr = false;
+        let mut events = Vec::new();
+        while read_line(&mut reader, &mut line, &mut after_cr).unwrap() {
+            events.extend(parser.line(core::str::from_utf8(&line).unwrap()));
+        }
+        events
+    }
+
+    #[test]
+    fn events() {
+        let events = parse(
+            ": comment\n\
+             data: first\n\
+             data:second\n\
+             \n\
+             event: update\r\n\
+             id: 7\r\n\
+             retry: 500\r\n\
+             data\r\n\
+             \r\n\
+             data: no id\rid\r\r\
+             data: cut off",
+        );
+        assert_eq!(
+            events,
+            vec![
+                Event {
+                    event: "message".into(),
+                    data: "first\nsecond".into(),
+                    id: None,
+                    retry: None,
+                },
+                Event {
+                    event: "update".into(),
+                    data: "".into(),
+                    id: Some("7".into()),
+                    retry: Some(Duration::from_millis(500)),
+                },
+                Event {
+                    event: "message".into(),
+                    data: "no id".into(),
+                    id: None,
+                    retry: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn blocks_without_data() {
+        let events = parse("event: ignored\nid: 1\nretry: x\n\ndata: a\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "message");
+        assert_eq!(events[0].id.as_deref(), Some("1"));
+        assert_eq!(events[0].retry, None);
+    }
+}
@@ -0,0 +1,140 @@
+//! Hooks reporting the stages of a request, to see where the time goes.
+
+use crate::error::Error;
+#[cfg(not(feature = "std"))]
+use alloc::rc::Rc;
+use core::net::SocketAddr;
+use core2::io;
+#[cfg(feature = "std")]
+use std::rc::Rc;
+
+/// Called as a request made with `HttpRequestBuilder::execute` goes through its stages, to
+/// diagnose slow requests by recording when each happens. See `HttpRequestBuilder::trace`.
+///
+/// All methods do nothing by default, so implement only the ones needed. Requests made with a
+/// `connector` only report the first byte and the end of the body, as the connector does the
+/// rest.
+pub trait Trace {
+    /// Called before resolving the host, the server's or the proxy's.
+    fn dns_start(&self, _host: &str) {}
+
+    /// Called once the host was resolved, with its addresses.
+    fn dns_done(&self, _host: &str, _addrs: &[SocketAddr]) {}
+
+    /// Called before connecting to each of the host's addresses, until one works.
+    fn connect_start(&self, _addr: SocketAddr) {}
+
+    /// Called once connecting to the address succeeded or failed.
+    fn connect_done(&self, _addr: SocketAddr, _result: core::result::Result<(), &Error>) {}
+
+    /// Called before the TLS handshake with the host of an https url.
+    fn tls_start(&self, _host: &str) {}
+
+    /// Called once the TLS handshake succeeded, before the server's certificate is checked
+    /// against the pins and verifier.
+    fn tls_done(&self, _host: &str) {}
+
+    /// Called when the first byte of the response is received.
+    fn first_byte(&self) {}
+
+    /// Called when the end of the response body is read, with its length as given to the
+    /// caller, after any decompression.
+    fn body_done(&self, _length: u64) {}
+}
+
+/// A stream which reports the first byte read from it.
+pub(super) struct TracedStream<S> {
+    inner: S,
+    trace: Option<Rc<dyn Trace>>,
+}
+
+impl<S> TracedStream<S> {
+    pub fn new(inner: S, trace: Option<Rc<dyn Trace>>) -> Self {
+        TracedStream { inner, trace }
+    }
+}
+
+impl<S: io::Read> io::Read for TracedStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            if let Some(trace) = self.trace.take() {
+                trace.first_byte();
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl<S: io::Write> io::Write for TracedStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A body which reports its length once it was read to the end.
+pub(super) struct TracedBody<R> {
+    inner: R,
+    read: u64,
+    trace: Option<Rc<dyn Trace>>,
+}
+
+impl<R: io::Read> TracedBody<R> {
+    pub fn new(inner: R, trace: Rc<dyn Trace>) -> Self {
+        TracedBody {
+            inner,
+            read: 0,
+            trace: Some(trace),
+        }
+    }
+}
+
+impl<R: io::Read> io::Read for TracedBody<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        if read == 0 && !buf.is_empty() {
+            if let Some(trace) = self.trace.take() {
+                trace.body_done(self.read);
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Trace, TracedBody, TracedStream};
+    use core2::io::{self, Read};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Recorder(RefCell<Vec<String>>);
+
+    impl Trace for Recorder {
+        fn first_byte(&self) {
+            self.0.borrow_mut().push("first byte".into());
+        }
+
+        fn body_done(&self, length: u64) {
+            self.0.borrow_mut().push(format!("body done {}", length));
+        }
+    }
+
+    #[test]
+    fn reports_once() {
+        let recorder = Rc::new(Recorder::default());
+        let stream =
+            TracedStream::new(io::Cursor::new(&b"hello world"[..]), Some(recorder.clone()));
+        let mut body = TracedBody::new(stream, recorder.clone());
+        let mut buffer = [0; 4];
+        while body.read(&mut buffer).unwrap() > 0 {}
+        assert_eq!(body.read(&mut buffer).unwrap(), 0);
+        assert_eq!(*recorder.0.borrow(), vec!["first byte", "body done 11"]);
+    }
+}
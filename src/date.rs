@@ -0,0 +1,264 @@
+//! Code for HTTP dates, like those in the `Date` header.
+
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+use core::fmt;
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+/// The full names of the weekdays, which the obsolete RFC 850 format uses.
+const WEEKDAY_NAMES: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A source of the current time, as seconds since the unix epoch.
+pub type Clock = fn() -> u64;
+
+/// A `Clock` reading the system time.
+///
+/// *This function is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+pub fn system_clock() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Convert days since the unix epoch to a (year, month, day) date, with months starting at 1.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Count from 0000-03-01, so leap days fall at the end of each year.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Convert a (year, month, day) date, with months starting at 1, to days since the unix epoch.
+/// Returns `None` if the number of days doesn't fit.
+fn days_from_civil(year: u64, month: u64, day: u64) -> Option<u64> {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era.checked_mul(146_097)?
+        .checked_add(day_of_era)?
+        .checked_sub(719_468)
+}
+
+/// Parse an HTTP date to seconds since the unix epoch. Returns `None` if it isn't valid, is
+/// before the epoch or is after the year 9999.
+///
+/// Dates should be in the IMF-fixdate format, like `Sun, 06 Nov 1994 08:49:37 GMT`, but the two
+/// obsolete formats RFC 7231 section 7.1.1.1 asks recipients to accept are too: RFC 850 dates,
+/// like `Sunday, 06-Nov-94 08:49:37 GMT`, and asctime dates, like `Sun Nov  6 08:49:37 1994`.
+/// Two-digit years are taken to be from 1970 to 2069. The dashed dates of cookies with four-digit
+/// years, like `Sun, 06-Nov-1994 08:49:37 GMT`, are accepted as well.
+pub fn parse_http_date(s: &str) -> Option<u64> {
+    let mut parts = s.split_ascii_whitespace();
+    let first = parts.next()?;
+    let (weekday, day, month, year, time) = match first.strip_suffix(',') {
+        Some(weekday) => {
+            let date = parts.next()?;
+            let (day, month, year) = if date.contains('-') {
+                let mut date = date.splitn(3, '-');
+                (date.next()?, date.next()?, date.next()?)
+            } else {
+                (date, parts.next()?, parts.next()?)
+            };
+            let time = parts.next()?;
+            if parts.next()? != "GMT" {
+                return None;
+            }
+            (weekday, day, month, year, time)
+        }
+        // asctime, which has no zone and the year last.
+        None => {
+            let month = parts.next()?;
+            let day = parts.next()?;
+            let time = parts.next()?;
+            (first, day, month, parts.next()?, time)
+        }
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let number = |s: &str| -> Option<u64> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    };
+    let day = number(day)?;
+    let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as u64 + 1;
+    let year = match (year.len(), number(year)?) {
+        (2, year) if year < 70 => 2000 + year,
+        (2, year) => 1900 + year,
+        (_, year) => year,
+    };
+    let mut time = time.split(':').map(number);
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    let weekday_ok = WEEKDAYS
+        .iter()
+        .chain(&WEEKDAY_NAMES)
+        .any(|w| w.eq_ignore_ascii_case(weekday));
+    if time.next().is_some()
+        || !weekday_ok
+        || year < 1970
+        || year > 9999
+        || day == 0
+        || day > 31
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day)?;
+    // Reject days past the end of the month, like 31 Apr.
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    days.checked_mul(86400)?
+        .checked_add(hour * 3600 + minute * 60 + second)
+}
+
+/// Write seconds since the unix epoch as an HTTP date in the IMF-fixdate format, like
+/// `Sun, 06 Nov 1994 08:49:37 GMT`, without allocating.
+pub fn write_http_date<W: fmt::Write>(w: &mut W, secs: u64) -> fmt::Result {
+    let days = secs / 86400;
+    let secs_of_day = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    write!(
+        w,
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        // The epoch was a Thursday.
+        WEEKDAYS[((days + 4) % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+/// Format seconds since the unix epoch as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn format_http_date(secs: u64) -> String {
+    let mut date = String::new();
+    // Writing to a `String` can't fail.
+    let _ = write_http_date(&mut date, secs);
+    date
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_http_date, parse_http_date, write_http_date};
+
+    #[test]
+    fn format_epoch() {
+        assert_eq!(format_http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn format_rfc_example() {
+        assert_eq!(
+            format_http_date(784_111_777),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+    }
+
+    #[test]
+    fn format_leap_day() {
+        assert_eq!(
+            format_http_date(951_782_400),
+            "Tue, 29 Feb 2000 00:00:00 GMT"
+        );
+        assert_eq!(
+            format_http_date(951_868_799),
+            "Tue, 29 Feb 2000 23:59:59 GMT"
+        );
+        assert_eq!(
+            format_http_date(951_868_800),
+            "Wed, 01 Mar 2000 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        for &secs in &[0, 784_111_777, 951_782_400, 951_868_799, 4_102_444_800] {
+            assert_eq!(parse_http_date(&format_http_date(secs)), Some(secs));
+        }
+    }
+
+    #[test]
+    fn parse_obsolete_formats() {
+        let expected = Some(784_111_777);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), expected);
+        assert_eq!(parse_http_date("Sun, 06-Nov-1994 08:49:37 GMT"), expected);
+        assert_eq!(parse_http_date("Thursday, 01-Jan-70 00:00:00 GMT"), Some(0));
+        assert_eq!(
+            parse_http_date("Friday, 01-Jan-38 00:00:00 GMT"),
+            Some(2_145_916_800)
+        );
+    }
+
+    #[test]
+    fn write_without_allocating() {
+        struct Fixed([u8; 29], usize);
+        impl core::fmt::Write for Fixed {
+            fn write_str(&mut self, s: &str) -> core::fmt::Result {
+                let end = self.1 + s.len();
+                let free = self.0.get_mut(self.1..end).ok_or(core::fmt::Error)?;
+                free.copy_from_slice(s.as_bytes());
+                self.1 = end;
+                Ok(())
+            }
+        }
+        let mut date = Fixed([0; 29], 0);
+        write_http_date(&mut date, 784_111_777).unwrap();
+        assert_eq!(&date.0[..], b"Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+        assert_eq!(parse_http_date("Sun, 31 Apr 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Sun 06 Nov 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Wed, 31 Dec 1969 23:59:59 GMT"), None);
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 99999999999999999 08:49:37 GMT"),
+            None
+        );
+        assert_eq!(parse_http_date("Sat, 01 Jan 10000 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37"), None);
+        assert_eq!(parse_http_date("Sun, 06-Nov-94-1 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994 GMT"), None);
+        assert_eq!(parse_http_date("Sun Nov +6 08:49:37 1994"), None);
+    }
+}
@@ -16,6 +16,27 @@ pub enum Error {
     UnexpectedMethod(HttpMethod),
     UrlError(String),
     LengthRequired,
+    /// The server's certificate chain matched none of the pins for its host. See
+    /// `CertificatePins`.
+    CertificatePinMismatch(String),
+    /// The verifier given to `HttpRequestBuilder::certificate_verifier` rejected the server's
+    /// certificate chain.
+    UntrustedCertificate(String),
+    /// No addresses were found for the host. See `client::Resolve`.
+    UnknownHost(String),
+    /// The body is longer than the limit given to read it, in bytes.
+    BodyTooLarge(usize),
+    /// The status line and headers of a response are longer than the limit, in bytes. See
+    /// `HttpRequestBuilder::max_header_size`.
+    HeadersTooLarge(usize),
+    /// A message doesn't fit in the buffer it is serialized into, of the given length. See
+    /// `HttpResponse::serialize_into`.
+    BufferTooSmall(usize),
+    /// Connecting, reading or writing took longer than allowed. See
+    /// `HttpRequestBuilder::read_timeout`.
+    Timeout,
+    /// The request violates the protocol. See `HttpServer::set_strict_mode`.
+    BadRequest(String),
     Other(String),
 
     #[cfg(feature = "std")]
@@ -25,6 +46,35 @@ pub enum Error {
     #[cfg(feature = "openssl")]
     SslError(String),
 
+    #[cfg(any(feature = "rustls", feature = "embedded-tls"))]
+    /// *This variant is available if http_io is built with the `"rustls"` or `"embedded-tls"`
+    /// feature.*
+    TlsError(String),
+
+    #[cfg(feature = "http2")]
+    /// The other end of the connection broke the HTTP/2 protocol.
+    ///
+    /// *This variant is available if http_io is built with the `"http2"` feature.*
+    Http2Error(String),
+
+    #[cfg(feature = "json")]
+    /// A JSON body couldn't be serialized or deserialized.
+    ///
+    /// *This variant is available if http_io is built with the `"json"` feature.*
+    JsonError(String),
+
+    #[cfg(feature = "socks5")]
+    /// The SOCKS5 proxy refused the request or broke the protocol.
+    ///
+    /// *This variant is available if http_io is built with the `"socks5"` feature.*
+    Socks5Error(String),
+
+    #[cfg(feature = "websocket")]
+    /// The WebSocket peer broke the protocol.
+    ///
+    /// *This variant is available if http_io is built with the `"websocket"` feature.*
+    WebSocketError(String),
+
     IoError(core2::io::Error),
 }
 
@@ -86,6 +136,19 @@ impl<S: fmt::Debug> From<openssl::ssl::HandshakeError<S>> for Error {
     }
 }
 
+#[cfg(feature = "rustls")]
+impl From<rustls::Error> for Error {
+    fn from(e: rustls::Error) -> Self {
+        Error::TlsError(e.to_string())
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::JsonError(e.to_string())
+    }
+}
 
 impl From<core2::io::Error> for Error {
     fn from(e: core2::io::Error) -> Self {
@@ -3,6 +3,9 @@
 //! See the `client` module for HTTP client code.
 //! See the `server` module for HTTP server code.
 //! See the `url` module for code representing urls.
+//! See the `date` module for code handling HTTP dates.
+//! See the `negotiate` module for choosing a response's media type.
+//! See the `websocket` module for WebSocket connections, with the `"websocket"` feature.
 #![cfg_attr(not(feature = "std"), no_std)]
 
 #[cfg(not(feature = "std"))]
@@ -11,9 +14,13 @@ extern crate alloc;
 pub mod client;
 pub mod server;
 
+pub mod date;
 pub mod error;
+pub mod negotiate;
 pub mod protocol;
 pub mod url;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 //#[cfg(not(feature = "std"))]
 //pub mod io;
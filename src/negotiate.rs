@@ -0,0 +1,233 @@
+//! Content negotiation using the `Accept` request header.
+//!
+//! # Example
+//! ```rust
+//! use http_io::negotiate::negotiate;
+//! use http_io::protocol::{HttpMethod, HttpRequest};
+//!
+//! let mut request = HttpRequest::new(HttpMethod::Get, "/");
+//! request.add_header("Accept", "text/html;q=0.9, application/json");
+//! let chosen = negotiate(&request.headers, &["text/html", "application/json"]);
+//! assert_eq!(chosen, Some("application/json"));
+//! ```
+
+use crate::protocol::HttpHeaders;
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+
+/// One media range from an `Accept` header, like `text/*;q=0.5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaRange {
+    /// The media range without its parameters, e.g. `text/*`.
+    pub media_type: String,
+    /// The preference for this range, in thousandths. `1000` is the most preferred and `0` means
+    /// not acceptable.
+    pub quality: u16,
+}
+
+impl MediaRange {
+    /// How specific the range is: `*/*` is 0, `type/*` is 1 and `type/subtype` is 2.
+    fn specificity(&self) -> u8 {
+        if self.media_type == "*/*" {
+            0
+        } else if self.media_type.ends_with("/*") {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Whether this range includes the given media type.
+    pub fn matches(&self, media_type: &str) -> bool {
+        let media_type = media_type.split(';').next().unwrap_or("").trim();
+        match self.specificity() {
+            0 => true,
+            1 => {
+                let prefix = &self.media_type[..self.media_type.len() - 1];
+                media_type.len() > prefix.len()
+                    && media_type[..prefix.len()].eq_ignore_ascii_case(prefix)
+            }
+            _ => media_type.eq_ignore_ascii_case(&self.media_type),
+        }
+    }
+}
+
+fn parse_quality(value: &str) -> Option<u16> {
+    let q: f32 = value.trim().parse().ok()?;
+    if !(0.0..=1.0).contains(&q) {
+        return None;
+    }
+    Some((q * 1000.0 + 0.5) as u16)
+}
+
+/// Parse the value of an `Accept` header. The ranges are returned most preferred first; ranges
+/// with equal quality are ordered most specific first, then in the order they appear.
+/// Malformed ranges are skipped.
+pub fn parse_accept(value: &str) -> Vec<MediaRange> {
+    let mut ranges = Vec::new();
+    for item in value.split(',') {
+        let mut params = item.split(';');
+        let media_type = params.next().unwrap_or("").trim();
+        if media_type.is_empty() || !media_type.contains('/') {
+            continue;
+        }
+
+        let mut quality = Some(1000);
+        for param in params {
+            let mut parts = param.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            if name.eq_ignore_ascii_case("q") {
+                quality = parts.next().and_then(parse_quality);
+            }
+        }
+
+        if let Some(quality) = quality {
+            ranges.push(MediaRange {
+                media_type: media_type.to_ascii_lowercase(),
+                quality,
+            });
+        }
+    }
+
+    // sort_by is stable, so equal ranges keep their order.
+    ranges.sort_by(|a, b| {
+        b.quality
+            .cmp(&a.quality)
+            .then(b.specificity().cmp(&a.specificity()))
+    });
+    ranges
+}
+
+/// The quality the client gave the given media type: that of the most specific range which
+/// matches it.
+fn quality_of(ranges: &[MediaRange], media_type: &str) -> u16 {
+    ranges
+        .iter()
+        .filter(|r| r.matches(media_type))
+        .max_by_key(|r| r.specificity())
+        .map(|r| r.quality)
+        .unwrap_or(0)
+}
+
+/// Choose which of the `available` media types to respond with, given the request's headers.
+///
+/// Returns the available type the client most prefers, preferring earlier entries in `available`
+/// on ties. If the request has no `Accept` header, the first available type is returned. Returns
+/// `None` if the client accepts none of them, in which case the server may respond with
+/// `406 Not Acceptable`.
+pub fn negotiate<'a>(request_headers: &HttpHeaders, available: &[&'a str]) -> Option<&'a str> {
+    let accept = match request_headers.get("Accept") {
+        Some(accept) => accept,
+        None => return available.first().copied(),
+    };
+    let ranges = parse_accept(accept);
+
+    let mut best = None;
+    let mut best_quality = 0;
+    for media_type in available {
+        let quality = quality_of(&ranges, media_type);
+        if quality > best_quality {
+            best = Some(*media_type);
+            best_quality = quality;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{negotiate, parse_accept, MediaRange};
+    use crate::protocol::{HttpMethod, HttpRequest};
+
+    fn range(media_type: &str, quality: u16) -> MediaRange {
+        MediaRange {
+            media_type: media_type.into(),
+            quality,
+        }
+    }
+
+    #[test]
+    fn parse_orders_by_quality() {
+        assert_eq!(
+            parse_accept("text/*;q=0.3, text/html;q=0.7, text/html;level=1, */*;q=0.5"),
+            vec![
+                range("text/html", 1000),
+                range("text/html", 700),
+                range("*/*", 500),
+                range("text/*", 300),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_orders_by_specificity() {
+        assert_eq!(
+            parse_accept("*/*, text/*, text/plain"),
+            vec![
+                range("text/plain", 1000),
+                range("text/*", 1000),
+                range("*/*", 1000),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_skips_malformed() {
+        assert_eq!(
+            parse_accept("text, , text/plain;q=2, image/png;q=abc, text/html"),
+            vec![range("text/html", 1000)]
+        );
+    }
+
+    fn negotiate_with(accept: Option<&str>, available: &[&'static str]) -> Option<&'static str> {
+        let mut request = HttpRequest::new(HttpMethod::Get, "/");
+        if let Some(accept) = accept {
+            request.add_header("Accept", accept);
+        }
+        negotiate(&request.headers, available)
+    }
+
+    #[test]
+    fn negotiate_prefers_quality() {
+        let available = ["application/json", "text/html"];
+        assert_eq!(
+            negotiate_with(Some("text/html, application/json;q=0.9"), &available),
+            Some("text/html")
+        );
+        assert_eq!(
+            negotiate_with(Some("text/*;q=0.5, application/json"), &available),
+            Some("application/json")
+        );
+    }
+
+    #[test]
+    fn negotiate_ties_use_server_order() {
+        let available = ["application/json", "text/html"];
+        assert_eq!(
+            negotiate_with(Some("*/*"), &available),
+            Some("application/json")
+        );
+    }
+
+    #[test]
+    fn negotiate_most_specific_range_wins() {
+        let available = ["text/html", "text/plain"];
+        assert_eq!(
+            negotiate_with(Some("text/*, text/html;q=0"), &available),
+            Some("text/plain")
+        );
+    }
+
+    #[test]
+    fn negotiate_no_accept() {
+        assert_eq!(
+            negotiate_with(None, &["text/html", "text/plain"]),
+            Some("text/html")
+        );
+    }
+
+    #[test]
+    fn negotiate_not_acceptable() {
+        assert_eq!(negotiate_with(Some("image/png"), &["text/html"]), None);
+    }
+}
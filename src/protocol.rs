@@ -3,53 +3,84 @@
 // We do write! + '\r\n' and don't want to hide the line ending in a writeln!
 #![allow(clippy::write_with_newline)]
 
+pub mod push;
+
 use crate::error::{Error, Result};
 use core2::io::{self, Read, Write};
 #[cfg(not(feature = "std"))]
-use alloc::{boxed::Box, collections::BTreeMap, format, string::String, vec, vec::Vec};
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+#[cfg(not(feature = "std"))]
+use alloc::rc::Rc;
+use core::cell::RefCell;
 use core::cmp;
-use core::convert;
 use core::fmt;
 use core::str;
 #[cfg(feature = "std")]
-use std::collections::BTreeMap;
+use std::rc::Rc;
 
 struct HttpBodyChunk<S: core2::io::Read> {
-    inner: io::Take<HttpReadTilCloseBody<S>>,
+    inner: io::Take<S>,
 }
 
 pub struct HttpChunkedBody<S: core2::io::Read> {
     content_length: Option<u64>,
-    stream: Option<HttpReadTilCloseBody<S>>,
+    stream: Option<S>,
     chunk: Option<HttpBodyChunk<S>>,
+    trailers: Option<HttpHeaders>,
 }
 
 impl<S: core2::io::Read> HttpChunkedBody<S> {
-    fn new(content_length: Option<u64>, stream: HttpReadTilCloseBody<S>) -> Self {
+    fn new(content_length: Option<u64>, stream: S) -> Self {
         HttpChunkedBody {
             content_length,
             stream: Some(stream),
             chunk: None,
+            trailers: None,
+        }
+    }
+
+    /// The trailer section sent after the last chunk. It is only available once the body has been
+    /// read to the end, and is empty if the sender didn't send any trailers.
+    pub fn trailers(&self) -> Option<&HttpHeaders> {
+        self.trailers.as_ref()
+    }
+
+    /// The stream the body is read from, after the body if it was read to the end. `None` if
+    /// reading it failed part way.
+    pub fn into_inner(self) -> Option<S> {
+        match self.chunk {
+            Some(chunk) => Some(chunk.into_inner()),
+            None => self.stream,
         }
     }
+
+    fn read_trailers(&mut self, mut stream: S) -> Result<()> {
+        let trailers = HttpHeaders::deserialize(&mut CrLfStream::new(&mut stream))?;
+        self.trailers = Some(trailers);
+        self.stream = Some(stream);
+        Ok(())
+    }
 }
 
 impl<S: core2::io::Read> HttpBodyChunk<S> {
-    fn new(mut stream: HttpReadTilCloseBody<S>) -> Result<Option<Self>> {
+    /// Read the size of the next chunk. The stream is given back if it is the last chunk.
+    fn new(mut stream: S) -> Result<core::result::Result<Self, S>> {
         let mut ts = CrLfStream::new(&mut stream);
         let size_str = ts.expect_next()?;
         drop(ts);
-        let size = u64::from_str_radix(&size_str, 16)?;
+        // Chunk extensions, like `5;name=value`, aren't used.
+        let size = size_str.split(';').next().unwrap_or("").trim();
+        let size = u64::from_str_radix(size, 16)?;
         Ok(if size == 0 {
-            None
+            Err(stream)
         } else {
-            Some(HttpBodyChunk {
+            Ok(HttpBodyChunk {
                 inner: stream.take(size),
             })
         })
     }
 
-    fn into_inner(self) -> HttpReadTilCloseBody<S> {
+    fn into_inner(self) -> S {
         self.inner.into_inner()
     }
 }
@@ -74,14 +105,24 @@ impl<S: core2::io::Read> core2::io::Read for HttpChunkedBody<S> {
                 self.chunk.replace(chunk);
                 Ok(read)
             }
+        } else if self.trailers.is_some() {
+            Ok(0)
         } else if let Some(stream) = self.stream.take() {
             let new_chunk = HttpBodyChunk::new(stream).map_err( |e| core2::io::Error::new(core2::io::ErrorKind::Other,"HttpBodyChunk"))?;
             match new_chunk {
-                Some(chunk) => {
+                Ok(chunk) => {
                     self.chunk = Some(chunk);
                     self.read(buffer)
                 }
-                None => Ok(0),
+                Err(stream) => {
+                    self.read_trailers(stream).map_err(|_| {
+                        core2::io::Error::new(
+                            core2::io::ErrorKind::Other,
+                            "HttpChunkedBody trailers",
+                        )
+                    })?;
+                    Ok(0)
+                }
             }
         } else {
             Ok(0)
@@ -97,7 +138,7 @@ mod chunked_encoding_tests {
     use std::core2::io::Read;
 
     fn chunk_test(i: &'static str) -> Result<String> {
-        let input = io::BufReader::new(io::Cursor::new(i));
+        let input = io::Cursor::new(i);
         let mut body = HttpChunkedBody::new(None, input);
 
         let mut output = String::new();
@@ -108,11 +149,49 @@ mod chunked_encoding_tests {
     #[test]
     fn simple_chunk() {
         assert_eq!(
-            &chunk_test("a\r\n0123456789\r\n0\r\n").unwrap(),
+            &chunk_test("a\r\n0123456789\r\n0\r\n\r\n").unwrap(),
             "0123456789"
         );
     }
 
+    #[test]
+    fn chunk_extensions() {
+        assert_eq!(
+            &chunk_test("5;name=value\r\nhello\r\n6 ; a ; b=\"c;d\"\r\n world\r\n0;last\r\n\r\n")
+                .unwrap(),
+            "hello world"
+        );
+    }
+
+    #[test]
+    fn chunk_trailers() {
+        let input = io::Cursor::new("a\r\n0123456789\r\n0\r\nContent-MD5: abc\r\nA: b\r\n\r\n");
+        let mut body = HttpChunkedBody::new(None, input);
+        assert!(body.trailers().is_none());
+
+        let mut output = String::new();
+        body.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "0123456789");
+
+        let trailers = body.trailers().unwrap();
+        assert_eq!(trailers.get("Content-MD5"), Some("abc"));
+        assert_eq!(trailers.get("A"), Some("b"));
+    }
+
+    #[test]
+    fn chunk_no_trailers() {
+        let input = io::Cursor::new("a\r\n0123456789\r\n0\r\n\r\n");
+        let mut body = HttpChunkedBody::new(None, input);
+        let mut output = String::new();
+        body.read_to_string(&mut output).unwrap();
+        assert!(body.trailers().unwrap().get("A").is_none());
+    }
+
+    #[test]
+    fn chunk_missing_trailer_section() {
+        assert!(chunk_test("a\r\n0123456789\r\n0\r\n").is_err());
+    }
+
     #[test]
     fn chunk_missing_last_chunk() {
         assert!(chunk_test("a\r\n0123456789\r\n").is_err());
@@ -124,13 +203,13 @@ mod chunked_encoding_tests {
     }
 }
 
-type HttpReadTilCloseBody<S> = io::BufReader<S,1024>;
-type HttpLimitedBody<S> = io::Take<HttpReadTilCloseBody<S>>;
-
+/// The body of a request or response. It reads directly from the underlying stream, which should
+/// be buffered. Any bytes after the end of the body are left unread in the stream.
 pub enum HttpBody<S: core2::io::Read> {
     Chunked(HttpChunkedBody<S>),
-    Limited(HttpLimitedBody<S>),
-    ReadTilClose(HttpReadTilCloseBody<S>),
+    Limited(io::Take<S>),
+    ReadTilClose(S),
+    Decoded(DecodedBody<S>),
 }
 
 impl<S: core2::io::Read> core2::io::Read for HttpBody<S> {
@@ -139,17 +218,16 @@ impl<S: core2::io::Read> core2::io::Read for HttpBody<S> {
             HttpBody::Chunked(i) => i.read(buffer),
             HttpBody::Limited(i) => i.read(buffer),
             HttpBody::ReadTilClose(i) => i.read(buffer),
+            HttpBody::Decoded(i) => i.read(buffer),
         }
     }
 }
 
 impl<S: core2::io::Read> HttpBody<S> {
-    pub fn new(
-        encoding: Option<&str>,
-        content_length: Option<u64>,
-        body: io::BufReader<S,1024>,
-    ) -> Self {
-        if encoding == Some("chunked") {
+    /// A body with the given `Transfer-Encoding` value, which is chunked if chunked is its last
+    /// coding, or `Content-Length`.
+    pub fn new(encoding: Option<&str>, content_length: Option<u64>, body: S) -> Self {
+        if is_chunked(encoding.into_iter()) {
             HttpBody::Chunked(HttpChunkedBody::new(content_length, body))
         } else if let Some(length) = content_length {
             HttpBody::Limited(body.take(length))
@@ -158,11 +236,26 @@ impl<S: core2::io::Read> HttpBody<S> {
         }
     }
 
+    /// The body of a message with the given headers. It is chunked if chunked is the last
+    /// transfer coding, and read until the connection is closed for any other transfer coding,
+    /// which overrides `Content-Length`. Fails if `Content-Length` isn't valid, see
+    /// `HttpHeaders::content_length`.
+    pub fn from_headers(headers: &HttpHeaders, body: S) -> Result<Self> {
+        let content_length = headers.content_length()?;
+        Ok(if headers.is_chunked() {
+            HttpBody::Chunked(HttpChunkedBody::new(content_length, body))
+        } else if headers.get("Transfer-Encoding").is_some() {
+            HttpBody::ReadTilClose(body)
+        } else {
+            HttpBody::new(None, content_length, body)
+        })
+    }
+
     pub fn require_length(&self) -> Result<()> {
         let has_length = match self {
             HttpBody::Chunked(_) => true,
             HttpBody::Limited(_) => true,
-            HttpBody::ReadTilClose(_) => false,
+            HttpBody::ReadTilClose(_) | HttpBody::Decoded(_) => false,
         };
 
         if !has_length {
@@ -172,36 +265,178 @@ impl<S: core2::io::Read> HttpBody<S> {
         }
     }
 
+    /// The trailers sent after a chunked body. See `HttpChunkedBody::trailers`. Bodies which aren't
+    /// chunked never have trailers.
+    pub fn trailers(&self) -> Option<&HttpHeaders> {
+        match self {
+            HttpBody::Chunked(c) => c.trailers(),
+            HttpBody::Decoded(d) => d.trailers(),
+            _ => None,
+        }
+    }
+
+    /// Read the next piece of the body into `buffer`, returning the part of it which was filled,
+    /// or `None` at the end of the body. A piece of a chunked body never spans two chunks. It is
+    /// for event loops handling the body as it comes, without copying it out of `buffer` first.
+    ///
+    /// ```rust
+    /// # use http_io::protocol::HttpBody;
+    /// let mut body = HttpBody::new(Some("chunked"), None, &b"5\r\nhello\r\n0\r\n\r\n"[..]);
+    /// let mut buffer = [0; 1024];
+    /// while let Some(piece) = body.next_chunk(&mut buffer)? {
+    ///     assert_eq!(piece, b"hello");
+    /// }
+    /// # Ok::<(), http_io::error::Error>(())
+    /// ```
+    pub fn next_chunk<'b>(&mut self, buffer: &'b mut [u8]) -> Result<Option<&'b [u8]>> {
+        if buffer.is_empty() {
+            return Err(Error::Other("empty buffer".into()));
+        }
+        match self.read(buffer)? {
+            0 => Ok(None),
+            read => Ok(Some(&buffer[..read])),
+        }
+    }
+
+    /// The stream the body is read from, like the connection it was received on. If the body was
+    /// read to the end, the stream is positioned after it, ready for the next message or another
+    /// protocol; otherwise the rest of the body comes first. `None` if reading a chunked body
+    /// failed part way, leaving the stream in an unknown state.
+    pub fn into_inner(self) -> Option<S> {
+        match self {
+            HttpBody::Chunked(c) => c.into_inner(),
+            HttpBody::Limited(l) => Some(l.into_inner()),
+            HttpBody::ReadTilClose(s) => Some(s),
+            HttpBody::Decoded(d) => Some(d.inner),
+        }
+    }
+
     pub fn content_length(&self) -> Option<u64> {
         match self {
             HttpBody::Chunked(c) => c.content_length.clone(),
             HttpBody::Limited(c) => Some(c.limit()),
-            HttpBody::ReadTilClose(_) => None,
+            HttpBody::ReadTilClose(_) | HttpBody::Decoded(_) => None,
+        }
+    }
+}
+
+/// Where a chunked body read through other readers, like a decompressor, leaves its trailers
+/// when it ends. See `DecodedBody`.
+pub(crate) type TrailerSlot = Rc<RefCell<Option<HttpHeaders>>>;
+
+/// Reads a body, and leaves its trailers in the slot once it ends.
+pub(crate) struct SharedTrailers<S: core2::io::Read> {
+    body: HttpBody<S>,
+    slot: TrailerSlot,
+}
+
+impl<S: core2::io::Read> SharedTrailers<S> {
+    pub(crate) fn new(body: HttpBody<S>, slot: TrailerSlot) -> Self {
+        SharedTrailers { body, slot }
+    }
+}
+
+impl<S: core2::io::Read> core2::io::Read for SharedTrailers<S> {
+    fn read(&mut self, buffer: &mut [u8]) -> core2::io::Result<usize> {
+        let read = self.body.read(buffer)?;
+        if read == 0 && !buffer.is_empty() {
+            if let Some(trailers) = self.body.trailers() {
+                *self.slot.borrow_mut() = Some(trailers.clone());
+            }
         }
+        Ok(read)
     }
 }
 
+/// A body read through other readers, like the decompressed bodies of the responses
+/// `client::HttpRequestBuilder::execute` returns. The trailers of the chunked body underneath are
+/// available from `trailers` once it was read to the end.
+pub struct DecodedBody<S: core2::io::Read> {
+    inner: S,
+    slot: TrailerSlot,
+    trailers: Option<HttpHeaders>,
+}
+
+impl<S: core2::io::Read> DecodedBody<S> {
+    pub(crate) fn new(inner: S, slot: TrailerSlot) -> Self {
+        DecodedBody {
+            inner,
+            slot,
+            trailers: None,
+        }
+    }
+
+    /// The trailers sent after the body underneath, once it was read to the end, if it was chunked.
+    /// They are empty if the sender didn't send any.
+    pub fn trailers(&self) -> Option<&HttpHeaders> {
+        self.trailers.as_ref()
+    }
+}
+
+impl<S: core2::io::Read> core2::io::Read for DecodedBody<S> {
+    fn read(&mut self, buffer: &mut [u8]) -> core2::io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        if read == 0 && !buffer.is_empty() && self.trailers.is_none() {
+            self.trailers = self.slot.borrow_mut().take();
+        }
+        Ok(read)
+    }
+}
+
+#[test]
+fn body_next_chunk() {
+    let mut body = HttpBody::new(
+        Some("chunked"),
+        None,
+        &b"5\r\nhello\r\n3\r\nabc\r\n0\r\nA: b\r\n\r\n"[..],
+    );
+    let mut buffer = [0; 4];
+    let mut pieces = Vec::new();
+    while let Some(piece) = body.next_chunk(&mut buffer).unwrap() {
+        pieces.push(piece.to_vec());
+    }
+    assert_eq!(pieces, [&b"hell"[..], b"o", b"abc"]);
+    assert_eq!(body.trailers().unwrap().get("A"), Some("b"));
+    assert!(body.next_chunk(&mut []).is_err());
+}
+
+#[test]
+fn body_into_inner() {
+    let mut body = HttpBody::new(Some("chunked"), None, &b"5\r\nhello\r\n0\r\n\r\nnext"[..]);
+    let mut buffer = [0; 3];
+    body.read_exact(&mut buffer).unwrap();
+    let mut stream = body.into_inner().unwrap();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"lo\r\n0\r\n\r\nnext");
+
+    let mut body = HttpBody::new(None, Some(5), &b"hellonext"[..]);
+    let mut buffer = Vec::new();
+    body.read_to_end(&mut buffer).unwrap();
+    assert_eq!(body.into_inner().unwrap(), b"next");
+}
+
 #[test]
 fn chunked_body_no_content_length() {
-    let body = HttpBody::new(Some("chunked"), None, io::BufReader::new(io::empty()));
+    let body = HttpBody::new(Some("chunked"), None, io::empty());
     assert_eq!(body.content_length(), None);
 }
 
 #[test]
 fn chunked_body_content_length() {
-    let body = HttpBody::new(Some("chunked"), Some(12), io::BufReader::new(io::empty()));
+    let body = HttpBody::new(Some("chunked"), Some(12), io::empty());
     assert_eq!(body.content_length(), Some(12));
 }
 
 #[test]
 fn read_till_close_body_has_no_content_length() {
-    let body = HttpBody::new(None, None, io::BufReader::new(io::empty()));
+    let body = HttpBody::new(None, None, io::empty());
     assert_eq!(body.content_length(), None);
 }
 
 #[test]
 fn limited_body_content_length() {
-    let body = HttpBody::new(None, Some(12), io::BufReader::new(io::empty()));
+    let body = HttpBody::new(None, Some(12), io::empty());
     assert_eq!(body.content_length(), Some(12));
 }
 
@@ -528,15 +763,63 @@ mod parser_tests {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-struct HttpVersion {
-    major: u32,
-    minor: u32,
+/// The version of HTTP a request or response uses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HttpVersion {
+    /// HTTP/0.9, whose requests are only a request line without a version, like `GET /`, and
+    /// whose responses are only the body, ended by closing the connection.
+    Http09,
+    Http10,
+    Http11,
+    /// Another version, with its major and minor numbers. `HttpVersion::new` gives the variants
+    /// above for the versions they stand for.
+    Other(u8, u8),
 }
 
 impl HttpVersion {
-    fn new(major: u32, minor: u32) -> Self {
-        HttpVersion { major, minor }
+    pub fn new(major: u8, minor: u8) -> Self {
+        match (major, minor) {
+            (0, 9) => HttpVersion::Http09,
+            (1, 0) => HttpVersion::Http10,
+            (1, 1) => HttpVersion::Http11,
+            (major, minor) => HttpVersion::Other(major, minor),
+        }
+    }
+
+    pub fn major(&self) -> u8 {
+        self.numbers().0
+    }
+
+    pub fn minor(&self) -> u8 {
+        self.numbers().1
+    }
+
+    fn numbers(&self) -> (u8, u8) {
+        match *self {
+            HttpVersion::Http09 => (0, 9),
+            HttpVersion::Http10 => (1, 0),
+            HttpVersion::Http11 => (1, 1),
+            HttpVersion::Other(major, minor) => (major, minor),
+        }
+    }
+
+    /// Whether messages of this version can use the chunked transfer coding and keep the
+    /// connection open by default, which HTTP/1.1 brought. Otherwise a body of unknown length is
+    /// ended by closing the connection.
+    pub fn supports_chunked(&self) -> bool {
+        self.numbers() >= (1, 1)
+    }
+}
+
+impl PartialOrd for HttpVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HttpVersion {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.numbers().cmp(&other.numbers())
     }
 }
 
@@ -550,13 +833,14 @@ impl str::FromStr for HttpVersion {
         let major = parser.parse_digit()?;
         parser.expect(".")?;
         let minor = parser.parse_digit()?;
-        Ok(HttpVersion::new(major, minor))
+        // Single digits always fit.
+        Ok(HttpVersion::new(major as u8, minor as u8))
     }
 }
 
 impl fmt::Display for HttpVersion {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "HTTP/{}.{}", self.major, self.minor)
+        write!(f, "HTTP/{}.{}", self.major(), self.minor())
     }
 }
 
@@ -575,6 +859,10 @@ mod http_version_tests {
             "HTTP/1.2".parse::<HttpVersion>().unwrap(),
             HttpVersion::new(1, 2)
         );
+        assert_eq!(
+            "HTTP/1.0".parse::<HttpVersion>().unwrap(),
+            HttpVersion::new(1, 0)
+        );
     }
 
     #[test]
@@ -585,9 +873,25 @@ mod http_version_tests {
         assert!("HRRP/1.2".parse::<HttpVersion>().is_err());
     }
 
+    #[test]
+    fn variants() {
+        assert_eq!(
+            "HTTP/0.9".parse::<HttpVersion>().unwrap(),
+            HttpVersion::Http09
+        );
+        assert_eq!(HttpVersion::new(1, 0), HttpVersion::Http10);
+        assert_eq!(HttpVersion::new(1, 1), HttpVersion::Http11);
+        assert_eq!(HttpVersion::new(2, 0), HttpVersion::Other(2, 0));
+        assert!(HttpVersion::Http10 < HttpVersion::Http11);
+        assert!(HttpVersion::Http11 < HttpVersion::Other(1, 2));
+        assert!(!HttpVersion::Http10.supports_chunked());
+        assert!(HttpVersion::Http11.supports_chunked());
+    }
+
     #[test]
     fn display() {
         assert_eq!(&HttpVersion::new(1, 3).to_string(), "HTTP/1.3");
+        assert_eq!(&HttpVersion::Http09.to_string(), "HTTP/0.9");
     }
 
     #[test]
@@ -599,37 +903,55 @@ mod http_version_tests {
     }
 }
 
+/// The status of a response. Codes without a variant, like ones servers make up, are kept as
+/// `Unknown`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HttpStatus {
     Accepted,
+    AlreadyReported,
     BadGateway,
     BadRequest,
     Conflict,
     Continue,
     Created,
+    EarlyHints,
     ExpectationFailed,
+    FailedDependency,
     Forbidden,
     Found,
     GatewayTimeout,
     Gone,
     HttpVersionNotSupported,
+    ImATeapot,
+    ImUsed,
+    InsufficientStorage,
     InternalServerError,
     LengthRequired,
+    Locked,
+    LoopDetected,
     MethodNotAllowed,
+    MisdirectedRequest,
     MovedPermanently,
+    MultiStatus,
     MultipleChoices,
+    NetworkAuthenticationRequired,
     NoContent,
     NonAuthoritativeInformation,
     NotAcceptable,
+    NotExtended,
     NotFound,
     NotImplemented,
     NotModified,
     OK,
     PartialContent,
     PaymentRequired,
+    PermanentRedirect,
     PreconditionFailed,
+    PreconditionRequired,
+    Processing,
     ProxyAuthenticationRequired,
     RequestEntityTooLarge,
+    RequestHeaderFieldsTooLarge,
     RequestTimeout,
     RequestUriTooLong,
     RequestedRangeNotSatisfiable,
@@ -638,120 +960,222 @@ pub enum HttpStatus {
     ServiceUnavailable,
     SwitchingProtocols,
     TemporaryRedirect,
+    TooEarly,
+    TooManyRequests,
     Unauthorized,
+    UnavailableForLegalReasons,
+    UnprocessableEntity,
     UnsupportedMediaType,
+    UpgradeRequired,
     UseProxy,
-    Unknown(u32),
+    VariantAlsoNegotiates,
+    Unknown(u16),
 }
 
+/// Each status with a variant, its code and its reason phrase.
+const STATUSES: [(HttpStatus, u16, &str); 62] = [
+    (HttpStatus::Continue, 100, "Continue"),
+    (HttpStatus::SwitchingProtocols, 101, "Switching Protocols"),
+    (HttpStatus::Processing, 102, "Processing"),
+    (HttpStatus::EarlyHints, 103, "Early Hints"),
+    (HttpStatus::OK, 200, "OK"),
+    (HttpStatus::Created, 201, "Created"),
+    (HttpStatus::Accepted, 202, "Accepted"),
+    (
+        HttpStatus::NonAuthoritativeInformation,
+        203,
+        "Non-Authoritative Information",
+    ),
+    (HttpStatus::NoContent, 204, "No Content"),
+    (HttpStatus::ResetContent, 205, "Reset Content"),
+    (HttpStatus::PartialContent, 206, "Partial Content"),
+    (HttpStatus::MultiStatus, 207, "Multi-Status"),
+    (HttpStatus::AlreadyReported, 208, "Already Reported"),
+    (HttpStatus::ImUsed, 226, "IM Used"),
+    (HttpStatus::MultipleChoices, 300, "Multiple Choices"),
+    (HttpStatus::MovedPermanently, 301, "Moved Permanently"),
+    (HttpStatus::Found, 302, "Found"),
+    (HttpStatus::SeeOther, 303, "See Other"),
+    (HttpStatus::NotModified, 304, "Not Modified"),
+    (HttpStatus::UseProxy, 305, "Use Proxy"),
+    (HttpStatus::TemporaryRedirect, 307, "Temporary Redirect"),
+    (HttpStatus::PermanentRedirect, 308, "Permanent Redirect"),
+    (HttpStatus::BadRequest, 400, "Bad Request"),
+    (HttpStatus::Unauthorized, 401, "Unauthorized"),
+    (HttpStatus::PaymentRequired, 402, "Payment Required"),
+    (HttpStatus::Forbidden, 403, "Forbidden"),
+    (HttpStatus::NotFound, 404, "Not Found"),
+    (HttpStatus::MethodNotAllowed, 405, "Method Not Allowed"),
+    (HttpStatus::NotAcceptable, 406, "Not Acceptable"),
+    (
+        HttpStatus::ProxyAuthenticationRequired,
+        407,
+        "Proxy Authentication Required",
+    ),
+    (HttpStatus::RequestTimeout, 408, "Request Timeout"),
+    (HttpStatus::Conflict, 409, "Conflict"),
+    (HttpStatus::Gone, 410, "Gone"),
+    (HttpStatus::LengthRequired, 411, "Length Required"),
+    (HttpStatus::PreconditionFailed, 412, "Precondition Failed"),
+    (
+        HttpStatus::RequestEntityTooLarge,
+        413,
+        "Request Entity Too Large",
+    ),
+    (HttpStatus::RequestUriTooLong, 414, "Request URI Too Long"),
+    (
+        HttpStatus::UnsupportedMediaType,
+        415,
+        "Unsupported Media Type",
+    ),
+    (
+        HttpStatus::RequestedRangeNotSatisfiable,
+        416,
+        "Requested Range Not Satisfiable",
+    ),
+    (HttpStatus::ExpectationFailed, 417, "Expectation Failed"),
+    (HttpStatus::ImATeapot, 418, "I'm a teapot"),
+    (HttpStatus::MisdirectedRequest, 421, "Misdirected Request"),
+    (HttpStatus::UnprocessableEntity, 422, "Unprocessable Entity"),
+    (HttpStatus::Locked, 423, "Locked"),
+    (HttpStatus::FailedDependency, 424, "Failed Dependency"),
+    (HttpStatus::TooEarly, 425, "Too Early"),
+    (HttpStatus::UpgradeRequired, 426, "Upgrade Required"),
+    (
+        HttpStatus::PreconditionRequired,
+        428,
+        "Precondition Required",
+    ),
+    (HttpStatus::TooManyRequests, 429, "Too Many Requests"),
+    (
+        HttpStatus::RequestHeaderFieldsTooLarge,
+        431,
+        "Request Header Fields Too Large",
+    ),
+    (
+        HttpStatus::UnavailableForLegalReasons,
+        451,
+        "Unavailable For Legal Reasons",
+    ),
+    (
+        HttpStatus::InternalServerError,
+        500,
+        "Internal Server Error",
+    ),
+    (HttpStatus::NotImplemented, 501, "Not Implemented"),
+    (HttpStatus::BadGateway, 502, "Bad Gateway"),
+    (HttpStatus::ServiceUnavailable, 503, "Service Unavailable"),
+    (HttpStatus::GatewayTimeout, 504, "Gateway Timeout"),
+    (
+        HttpStatus::HttpVersionNotSupported,
+        505,
+        "HTTP Version Not Supported",
+    ),
+    (
+        HttpStatus::VariantAlsoNegotiates,
+        506,
+        "Variant Also Negotiates",
+    ),
+    (HttpStatus::InsufficientStorage, 507, "Insufficient Storage"),
+    (HttpStatus::LoopDetected, 508, "Loop Detected"),
+    (HttpStatus::NotExtended, 510, "Not Extended"),
+    (
+        HttpStatus::NetworkAuthenticationRequired,
+        511,
+        "Network Authentication Required",
+    ),
+];
+
 impl str::FromStr for HttpStatus {
     type Err = Error;
 
+    /// Parse the code at the start of a status, like `404` or `404 Not Found`. The reason phrase
+    /// is ignored.
     fn from_str(s: &str) -> Result<Self> {
         let mut parser = Parser::new(s);
-        match parser.parse_number()? {
-            100 => Ok(HttpStatus::Continue),
-            101 => Ok(HttpStatus::SwitchingProtocols),
-            200 => Ok(HttpStatus::OK),
-            201 => Ok(HttpStatus::Created),
-            202 => Ok(HttpStatus::Accepted),
-            203 => Ok(HttpStatus::NonAuthoritativeInformation),
-            204 => Ok(HttpStatus::NoContent),
-            205 => Ok(HttpStatus::ResetContent),
-            206 => Ok(HttpStatus::PartialContent),
-            300 => Ok(HttpStatus::MultipleChoices),
-            301 => Ok(HttpStatus::MovedPermanently),
-            302 => Ok(HttpStatus::Found),
-            303 => Ok(HttpStatus::SeeOther),
-            304 => Ok(HttpStatus::NotModified),
-            305 => Ok(HttpStatus::UseProxy),
-            307 => Ok(HttpStatus::TemporaryRedirect),
-            400 => Ok(HttpStatus::BadRequest),
-            401 => Ok(HttpStatus::Unauthorized),
-            402 => Ok(HttpStatus::PaymentRequired),
-            403 => Ok(HttpStatus::Forbidden),
-            404 => Ok(HttpStatus::NotFound),
-            405 => Ok(HttpStatus::MethodNotAllowed),
-            406 => Ok(HttpStatus::NotAcceptable),
-            407 => Ok(HttpStatus::ProxyAuthenticationRequired),
-            408 => Ok(HttpStatus::RequestTimeout),
-            409 => Ok(HttpStatus::Conflict),
-            410 => Ok(HttpStatus::Gone),
-            411 => Ok(HttpStatus::LengthRequired),
-            412 => Ok(HttpStatus::PreconditionFailed),
-            413 => Ok(HttpStatus::RequestEntityTooLarge),
-            414 => Ok(HttpStatus::RequestUriTooLong),
-            415 => Ok(HttpStatus::UnsupportedMediaType),
-            416 => Ok(HttpStatus::RequestedRangeNotSatisfiable),
-            417 => Ok(HttpStatus::ExpectationFailed),
-            500 => Ok(HttpStatus::InternalServerError),
-            501 => Ok(HttpStatus::NotImplemented),
-            502 => Ok(HttpStatus::BadGateway),
-            503 => Ok(HttpStatus::ServiceUnavailable),
-            504 => Ok(HttpStatus::GatewayTimeout),
-            505 => Ok(HttpStatus::HttpVersionNotSupported),
-            v => Ok(HttpStatus::Unknown(v)),
+        let code = parser.parse_number()?;
+        if !(100..1000).contains(&code) {
+            return Err(Error::ParseError(format!("Invalid status code {}", code)));
+        }
+        Ok(HttpStatus::from_u16(code as u16))
+    }
+}
+
+impl HttpStatus {
+    /// The status with the given code, `HttpStatus::Unknown` if it has no variant.
+    pub fn from_u16(code: u16) -> Self {
+        STATUSES
+            .iter()
+            .find(|(_, c, _)| *c == code)
+            .map_or(HttpStatus::Unknown(code), |(status, _, _)| *status)
+    }
+
+    /// The numeric code, like 404 for `HttpStatus::NotFound`.
+    pub fn as_u16(&self) -> u16 {
+        match self {
+            HttpStatus::Unknown(code) => *code,
+            status => STATUSES
+                .iter()
+                .find(|(s, _, _)| s == status)
+                .map_or(0, |(_, code, _)| *code),
         }
     }
+
+    /// The canonical reason phrase, like `Not Found`, or `None` for an unknown status.
+    pub fn reason_phrase(&self) -> Option<&'static str> {
+        STATUSES
+            .iter()
+            .find(|(status, _, _)| status == self)
+            .map(|(_, _, phrase)| *phrase)
+    }
+
+    /// Whether the code is 1xx, informational. Like the other class checks, this works for
+    /// unknown codes too, which are to be treated like the x00 code of their class (RFC 7231
+    /// section 6).
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.as_u16())
+    }
+
+    /// Whether the code is 2xx, success.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.as_u16())
+    }
+
+    /// Whether the code is 3xx, redirection.
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.as_u16())
+    }
+
+    /// Whether the code is 4xx, client error.
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.as_u16())
+    }
+
+    /// Whether the code is 5xx, server error.
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.as_u16())
+    }
+
+    /// Whether this is an interim 1xx response, like 100 Continue or 103 Early Hints, which comes
+    /// before the final response to the request. 101 Switching Protocols is final, as the
+    /// connection stops speaking HTTP after it.
+    pub fn is_interim(&self) -> bool {
+        self.is_informational() && *self != HttpStatus::SwitchingProtocols
+    }
 }
 
 impl fmt::Display for HttpStatus {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            HttpStatus::Accepted => write!(f, "202 Accepted"),
-            HttpStatus::BadGateway => write!(f, "502 Bad Gateway"),
-            HttpStatus::BadRequest => write!(f, "400 Bad Request"),
-            HttpStatus::Conflict => write!(f, "409 Conflict"),
-            HttpStatus::Continue => write!(f, "100 Continue"),
-            HttpStatus::Created => write!(f, "201 Created"),
-            HttpStatus::ExpectationFailed => write!(f, "417 Expectation Failed"),
-            HttpStatus::Forbidden => write!(f, "403 Forbidden"),
-            HttpStatus::Found => write!(f, "302 Found"),
-            HttpStatus::GatewayTimeout => write!(f, "504 Gateway Timeout"),
-            HttpStatus::Gone => write!(f, "410 Gone"),
-            HttpStatus::HttpVersionNotSupported => write!(f, "505 HTTP Version Not Supported"),
-            HttpStatus::InternalServerError => write!(f, "500 Internal Server Error"),
-            HttpStatus::LengthRequired => write!(f, "411 Length Required"),
-            HttpStatus::MethodNotAllowed => write!(f, "405 Method Not Allowed"),
-            HttpStatus::MovedPermanently => write!(f, "301 Moved Permanently"),
-            HttpStatus::MultipleChoices => write!(f, "300 Multiple Choices"),
-            HttpStatus::NoContent => write!(f, "204 No Content"),
-            HttpStatus::NonAuthoritativeInformation => {
-                write!(f, "203 No Authoritative Information")
-            }
-            HttpStatus::NotAcceptable => write!(f, "406 Not Acceptable"),
-            HttpStatus::NotFound => write!(f, "404 Not Found"),
-            HttpStatus::NotImplemented => write!(f, "501 Not Implemented"),
-            HttpStatus::NotModified => write!(f, "304 NotModified"),
-            HttpStatus::OK => write!(f, "200 OK"),
-            HttpStatus::PartialContent => write!(f, "206 Partial Content"),
-            HttpStatus::PaymentRequired => write!(f, "402 Payment Required"),
-            HttpStatus::PreconditionFailed => write!(f, "412 Precondition Failed"),
-            HttpStatus::ProxyAuthenticationRequired => {
-                write!(f, "407 Prozy Authentication Required")
-            }
-            HttpStatus::RequestEntityTooLarge => write!(f, "413 Request Entity Too Large"),
-            HttpStatus::RequestTimeout => write!(f, "408 Request Timeout"),
-            HttpStatus::RequestUriTooLong => write!(f, "414 Request URI Too Long"),
-            HttpStatus::RequestedRangeNotSatisfiable => {
-                write!(f, "416 Requested Range Not Satisfiable")
-            }
-            HttpStatus::ResetContent => write!(f, "205 Reset Content"),
-            HttpStatus::SeeOther => write!(f, "303 See Other"),
-            HttpStatus::ServiceUnavailable => write!(f, "503 Service Unavailable"),
-            HttpStatus::SwitchingProtocols => write!(f, "101 Switching Protocols"),
-            HttpStatus::TemporaryRedirect => write!(f, "307 Temporary Redirect"),
-            HttpStatus::Unauthorized => write!(f, "401 Unauthorized"),
-            HttpStatus::UnsupportedMediaType => write!(f, "415 Unsupported Media Type"),
-            HttpStatus::UseProxy => write!(f, "305 Use Proxy"),
-            HttpStatus::Unknown(v) => write!(f, "{}", v),
+        match self.reason_phrase() {
+            Some(phrase) => write!(f, "{} {}", self.as_u16(), phrase),
+            None => write!(f, "{}", self.as_u16()),
         }
     }
 }
 
 #[cfg(test)]
 mod http_status_tests {
-    use super::HttpStatus;
+    use super::{HttpStatus, STATUSES};
     use std::string::ToString;
 
     #[test]
@@ -885,10 +1309,55 @@ mod http_status_tests {
         );
     }
 
+    #[test]
+    fn is_interim() {
+        assert!(HttpStatus::Continue.is_interim());
+        assert!(HttpStatus::EarlyHints.is_interim());
+        assert!(!HttpStatus::SwitchingProtocols.is_interim());
+        assert!(!HttpStatus::OK.is_interim());
+        assert!(!HttpStatus::Unknown(299).is_interim());
+    }
+
     #[test]
     fn parse_error() {
         assert!("abc".parse::<HttpStatus>().is_err());
         assert!("301a".parse::<HttpStatus>().is_err());
+        assert!("99".parse::<HttpStatus>().is_err());
+        assert!("1000".parse::<HttpStatus>().is_err());
+    }
+
+    #[test]
+    fn codes() {
+        for &(status, code, phrase) in STATUSES.iter() {
+            assert_eq!(HttpStatus::from_u16(code), status);
+            assert_eq!(status.as_u16(), code);
+            assert_eq!(status.reason_phrase(), Some(phrase));
+        }
+        assert_eq!(HttpStatus::from_u16(418), HttpStatus::ImATeapot);
+        assert_eq!(
+            "451 Unavailable For Legal Reasons"
+                .parse::<HttpStatus>()
+                .unwrap(),
+            HttpStatus::UnavailableForLegalReasons
+        );
+        assert_eq!(HttpStatus::from_u16(599), HttpStatus::Unknown(599));
+        assert_eq!(HttpStatus::Unknown(599).as_u16(), 599);
+        assert_eq!(HttpStatus::Unknown(599).reason_phrase(), None);
+        assert_eq!(
+            &HttpStatus::TooManyRequests.to_string(),
+            "429 Too Many Requests"
+        );
+    }
+
+    #[test]
+    fn classes() {
+        assert!(HttpStatus::EarlyHints.is_informational());
+        assert!(HttpStatus::NoContent.is_success());
+        assert!(HttpStatus::Unknown(299).is_success());
+        assert!(HttpStatus::PermanentRedirect.is_redirection());
+        assert!(HttpStatus::Unknown(499).is_client_error());
+        assert!(!HttpStatus::Unknown(499).is_server_error());
+        assert!(HttpStatus::Unknown(520).is_server_error());
     }
 
     #[test]
@@ -937,7 +1406,7 @@ mod http_status_tests {
         assert_eq!(&HttpStatus::NoContent.to_string(), "204 No Content");
         assert_eq!(
             &HttpStatus::NonAuthoritativeInformation.to_string(),
-            "203 No Authoritative Information"
+            "203 Non-Authoritative Information"
         );
         assert_eq!(&HttpStatus::NotAcceptable.to_string(), "406 Not Acceptable");
         assert_eq!(&HttpStatus::NotFound.to_string(), "404 Not Found");
@@ -945,7 +1414,7 @@ mod http_status_tests {
             &HttpStatus::NotImplemented.to_string(),
             "501 Not Implemented"
         );
-        assert_eq!(&HttpStatus::NotModified.to_string(), "304 NotModified");
+        assert_eq!(&HttpStatus::NotModified.to_string(), "304 Not Modified");
         assert_eq!(&HttpStatus::OK.to_string(), "200 OK");
         assert_eq!(
             &HttpStatus::PartialContent.to_string(),
@@ -961,7 +1430,7 @@ mod http_status_tests {
         );
         assert_eq!(
             &HttpStatus::ProxyAuthenticationRequired.to_string(),
-            "407 Prozy Authentication Required",
+            "407 Proxy Authentication Required",
         );
         assert_eq!(
             &HttpStatus::RequestEntityTooLarge.to_string(),
@@ -1101,11 +1570,11 @@ mod http_status_tests {
             "204 No Content"
         );
         assert_eq!(
-            "203 No Authoritative Information"
+            "203 Non-Authoritative Information"
                 .parse::<HttpStatus>()
                 .unwrap()
                 .to_string(),
-            "203 No Authoritative Information"
+            "203 Non-Authoritative Information"
         );
         assert_eq!(
             "406 Not Acceptable"
@@ -1126,8 +1595,11 @@ mod http_status_tests {
             "501 Not Implemented"
         );
         assert_eq!(
-            "304 NotModified".parse::<HttpStatus>().unwrap().to_string(),
-            "304 NotModified"
+            "304 Not Modified"
+                .parse::<HttpStatus>()
+                .unwrap()
+                .to_string(),
+            "304 Not Modified"
         );
         assert_eq!(
             "200 OK".parse::<HttpStatus>().unwrap().to_string(),
@@ -1155,11 +1627,11 @@ mod http_status_tests {
             "412 Precondition Failed"
         );
         assert_eq!(
-            "407 Prozy Authentication Required"
+            "407 Proxy Authentication Required"
                 .parse::<HttpStatus>()
                 .unwrap()
                 .to_string(),
-            "407 Prozy Authentication Required"
+            "407 Proxy Authentication Required"
         );
         assert_eq!(
             "413 Request Entity Too Large"
@@ -1243,7 +1715,7 @@ mod http_status_tests {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct HttpHeader {
     key: String,
     value: String,
@@ -1289,24 +1761,227 @@ mod http_header_tests {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// The headers of a request or response, in the order they were added. Names are compared
+/// ignoring case, and a name can have several values, like `Set-Cookie`, which are sent as
+/// separate headers.
+#[derive(Debug, Clone, Default)]
 pub struct HttpHeaders {
-    headers: BTreeMap<String, String>,
+    headers: Vec<HttpHeader>,
 }
 
+/// Another name for `HttpHeaders`.
+pub type HeaderMap = HttpHeaders;
+
 impl HttpHeaders {
-    fn new() -> Self {
+    pub fn new() -> Self {
         HttpHeaders {
-            headers: BTreeMap::new(),
+            headers: Vec::new(),
         }
     }
 
+    /// The first value of the header with the given name.
     pub fn get(&self, key: &str) -> Option<&str> {
-        self.headers.get(key).map(convert::AsRef::as_ref)
+        self.headers
+            .iter()
+            .find(|h| h.key.eq_ignore_ascii_case(key))
+            .map(|h| h.value.as_str())
+    }
+
+    /// All the values of the header with the given name, in order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |h| h.key.eq_ignore_ascii_case(key))
+            .map(|h| h.value.as_str())
     }
 
+    /// Set the header with the given name to the value, replacing any values it had. It keeps the
+    /// place of its first value, if it had any.
     pub fn insert<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
-        self.headers.insert(key.into(), value.into());
+        let header = HttpHeader::new(key, value);
+        match self
+            .headers
+            .iter()
+            .position(|h| h.key.eq_ignore_ascii_case(&header.key))
+        {
+            Some(first) => {
+                let mut rest = self.headers.split_off(first + 1);
+                rest.retain(|h| !h.key.eq_ignore_ascii_case(&header.key));
+                self.headers[first] = header;
+                self.headers.append(&mut rest);
+            }
+            None => self.headers.push(header),
+        }
+    }
+
+    /// Add a value to the header with the given name, after any it already has.
+    pub fn append<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        self.headers.push(HttpHeader::new(key, value));
+    }
+
+    /// Remove the header with the given name, returning its first value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let first = self
+            .headers
+            .iter()
+            .position(|h| h.key.eq_ignore_ascii_case(key))?;
+        let value = self.headers.remove(first).value;
+        self.headers.retain(|h| !h.key.eq_ignore_ascii_case(key));
+        Some(value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// The names of all the headers, each once, in the order they were first added.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.headers
+            .iter()
+            .enumerate()
+            .filter(move |(i, h)| {
+                !self.headers[..*i]
+                    .iter()
+                    .any(|earlier| earlier.key.eq_ignore_ascii_case(&h.key))
+            })
+            .map(|(_, h)| h.key.as_str())
+    }
+
+    /// The name and value of each header, in order, with a name appearing once for each of its
+    /// values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers
+            .iter()
+            .map(|h| (h.key.as_str(), h.value.as_str()))
+    }
+
+    /// The length of the body from the `Content-Length` header. Fails with `Error::ParseError` if
+    /// it isn't a number, or if it is given more than once with different values.
+    pub fn content_length(&self) -> Result<Option<u64>> {
+        content_length(self.get_all("Content-Length"))
+    }
+
+    pub fn set_content_length(&mut self, length: u64) {
+        self.insert("Content-Length", format!("{}", length));
+    }
+
+    /// The media type and charset from the `Content-Type` header. Fails with `Error::ParseError`
+    /// if it isn't a media type like `text/html`.
+    pub fn content_type(&self) -> Result<Option<ContentType>> {
+        let value = match self.get("Content-Type") {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let mut parts = value.split(';');
+        let media_type = parts.next().unwrap_or("").trim();
+        match media_type.split_once('/') {
+            Some((kind, subtype)) if is_token(kind) && is_token(subtype) => {}
+            _ => {
+                return Err(Error::ParseError(format!(
+                    "Invalid Content-Type {:?}",
+                    value
+                )))
+            }
+        }
+        let charset = parts.find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            if !name.trim().eq_ignore_ascii_case("charset") {
+                return None;
+            }
+            Some(value.trim().trim_matches('"').to_ascii_lowercase())
+        });
+        Ok(Some(ContentType {
+            media_type: media_type.to_ascii_lowercase(),
+            charset,
+        }))
+    }
+
+    /// Set the `Content-Type` header to the media type, with the charset if one is given.
+    pub fn set_content_type(&mut self, media_type: &str, charset: Option<&str>) {
+        let value = match charset {
+            Some(charset) => format!("{}; charset={}", media_type, charset),
+            None => media_type.into(),
+        };
+        self.insert("Content-Type", value);
+    }
+
+    /// The date in a header holding an HTTP date, like `Date`, `Last-Modified` or `Expires`, as
+    /// seconds since the unix epoch. Returns `None` if there isn't one or it isn't a valid date.
+    /// See `date::parse_http_date`.
+    pub fn http_date(&self, key: &str) -> Option<u64> {
+        crate::date::parse_http_date(self.get(key)?)
+    }
+
+    /// Set the header to the date, given as seconds since the unix epoch.
+    pub fn set_http_date<K: Into<String>>(&mut self, key: K, secs: u64) {
+        self.insert(key, crate::date::format_http_date(secs));
+    }
+
+    /// The transfer codings from the `Transfer-Encoding` header, in the order they were applied
+    /// and in lower case, like `["gzip", "chunked"]`.
+    pub fn transfer_encoding(&self) -> Vec<String> {
+        self.get_all("Transfer-Encoding")
+            .flat_map(|v| v.split(','))
+            .map(|coding| coding.trim().to_ascii_lowercase())
+            .filter(|coding| !coding.is_empty())
+            .collect()
+    }
+
+    /// Whether the body uses the chunked transfer coding, which must come last.
+    pub fn is_chunked(&self) -> bool {
+        is_chunked(self.get_all("Transfer-Encoding"))
+    }
+
+    /// The options from the `Connection` header in lower case, like `["close"]`, or the names of
+    /// headers only meant for the next hop.
+    pub fn connection(&self) -> Vec<String> {
+        self.get_all("Connection")
+            .flat_map(|v| v.split(','))
+            .map(|option| option.trim().to_ascii_lowercase())
+            .filter(|option| !option.is_empty())
+            .collect()
+    }
+
+    /// The host and port from the `Host` header. IPv6 addresses keep their brackets, like
+    /// `[::1]`. Fails with `Error::ParseError` if it isn't a valid host.
+    pub fn host(&self) -> Result<Option<(&str, Option<u16>)>> {
+        let value = match self.get("Host") {
+            Some(value) => value.trim(),
+            None => return Ok(None),
+        };
+        let invalid = || Error::ParseError(format!("Invalid Host {:?}", value));
+        let (host, port) = match value.strip_prefix('[') {
+            Some(rest) => {
+                let end = rest.find(']').ok_or_else(invalid)? + 2;
+                (&value[..end], &value[end..])
+            }
+            None => match value.rfind(':') {
+                Some(colon) => (&value[..colon], &value[colon..]),
+                None => (value, ""),
+            },
+        };
+        let port = match port {
+            "" => None,
+            port => Some(
+                port.strip_prefix(':')
+                    .filter(|port| port.bytes().all(|b| b.is_ascii_digit()))
+                    .and_then(|port| port.parse().ok())
+                    .ok_or_else(invalid)?,
+            ),
+        };
+        if host.is_empty() || host.contains(|c: char| c.is_whitespace() || c == '/' || c == '@') {
+            return Err(invalid());
+        }
+        Ok(Some((host, port)))
+    }
+
+    /// Set the `Host` header to the host, and the port if one is given.
+    pub fn set_host(&mut self, host: &str, port: Option<u16>) {
+        let value = match port {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.into(),
+        };
+        self.insert("Host", value);
     }
 
     fn deserialize<R: core2::io::Read>(s: &mut CrLfStream<R>) -> Result<Self> {
@@ -1326,26 +2001,111 @@ impl HttpHeaders {
     }
 
     fn serialize<W: core2::io::Write>(&self, mut w: W) -> Result<()> {
-        for (key, value) in &self.headers {
+        for HttpHeader { key, value } in &self.headers {
             write!(&mut w, "{}: {}\r\n", key, value)?;
         }
         Ok(())
     }
 }
 
-impl From<Vec<HttpHeader>> for HttpHeaders {
-    fn from(mut headers: Vec<HttpHeader>) -> Self {
-        let mut map = BTreeMap::new();
-        for h in headers.drain(..) {
-            map.insert(h.key, h.value);
+/// Headers are equal if they have the same values in the same order, whatever the case of their
+/// names.
+impl PartialEq for HttpHeaders {
+    fn eq(&self, other: &Self) -> bool {
+        self.headers.len() == other.headers.len()
+            && self
+                .headers
+                .iter()
+                .zip(&other.headers)
+                .all(|(a, b)| a.key.eq_ignore_ascii_case(&b.key) && a.value == b.value)
+    }
+}
+
+impl Eq for HttpHeaders {}
+
+/// The value of a `Content-Type` header. See `HttpHeaders::content_type`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentType {
+    /// The media type without parameters, in lower case, like `text/html`.
+    pub media_type: String,
+    /// The `charset` parameter in lower case, like `utf-8`, if there is one.
+    pub charset: Option<String>,
+}
+
+/// Whether the string is a token, which can't be empty or have separators (RFC 7230 section
+/// 3.2.6), like a method or a header name.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// The length of the body from the values of the `Content-Length` header. See
+/// `HttpHeaders::content_length`.
+fn content_length<'a>(values: impl Iterator<Item = &'a str>) -> Result<Option<u64>> {
+    let mut length = None;
+    // A list of the same length is allowed too (RFC 7230 section 3.3.2).
+    for value in values.flat_map(|v| v.split(',')) {
+        let value = value.trim();
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Error::ParseError(format!(
+                "Invalid Content-Length {:?}",
+                value
+            )));
+        }
+        let value = value.parse()?;
+        if length.map_or(false, |length| length != value) {
+            return Err(Error::ParseError(
+                "Conflicting Content-Length values".into(),
+            ));
         }
-        HttpHeaders { headers: map }
+        length = Some(value);
+    }
+    Ok(length)
+}
+
+/// Whether a response with the given status to a request with the given method, if known, has a
+/// body (RFC 7230 section 3.3.3). Responses to HEAD, 1xx, 204 No Content and 304 Not Modified
+/// don't.
+pub(crate) fn response_has_body(status: HttpStatus, method: Option<HttpMethod>) -> bool {
+    method != Some(HttpMethod::Head)
+        && !status.is_informational()
+        && status != HttpStatus::NoContent
+        && status != HttpStatus::NotModified
+}
+
+/// Whether the last of the codings in the values of the `Transfer-Encoding` header is chunked.
+fn is_chunked<'a>(values: impl Iterator<Item = &'a str>) -> bool {
+    values
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty())
+        .last()
+        .map_or(false, |coding| coding.eq_ignore_ascii_case("chunked"))
+}
+
+/// The body of a request with the given headers. A request can't be ended by closing the
+/// connection, so other transfer codings than chunked are refused.
+fn request_body<S: core2::io::Read>(headers: &HttpHeaders, body: S) -> Result<HttpBody<S>> {
+    if !headers.is_chunked() && headers.get("Transfer-Encoding").is_some() {
+        return Err(unframed_request());
+    }
+    HttpBody::from_headers(headers, body)
+}
+
+fn unframed_request() -> Error {
+    Error::BadRequest("request body must use chunked as the final encoding".into())
+}
+
+impl From<Vec<HttpHeader>> for HttpHeaders {
+    fn from(headers: Vec<HttpHeader>) -> Self {
+        HttpHeaders { headers }
     }
 }
 
 #[cfg(test)]
 mod http_headers_tests {
-    use super::{CrLfStream, HttpHeader, HttpHeaders};
+    use super::{ContentType, CrLfStream, HttpHeader, HttpHeaders};
     use std::str;
 
     #[test]
@@ -1381,56 +2141,234 @@ mod http_headers_tests {
             HttpHeaders::from(vec![HttpHeader::new("a", "b e"), HttpHeader::new("c", "d")]);
         assert_eq!(actual, expected);
     }
-}
 
-pub struct HttpResponse<B: core2::io::Read> {
-    version: HttpVersion,
-    pub status: HttpStatus,
-    pub headers: HttpHeaders,
-    pub body: HttpBody<B>,
-}
+    #[test]
+    fn case_insensitive() {
+        let mut headers = HttpHeaders::new();
+        headers.insert("Content-Type", "text/plain");
+        assert_eq!(headers.get("content-type"), Some("text/plain"));
+        headers.insert("CONTENT-TYPE", "text/html");
+        assert_eq!(headers.get("Content-Type"), Some("text/html"));
+        assert_eq!(headers.keys().collect::<Vec<_>>(), ["CONTENT-TYPE"]);
+        assert_eq!(headers.remove("content-type"), Some("text/html".into()));
+        assert!(headers.is_empty());
+    }
 
-impl HttpResponse<Box<dyn core2::io::Read>> {
-    pub fn from_string<S: Into<String>>(status: HttpStatus, s: S) -> Self {
-        HttpResponse::new(status, Box::new(io::Cursor::new(s.into())))
+    #[test]
+    fn multiple_values() {
+        let mut input =
+            CrLfStream::new("Set-Cookie: a=1\r\nHost: a.com\r\nSet-Cookie: b=2\r\n\r\n".as_bytes());
+        let mut headers = HttpHeaders::deserialize(&mut input).unwrap();
+        assert_eq!(headers.get("set-cookie"), Some("a=1"));
+        assert_eq!(
+            headers.get_all("Set-Cookie").collect::<Vec<_>>(),
+            ["a=1", "b=2"]
+        );
+        assert_eq!(headers.keys().collect::<Vec<_>>(), ["Set-Cookie", "Host"]);
+
+        headers.append("Set-Cookie", "c=3");
+        let mut data = Vec::new();
+        headers.serialize(&mut data).unwrap();
+        assert_eq!(
+            str::from_utf8(&data).unwrap(),
+            "Set-Cookie: a=1\r\nHost: a.com\r\nSet-Cookie: b=2\r\nSet-Cookie: c=3\r\n"
+        );
+
+        // Inserting replaces all the values, in the place of the first.
+        headers.insert("Set-Cookie", "d=4");
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            [("Set-Cookie", "d=4"), ("Host", "a.com")]
+        );
     }
-}
 
-impl<B: core2::io::Read> HttpResponse<B> {
-    pub fn new(status: HttpStatus, body: B) -> Self {
-        let body = HttpBody::ReadTilClose(io::BufReader::new(body));
-        HttpResponse {
-            version: HttpVersion::new(1, 1),
-            status,
-            headers: HttpHeaders::new(),
-            body,
+    fn headers(pairs: &[(&str, &str)]) -> HttpHeaders {
+        let mut headers = HttpHeaders::new();
+        for (key, value) in pairs {
+            headers.append(*key, *value);
         }
+        headers
     }
 
-    pub fn deserialize(mut socket: B) -> Result<Self> {
-        let mut s = CrLfStream::new(&mut socket);
-        let first_line = s.expect_next()?;
-        let mut parser = Parser::new(&first_line);
-
-        let version = parser.parse_token()?.parse()?;
-        let status = parser.parse_remaining()?.parse()?;
+    #[test]
+    fn content_length() {
+        assert_eq!(headers(&[]).content_length().unwrap(), None);
+        let length = |value| headers(&[("content-length", value)]).content_length();
+        assert_eq!(length("42").unwrap(), Some(42));
+        assert_eq!(length("42, 42").unwrap(), Some(42));
+        assert!(length("42, 43").is_err());
+        assert!(length("+42").is_err());
+        assert!(length("").is_err());
+        let twice = headers(&[("Content-Length", "1"), ("Content-Length", "2")]);
+        assert!(twice.content_length().is_err());
+
+        let mut headers = HttpHeaders::new();
+        headers.set_content_length(7);
+        assert_eq!(headers.get("Content-Length"), Some("7"));
+    }
 
-        let headers = HttpHeaders::deserialize(&mut s)?;
-        drop(s);
+    #[test]
+    fn content_type() {
+        let content_type = |value| headers(&[("Content-Type", value)]).content_type();
+        assert_eq!(
+            content_type("Text/HTML; charset=\"UTF-8\"").unwrap(),
+            Some(ContentType {
+                media_type: "text/html".into(),
+                charset: Some("utf-8".into()),
+            })
+        );
+        assert_eq!(
+            content_type("application/json").unwrap().unwrap().charset,
+            None
+        );
+        assert!(content_type("text").is_err());
+        assert!(content_type("text/ html").is_err());
+
+        let mut headers = HttpHeaders::new();
+        headers.set_content_type("text/plain", Some("utf-8"));
+        assert_eq!(
+            headers.get("Content-Type"),
+            Some("text/plain; charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn transfer_encoding_and_connection() {
+        let headers = headers(&[
+            ("Transfer-Encoding", "GZIP"),
+            ("transfer-encoding", "chunked"),
+            ("Connection", "keep-alive, Upgrade"),
+        ]);
+        assert_eq!(headers.transfer_encoding(), ["gzip", "chunked"]);
+        assert!(headers.is_chunked());
+        assert_eq!(headers.connection(), ["keep-alive", "upgrade"]);
+        assert!(!HttpHeaders::new().is_chunked());
+    }
+
+    #[test]
+    fn http_date() {
+        let mut h = headers(&[
+            ("Last-Modified", "Sunday, 06-Nov-94 08:49:37 GMT"),
+            ("Expires", "0"),
+        ]);
+        assert_eq!(h.http_date("last-modified"), Some(784_111_777));
+        assert_eq!(h.http_date("Expires"), None);
+        assert_eq!(h.http_date("Date"), None);
+        h.set_http_date("Date", 0);
+        assert_eq!(h.get("Date"), Some("Thu, 01 Jan 1970 00:00:00 GMT"));
+    }
+
+    #[test]
+    fn host() {
+        let host = |value| {
+            headers(&[("Host", value)])
+                .host()
+                .map(|h| h.map(|(h, p)| (h.to_owned(), p)))
+        };
+        assert_eq!(host("a.com").unwrap(), Some(("a.com".into(), None)));
+        assert_eq!(
+            host("a.com:8080").unwrap(),
+            Some(("a.com".into(), Some(8080)))
+        );
+        assert_eq!(host("[::1]:80").unwrap(), Some(("[::1]".into(), Some(80))));
+        assert_eq!(host("[::1]").unwrap(), Some(("[::1]".into(), None)));
+        assert!(host("a.com:").is_err());
+        assert!(host("a.com:http").is_err());
+        assert!(host("a.com/b").is_err());
+        assert!(host("user@a.com").is_err());
+        assert_eq!(HttpHeaders::new().host().unwrap(), None);
+
+        let mut headers = HttpHeaders::new();
+        headers.set_host("a.com", Some(8080));
+        assert_eq!(headers.get("Host"), Some("a.com:8080"));
+    }
+}
+
+pub struct HttpResponse<B: core2::io::Read> {
+    /// The version of the status line. Responses with `HttpVersion::Http09` are serialized
+    /// without a status line or headers.
+    pub version: HttpVersion,
+    pub status: HttpStatus,
+    /// The reason phrase to send instead of the status's, or the one the server sent.
+    reason: Option<String>,
+    pub headers: HttpHeaders,
+    pub body: HttpBody<B>,
+    /// Headers sent after the body. They can only be sent when the body uses the chunked transfer
+    /// coding, otherwise they are dropped.
+    pub trailers: HttpHeaders,
+}
+
+impl HttpResponse<Box<dyn core2::io::Read>> {
+    pub fn from_string<S: Into<String>>(status: HttpStatus, s: S) -> Self {
+        HttpResponse::new(status, Box::new(io::Cursor::new(s.into())))
+    }
+}
+
+impl<B: core2::io::Read> HttpResponse<B> {
+    pub fn new(status: HttpStatus, body: B) -> Self {
+        let body = HttpBody::ReadTilClose(body);
+        HttpResponse {
+            version: HttpVersion::Http11,
+            status,
+            reason: None,
+            headers: HttpHeaders::new(),
+            body,
+            trailers: HttpHeaders::new(),
+        }
+    }
+
+    pub fn deserialize(socket: B) -> Result<Self> {
+        Self::deserialize_with_header_limit(socket, usize::MAX)
+    }
+
+    /// Like `deserialize`, but fails with `Error::HeadersTooLarge` if the status line and headers
+    /// are longer than `limit` bytes.
+    pub fn deserialize_with_header_limit(mut socket: B, limit: usize) -> Result<Self> {
+        let mut head = (&mut socket).take(limit as u64);
+        let mut s = CrLfStream::new(&mut head);
+        let parsed = (|| -> Result<_> {
+            let first_line = s.expect_next()?;
+            let mut parser = Parser::new(&first_line);
 
-        let encoding = headers.get("Transfer-Encoding");
-        let content_length = headers.get("Content-Length").map(str::parse).transpose()?;
+            let version: HttpVersion = parser.parse_token()?.parse()?;
+            let remaining = parser.parse_remaining()?;
+            let status: HttpStatus = remaining.parse()?;
+            let reason = remaining.split_once(' ').map(|(_, reason)| reason.into());
 
-        let body = HttpBody::new(encoding, content_length, io::BufReader::new(socket));
+            let headers = HttpHeaders::deserialize(&mut s)?;
+            Ok((version, status, reason, headers))
+        })();
+        drop(s);
+        let (version, status, reason, headers) = match parsed {
+            Err(_) if head.limit() == 0 => return Err(Error::HeadersTooLarge(limit)),
+            parsed => parsed?,
+        };
+
+        let body = HttpBody::from_headers(&headers, socket)?;
 
         Ok(HttpResponse {
             version,
             status,
+            reason,
             headers,
             body,
+            trailers: HttpHeaders::new(),
         })
     }
 
+    /// The reason phrase of the status line: the one set with `set_reason_phrase` or sent by the
+    /// server, and otherwise the status's canonical one, if it has one.
+    pub fn reason_phrase(&self) -> Option<&str> {
+        self.reason
+            .as_deref()
+            .or_else(|| self.status.reason_phrase())
+    }
+
+    /// Send the reason phrase instead of the status's canonical one, for clients which look at it.
+    pub fn set_reason_phrase<S: Into<String>>(&mut self, reason: S) {
+        self.reason = Some(reason.into());
+    }
+
     pub fn get_header(&self, key: &str) -> Option<&str> {
         self.headers.get(key)
     }
@@ -1439,19 +2377,150 @@ impl<B: core2::io::Read> HttpResponse<B> {
         self.headers.insert(key, value);
     }
 
+    /// Add a header to send after the body. See `HttpResponse::trailers`.
+    pub fn add_trailer<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        self.trailers.insert(key, value);
+    }
+
     pub fn serialize<W: core2::io::Write>(&self, mut w: W) -> Result<()> {
-        write!(&mut w, "{} {}\r\n", self.version, self.status)?;
+        if self.version == HttpVersion::Http09 {
+            return Ok(());
+        }
+        // The space after the code is needed even without a reason phrase.
+        let phrase = self.reason_phrase().unwrap_or("");
+        if phrase.contains(|c| c == '\r' || c == '\n') {
+            return Err(Error::Other("line break in reason phrase".into()));
+        }
+        write!(
+            &mut w,
+            "{} {} {}\r\n",
+            self.version,
+            self.status.as_u16(),
+            phrase
+        )?;
         self.headers.serialize(&mut w)?;
         write!(&mut w, "\r\n")?;
         Ok(())
     }
+
+    /// Serialize the whole response, reading its body, into a new `Vec`. See `serialize_into`.
+    pub fn serialize_to_vec(&mut self) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.serialize(&mut buffer)?;
+        write_body(&self.headers, &mut self.body, &self.trailers, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Serialize the whole response, reading its body, into the buffer, for sending over a
+    /// transport which isn't a `Write`. Returns the length of the response. The body is encoded
+    /// with the chunked transfer coding, followed by the trailers, if the `Transfer-Encoding`
+    /// header says so, and otherwise sent as it is. Fails with `Error::BufferTooSmall` if the
+    /// buffer can't hold the response.
+    pub fn serialize_into(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        let mut w = SliceWriter::new(buffer);
+        let result = self
+            .serialize(&mut w)
+            .and_then(|_| write_body(&self.headers, &mut self.body, &self.trailers, &mut w));
+        w.finish(result)
+    }
+
+    /// Read the whole body. Fails with `Error::BodyTooLarge` if it is longer than `limit` bytes.
+    pub fn bytes(mut self, limit: usize) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        self.body
+            .by_ref()
+            .take(limit as u64 + 1)
+            .read_to_end(&mut body)?;
+        if body.len() > limit {
+            return Err(Error::BodyTooLarge(limit));
+        }
+        Ok(body)
+    }
+
+    /// Read the whole body as text, decoded using the charset from the `Content-Type` header.
+    /// UTF-8, US-ASCII and ISO-8859-1 are supported, and UTF-8 is assumed without a charset.
+    /// Fails with `Error::BodyTooLarge` if the body is longer than `limit` bytes.
+    pub fn text(self, limit: usize) -> Result<String> {
+        let charset = match self.headers.content_type() {
+            Ok(Some(ContentType {
+                charset: Some(charset),
+                ..
+            })) => charset,
+            _ => "utf-8".into(),
+        };
+        let body = self.bytes(limit)?;
+        match charset.as_str() {
+            "utf-8" | "utf8" | "us-ascii" | "ascii" => {
+                String::from_utf8(body).map_err(|e| Error::Utf8Error(e.utf8_error()))
+            }
+            "iso-8859-1" | "latin1" => Ok(body.iter().map(|&b| b as char).collect()),
+            c => Err(Error::Other(format!("unsupported charset {}", c))),
+        }
+    }
+
+    /// The methods in the `Allow` header, which is sent in response to OPTIONS requests and with
+    /// 405 Method Not Allowed. Methods `HttpMethod` doesn't have are skipped; read the header
+    /// itself to see them.
+    pub fn allowed_methods(&self) -> Vec<HttpMethod> {
+        self.get_header("Allow")
+            .map(|allow| {
+                allow
+                    .split(',')
+                    .filter_map(|method| method.trim().parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether the status is 304 Not Modified, the answer to a conditional request for a resource
+    /// which hasn't changed. The response has no body, and the version already held is current.
+    pub fn is_not_modified(&self) -> bool {
+        self.status == HttpStatus::NotModified
+    }
+
+    /// The stream the response was read from. See `HttpBody::into_inner`.
+    ///
+    /// For the responses of `client::send_request` and `OutgoingBody::finish`, it is the buffered
+    /// connection, whose buffer may already hold bytes sent after the body. Once the body was read
+    /// to the end, the connection can be used for another request, if the server keeps it open.
+    pub fn into_inner(self) -> Option<B> {
+        self.body.into_inner()
+    }
+
+    /// The status, headers and body of the response.
+    pub fn into_parts(self) -> (HttpStatus, HttpHeaders, HttpBody<B>) {
+        (self.status, self.headers, self.body)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<B: core2::io::Read> HttpResponse<B> {
+    /// Read the whole body and deserialize it from JSON. Fails with `Error::BodyTooLarge` if the
+    /// body is longer than `limit` bytes.
+    ///
+    /// *This function is available if http_io is built with the `"json"` feature.*
+    pub fn json<T: serde::de::DeserializeOwned>(self, limit: usize) -> Result<T> {
+        let body = self.bytes(limit)?;
+        Ok(serde_json::from_slice(&body)?)
+    }
 }
 
 #[cfg(test)]
 mod http_response_tests {
-    use super::{HttpResponse, HttpStatus};
+    use super::{response_has_body, HttpMethod, HttpResponse, HttpStatus, HttpVersion};
+    use crate::error::Error;
     use std::io;
 
+    #[test]
+    fn bodiless_responses() {
+        assert!(response_has_body(HttpStatus::OK, Some(HttpMethod::Get)));
+        assert!(response_has_body(HttpStatus::OK, None));
+        assert!(!response_has_body(HttpStatus::OK, Some(HttpMethod::Head)));
+        assert!(!response_has_body(HttpStatus::EarlyHints, None));
+        assert!(!response_has_body(HttpStatus::NoContent, None));
+        assert!(!response_has_body(HttpStatus::NotModified, None));
+    }
+
     #[test]
     fn parse_success() {
         let input = "HTTP/1.1 200 OK\r\nA: B\r\nC: D\r\n\r\n".as_bytes();
@@ -1463,45 +2532,292 @@ mod http_response_tests {
         assert_eq!(actual.status, expected.status);
         assert_eq!(actual.headers, expected.headers);
     }
+
+    #[test]
+    fn unknown_status() {
+        for (line, code) in [
+            ("HTTP/1.1 499 Client Closed Request", 499),
+            ("HTTP/1.1 520 ", 520),
+            ("HTTP/1.1 299", 299),
+        ] {
+            let input = format!("{}\r\nContent-Length: 0\r\n\r\n", line);
+            let response = HttpResponse::deserialize(input.as_bytes()).unwrap();
+            assert_eq!(response.status, HttpStatus::Unknown(code));
+        }
+
+        let response = HttpResponse::new(HttpStatus::Unknown(520), io::empty());
+        let mut output = Vec::new();
+        response.serialize(&mut output).unwrap();
+        assert_eq!(output, b"HTTP/1.1 520 \r\n\r\n");
+    }
+
+    #[test]
+    fn reason_phrase() {
+        let input = "HTTP/1.1 200 Okey Dokey\r\n\r\n".as_bytes();
+        let response = HttpResponse::deserialize(input).unwrap();
+        assert_eq!(response.status, HttpStatus::OK);
+        assert_eq!(response.reason_phrase(), Some("Okey Dokey"));
+        let mut output = Vec::new();
+        response.serialize(&mut output).unwrap();
+        assert_eq!(output, b"HTTP/1.1 200 Okey Dokey\r\n\r\n");
+
+        let mut response = HttpResponse::new(HttpStatus::NotFound, io::empty());
+        assert_eq!(response.reason_phrase(), Some("Not Found"));
+        response.set_reason_phrase("Nothing Here");
+        let mut output = Vec::new();
+        response.serialize(&mut output).unwrap();
+        assert_eq!(output, b"HTTP/1.1 404 Nothing Here\r\n\r\n");
+
+        response.set_reason_phrase("Bad\r\nX-Injected: 1");
+        assert!(response.serialize(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn serialize_into() {
+        let mut response = HttpResponse::new(HttpStatus::OK, &b"hello"[..]);
+        response.add_header("Content-Length", "5");
+        let expected = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+        let mut buffer = [0; 64];
+        assert_eq!(
+            response.serialize_into(&mut buffer).unwrap(),
+            expected.len()
+        );
+        assert_eq!(&buffer[..expected.len()], expected);
+
+        let mut response = HttpResponse::new(HttpStatus::OK, &b"hello"[..]);
+        response.add_header("Content-Length", "5");
+        let mut buffer = [0; 40];
+        assert!(matches!(
+            response.serialize_into(&mut buffer),
+            Err(Error::BufferTooSmall(40))
+        ));
+    }
+
+    #[test]
+    fn serialize_to_vec_chunked() {
+        let mut response = HttpResponse::new(HttpStatus::OK, &b"hello"[..]);
+        response.add_header("Transfer-Encoding", "chunked");
+        response.add_trailer("Checksum", "abc");
+        assert_eq!(
+            response.serialize_to_vec().unwrap(),
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5\r\nhello\r\n0\r\nChecksum: abc\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn serialize_http_0_9() {
+        let mut response = HttpResponse::new(HttpStatus::OK, io::empty());
+        response.add_header("A", "B");
+        response.version = HttpVersion::Http09;
+        let mut output = Vec::new();
+        response.serialize(&mut output).unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn parse_header_limit() {
+        let input = "HTTP/1.1 200 OK\r\nA: B\r\n\r\nbody".as_bytes();
+        assert!(HttpResponse::deserialize_with_header_limit(input, 25).is_ok());
+        assert!(matches!(
+            HttpResponse::deserialize_with_header_limit(input, 24),
+            Err(Error::HeadersTooLarge(24))
+        ));
+    }
+
+    #[test]
+    fn allowed_methods() {
+        let mut response = HttpResponse::new(HttpStatus::OK, &b""[..]);
+        assert_eq!(response.allowed_methods(), vec![]);
+        response.add_header("Allow", "GET, head,PATCH , OPTIONS");
+        assert_eq!(
+            response.allowed_methods(),
+            vec![
+                HttpMethod::Get,
+                HttpMethod::Head,
+                HttpMethod::Patch,
+                HttpMethod::Options
+            ]
+        );
+    }
+
+    #[test]
+    fn is_not_modified() {
+        assert!(HttpResponse::new(HttpStatus::NotModified, &b""[..]).is_not_modified());
+        assert!(!HttpResponse::new(HttpStatus::OK, &b""[..]).is_not_modified());
+    }
+
+    fn response(content_type: &str, body: &'static [u8]) -> HttpResponse<&'static [u8]> {
+        let mut response = HttpResponse::new(HttpStatus::OK, body);
+        response.add_header("Content-Type", content_type);
+        response
+    }
+
+    #[test]
+    fn bytes() {
+        let body = response("application/octet-stream", b"\x00\x01\x02");
+        assert_eq!(body.bytes(3).unwrap(), vec![0, 1, 2]);
+        let body = response("application/octet-stream", b"\x00\x01\x02");
+        assert!(matches!(body.bytes(2), Err(Error::BodyTooLarge(2))));
+    }
+
+    #[test]
+    fn text_charsets() {
+        let body = response("text/plain", "caf\u{e9}".as_bytes());
+        assert_eq!(body.text(100).unwrap(), "caf\u{e9}");
+        let body = response("text/plain; charset=\"ISO-8859-1\"", b"caf\xe9");
+        assert_eq!(body.text(100).unwrap(), "caf\u{e9}");
+        let body = response("text/plain; charset=utf-8", b"caf\xe9");
+        assert!(matches!(body.text(100), Err(Error::Utf8Error(_))));
+        let body = response("text/plain; charset=shift_jis", b"a");
+        assert!(body.text(100).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json() {
+        let body = response("application/json", b"[1, 2, 3]");
+        assert_eq!(body.json::<Vec<u32>>(100).unwrap(), vec![1, 2, 3]);
+        let body = response("application/json", b"[1, 2,");
+        assert!(matches!(
+            body.json::<Vec<u32>>(100),
+            Err(Error::JsonError(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod chunked_writer_tests {
+    use super::{ChunkedWriter, HttpHeaders};
+    use core2::io::Write;
+
+    #[test]
+    fn finish_without_trailers() {
+        let mut output = vec![];
+        let mut w = ChunkedWriter::new(&mut output);
+        w.write_all(b"hello").unwrap();
+        w.finish().unwrap();
+        assert_eq!(output, b"5\r\nhello\r\n0\r\n\r\n");
+    }
+
+    #[test]
+    fn finish_with_trailers() {
+        let mut trailers = HttpHeaders::new();
+        trailers.insert("Content-MD5", "abc");
+
+        let mut output = vec![];
+        let mut w = ChunkedWriter::new(&mut output);
+        w.write_all(b"hello").unwrap();
+        w.finish_with_trailers(&trailers).unwrap();
+        assert_eq!(output, b"5\r\nhello\r\n0\r\nContent-MD5: abc\r\n\r\n");
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum HttpMethod {
+    Connect,
     Delete,
     Get,
     Head,
     Options,
+    Patch,
     Post,
     Put,
     Trace,
+    /// Any other method, like WebDAV's PROPFIND or REPORT.
+    Other(ExtensionMethod),
+}
+
+/// The name of a method `HttpMethod` has no variant for. It is kept inline, so that `HttpMethod`
+/// stays `Copy`, and so can be at most `ExtensionMethod::MAX_LENGTH` bytes long.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExtensionMethod {
+    length: u8,
+    name: [u8; ExtensionMethod::MAX_LENGTH],
+}
+
+impl ExtensionMethod {
+    pub const MAX_LENGTH: usize = 24;
+
+    /// Fails with `Error::ParseError` if the name is empty, too long, or has characters methods
+    /// can't have (RFC 7230 section 3.2.6). Method names are case-sensitive.
+    pub fn new(name: &str) -> Result<Self> {
+        if name.len() > Self::MAX_LENGTH || !is_token(name) {
+            return Err(Error::ParseError(format!("Invalid method {:?}", name)));
+        }
+        let mut method = ExtensionMethod {
+            length: name.len() as u8,
+            name: [0; Self::MAX_LENGTH],
+        };
+        method.name[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(method)
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ASCII is ever stored.
+        str::from_utf8(&self.name[..self.length as usize]).unwrap_or("")
+    }
+}
+
+impl fmt::Debug for ExtensionMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
 }
 
 impl str::FromStr for HttpMethod {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
-        match s.to_uppercase().as_ref() {
-            "DELETE" => Ok(HttpMethod::Delete),
-            "GET" => Ok(HttpMethod::Get),
-            "HEAD" => Ok(HttpMethod::Head),
-            "OPTIONS" => Ok(HttpMethod::Options),
-            "POST" => Ok(HttpMethod::Post),
-            "PUT" => Ok(HttpMethod::Put),
-            "TRACE" => Ok(HttpMethod::Trace),
-            m => Err(Error::ParseError(format!("Unknown method {}", m))),
-        }
+        // Compared without allocating, as this is done for every request.
+        const METHODS: [(&str, HttpMethod); 9] = [
+            ("CONNECT", HttpMethod::Connect),
+            ("DELETE", HttpMethod::Delete),
+            ("GET", HttpMethod::Get),
+            ("HEAD", HttpMethod::Head),
+            ("OPTIONS", HttpMethod::Options),
+            ("PATCH", HttpMethod::Patch),
+            ("POST", HttpMethod::Post),
+            ("PUT", HttpMethod::Put),
+            ("TRACE", HttpMethod::Trace),
+        ];
+        METHODS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, method)| *method)
+            .ok_or_else(|| Error::ParseError(format!("Unknown method {}", s.to_uppercase())))
+    }
+}
+
+impl HttpMethod {
+    /// A method `HttpMethod` has no variant for, like `HttpMethod::other("PROPFIND")`. See
+    /// `ExtensionMethod::new`.
+    pub fn other(name: &str) -> Result<Self> {
+        Ok(HttpMethod::Other(ExtensionMethod::new(name)?))
+    }
+
+    /// Whether sending the request more than once has the same effect as sending it once (RFC
+    /// 7231 section 4.2.2), so it can be retried. Other methods are assumed not to be.
+    pub fn is_idempotent(&self) -> bool {
+        !matches!(
+            self,
+            HttpMethod::Connect | HttpMethod::Patch | HttpMethod::Post | HttpMethod::Other(_)
+        )
     }
 }
 
 impl fmt::Display for HttpMethod {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            HttpMethod::Connect => write!(f, "CONNECT"),
             HttpMethod::Delete => write!(f, "DELETE"),
             HttpMethod::Get => write!(f, "GET"),
             HttpMethod::Head => write!(f, "HEAD"),
             HttpMethod::Options => write!(f, "OPTIONS"),
+            HttpMethod::Patch => write!(f, "PATCH"),
             HttpMethod::Post => write!(f, "POST"),
             HttpMethod::Put => write!(f, "PUT"),
             HttpMethod::Trace => write!(f, "TRACE"),
+            HttpMethod::Other(method) => write!(f, "{}", method.as_str()),
         }
     }
 }
@@ -1513,6 +2829,10 @@ mod http_method_tests {
 
     #[test]
     fn parse_success() {
+        assert_eq!(
+            "CONNECT".parse::<HttpMethod>().unwrap(),
+            HttpMethod::Connect
+        );
         assert_eq!("DELETE".parse::<HttpMethod>().unwrap(), HttpMethod::Delete);
         assert_eq!("GET".parse::<HttpMethod>().unwrap(), HttpMethod::Get);
         assert_eq!("HEAD".parse::<HttpMethod>().unwrap(), HttpMethod::Head);
@@ -1533,6 +2853,7 @@ mod http_method_tests {
 
     #[test]
     fn display() {
+        assert_eq!(&HttpMethod::Connect.to_string(), "CONNECT");
         assert_eq!(&HttpMethod::Delete.to_string(), "DELETE");
         assert_eq!(&HttpMethod::Get.to_string(), "GET");
         assert_eq!(&HttpMethod::Head.to_string(), "HEAD");
@@ -1544,6 +2865,10 @@ mod http_method_tests {
 
     #[test]
     fn parse_display_round_trip() {
+        assert_eq!(
+            &"CONNECT".parse::<HttpMethod>().unwrap().to_string(),
+            "CONNECT"
+        );
         assert_eq!(
             &"DELETE".parse::<HttpMethod>().unwrap().to_string(),
             "DELETE"
@@ -1558,14 +2883,48 @@ mod http_method_tests {
         assert_eq!(&"PUT".parse::<HttpMethod>().unwrap().to_string(), "PUT");
         assert_eq!(&"TRACE".parse::<HttpMethod>().unwrap().to_string(), "TRACE");
     }
+
+    #[test]
+    fn other() {
+        assert_eq!("patch".parse::<HttpMethod>().unwrap(), HttpMethod::Patch);
+        let propfind = HttpMethod::other("PROPFIND").unwrap();
+        assert_eq!(&propfind.to_string(), "PROPFIND");
+        assert_eq!(format!("{:?}", propfind), "Other(\"PROPFIND\")");
+        assert!(!propfind.is_idempotent());
+        assert!(HttpMethod::other("").is_err());
+        assert!(HttpMethod::other("BAD METHOD").is_err());
+        assert!(HttpMethod::other("VERSION-CONTROL-AND-MORE-STUFF").is_err());
+    }
+}
+
+/// The identity a peer authenticated itself with, e.g. from a verified TLS client certificate.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerIdentity {
+    /// The subject's distinguished name, e.g. `CN=device-1, O=Example`.
+    pub subject: String,
+    /// The subject alternative names: DNS names, email addresses, URIs and IP addresses.
+    pub subject_alt_names: Vec<String>,
+}
+
+/// Information about the connection a request was received on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// The verified identity of the peer, if it authenticated itself.
+    pub peer_identity: Option<PeerIdentity>,
+    /// The IP address of the peer, if the transport has one.
+    pub peer_address: Option<String>,
+    /// The application protocol negotiated with ALPN during the TLS handshake, like `h2`.
+    pub alpn_protocol: Option<String>,
 }
 
 pub struct HttpRequest<B: core2::io::Read> {
     pub method: HttpMethod,
     pub uri: String,
-    version: HttpVersion,
+    /// The version of the request line. Requests with `HttpVersion::Http09` have no headers.
+    pub version: HttpVersion,
     pub headers: HttpHeaders,
     pub body: HttpBody<B>,
+    pub connection: ConnectionInfo,
 }
 
 impl HttpRequest<io::Empty> {
@@ -1580,48 +2939,240 @@ impl HttpRequest<io::Empty> {
         HttpRequest {
             method,
             uri,
-            version: HttpVersion::new(1, 1),
+            version: HttpVersion::Http11,
             headers: HttpHeaders::new(),
-            body: HttpBody::ReadTilClose(io::BufReader::new(io::empty())),
+            body: HttpBody::ReadTilClose(io::empty()),
+            connection: ConnectionInfo::default(),
         }
     }
 }
 
-pub struct OutgoingBody<S: core2::io::Read + core2::io::Write> {
-    socket: io::BufWriter<S,1024>,
+/// Encodes everything written to it using the chunked transfer coding.
+pub struct ChunkedWriter<W: core2::io::Write> {
+    inner: W,
+}
+
+impl<W: core2::io::Write> ChunkedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ChunkedWriter { inner }
+    }
+
+    /// Write the last chunk and return the wrapped writer.
+    pub fn finish(self) -> Result<W> {
+        self.finish_with_trailers(&HttpHeaders::new())
+    }
+
+    /// Write the last chunk followed by the given trailers and return the wrapped writer.
+    pub fn finish_with_trailers(mut self, trailers: &HttpHeaders) -> Result<W> {
+        write!(&mut self.inner, "0\r\n")?;
+        trailers.serialize(&mut self.inner)?;
+        write!(&mut self.inner, "\r\n")?;
+        Ok(self.inner)
+    }
 }
 
-impl<S: core2::io::Read + core2::io::Write> core2::io::Write for OutgoingBody<S> {
+impl<W: core2::io::Write> core2::io::Write for ChunkedWriter<W> {
     fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
         let len = buf.len();
         if len == 0 {
             return Ok(0);
         }
-        write!(&mut self.socket, "{:x}\r\n", len)?;
-        self.socket.write_all(buf)?;
-        write!(&mut self.socket, "\r\n")?;
+        write!(&mut self.inner, "{:x}\r\n", len)?;
+        self.inner.write_all(buf)?;
+        write!(&mut self.inner, "\r\n")?;
         Ok(len)
     }
 
     fn flush(&mut self) -> core2::io::Result<()> {
-        self.socket.flush()
+        self.inner.flush()
+    }
+}
+
+/// Write the body of a message with the given headers, using the chunked transfer coding if they
+/// say so, in which case the trailers follow it.
+fn write_body<R: core2::io::Read, W: core2::io::Write>(
+    headers: &HttpHeaders,
+    body: &mut R,
+    trailers: &HttpHeaders,
+    mut w: W,
+) -> Result<()> {
+    if headers.is_chunked() {
+        let mut chunked = ChunkedWriter::new(&mut w);
+        io::copy(body, &mut chunked)?;
+        chunked.finish_with_trailers(trailers)?;
+    } else {
+        io::copy(body, &mut w)?;
+    }
+    Ok(())
+}
+
+/// Writes into a slice, remembering whether anything didn't fit.
+struct SliceWriter<'a> {
+    buffer: &'a mut [u8],
+    written: usize,
+    overflowed: bool,
+}
+
+impl<'a> SliceWriter<'a> {
+    fn new(buffer: &'a mut [u8]) -> Self {
+        SliceWriter {
+            buffer,
+            written: 0,
+            overflowed: false,
+        }
+    }
+
+    /// The number of bytes written if writing them had the given result, or
+    /// `Error::BufferTooSmall` if some didn't fit.
+    fn finish(self, result: Result<()>) -> Result<usize> {
+        if self.overflowed {
+            return Err(Error::BufferTooSmall(self.buffer.len()));
+        }
+        result?;
+        Ok(self.written)
+    }
+}
+
+impl core2::io::Write for SliceWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        let free = &mut self.buffer[self.written..];
+        if buf.len() > free.len() {
+            self.overflowed = true;
+            return Err(core2::io::Error::new(
+                core2::io::ErrorKind::WriteZero,
+                "buffer too small",
+            ));
+        }
+        free[..buf.len()].copy_from_slice(buf);
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> core2::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The body of a request being sent, written through a buffer of `W` bytes. The response is read
+/// through a buffer of `R` bytes once it is finished.
+pub struct OutgoingBody<
+    S: core2::io::Read + core2::io::Write,
+    const R: usize = 1024,
+    const W: usize = 1024,
+> {
+    socket: BodyWriter<io::BufWriter<S, W>>,
+}
+
+/// How an `OutgoingBody` frames the body it is sending.
+enum BodyWriter<W: core2::io::Write> {
+    Chunked(ChunkedWriter<W>),
+    /// The body has a `Content-Length`; this is how much of it is left to write.
+    Limited(W, u64),
+}
+
+impl<S: core2::io::Read + core2::io::Write, const R: usize, const W: usize> core2::io::Write
+    for OutgoingBody<S, R, W>
+{
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        match &mut self.socket {
+            BodyWriter::Chunked(w) => w.write(buf),
+            BodyWriter::Limited(w, remaining) => {
+                if buf.len() as u64 > *remaining {
+                    return Err(core2::io::Error::new(
+                        core2::io::ErrorKind::InvalidInput,
+                        "body longer than Content-Length",
+                    ));
+                }
+                let written = w.write(buf)?;
+                *remaining -= written as u64;
+                Ok(written)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> core2::io::Result<()> {
+        match &mut self.socket {
+            BodyWriter::Chunked(w) => w.flush(),
+            BodyWriter::Limited(w, _) => w.flush(),
+        }
     }
 }
 
-impl<S: core2::io::Read + core2::io::Write> OutgoingBody<S> {
-    fn new(socket: io::BufWriter<S,1024>) -> Self {
+impl<S: core2::io::Read + core2::io::Write, const R: usize, const W: usize> OutgoingBody<S, R, W> {
+    /// Write a body of `content_length` bytes, or a chunked body if it is `None`, to `socket`.
+    pub(crate) fn new(socket: io::BufWriter<S, W>, content_length: Option<u64>) -> Self {
+        let socket = match content_length {
+            Some(length) => BodyWriter::Limited(socket, length),
+            None => BodyWriter::Chunked(ChunkedWriter::new(socket)),
+        };
         OutgoingBody { socket }
     }
 
-    pub fn finish(mut self) -> Result<HttpResponse<S>> {
-        write!(&mut self.socket, "0\r\n\r\n")?;
-        self.socket.flush()?;
+    pub fn finish(self) -> Result<HttpResponse<io::BufReader<S, R>>> {
+        self.finish_with_header_limit(usize::MAX)
+    }
+
+    /// Like `finish`, but fails with `Error::HeadersTooLarge` if the response's status line and
+    /// headers are longer than `limit` bytes.
+    pub fn finish_with_header_limit(
+        self,
+        limit: usize,
+    ) -> Result<HttpResponse<io::BufReader<S, R>>> {
+        self.finish_with_early_hints(limit, |_| {})
+    }
+
+    /// Like `finish_with_header_limit`, but calls `on_early_hints` with the headers of each 103
+    /// Early Hints response, like `Link` headers naming resources to preload, which come before
+    /// the final response. Other interim responses are skipped.
+    pub fn finish_with_early_hints<F: FnMut(&HttpHeaders)>(
+        self,
+        limit: usize,
+        mut on_early_hints: F,
+    ) -> Result<HttpResponse<io::BufReader<S, R>>> {
+        let mut socket = match self.socket {
+            BodyWriter::Chunked(w) => w.finish()?,
+            BodyWriter::Limited(_, remaining) if remaining > 0 => {
+                return Err(Error::Other("body shorter than Content-Length".into()));
+            }
+            BodyWriter::Limited(w, _) => w,
+        };
+        socket.flush()?;
 
-        let socket = self.socket.into_inner().map_err(|e| Error::IoError(core2::io::Error::new(
-            core2::io::ErrorKind::Other,
-            "Socket error finish",
-        )))?;
-        Ok(HttpResponse::deserialize(socket)?)
+        let socket = socket.into_inner().map_err(|_| {
+            Error::IoError(core2::io::Error::new(
+                core2::io::ErrorKind::Other,
+                "Socket error finish",
+            ))
+        })?;
+        let mut socket = io::BufReader::new(socket);
+        loop {
+            let (version, status, reason, headers) = {
+                let response = HttpResponse::deserialize_with_header_limit(&mut socket, limit)?;
+                (
+                    response.version,
+                    response.status,
+                    response.reason,
+                    response.headers,
+                )
+            };
+            // Interim responses like the answer of a server to `Expect: 100-continue`, which may
+            // come even though the body was sent without waiting for it.
+            if status == HttpStatus::EarlyHints {
+                on_early_hints(&headers);
+            }
+            if status.is_interim() {
+                continue;
+            }
+            let body = HttpBody::from_headers(&headers, socket)?;
+            return Ok(HttpResponse {
+                version,
+                status,
+                reason,
+                headers,
+                body,
+                trailers: HttpHeaders::new(),
+            });
+        }
     }
 }
 
@@ -1630,20 +3181,104 @@ impl<B: core2::io::Read> HttpRequest<B> {
         self.headers.insert(key, value);
     }
 
-    pub fn deserialize(mut stream: io::BufReader<B,1024>) -> Result<Self> {
-        let mut ts = CrLfStream::new(&mut stream);
-        let first_line = ts.expect_next()?;
-        let mut parser = Parser::new(&first_line);
+    /// Replace the body of the request. The headers aren't changed.
+    pub fn with_body<R: core2::io::Read>(self, body: R) -> HttpRequest<R> {
+        HttpRequest {
+            method: self.method,
+            uri: self.uri,
+            version: self.version,
+            headers: self.headers,
+            body: HttpBody::ReadTilClose(body),
+            connection: self.connection,
+        }
+    }
+
+    /// Send the request as HTTP/1.0 rather than HTTP/1.1.
+    pub fn use_http_1_0(&mut self) {
+        self.version = HttpVersion::Http10;
+    }
+
+    /// Whether the client understands the chunked transfer coding. HTTP/1.0 clients don't.
+    pub fn supports_chunked(&self) -> bool {
+        self.version.supports_chunked()
+    }
+
+    /// Whether the client is willing to send further requests on the same connection after this
+    /// one.
+    pub fn wants_keep_alive(&self) -> bool {
+        let close = self
+            .headers
+            .connection()
+            .iter()
+            .any(|option| option == "close");
+        self.version.supports_chunked() && !close
+    }
+
+    /// Check the MUST-level requirements RFC 7230 places on requests that the parser doesn't already
+    /// enforce. Returns `Error::BadRequest` describing the first violation found.
+    pub fn check_compliance(&self) -> Result<()> {
+        let bad_request = |reason: &str| Err(Error::BadRequest(reason.into()));
+
+        if self.version.major() != 1 {
+            return bad_request("unsupported HTTP version");
+        }
+
+        match self.headers.get("Host") {
+            None if self.version.minor() >= 1 => return bad_request("missing Host header"),
+            Some(host) if host.contains(|c: char| c.is_whitespace() || c == '/' || c == '@') => {
+                return bad_request("invalid Host header");
+            }
+            _ => {}
+        }
+
+        let target_ok = match self.method {
+            HttpMethod::Connect => !self.uri.contains('/') && self.uri.contains(':'),
+            HttpMethod::Options if self.uri == "*" => true,
+            _ => self.uri.starts_with('/') || self.uri.contains("://"),
+        };
+        if !target_ok {
+            return bad_request("invalid request target");
+        }
+
+        if self.headers.get("Transfer-Encoding").is_some() {
+            if self.headers.get("Content-Length").is_some() {
+                return bad_request("both Transfer-Encoding and Content-Length given");
+            }
+            if !self.headers.is_chunked() {
+                return bad_request("request body must use chunked as the final encoding");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read a request from the given stream. The head is read a byte at a time, so anything after
+    /// the end of the request, like a pipelined request, is left unread in the stream.
+    pub fn deserialize(mut stream: B) -> Result<Self> {
+        let first_line = CrLfStream::new(&mut stream).expect_next()?;
+        Self::deserialize_after_line(&first_line, stream)
+    }
+
+    /// Like `deserialize`, for when the request line has already been read off the stream.
+    pub(crate) fn deserialize_after_line(first_line: &str, mut stream: B) -> Result<Self> {
+        let mut parser = Parser::new(first_line);
 
         let method = parser.parse_token()?.parse()?;
         let uri = parser.parse_token()?.into();
-        let version = parser.parse_token()?.parse()?;
-        let headers = HttpHeaders::deserialize(&mut ts)?;
-        drop(ts);
+        let version = match parser.parse_token() {
+            Ok(version) => version.parse()?,
+            Err(_) => HttpVersion::Http09,
+        };
+        let headers = match version {
+            HttpVersion::Http09 => HttpHeaders::new(),
+            _ => HttpHeaders::deserialize(&mut CrLfStream::new(&mut stream))?,
+        };
 
-        let encoding = headers.get("Transfer-Encoding");
-        let content_length = headers.get("Content-Length").map(str::parse).transpose()?;
-        let body = HttpBody::new(encoding, content_length, stream);
+        let body = match version {
+            // HTTP/0.9 requests have no body.
+            HttpVersion::Http09 => HttpBody::new(None, Some(0), stream),
+            _ => request_body(&headers, stream)?,
+        };
 
         Ok(HttpRequest {
             method,
@@ -1651,31 +3286,373 @@ impl<B: core2::io::Read> HttpRequest<B> {
             version,
             headers,
             body,
+            connection: ConnectionInfo::default(),
+        })
+    }
+
+    /// Read the body from `stream` instead, framed the way the headers say, as `deserialize` does.
+    /// This is for requests whose head was read before it was known where the body would be read
+    /// from.
+    pub(crate) fn with_framed_body<R: core2::io::Read>(self, stream: R) -> Result<HttpRequest<R>> {
+        let body = match self.version {
+            HttpVersion::Http09 => HttpBody::new(None, Some(0), stream),
+            _ => request_body(&self.headers, stream)?,
+        };
+        Ok(HttpRequest {
+            method: self.method,
+            uri: self.uri,
+            version: self.version,
+            headers: self.headers,
+            body,
+            connection: self.connection,
         })
     }
 }
 
+/// The request line and headers of a request, borrowed from the buffer they were parsed from
+/// rather than copied, so that parsing them doesn't allocate. See `HttpRequestRef::parse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpRequestRef<'buf> {
+    pub method: HttpMethod,
+    pub uri: &'buf str,
+    /// The version of the request line. Requests with `HttpVersion::Http09` have no headers.
+    pub version: HttpVersion,
+    pub headers: HeadersRef<'buf>,
+}
+
+impl<'buf> HttpRequestRef<'buf> {
+    /// Parse the head of the request at the start of `buffer`. Returns the request and the length
+    /// of its head, which is where the body starts, or `None` if the buffer doesn't hold all of
+    /// the head yet.
+    ///
+    /// Unlike `HttpRequest::deserialize`, headers continued on several lines are rejected, as
+    /// they can't be joined without copying them.
+    pub fn parse(buffer: &'buf [u8]) -> Result<Option<(Self, usize)>> {
+        let line_end = match find_crlf(buffer, 0) {
+            Some(line_end) => line_end,
+            None => return Ok(None),
+        };
+        let mut parser = Parser::new(str::from_utf8(&buffer[..line_end])?);
+
+        let method = parser.parse_token()?.parse()?;
+        let uri = parser.parse_token()?;
+        let version = match parser.parse_token() {
+            Ok(version) => version.parse()?,
+            Err(_) => HttpVersion::Http09,
+        };
+        let start = line_end + 2;
+        let mut end = start;
+        if version != HttpVersion::Http09 {
+            loop {
+                let line_end = match find_crlf(buffer, end) {
+                    Some(line_end) => line_end,
+                    None => return Ok(None),
+                };
+                if line_end == end {
+                    break;
+                }
+                HeadersRef::split(str::from_utf8(&buffer[end..line_end])?)?;
+                end = line_end + 2;
+            }
+        }
+        let headers = HeadersRef {
+            lines: str::from_utf8(&buffer[start..end])?,
+        };
+        let request = HttpRequestRef {
+            method,
+            uri,
+            version,
+            headers,
+        };
+        // The empty line ending the headers, which HTTP/0.9 requests don't have.
+        let head_length = match version {
+            HttpVersion::Http09 => end,
+            _ => end + 2,
+        };
+        Ok(Some((request, head_length)))
+    }
+
+    /// The length of the body, which is `None` if it is chunked. Requests with neither
+    /// `Content-Length` nor `Transfer-Encoding` have no body.
+    pub fn body_length(&self) -> Result<Option<u64>> {
+        // Framed the way `HttpRequest::deserialize` reads it.
+        let length = self.headers.content_length()?;
+        if self.headers.is_chunked() {
+            return Ok(None);
+        }
+        if self.headers.get("Transfer-Encoding").is_some() {
+            return Err(unframed_request());
+        }
+        Ok(Some(length.unwrap_or(0)))
+    }
+
+    /// Copy the request into an `HttpRequest`, with the body read from `body`.
+    pub fn to_request<B: core2::io::Read>(&self, body: B) -> Result<HttpRequest<B>> {
+        let headers = HttpHeaders::from(self.headers);
+        let body = match self.version {
+            HttpVersion::Http09 => HttpBody::new(None, Some(0), body),
+            _ => request_body(&headers, body)?,
+        };
+        Ok(HttpRequest {
+            method: self.method,
+            uri: self.uri.into(),
+            version: self.version,
+            headers,
+            body,
+            connection: ConnectionInfo::default(),
+        })
+    }
+}
+
+/// The headers of an `HttpRequestRef`, borrowed from the buffer they were parsed from. Names are
+/// compared ignoring case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadersRef<'buf> {
+    /// The header lines, each ending with CRLF, which were checked when they were parsed.
+    lines: &'buf str,
+}
+
+impl<'buf> HeadersRef<'buf> {
+    /// Split a header line into its name and value, without the whitespace around the value.
+    fn split(line: &str) -> Result<(&str, &str)> {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            return Err(Error::ParseError("Header continued on another line".into()));
+        }
+        match line.split_once(':') {
+            Some((name, value)) if is_token(name) => {
+                Ok((name, value.trim_matches(&[' ', '\t'][..])))
+            }
+            _ => Err(Error::ParseError(format!("Invalid header {:?}", line))),
+        }
+    }
+
+    /// The name and value of each header, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'buf str, &'buf str)> {
+        self.lines
+            .split_terminator("\r\n")
+            .filter_map(|line| Self::split(line).ok())
+    }
+
+    /// The first value of the header with the given name.
+    pub fn get(&self, key: &str) -> Option<&'buf str> {
+        self.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    /// All the values of the header with the given name, in order.
+    pub fn get_all<'a>(&self, key: &'a str) -> impl Iterator<Item = &'buf str> + 'a
+    where
+        'buf: 'a,
+    {
+        self.iter()
+            .filter(move |(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// See `HttpHeaders::content_length`.
+    pub fn content_length(&self) -> Result<Option<u64>> {
+        content_length(self.get_all("Content-Length"))
+    }
+
+    /// See `HttpHeaders::is_chunked`.
+    pub fn is_chunked(&self) -> bool {
+        is_chunked(self.get_all("Transfer-Encoding"))
+    }
+}
+
+impl From<HeadersRef<'_>> for HttpHeaders {
+    fn from(headers: HeadersRef) -> Self {
+        let mut owned = HttpHeaders::new();
+        for (name, value) in headers.iter() {
+            owned.append(name, value);
+        }
+        owned
+    }
+}
+
+/// The position of the first CRLF in `buffer` from `start`.
+fn find_crlf(buffer: &[u8], start: usize) -> Option<usize> {
+    buffer[start..]
+        .windows(2)
+        .position(|w| w == b"\r\n")
+        .map(|i| start + i)
+}
+
+#[cfg(test)]
+mod http_request_ref_tests {
+    use super::{HttpHeaders, HttpMethod, HttpRequestRef, HttpVersion};
+
+    #[test]
+    fn parse_success() {
+        let input = b"GET /foo HTTP/1.1\r\nHost: example.com\r\nX-List: a\r\nx-list:b \r\n\r\nbody";
+        let (request, length) = HttpRequestRef::parse(input).unwrap().unwrap();
+        assert_eq!(request.method, HttpMethod::Get);
+        assert_eq!(request.uri, "/foo");
+        assert_eq!(request.version, HttpVersion::Http11);
+        assert_eq!(request.headers.get("host"), Some("example.com"));
+        assert_eq!(
+            request.headers.get_all("X-List").collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert_eq!(&input[length..], b"body");
+        assert_eq!(request.body_length().unwrap(), Some(0));
+    }
+
+    #[test]
+    fn parse_incomplete() {
+        let input = b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\n";
+        for end in 0..input.len() {
+            assert!(HttpRequestRef::parse(&input[..end]).unwrap().is_none());
+        }
+        let (request, length) = HttpRequestRef::parse(input).unwrap().unwrap();
+        assert_eq!(length, input.len());
+        assert_eq!(request.body_length().unwrap(), Some(4));
+    }
+
+    #[test]
+    fn parse_http_0_9() {
+        let (request, length) = HttpRequestRef::parse(b"GET /\r\n").unwrap().unwrap();
+        assert_eq!(request.version, HttpVersion::Http09);
+        assert!(request.headers.is_empty());
+        assert_eq!(length, 7);
+    }
+
+    #[test]
+    fn parse_failure() {
+        assert!(HttpRequestRef::parse(b"GET / HTTP/1.1\r\nno colon\r\n\r\n").is_err());
+        assert!(HttpRequestRef::parse(b"GET / HTTP/1.1\r\nbad name: x\r\n\r\n").is_err());
+        assert!(HttpRequestRef::parse(b"GET / HTTP/1.1\r\na: b\r\n c\r\n\r\n").is_err());
+        assert!(HttpRequestRef::parse(b"BREW / HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn chunked() {
+        let input = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let (request, _) = HttpRequestRef::parse(input).unwrap().unwrap();
+        assert!(request.headers.is_chunked());
+        assert_eq!(request.body_length().unwrap(), None);
+
+        let input = b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, Chunked\r\n\r\n";
+        let (request, _) = HttpRequestRef::parse(input).unwrap().unwrap();
+        assert!(request.headers.is_chunked());
+    }
+
+    #[test]
+    fn to_request() {
+        let input = b"PUT /a HTTP/1.0\r\nContent-Length: 2\r\nB: 1\r\n\r\nhi!";
+        let (head, length) = HttpRequestRef::parse(input).unwrap().unwrap();
+        let mut request = head.to_request(&input[length..]).unwrap();
+        assert_eq!(request.method, HttpMethod::Put);
+        assert_eq!(request.uri, "/a");
+        assert_eq!(request.version, HttpVersion::Http10);
+        let mut expected = HttpHeaders::new();
+        expected.append("Content-Length", "2");
+        expected.append("B", "1");
+        assert_eq!(request.headers, expected);
+        let mut body = String::new();
+        core2::io::Read::read_to_string(&mut request.body, &mut body).unwrap();
+        assert_eq!(body, "hi");
+    }
+}
+
 impl<B: core2::io::Read> HttpRequest<B> {
     pub fn serialize<S: core2::io::Read + core2::io::Write>(
         &self,
         mut w: io::BufWriter<S,1024>,
     ) -> Result<OutgoingBody<S>> {
+        self.serialize_head(&mut w)?;
+        Ok(self.body_writer(w))
+    }
+
+    /// Like `serialize`, but with the given sizes for the buffers the body is written through and
+    /// the response is read through, like `serialize_with_buffers::<_, 512, 256>(writer)`. Small
+    /// buffers save memory on small devices, and large ones save system calls.
+    pub fn serialize_with_buffers<
+        S: core2::io::Read + core2::io::Write,
+        const R: usize,
+        const W: usize,
+    >(
+        &self,
+        mut w: io::BufWriter<S, W>,
+    ) -> Result<OutgoingBody<S, R, W>> {
+        self.serialize_head(&mut w)?;
+        Ok(OutgoingBody::new(w, self.outgoing_length()))
+    }
+
+    /// The `OutgoingBody` to write the body of the request to, once its head was written with
+    /// `serialize_head`.
+    pub fn body_writer<S: core2::io::Read + core2::io::Write>(
+        &self,
+        w: io::BufWriter<S, 1024>,
+    ) -> OutgoingBody<S> {
+        OutgoingBody::new(w, self.outgoing_length())
+    }
+
+    /// The length of the body to send, `None` if it is chunked because the request doesn't give
+    /// it.
+    fn outgoing_length(&self) -> Option<u64> {
+        match self.headers.get("Transfer-Encoding") {
+            Some(_) => None,
+            None => self
+                .headers
+                .get("Content-Length")
+                .and_then(|l| l.parse().ok()),
+        }
+    }
+
+    /// Write the request line and headers, everything but the body. HTTP/0.9 requests are only
+    /// the request line.
+    pub fn serialize_head<W: core2::io::Write>(&self, mut w: W) -> Result<()> {
+        if self.version == HttpVersion::Http09 {
+            write!(&mut w, "{} {}\r\n", self.method, self.uri)?;
+            return Ok(());
+        }
         write!(&mut w, "{} {} {}\r\n", self.method, self.uri, self.version)?;
         self.headers.serialize(&mut w)?;
         write!(&mut w, "\r\n")?;
-        Ok(OutgoingBody::new(w))
+        Ok(())
+    }
+
+    /// Serialize the whole request, reading its body, into a new `Vec`. See `serialize_into`.
+    pub fn serialize_to_vec(&mut self) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.serialize_head(&mut buffer)?;
+        write_body(
+            &self.headers,
+            &mut self.body,
+            &HttpHeaders::new(),
+            &mut buffer,
+        )?;
+        Ok(buffer)
+    }
+
+    /// Serialize the whole request, reading its body, into the buffer, for sending over a
+    /// transport which isn't a `Write`. Returns the length of the request. The body is encoded
+    /// with the chunked transfer coding if the `Transfer-Encoding` header says so, and otherwise
+    /// sent as it is. Fails with `Error::BufferTooSmall` if the buffer can't hold the request.
+    pub fn serialize_into(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        let mut w = SliceWriter::new(buffer);
+        let result = self
+            .serialize_head(&mut w)
+            .and_then(|_| write_body(&self.headers, &mut self.body, &HttpHeaders::new(), &mut w));
+        w.finish(result)
     }
 }
 
 #[cfg(test)]
 mod http_request_tests {
-    use super::{HttpMethod, HttpRequest};
+    use super::{HttpBody, HttpMethod, HttpRequest, HttpStatus, HttpVersion};
     use std::io;
 
     #[test]
     fn parse_success() {
         let mut input = "GET /a/b HTTP/1.1\r\nA: B\r\nC: D\r\n\r\n".as_bytes();
-        let actual = HttpRequest::deserialize(io::BufReader::new(&mut input)).unwrap();
+        let actual = HttpRequest::deserialize(&mut input).unwrap();
         let mut expected = HttpRequest::new(HttpMethod::Get, "/a/b");
         expected.add_header("A", "B");
         expected.add_header("C", "D");
@@ -1683,4 +3660,143 @@ mod http_request_tests {
         assert_eq!(actual.method, expected.method);
         assert_eq!(actual.headers, expected.headers);
     }
+
+    #[test]
+    fn versions() {
+        let mut input = "GET / HTTP/1.0\r\n\r\n".as_bytes();
+        let request = HttpRequest::deserialize(&mut input).unwrap();
+        assert_eq!(request.version, HttpVersion::Http10);
+        assert!(!request.supports_chunked());
+
+        let mut input = "GET /a\r\nrest".as_bytes();
+        let request = HttpRequest::deserialize(&mut input).unwrap();
+        assert_eq!(request.version, HttpVersion::Http09);
+        assert!(request.headers.is_empty());
+
+        let mut output = Vec::new();
+        request.serialize_head(&mut output).unwrap();
+        assert_eq!(output, b"GET /a\r\n");
+        drop(request);
+        assert_eq!(input, b"rest");
+    }
+
+    #[test]
+    fn serialize_into() {
+        let mut request = HttpRequest::new(HttpMethod::Post, "/a").with_body(&b"hi"[..]);
+        request.add_header("Content-Length", "2");
+        let expected = b"POST /a HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi";
+        let mut buffer = [0; 64];
+        let length = request.serialize_into(&mut buffer).unwrap();
+        assert_eq!(&buffer[..length], expected);
+
+        let mut request = HttpRequest::new(HttpMethod::Get, "/");
+        assert_eq!(
+            request.serialize_to_vec().unwrap(),
+            b"GET / HTTP/1.1\r\n\r\n"
+        );
+    }
+
+    fn check_compliance(request: &str) -> bool {
+        let mut input = request.as_bytes();
+        let request = HttpRequest::deserialize(&mut input).unwrap();
+        request.check_compliance().is_ok()
+    }
+
+    #[test]
+    fn compliance() {
+        assert!(check_compliance("GET /a HTTP/1.1\r\nHost: a.com\r\n\r\n"));
+        assert!(check_compliance("GET /a HTTP/1.0\r\n\r\n"));
+        assert!(check_compliance(
+            "GET http://a.com/a HTTP/1.1\r\nHost: a.com\r\n\r\n"
+        ));
+        assert!(check_compliance(
+            "OPTIONS * HTTP/1.1\r\nHost: a.com\r\n\r\n"
+        ));
+        assert!(check_compliance(
+            "CONNECT a.com:443 HTTP/1.1\r\nHost: a.com\r\n\r\n"
+        ));
+        assert!(check_compliance(
+            "POST / HTTP/1.1\r\nHost: a.com\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"
+        ));
+    }
+
+    /// A connection to a server which answers with `response` whatever is sent.
+    struct Canned {
+        response: &'static [u8],
+        sent: Vec<u8>,
+    }
+
+    impl core2::io::Read for Canned {
+        fn read(&mut self, buf: &mut [u8]) -> core2::io::Result<usize> {
+            core2::io::Read::read(&mut self.response, buf)
+        }
+    }
+
+    impl core2::io::Write for Canned {
+        fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+            self.sent.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> core2::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn finish_skips_interim_responses() {
+        let stream = Canned {
+            response: b"HTTP/1.1 100 Continue\r\n\r\n\
+                HTTP/1.1 103 Early Hints\r\nLink: </a.css>; rel=preload\r\n\r\n\
+                HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi",
+            sent: Vec::new(),
+        };
+        let mut request = HttpRequest::new(HttpMethod::Post, "/");
+        request.add_header("Content-Length", "0");
+        let outgoing = request
+            .serialize(core2::io::BufWriter::new(stream))
+            .unwrap();
+        let mut hints = Vec::new();
+        let mut response = outgoing
+            .finish_with_early_hints(usize::MAX, |headers| {
+                hints.push(headers.get("Link").unwrap().to_owned())
+            })
+            .unwrap();
+        assert_eq!(response.status, HttpStatus::OK);
+        assert_eq!(hints, ["</a.css>; rel=preload"]);
+        let mut body = String::new();
+        core2::io::Read::read_to_string(&mut response.body, &mut body).unwrap();
+        assert_eq!(body, "hi");
+    }
+
+    #[test]
+    fn compliance_failure() {
+        assert!(!check_compliance("GET /a HTTP/1.1\r\n\r\n"));
+        assert!(!check_compliance("GET /a HTTP/2.0\r\nHost: a.com\r\n\r\n"));
+        assert!(!check_compliance(
+            "GET /a HTTP/1.1\r\nHost: a.com/b\r\n\r\n"
+        ));
+        assert!(!check_compliance("GET a HTTP/1.1\r\nHost: a.com\r\n\r\n"));
+        assert!(!check_compliance(
+            "CONNECT /a HTTP/1.1\r\nHost: a.com\r\n\r\n"
+        ));
+        assert!(!check_compliance(
+            "POST / HTTP/1.1\r\nHost: a.com\r\nTransfer-Encoding: chunked\r\n\
+             Content-Length: 5\r\n\r\n"
+        ));
+        // A body which isn't chunked can't be framed, so the request isn't even read.
+        let mut input = &b"POST / HTTP/1.1\r\nHost: a.com\r\nTransfer-Encoding: gzip\r\n\r\n"[..];
+        assert!(HttpRequest::deserialize(&mut input).is_err());
+    }
+
+    #[test]
+    fn framing() {
+        let mut input =
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip, Chunked\r\n\r\n0\r\n\r\n"[..];
+        let request = HttpRequest::deserialize(&mut input).unwrap();
+        assert!(matches!(request.body, HttpBody::Chunked(_)));
+
+        let mut input = &b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab"[..];
+        assert!(HttpRequest::deserialize(&mut input).is_err());
+    }
 }
@@ -0,0 +1,604 @@
+//! A parser for requests and responses which is given their bytes as they arrive, rather than
+//! reading them from a stream. It does no I/O itself, so it can be driven by any event loop, and
+//! fed arbitrary input.
+
+use super::{response_has_body, HttpBody, HttpMethod, HttpRequest, HttpRequestRef, HttpResponse};
+use crate::error::{Error, Result};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::str;
+use core2::io;
+
+/// The stream the body of a message parsed by a `Parser` is read from: the whole message, read
+/// into memory.
+pub type BufferedMessage = io::Cursor<Vec<u8>>;
+
+/// What a `Parser` made of the input it was fed.
+pub enum ParseProgress {
+    /// The next message isn't complete yet.
+    Incomplete,
+    Request(HttpRequest<BufferedMessage>),
+    Response(HttpResponse<BufferedMessage>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MessageKind {
+    Request,
+    /// A response to a request with the given method.
+    Response(HttpMethod),
+}
+
+/// Parses the requests or responses of a connection from the bytes fed to it, which can be split
+/// anywhere. Each message is kept in memory until it is complete, so this is best suited to small
+/// messages.
+///
+/// ```
+/// use http_io::protocol::push::{ParseProgress, Parser};
+///
+/// let mut parser = Parser::request();
+/// assert!(matches!(parser.feed(b"GET / HTTP/1.1\r\nHo").unwrap(), ParseProgress::Incomplete));
+/// match parser.feed(b"st: example.com\r\n\r\n").unwrap() {
+///     ParseProgress::Request(request) => assert_eq!(request.uri, "/"),
+///     _ => panic!("expected a request"),
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Parser {
+    kind: MessageKind,
+    input: Vec<u8>,
+    header_limit: usize,
+    body_limit: usize,
+    closed: bool,
+    scan: Scan,
+}
+
+/// How far a `Parser` got through the message at the start of its input, so that each call to
+/// `feed` only looks at what is new.
+#[derive(Debug, Clone)]
+enum Scan {
+    /// Looking for the end of the head, which isn't in the first `searched` bytes of input. The
+    /// request line ends at `line_end`, once it was found.
+    Head {
+        searched: usize,
+        line_end: Option<usize>,
+    },
+    /// The message is complete once the input holds this many bytes.
+    Length(usize),
+    /// The chunked body starting `start` bytes into the input ends where the scanner finds its
+    /// end. The first `scanned` bytes of input were given to the scanner already.
+    Chunked {
+        start: usize,
+        scanned: usize,
+        scanner: ChunkedScanner,
+    },
+    /// The body after the head of the given length is everything until the connection is closed.
+    UntilClose(usize),
+}
+
+impl Scan {
+    fn new() -> Self {
+        Scan::Head {
+            searched: 0,
+            line_end: None,
+        }
+    }
+}
+
+impl Parser {
+    /// A parser for the requests a client sends.
+    pub fn request() -> Self {
+        Self::new(MessageKind::Request)
+    }
+
+    /// A parser for the responses to requests with the given method, which tells whether they
+    /// have a body. Interim responses, like 100 Continue, are returned like any other.
+    pub fn response(method: HttpMethod) -> Self {
+        Self::new(MessageKind::Response(method))
+    }
+
+    fn new(kind: MessageKind) -> Self {
+        Parser {
+            kind,
+            input: Vec::new(),
+            header_limit: usize::MAX,
+            body_limit: usize::MAX,
+            closed: false,
+            scan: Scan::new(),
+        }
+    }
+
+    /// Fail with `Error::HeadersTooLarge` if the start line and headers of a message are longer
+    /// than `limit` bytes, rather than keep waiting for the end of them. There is no limit by
+    /// default.
+    pub fn header_limit(mut self, limit: usize) -> Self {
+        self.header_limit = limit;
+        self
+    }
+
+    /// Fail with `Error::BodyTooLarge` if the body of a message, as sent, is longer than `limit`
+    /// bytes. A chunked body counts with its chunk sizes and trailers. There is no limit by
+    /// default.
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
+    /// The method of the request the next responses answer. Only used by response parsers.
+    pub fn set_method(&mut self, method: HttpMethod) {
+        if let MessageKind::Response(_) = self.kind {
+            self.kind = MessageKind::Response(method);
+        }
+    }
+
+    /// Add the input to what was fed before, and return the next message if it is now complete.
+    /// The input can hold more than one message, so once one is returned, `feed(&[])` should be
+    /// called until it returns `ParseProgress::Incomplete`.
+    pub fn feed(&mut self, input: &[u8]) -> Result<ParseProgress> {
+        let message = match self.next_message(input)? {
+            Some(message) => io::Cursor::new(message),
+            None => return Ok(ParseProgress::Incomplete),
+        };
+        Ok(match self.kind {
+            MessageKind::Request => ParseProgress::Request(HttpRequest::deserialize(message)?),
+            MessageKind::Response(method) => {
+                let mut response = HttpResponse::deserialize(message)?;
+                if !response_has_body(response.status, Some(method)) {
+                    response.body = HttpBody::new(None, Some(0), io::Cursor::new(Vec::new()));
+                }
+                ParseProgress::Response(response)
+            }
+        })
+    }
+
+    /// Like `feed`, but returns the bytes of the next complete message rather than parsing it.
+    pub(crate) fn next_message(&mut self, input: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.input.extend_from_slice(input);
+        let len = match self.message_len()? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        self.scan = Scan::new();
+        Ok(Some(self.input.drain(..len).collect()))
+    }
+
+    /// Tell the parser the other end closed the connection, which ends a response without a
+    /// length. Returns the message this completes, if any. Fails if part of a message is left over.
+    pub fn finish(&mut self) -> Result<ParseProgress> {
+        self.closed = true;
+        match self.feed(&[])? {
+            ParseProgress::Incomplete if !self.input.is_empty() => Err(Error::UnexpectedEof(
+                "Connection closed in the middle of a message".into(),
+            )),
+            progress => Ok(progress),
+        }
+    }
+
+    /// The input fed to the parser which isn't part of a message returned yet.
+    pub fn buffered(&self) -> &[u8] {
+        &self.input
+    }
+
+    /// The length of the message at the start of the input, or `None` if it isn't all there yet.
+    fn message_len(&mut self) -> Result<Option<usize>> {
+        if let Scan::Head { searched, line_end } = self.scan {
+            self.scan = match self.head_len(searched, line_end)? {
+                Some(head_len) => self.body_scan(head_len)?,
+                None => return Ok(None),
+            };
+        }
+
+        let len = self.input.len();
+        match &mut self.scan {
+            Scan::Head { .. } => unreachable!(),
+            Scan::Length(message_len) => Ok(if len >= *message_len {
+                Some(*message_len)
+            } else {
+                None
+            }),
+            Scan::Chunked {
+                start,
+                scanned,
+                scanner,
+            } => {
+                let end = scanner
+                    .scan(&self.input[*scanned..])?
+                    .map(|rest| *scanned + rest);
+                *scanned = end.unwrap_or(len);
+                check_body_limit(*scanned - *start, self.body_limit)?;
+                Ok(end)
+            }
+            Scan::UntilClose(_) if self.closed => Ok(Some(len)),
+            Scan::UntilClose(head_len) => {
+                check_body_limit(len - *head_len, self.body_limit).map(|_| None)
+            }
+        }
+    }
+
+    /// Find the end of the head, looking only at input which wasn't `searched` before.
+    fn head_len(&mut self, searched: usize, mut line_end: Option<usize>) -> Result<Option<usize>> {
+        // An HTTP/0.9 request has no headers, so it ends with its request line. This is checked
+        // first, as an empty line after it is not part of it.
+        if self.kind == MessageKind::Request && line_end.is_none() {
+            let from = searched.saturating_sub(1);
+            if let Some(i) = find(&self.input[from..], b"\r\n") {
+                let end = from + i + 2;
+                if let Some((_, head_len)) = HttpRequestRef::parse(&self.input[..end])? {
+                    return Ok(Some(head_len));
+                }
+                line_end = Some(end);
+            }
+        }
+        // Back up so an end split between the last input and this one is still found.
+        let from = searched.saturating_sub(3);
+        if let Some(i) = find(&self.input[from..], b"\r\n\r\n") {
+            return Ok(Some(from + i + 4));
+        }
+        check_header_limit(&self.input, self.header_limit)?;
+        self.scan = Scan::Head {
+            searched: self.input.len(),
+            line_end,
+        };
+        Ok(None)
+    }
+
+    /// How to find the end of the body of the message with a head of the given length.
+    fn body_scan(&self, head_len: usize) -> Result<Scan> {
+        let head = &self.input[..head_len];
+        check_header_limit(head, self.header_limit)?;
+        let length = match self.kind {
+            MessageKind::Request => {
+                // The head is looked at where it is, as it is parsed again once the whole request
+                // is here.
+                let (request, _) = HttpRequestRef::parse(head)?
+                    .ok_or_else(|| Error::ParseError("Malformed request head".into()))?;
+                request.body_length()?
+            }
+            MessageKind::Response(method) => {
+                let response = HttpResponse::deserialize(head)?;
+                if !response_has_body(response.status, Some(method)) {
+                    return Ok(Scan::Length(head_len));
+                }
+                let headers = &response.headers;
+                // Framed the way `HttpResponse::deserialize` reads it.
+                if headers.is_chunked() {
+                    None
+                } else if headers.get("Transfer-Encoding").is_some() {
+                    return Ok(Scan::UntilClose(head_len));
+                } else {
+                    match headers.content_length()? {
+                        Some(length) => Some(length),
+                        None => return Ok(Scan::UntilClose(head_len)),
+                    }
+                }
+            }
+        };
+        Ok(match length {
+            None => Scan::Chunked {
+                start: head_len,
+                scanned: head_len,
+                scanner: ChunkedScanner::new(),
+            },
+            Some(length) => {
+                let length =
+                    usize::try_from(length).map_err(|_| Error::BodyTooLarge(self.body_limit))?;
+                check_body_limit(length, self.body_limit)?;
+                Scan::Length(head_len + length)
+            }
+        })
+    }
+}
+
+pub(crate) fn find(buffer: &[u8], needle: &[u8]) -> Option<usize> {
+    buffer.windows(needle.len()).position(|w| w == needle)
+}
+
+/// The longest chunk size line, with its extensions, a `ChunkedScanner` accepts.
+const MAX_SIZE_LINE: usize = 1024;
+
+/// Finds where a chunked body ends, given it in pieces. Each byte is looked at once, so a body
+/// given a byte at a time takes no longer to scan than one given all at once.
+#[derive(Debug, Clone)]
+pub(crate) struct ChunkedScanner {
+    state: ChunkState,
+    /// The part of the chunk size line read so far.
+    line: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkState {
+    /// Reading a chunk size line.
+    Size,
+    /// Skipping the rest of a chunk's data and the line break after it.
+    Data(u64),
+    /// Reading the trailer section, which ends with an empty line. `len` is the length of the
+    /// current line so far, and `cr` whether it ends with a CR.
+    Trailers {
+        len: usize,
+        cr: bool,
+    },
+    Done,
+}
+
+impl ChunkedScanner {
+    pub(crate) fn new() -> Self {
+        ChunkedScanner {
+            state: ChunkState::Size,
+            line: Vec::new(),
+        }
+    }
+
+    /// Scan the next part of the body. Returns how much of `input` is left of the body if it
+    /// ends in it, or `None` if the body continues past it.
+    pub(crate) fn scan(&mut self, input: &[u8]) -> Result<Option<usize>> {
+        let mut pos = 0;
+        loop {
+            match self.state {
+                ChunkState::Done => return Ok(Some(pos)),
+                _ if pos == input.len() => return Ok(None),
+                ChunkState::Size => pos += self.scan_size_line(&input[pos..])?,
+                ChunkState::Data(left) => {
+                    let skipped = core::cmp::min(left, (input.len() - pos) as u64);
+                    pos += skipped as usize;
+                    self.state = match left - skipped {
+                        0 => ChunkState::Size,
+                        left => ChunkState::Data(left),
+                    };
+                }
+                ChunkState::Trailers { len, cr } => {
+                    let byte = input[pos];
+                    pos += 1;
+                    self.state = match byte {
+                        // The line is empty but for its CR.
+                        b'\n' if cr && len == 1 => ChunkState::Done,
+                        b'\n' if cr => ChunkState::Trailers { len: 0, cr: false },
+                        _ => ChunkState::Trailers {
+                            len: len.saturating_add(1),
+                            cr: byte == b'\r',
+                        },
+                    };
+                }
+            }
+        }
+    }
+
+    /// Read the chunk size line from the start of `input`, returning how much of it was used.
+    fn scan_size_line(&mut self, input: &[u8]) -> Result<usize> {
+        let used = match input.iter().position(|b| *b == b'\n') {
+            Some(i) => i + 1,
+            None => input.len(),
+        };
+        self.line.extend_from_slice(&input[..used]);
+        if !self.line.ends_with(b"\r\n") {
+            if self.line.len() > MAX_SIZE_LINE {
+                return Err(Error::ParseError("chunk size line too long".into()));
+            }
+            return Ok(used);
+        }
+
+        let line = str::from_utf8(&self.line[..self.line.len() - 2])?;
+        let size_str = line.split(';').next().unwrap_or("").trim();
+        let size = u64::from_str_radix(size_str, 16)?;
+        self.line.clear();
+        self.state = match size {
+            0 => ChunkState::Trailers { len: 0, cr: false },
+            // The data is followed by a line break.
+            size => ChunkState::Data(
+                size.checked_add(2)
+                    .ok_or_else(|| Error::ParseError("chunk size too large".into()))?,
+            ),
+        };
+        Ok(used)
+    }
+}
+
+/// Fail if the head of a message still isn't complete after `limit` bytes.
+fn check_header_limit(buffer: &[u8], limit: usize) -> Result<()> {
+    if buffer.len() > limit {
+        return Err(Error::HeadersTooLarge(limit));
+    }
+    Ok(())
+}
+
+/// Fail if a body of the given length is longer than `limit` bytes.
+fn check_body_limit(len: usize, limit: usize) -> Result<()> {
+    if len > limit {
+        return Err(Error::BodyTooLarge(limit));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkedScanner, ParseProgress, Parser};
+    use crate::error::Error;
+    use crate::protocol::{HttpMethod, HttpStatus};
+    use core2::io::Read;
+
+    fn body<R: Read>(mut body: R) -> String {
+        let mut s = String::new();
+        body.read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn request_byte_by_byte() {
+        let input = b"POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhi\r\n0\r\n\r\n";
+        let mut parser = Parser::request();
+        for byte in &input[..input.len() - 1] {
+            assert!(matches!(
+                parser.feed(&[*byte]).unwrap(),
+                ParseProgress::Incomplete
+            ));
+        }
+        match parser.feed(&input[input.len() - 1..]).unwrap() {
+            ParseProgress::Request(request) => {
+                assert_eq!(request.method, HttpMethod::Post);
+                assert_eq!(body(request.body), "hi");
+            }
+            _ => panic!("expected a request"),
+        }
+        assert!(parser.buffered().is_empty());
+    }
+
+    #[test]
+    fn pipelined_requests() {
+        let mut parser = Parser::request();
+        let input = b"GET /1 HTTP/1.1\r\n\r\nPUT /2 HTTP/1.1\r\nContent-Length: 3\r\n\r\nabcGET";
+        let mut uris = vec![];
+        let mut progress = parser.feed(input).unwrap();
+        while let ParseProgress::Request(request) = progress {
+            uris.push(request.uri.clone());
+            progress = parser.feed(&[]).unwrap();
+        }
+        assert_eq!(uris, ["/1", "/2"]);
+        assert_eq!(parser.buffered(), b"GET");
+    }
+
+    #[test]
+    fn response_until_close() {
+        let mut parser = Parser::response(HttpMethod::Get);
+        let input = b"HTTP/1.1 200 OK\r\n\r\nhello";
+        assert!(matches!(
+            parser.feed(input).unwrap(),
+            ParseProgress::Incomplete
+        ));
+        match parser.finish().unwrap() {
+            ParseProgress::Response(response) => assert_eq!(body(response.body), "hello"),
+            _ => panic!("expected a response"),
+        }
+    }
+
+    #[test]
+    fn framing() {
+        let mut parser = Parser::response(HttpMethod::Get);
+        let input = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: Chunked\r\nContent-Length: 9\r\n\r\n\
+                      2\r\nhi\r\n0\r\n\r\n";
+        match parser.feed(input).unwrap() {
+            ParseProgress::Response(response) => assert_eq!(body(response.body), "hi"),
+            _ => panic!("expected a response"),
+        }
+
+        let mut parser = Parser::request();
+        assert!(parser
+            .feed(b"PUT / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n")
+            .is_err());
+    }
+
+    #[test]
+    fn responses_without_body() {
+        let mut parser = Parser::response(HttpMethod::Head);
+        let input = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHTTP/1.1 204 No Content\r\n\r\n";
+        match parser.feed(input).unwrap() {
+            ParseProgress::Response(response) => assert_eq!(body(response.body), ""),
+            _ => panic!("expected a response"),
+        }
+        parser.set_method(HttpMethod::Get);
+        match parser.feed(&[]).unwrap() {
+            ParseProgress::Response(response) => {
+                assert_eq!(response.status, HttpStatus::NoContent)
+            }
+            _ => panic!("expected a response"),
+        }
+    }
+
+    #[test]
+    fn interim_response() {
+        let mut parser = Parser::response(HttpMethod::Post);
+        let input = b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        match parser.feed(input).unwrap() {
+            ParseProgress::Response(response) => assert_eq!(response.status, HttpStatus::Continue),
+            _ => panic!("expected a response"),
+        }
+        match parser.feed(&[]).unwrap() {
+            ParseProgress::Response(response) => assert_eq!(body(response.body), "ok"),
+            _ => panic!("expected a response"),
+        }
+    }
+
+    #[test]
+    fn header_limit() {
+        let mut parser = Parser::request().header_limit(20);
+        assert!(matches!(
+            parser.feed(b"GET / HTTP/1.1\r\n").unwrap(),
+            ParseProgress::Incomplete
+        ));
+        assert!(matches!(
+            parser.feed(b"Host: example.com\r\n"),
+            Err(Error::HeadersTooLarge(20))
+        ));
+    }
+
+    #[test]
+    fn body_limit() {
+        let mut parser = Parser::request().body_limit(4);
+        assert!(matches!(
+            parser.feed(b"PUT / HTTP/1.1\r\nContent-Length: 5\r\n\r\n"),
+            Err(Error::BodyTooLarge(4))
+        ));
+
+        let mut parser = Parser::request().body_limit(8);
+        assert!(matches!(
+            parser
+                .feed(b"PUT / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n")
+                .unwrap(),
+            ParseProgress::Incomplete
+        ));
+        assert!(matches!(parser.feed(b"1"), Err(Error::BodyTooLarge(8))));
+    }
+
+    #[test]
+    fn chunked_body_split_anywhere() {
+        let body = b"3;ext=1\r\nabc\r\n10\r\n0123456789abcdef\r\n0\r\nA: b\r\n\r\nrest";
+        for split in 0..body.len() {
+            let mut scanner = ChunkedScanner::new();
+            let end = match scanner.scan(&body[..split]).unwrap() {
+                Some(end) => end,
+                None => split + scanner.scan(&body[split..]).unwrap().unwrap(),
+            };
+            assert_eq!(&body[end..], b"rest", "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn chunked_body_byte_by_byte() {
+        let body = b"1\r\na\r\n0\r\n\r\n";
+        let mut scanner = ChunkedScanner::new();
+        for byte in &body[..body.len() - 1] {
+            assert_eq!(scanner.scan(&[*byte]).unwrap(), None);
+        }
+        assert_eq!(scanner.scan(&body[body.len() - 1..]).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn http09_request() {
+        let mut parser = Parser::request();
+        assert!(matches!(
+            parser.feed(b"GET /").unwrap(),
+            ParseProgress::Incomplete
+        ));
+        match parser.feed(b"a\r\nGET").unwrap() {
+            ParseProgress::Request(request) => assert_eq!(request.uri, "/a"),
+            _ => panic!("expected a request"),
+        }
+        assert_eq!(parser.buffered(), b"GET");
+
+        let mut parser = Parser::request();
+        match parser.feed(b"GET /b\r\n\r\n").unwrap() {
+            ParseProgress::Request(request) => assert_eq!(request.uri, "/b"),
+            _ => panic!("expected a request"),
+        }
+        assert_eq!(parser.buffered(), b"\r\n");
+    }
+
+    #[test]
+    fn truncated_message() {
+        let mut parser = Parser::response(HttpMethod::Get);
+        let input = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhel";
+        assert!(matches!(
+            parser.feed(input).unwrap(),
+            ParseProgress::Incomplete
+        ));
+        assert!(matches!(parser.finish(), Err(Error::UnexpectedEof(_))));
+    }
+}
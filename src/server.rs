@@ -1,5 +1,6 @@
 //! A very simple HTTP server. It is not suitable for production workloads.
-//! Users should write their own request handler which implements the `HttpRequestHandler` trait.
+//! Users should write their own request handler which implements the `HttpRequestHandler` trait,
+//! or the `HttpResponder` trait to avoid boxing response bodies.
 //!
 //! # File Server Example
 //! ```rust
@@ -67,14 +68,48 @@
 //!     Ok(())
 //! }
 //! ```
-use core2::io;
+use core2::io::{self, Write};
+use crate::date::Clock;
 use crate::error;
-use crate::protocol::{HttpBody, HttpMethod, HttpRequest, HttpResponse, HttpStatus};
+use crate::protocol::{
+    response_has_body, ChunkedWriter, ConnectionInfo, CrLfStream, HttpBody, HttpHeaders,
+    HttpMethod, HttpRequest, HttpResponse, HttpStatus, HttpVersion,
+};
+#[cfg(feature = "openssl")]
+use crate::protocol::PeerIdentity;
 #[cfg(not(feature = "std"))]
 use alloc::{
     boxed::Box,
+    format,
     string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use hashbrown::HashMap;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+mod cache;
+#[cfg(feature = "http2")]
+pub(crate) mod http2;
+mod nonblocking;
+#[cfg(feature = "std")]
+mod proxy;
+mod router;
+
+pub use self::cache::CachingHandler;
+#[cfg(feature = "mio")]
+pub use self::nonblocking::MioServer;
+pub use self::nonblocking::{
+    BufferedRequest, ConnectionState, NonBlockingConnection, DEFAULT_BODY_LIMIT,
+    DEFAULT_HEADER_LIMIT,
 };
+pub use self::router::Router;
+
+#[cfg(feature = "std")]
+pub use self::proxy::{ForwardProxy, ProxyHandler};
 
 
 type HttpResult<T> = core::result::Result<T, HttpResponse<Box<dyn core2::io::Read>>>;
@@ -85,6 +120,17 @@ impl From<error::Error> for HttpResponse<Box<dyn core2::io::Read>> {
             error::Error::LengthRequired => {
                 HttpResponse::from_string(HttpStatus::LengthRequired, "length required")
             }
+            error::Error::BadRequest(reason) => {
+                HttpResponse::from_string(HttpStatus::BadRequest, reason)
+            }
+            error::Error::HeadersTooLarge(_) => HttpResponse::from_string(
+                HttpStatus::RequestHeaderFieldsTooLarge,
+                "request header fields too large",
+            ),
+            error::Error::BodyTooLarge(_) => HttpResponse::from_string(
+                HttpStatus::RequestEntityTooLarge,
+                "request entity too large",
+            ),
             e => HttpResponse::from_string(HttpStatus::InternalServerError, e.to_string()),
         }
     }
@@ -94,6 +140,23 @@ impl From<error::Error> for HttpResponse<Box<dyn core2::io::Read>> {
 pub trait Listen {
     type Stream: core2::io::Read + core2::io::Write;
     fn accept(&self) -> error::Result<Self::Stream>;
+
+    /// Information about an accepted stream, made available to handlers as
+    /// `HttpRequest::connection`.
+    fn connection_info(&self, _stream: &Self::Stream) -> ConnectionInfo {
+        ConnectionInfo::default()
+    }
+
+    /// Limit how long reads from an accepted stream wait for data, or `None` to wait forever.
+    /// `HttpServer` uses this for its keep-alive timeout. By default this fails, so `HttpServer`
+    /// closes each connection after one response rather than wait on it forever.
+    fn set_read_timeout(
+        &self,
+        _stream: &Self::Stream,
+        _timeout: Option<Duration>,
+    ) -> error::Result<()> {
+        Err(error::Error::Other("read timeouts aren't supported".into()))
+    }
 }
 
 #[cfg(feature = "std")]
@@ -103,6 +166,21 @@ impl Listen for std::net::TcpListener {
         let (stream, _) = std::net::TcpListener::accept(self)?;
         Ok(stream)
     }
+
+    fn connection_info(&self, stream: &Self::Stream) -> ConnectionInfo {
+        ConnectionInfo {
+            peer_address: stream.peer_addr().ok().map(|a| a.ip().to_string()),
+            ..ConnectionInfo::default()
+        }
+    }
+
+    fn set_read_timeout(
+        &self,
+        stream: &Self::Stream,
+        timeout: Option<Duration>,
+    ) -> error::Result<()> {
+        Ok(stream.set_read_timeout(timeout)?)
+    }
 }
 
 #[cfg(feature = "openssl")]
@@ -111,11 +189,167 @@ pub struct SslListener<L> {
     acceptor: openssl::ssl::SslAcceptor,
 }
 
+/// Whether an `SslListener` asks connecting clients for a certificate.
+#[cfg(feature = "openssl")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// Verify the client's certificate if it presents one, but also accept clients without one.
+    Optional,
+    /// Reject clients which don't present a valid certificate.
+    Required,
+}
+
 #[cfg(feature = "openssl")]
 impl<L: Listen> SslListener<L> {
     pub fn new(listener: L, acceptor: openssl::ssl::SslAcceptor) -> Self {
         Self { listener, acceptor }
     }
+
+    /// Create an `SslListener` which verifies client certificates against the CA certificates in
+    /// `ca_file`. The identity of verified clients is available to handlers via
+    /// `HttpRequest::connection`.
+    pub fn with_client_auth<P: AsRef<std::path::Path>>(
+        listener: L,
+        mut acceptor: openssl::ssl::SslAcceptorBuilder,
+        ca_file: P,
+        auth: ClientAuth,
+    ) -> error::Result<Self> {
+        use openssl::ssl::SslVerifyMode;
+
+        acceptor.set_ca_file(ca_file.as_ref())?;
+        acceptor.set_client_ca_list(openssl::x509::X509Name::load_client_ca_file(
+            ca_file.as_ref(),
+        )?);
+        let mut mode = SslVerifyMode::PEER;
+        if auth == ClientAuth::Required {
+            mode |= SslVerifyMode::FAIL_IF_NO_PEER_CERT;
+        }
+        acceptor.set_verify(mode);
+        Ok(Self::new(listener, acceptor.build()))
+    }
+}
+
+/// A set of certificates for an `SslListener` to choose from, based on the hostname the client asks
+/// for using SNI.
+///
+/// *This struct is available if http_io is built with the `"openssl"` feature.*
+#[cfg(feature = "openssl")]
+#[derive(Default)]
+pub struct SniCertificates {
+    contexts: HashMap<String, openssl::ssl::SslContext>,
+}
+
+#[cfg(feature = "openssl")]
+impl SniCertificates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the given PEM certificate chain and private key for connections to `hostname`. The
+    /// hostname may be a wildcard like `*.example.com`.
+    pub fn add<P1: AsRef<std::path::Path>, P2: AsRef<std::path::Path>>(
+        &mut self,
+        hostname: &str,
+        certificate_chain_file: P1,
+        private_key_file: P2,
+    ) -> error::Result<()> {
+        use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+
+        let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+        builder.set_certificate_chain_file(certificate_chain_file)?;
+        builder.set_private_key_file(private_key_file, SslFiletype::PEM)?;
+        builder.check_private_key()?;
+        self.add_context(hostname, builder.build().into_context());
+        Ok(())
+    }
+
+    /// Use an already configured `SslContext` for connections to `hostname`.
+    pub fn add_context(&mut self, hostname: &str, context: openssl::ssl::SslContext) {
+        self.contexts.insert(normalize_host(hostname), context);
+    }
+
+    fn find(&self, hostname: &str) -> Option<&openssl::ssl::SslContext> {
+        self.contexts.get(hostname).or_else(|| {
+            let parent = hostname.splitn(2, '.').nth(1)?;
+            self.contexts.get(&format!("*.{}", parent))
+        })
+    }
+}
+
+#[cfg(feature = "openssl")]
+impl<L: Listen> SslListener<L> {
+    /// Create an `SslListener` which presents the certificate from `certificates` matching the
+    /// hostname the client asks for. Clients asking for an unknown hostname, or not using SNI, are
+    /// given the certificate configured on `acceptor`.
+    pub fn with_sni(
+        listener: L,
+        mut acceptor: openssl::ssl::SslAcceptorBuilder,
+        certificates: SniCertificates,
+    ) -> Self {
+        use openssl::ssl::{NameType, SniError};
+
+        acceptor.set_servername_callback(move |ssl, _alert| {
+            let hostname = match ssl.servername(NameType::HOST_NAME) {
+                Some(name) => normalize_host(name),
+                None => return Ok(()),
+            };
+            if let Some(context) = certificates.find(&hostname) {
+                ssl.set_ssl_context(context)
+                    .map_err(|_| SniError::ALERT_FATAL)?;
+            }
+            Ok(())
+        });
+        Self::new(listener, acceptor.build())
+    }
+}
+
+#[cfg(feature = "openssl")]
+fn format_ip_address(ip: &[u8]) -> Option<String> {
+    use std::convert::TryFrom;
+    use std::net::IpAddr;
+
+    match ip.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(ip).ok()?).to_string()),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(ip).ok()?).to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "openssl")]
+fn certificate_identity(cert: &openssl::x509::X509Ref) -> PeerIdentity {
+    let subject = cert
+        .subject_name()
+        .entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry
+                .data()
+                .as_utf8()
+                .map(|v| v.to_string())
+                .unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let subject_alt_names = cert
+        .subject_alt_names()
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|name| {
+                    name.dnsname()
+                        .or_else(|| name.email())
+                        .or_else(|| name.uri())
+                        .map(String::from)
+                        .or_else(|| name.ipaddress().and_then(format_ip_address))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    PeerIdentity {
+        subject,
+        subject_alt_names,
+    }
 }
 
 #[cfg(feature = "openssl")]
@@ -128,12 +362,93 @@ where
         let stream = self.listener.accept()?;
         Ok(self.acceptor.accept(stream)?)
     }
+
+    fn connection_info(&self, stream: &Self::Stream) -> ConnectionInfo {
+        let mut info = self.listener.connection_info(stream.get_ref());
+        let ssl = stream.ssl();
+        info.alpn_protocol = ssl
+            .selected_alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).into_owned());
+        info.peer_identity = match ssl.peer_certificate() {
+            Some(cert) if ssl.verify_result() == openssl::x509::X509VerifyResult::OK => {
+                Some(certificate_identity(&cert))
+            }
+            _ => None,
+        };
+        info
+    }
+
+    fn set_read_timeout(
+        &self,
+        stream: &Self::Stream,
+        timeout: Option<Duration>,
+    ) -> error::Result<()> {
+        self.listener.set_read_timeout(stream.get_ref(), timeout)
+    }
+}
+
+/// A `Listen` implementation which performs a TLS handshake using rustls on each accepted stream.
+///
+/// *This struct is available if http_io is built with the `"rustls"` feature.*
+#[cfg(feature = "rustls")]
+pub struct RustlsListener<L> {
+    listener: L,
+    config: std::sync::Arc<rustls::ServerConfig>,
+}
+
+#[cfg(feature = "rustls")]
+impl<L: Listen> RustlsListener<L> {
+    pub fn new(listener: L, config: std::sync::Arc<rustls::ServerConfig>) -> Self {
+        Self { listener, config }
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl<L: Listen> Listen for RustlsListener<L> {
+    type Stream = rustls::StreamOwned<rustls::ServerConnection, <L as Listen>::Stream>;
+    fn accept(&self) -> error::Result<Self::Stream> {
+        let mut stream = self.listener.accept()?;
+        let mut connection = rustls::ServerConnection::new(self.config.clone())?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream)?;
+        }
+        Ok(rustls::StreamOwned::new(connection, stream))
+    }
+
+    fn connection_info(&self, stream: &Self::Stream) -> ConnectionInfo {
+        let mut info = self.listener.connection_info(stream.get_ref());
+        info.alpn_protocol = stream
+            .conn
+            .alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).into_owned());
+        info
+    }
+
+    fn set_read_timeout(
+        &self,
+        stream: &Self::Stream,
+        timeout: Option<Duration>,
+    ) -> error::Result<()> {
+        self.listener.set_read_timeout(stream.get_ref(), timeout)
+    }
 }
 
 /// Represents the ability to service and respond to HTTP requests.
 pub trait HttpRequestHandler<I: core2::io::Read> {
     type Error: Into<HttpResponse<Box<dyn core2::io::Read>>>;
 
+    /// Handle a CONNECT request. The uri is the authority to connect to. Since a handler has no
+    /// access to the connection, it can't set up a tunnel; see `ForwardProxy` for that.
+    fn connect(
+        &mut self,
+        _uri: String,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        Ok(HttpResponse::from_string(
+            HttpStatus::MethodNotAllowed,
+            "CONNECT not allowed",
+        ))
+    }
+
     fn delete(&mut self, _uri: String) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
         Ok(HttpResponse::from_string(
             HttpStatus::MethodNotAllowed,
@@ -173,6 +488,17 @@ pub trait HttpRequestHandler<I: core2::io::Read> {
         ))
     }
 
+    fn patch(
+        &mut self,
+        _uri: String,
+        _stream: HttpBody<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        Ok(HttpResponse::from_string(
+            HttpStatus::MethodNotAllowed,
+            "PATCH not allowed",
+        ))
+    }
+
     fn post(
         &mut self,
         _uri: String,
@@ -190,59 +516,645 @@ pub trait HttpRequestHandler<I: core2::io::Read> {
             "TRACE not allowed",
         ))
     }
+
+    /// Handle a whole request. By default this dispatches to the method specific functions above.
+    /// Override it to get access to the request's headers.
+    fn handle(
+        &mut self,
+        request: HttpRequest<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        match request.method {
+            HttpMethod::Connect => self.connect(request.uri),
+            HttpMethod::Delete => self.delete(request.uri),
+            HttpMethod::Get => self.get(request.uri),
+            HttpMethod::Head => self.head(request.uri),
+            HttpMethod::Options => self.options(request.uri),
+            HttpMethod::Patch => self.patch(request.uri, request.body),
+            HttpMethod::Post => self.post(request.uri, request.body),
+            HttpMethod::Put => self.put(request.uri, request.body),
+            HttpMethod::Trace => self.trace(request.uri),
+            HttpMethod::Other(method) => Ok(HttpResponse::from_string(
+                HttpStatus::MethodNotAllowed,
+                format!("{} not allowed", method.as_str()),
+            )),
+        }
+    }
+}
+
+/// Like `HttpRequestHandler`, but the type of the response body is chosen by the implementation,
+/// so responses don't need to be boxed. Every `HttpRequestHandler` is an `HttpResponder` with a
+/// boxed body.
+///
+/// Errors are still converted to boxed responses, like errors the server itself encounters.
+pub trait HttpResponder<I: core2::io::Read> {
+    type Body: core2::io::Read;
+    type Error: Into<HttpResponse<Box<dyn core2::io::Read>>>;
+
+    fn respond(
+        &mut self,
+        request: HttpRequest<&mut I>,
+    ) -> Result<HttpResponse<Self::Body>, Self::Error>;
+}
+
+impl<I: core2::io::Read, H: HttpRequestHandler<I>> HttpResponder<I> for H {
+    type Body = Box<dyn core2::io::Read>;
+    type Error = H::Error;
+
+    fn respond(
+        &mut self,
+        request: HttpRequest<&mut I>,
+    ) -> Result<HttpResponse<Self::Body>, Self::Error> {
+        self.handle(request)
+    }
+}
+
+impl<I: core2::io::Read, T: HttpRequestHandler<I> + ?Sized> HttpRequestHandler<I> for Box<T> {
+    type Error = T::Error;
+
+    fn connect(
+        &mut self,
+        uri: String,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        (**self).connect(uri)
+    }
+
+    fn delete(
+        &mut self,
+        uri: String,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        (**self).delete(uri)
+    }
+
+    fn get(&mut self, uri: String) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        (**self).get(uri)
+    }
+
+    fn head(&mut self, uri: String) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        (**self).head(uri)
+    }
+
+    fn options(
+        &mut self,
+        uri: String,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        (**self).options(uri)
+    }
+
+    fn put(
+        &mut self,
+        uri: String,
+        stream: HttpBody<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        (**self).put(uri, stream)
+    }
+
+    fn patch(
+        &mut self,
+        uri: String,
+        stream: HttpBody<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        (**self).patch(uri, stream)
+    }
+
+    fn post(
+        &mut self,
+        uri: String,
+        stream: HttpBody<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        (**self).post(uri, stream)
+    }
+
+    fn trace(
+        &mut self,
+        uri: String,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        (**self).trace(uri)
+    }
+
+    fn handle(
+        &mut self,
+        request: HttpRequest<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        (**self).handle(request)
+    }
+}
+
+/// Strip the port and any trailing dot from a `Host` header value and lowercase it.
+fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let host = if host.starts_with('[') {
+        // IPv6 literal, the port comes after the closing bracket.
+        match host.find(']') {
+            Some(end) => &host[..=end],
+            None => host,
+        }
+    } else {
+        host.split(':').next().unwrap()
+    };
+    host.trim_end_matches('.').to_lowercase()
+}
+
+/// An `HttpRequestHandler` which dispatches requests to other handlers based on the `Host` header,
+/// allowing multiple sites to be served by one `HttpServer`.
+///
+/// To serve sites with different handler types, use `Box<dyn HttpRequestHandler<I, Error = E>>`
+/// as the handler type.
+pub struct VirtualHosts<H> {
+    hosts: HashMap<String, H>,
+    default: H,
+}
+
+impl<H> VirtualHosts<H> {
+    /// Create a `VirtualHosts` which sends requests for unknown hosts (or requests without a
+    /// `Host` header) to `default`.
+    pub fn new(default: H) -> Self {
+        Self {
+            hosts: HashMap::new(),
+            default,
+        }
+    }
+
+    /// Serve requests for `hostname` with the given handler. The hostname is matched
+    /// case-insensitively and without regard to the port.
+    pub fn add_host<S: AsRef<str>>(&mut self, hostname: S, handler: H) {
+        self.hosts
+            .insert(normalize_host(hostname.as_ref()), handler);
+    }
+}
+
+impl<I: core2::io::Read, H: HttpRequestHandler<I>> HttpRequestHandler<I> for VirtualHosts<H> {
+    type Error = H::Error;
+
+    fn handle(
+        &mut self,
+        request: HttpRequest<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        let host = request.headers.get("Host").map(normalize_host);
+        let hosts = &mut self.hosts;
+        let handler = match host.as_ref().and_then(|h| hosts.get_mut(h)) {
+            Some(handler) => handler,
+            None => &mut self.default,
+        };
+        handler.handle(request)
+    }
+}
+
+/// A buffered stream, for connections which aren't read a request at a time. It lives as long as
+/// the connection does, so bytes read ahead aren't lost.
+pub(crate) type Connection<S> = io::BufReader<S, 1024>;
+
+/// Counts the bytes written through it.
+struct CountingWriter<'a, W> {
+    inner: W,
+    count: &'a mut u64,
+}
+
+impl<'a, W: core2::io::Write> core2::io::Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        *self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> core2::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Write the response, framing the body with its `Content-Length` header if it has one or the
+/// chunked encoding otherwise. Responses to HEAD requests and responses which can't have a body
+/// are written without one.
+///
+/// If `allow_chunked` is false, as for HTTP/1.0 clients, a body without a `Content-Length` is
+/// written as is, and the response must close the connection to mark its end.
+///
+/// Returns the number of bytes written.
+fn write_response<B: core2::io::Read, W: core2::io::Write>(
+    response: &mut HttpResponse<B>,
+    method: Option<HttpMethod>,
+    allow_chunked: bool,
+    w: W,
+) -> error::Result<u64> {
+    let has_body = response_has_body(response.status, method);
+    let chunked = allow_chunked && has_body && response.get_header("Content-Length").is_none();
+    if chunked {
+        response.add_header("Transfer-Encoding", "chunked");
+        if !response.trailers.is_empty() {
+            let names: Vec<&str> = response.trailers.keys().collect();
+            let names = names.join(", ");
+            response.add_header("Trailer", names);
+        }
+    }
+
+    let mut written = 0;
+    let mut w = io::BufWriter::<_, 1024>::new(CountingWriter {
+        inner: w,
+        count: &mut written,
+    });
+    response.serialize(&mut w)?;
+    if chunked {
+        let mut body = ChunkedWriter::new(&mut w);
+        io::copy(&mut response.body, &mut body)?;
+        body.finish_with_trailers(&response.trailers)?;
+    } else if has_body {
+        io::copy(&mut response.body, &mut w)?;
+    }
+    w.flush()?;
+    drop(w);
+    Ok(written)
+}
+
+/// What `HttpServer::serve_one` did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServeSummary {
+    /// The method of the request, or `None` if the request couldn't be read.
+    pub method: Option<HttpMethod>,
+    /// The uri of the request, or `None` if the request couldn't be read.
+    pub uri: Option<String>,
+    /// The status of the response.
+    pub status: HttpStatus,
+    /// The number of bytes of response written, including the status line and headers.
+    pub bytes_written: u64,
+}
+
+/// Request headers which can hold credentials, so aren't echoed back by TRACE.
+const SENSITIVE_HEADERS: &[&str] = &["Authorization", "Cookie", "Proxy-Authorization"];
+
+/// Answer a TRACE request by echoing the request line and headers back.
+fn trace_response<B: core2::io::Read>(
+    mut request: HttpRequest<B>,
+) -> HttpResult<HttpResponse<Box<dyn core2::io::Read>>> {
+    for header in SENSITIVE_HEADERS {
+        request.headers.remove(header);
+    }
+    let mut message = Vec::new();
+    request.serialize_head(&mut message)?;
+
+    let length = message.len();
+    let mut response = HttpResponse::new(
+        HttpStatus::OK,
+        Box::new(io::Cursor::new(message)) as Box<dyn core2::io::Read>,
+    );
+    response.add_header("Content-Type", "message/http");
+    response.add_header("Content-Length", length.to_string());
+    Ok(response)
+}
+
+/// A request read by `HttpServer::serve_one` and the response to it.
+struct Served<B: core2::io::Read> {
+    method: HttpMethod,
+    uri: String,
+    allow_chunked: bool,
+    response: HttpResponse<B>,
+}
+
+/// Whether the request has a body. The handler might not read all of it, in which case we don't
+/// know where the next request on the connection starts.
+fn has_body<S: core2::io::Read>(request: &HttpRequest<S>) -> bool {
+    match &request.body {
+        HttpBody::Chunked(_) => true,
+        HttpBody::Limited(body) => body.limit() > 0,
+        HttpBody::ReadTilClose(_) | HttpBody::Decoded(_) => false,
+    }
+}
+
+/// Get the response to the request from `respond`, and work out how it should be sent. `last`
+/// says whether this is the last request to be served on the connection.
+fn respond_to<I, B, F>(request: HttpRequest<I>, last: bool, respond: F) -> HttpResult<Served<B>>
+where
+    I: core2::io::Read,
+    B: core2::io::Read,
+    F: FnOnce(HttpRequest<I>) -> HttpResult<HttpResponse<B>>,
+{
+    let keep_alive = request.wants_keep_alive() && !last;
+    let allow_chunked = request.supports_chunked();
+    let version = request.version;
+    let method = request.method;
+    let uri = request.uri.clone();
+
+    let mut response = respond(request)?;
+    if !keep_alive {
+        response.add_header("Connection", "close");
+    }
+    if version == HttpVersion::Http09 {
+        // Only the body, which the client reads until the connection closes.
+        response.version = HttpVersion::Http09;
+    }
+    Ok(Served {
+        method,
+        uri,
+        allow_chunked,
+        response,
+    })
+}
+
+/// What `HttpServer` and `NonBlockingConnection` do to requests and responses besides passing
+/// them to and from the handler.
+#[derive(Clone)]
+struct ServeOptions {
+    strict: bool,
+    trace: bool,
+    server_header: Option<String>,
+    clock: Option<Clock>,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            strict: false,
+            trace: false,
+            server_header: Some("http_io".into()),
+            #[cfg(feature = "std")]
+            clock: Some(crate::date::system_clock),
+            #[cfg(not(feature = "std"))]
+            clock: None,
+        }
+    }
+}
+
+impl ServeOptions {
+    /// Check a request read off a connection before it is handled.
+    fn check<B: core2::io::Read>(
+        &self,
+        request: error::Result<HttpRequest<B>>,
+    ) -> HttpResult<HttpRequest<B>> {
+        let mut request = request?;
+        if self.strict {
+            request.check_compliance()?;
+        }
+
+        if let HttpMethod::Post | HttpMethod::Put = request.method {
+            request.body.require_length()?;
+        }
+        Ok(request)
+    }
+
+    /// Whether the server answers the request itself, rather than the handler.
+    fn answers<B: core2::io::Read>(&self, request: &HttpRequest<B>) -> bool {
+        self.trace && request.method == HttpMethod::Trace
+    }
+
+    /// Add the configured `Server` and `Date` headers, unless the handler set them.
+    fn add_default_headers(&self, headers: &mut HttpHeaders) {
+        if let Some(server) = &self.server_header {
+            if headers.get("Server").is_none() {
+                headers.insert("Server", server.as_str());
+            }
+        }
+        if let Some(clock) = self.clock {
+            if headers.get("Date").is_none() {
+                headers.set_http_date("Date", clock());
+            }
+        }
+    }
+
+    /// Write the response, returning the number of bytes written and whether the connection
+    /// should be closed afterwards.
+    fn send<B: core2::io::Read, W: core2::io::Write>(
+        &self,
+        response: &mut HttpResponse<B>,
+        method: Option<HttpMethod>,
+        allow_chunked: bool,
+        w: W,
+    ) -> error::Result<(u64, bool)> {
+        self.add_default_headers(&mut response.headers);
+        let written = write_response(response, method, allow_chunked, w)?;
+        let close = response
+            .get_header("Connection")
+            .map(|v| v.eq_ignore_ascii_case("close"))
+            .unwrap_or(false);
+        Ok((written, close))
+    }
+
+    /// Send the response to a request, or the error encountered reading it. Returns a summary and
+    /// whether the connection should be closed afterwards.
+    fn send_served<B: core2::io::Read, W: core2::io::Write>(
+        &self,
+        served: HttpResult<Served<B>>,
+        w: W,
+    ) -> error::Result<(ServeSummary, bool)> {
+        Ok(match served {
+            Ok(mut served) => {
+                let (bytes_written, close) = self.send(
+                    &mut served.response,
+                    Some(served.method),
+                    served.allow_chunked,
+                    w,
+                )?;
+                let summary = ServeSummary {
+                    method: Some(served.method),
+                    uri: Some(served.uri),
+                    status: served.response.status,
+                    bytes_written,
+                };
+                (summary, close)
+            }
+            Err(mut response) => {
+                // We might not know what the client supports, but the connection is closed so the
+                // body doesn't need framing.
+                response.add_header("Connection", "close");
+                let (bytes_written, close) = self.send(&mut response, None, false, w)?;
+                let summary = ServeSummary {
+                    method: None,
+                    uri: None,
+                    status: response.status,
+                    bytes_written,
+                };
+                (summary, close)
+            }
+        })
+    }
 }
 
+/// Called with each error the `serve_forever` functions continue past, like a client closing its
+/// connection part way through a request.
+pub type ErrorCallback = fn(&error::Error);
+
 /// A simple HTTP server. Not suited for production workloads, better used in tests and small
 /// projects.
-pub struct HttpServer<L: Listen, H: HttpRequestHandler<L::Stream>> {
+///
+/// Connections are kept open between requests for a few seconds, see
+/// `HttpServer::set_keep_alive_timeout`. Since the server handles one request at a time, a client
+/// holding a connection open keeps other clients waiting until then.
+///
+/// Requests are read straight from the stream, without buffering, so nothing the client sends
+/// after a request, like a pipelined request, is lost.
+pub struct HttpServer<L: Listen, H: HttpResponder<L::Stream>> {
     connection_stream: L,
     request_handler: H,
+    connection: Option<L::Stream>,
+    options: ServeOptions,
+    on_error: Option<ErrorCallback>,
+    keep_alive_timeout: Option<Duration>,
+    max_requests_per_connection: Option<usize>,
+    requests_on_connection: usize,
 }
 
-impl<L: Listen, H: HttpRequestHandler<L::Stream>> HttpServer<L, H> {
+impl<L: Listen, H: HttpResponder<L::Stream>> HttpServer<L, H> {
     pub fn new(connection_stream: L, request_handler: H) -> Self {
         HttpServer {
             connection_stream,
             request_handler,
+            connection: None,
+            options: ServeOptions::default(),
+            on_error: None,
+            keep_alive_timeout: Some(Duration::from_secs(5)),
+            max_requests_per_connection: None,
+            requests_on_connection: 0,
         }
     }
 
-    pub fn serve_one(&mut self) -> error::Result<()> {
-        let mut stream = self.connection_stream.accept()?;
-        let mut response = match self.serve_one_inner(&mut stream) {
-            Ok(response) => response,
-            Err(response) => response,
-        };
+    /// Set the value of the `Server` header added to responses, or `None` to not add one. It is
+    /// `http_io` by default. Handlers can still set the header themselves.
+    pub fn set_server_header<S: Into<String>>(&mut self, server: Option<S>) {
+        self.options.server_header = server.map(Into::into);
+    }
 
-        response.serialize(&mut stream)?;
-        io::copy(&mut response.body, &mut stream)?;
+    /// Set the clock used for the `Date` header added to responses, or `None` to not add one. When
+    /// http_io is built with the `"std"` feature it is `date::system_clock` by default, otherwise
+    /// there is no default and no `Date` header is added. Handlers can still set the header
+    /// themselves.
+    pub fn set_clock(&mut self, clock: Option<Clock>) {
+        self.options.clock = clock;
+    }
 
-        Ok(())
+    /// Turn strict compliance checking on or off. It is off by default. When on, requests which
+    /// violate requirements of the protocol, like HTTP/1.1 requests without a `Host` header, are
+    /// answered with 400 Bad Request. See `HttpRequest::check_compliance` for what is checked.
+    pub fn set_strict_mode(&mut self, strict: bool) {
+        self.options.strict = strict;
+    }
+
+    /// Turn answering TRACE requests on or off. It is off by default, since echoing requests back
+    /// can reveal information, so TRACE requests go to the handler. When on, the server answers
+    /// TRACE requests itself with the request line and headers it received, leaving out headers
+    /// which can hold credentials.
+    pub fn set_trace_enabled(&mut self, trace: bool) {
+        self.options.trace = trace;
+    }
+
+    /// Set how long a kept-alive connection may sit idle waiting for the next request before it is
+    /// closed, or `None` to close every connection after one response. It is 5 seconds by default.
+    /// Since the server serves one connection at a time, an idle client holds up every other
+    /// client until this passes.
+    ///
+    /// The timeout relies on `Listen::set_read_timeout`, so connections aren't kept open with
+    /// listeners which don't implement it.
+    pub fn set_keep_alive_timeout(&mut self, timeout: Option<Duration>) {
+        self.keep_alive_timeout = timeout;
+    }
+
+    /// Set the function called with the errors `serve_forever` and `serve_forever_http2` continue
+    /// past, or `None` to ignore them. It is `None` by default.
+    pub fn set_error_callback(&mut self, callback: Option<ErrorCallback>) {
+        self.on_error = callback;
+    }
+
+    /// Set the maximum number of requests served on one connection, or `None` for no limit. It is
+    /// `None` by default. The response to the last request allowed has `Connection: close`.
+    pub fn set_max_requests_per_connection(&mut self, max: Option<usize>) {
+        self.max_requests_per_connection = max;
+    }
+
+    /// Wait for the next request on a kept-alive connection, for at most the keep-alive timeout,
+    /// and read its head. Returns `None` if the client closed the connection or didn't send a
+    /// request in time.
+    fn wait_for_next_request(
+        &self,
+        stream: &mut L::Stream,
+    ) -> Option<error::Result<HttpRequest<io::Empty>>> {
+        let timeout = self.keep_alive_timeout?;
+        let listener = &self.connection_stream;
+        listener.set_read_timeout(stream, Some(timeout)).ok()?;
+        let first_line = CrLfStream::new(&mut *stream).expect_next();
+        // The timeout is only for the wait between requests, not for reading the request itself.
+        listener.set_read_timeout(stream, None).ok()?;
+        let first_line = first_line.ok()?;
+        Some(
+            HttpRequest::deserialize_after_line(&first_line, &mut *stream)
+                .map(|head| head.with_body(io::empty())),
+        )
+    }
+
+    /// Serve one request. It is read from the connection kept open after the previous request if
+    /// there is one, otherwise a new connection is accepted.
+    pub fn serve_one(&mut self) -> error::Result<ServeSummary> {
+        if let Some(mut stream) = self.connection.take() {
+            if let Some(head) = self.wait_for_next_request(&mut stream) {
+                return self.serve_stream(stream, Some(head));
+            }
+        }
+        let stream = self.connection_stream.accept()?;
+        self.requests_on_connection = 0;
+        self.serve_stream(stream, None)
     }
 
-    /// Accept one new HTTP stream and serve one request off it.
+    /// Read one request off the stream and get the handler's response to it, without sending it.
     pub fn serve_one_inner(
         &mut self,
         stream: &mut <L as Listen>::Stream,
-    ) -> HttpResult<HttpResponse<Box<dyn core2::io::Read>>> {
-        let request = HttpRequest::deserialize(io::BufReader::new(stream))?;
+    ) -> HttpResult<HttpResponse<H::Body>> {
+        let request = self.read_request(stream, None)?;
+        self.request_handler.respond(request).map_err(|e| e.into())
+    }
 
-        match request.method {
-            HttpMethod::Delete => self.request_handler.delete(request.uri),
-            HttpMethod::Get => self.request_handler.get(request.uri),
-            HttpMethod::Head => self.request_handler.head(request.uri),
-            HttpMethod::Options => self.request_handler.options(request.uri),
-            HttpMethod::Post => {
-                request.body.require_length()?;
-                self.request_handler.post(request.uri, request.body)
+    /// Serve one request off the stream, then keep the stream for the next request unless the
+    /// connection is to be closed. `head` is the head of the request, if it was already read.
+    fn serve_stream(
+        &mut self,
+        mut stream: L::Stream,
+        head: Option<error::Result<HttpRequest<io::Empty>>>,
+    ) -> error::Result<ServeSummary> {
+        self.requests_on_connection += 1;
+        // Without a read timeout there would be no limit on the wait for the next request.
+        let keep_alive = self.keep_alive_timeout.is_some()
+            && self
+                .connection_stream
+                .set_read_timeout(&stream, None)
+                .is_ok();
+        let last = !keep_alive
+            || self
+                .max_requests_per_connection
+                .map(|max| self.requests_on_connection >= max)
+                .unwrap_or(false);
+
+        let request = self.read_request(&mut stream, head);
+        let last = last || request.as_ref().map(has_body).unwrap_or(false);
+        let options = &self.options;
+        let (summary, close) = match request {
+            Ok(request) if options.answers(&request) => {
+                let served = respond_to(request, last, trace_response);
+                options.send_served(served, &mut stream)?
             }
-            HttpMethod::Put => {
-                request.body.require_length()?;
-                self.request_handler.put(request.uri, request.body)
+            Ok(request) => {
+                let handler = &mut self.request_handler;
+                let served =
+                    respond_to(request, last, |r| handler.respond(r).map_err(|e| e.into()));
+                options.send_served(served, &mut stream)?
             }
-            HttpMethod::Trace => self.request_handler.trace(request.uri),
+            Err(response) => options.send_served::<H::Body, _>(Err(response), &mut stream)?,
+        };
+        if !close {
+            self.connection = Some(stream);
         }
-        .map_err(|e| e.into())
+
+        Ok(summary)
+    }
+
+    /// Read one request off the stream, or just its body if its head was already read.
+    fn read_request<'s>(
+        &self,
+        stream: &'s mut L::Stream,
+        head: Option<error::Result<HttpRequest<io::Empty>>>,
+    ) -> HttpResult<HttpRequest<&'s mut L::Stream>> {
+        let info = self.connection_stream.connection_info(stream);
+        let request = match head {
+            Some(head) => head.and_then(|head| head.with_framed_body(stream)),
+            None => HttpRequest::deserialize(stream),
+        };
+        let mut request = self.options.check(request)?;
+        request.connection = info;
+        Ok(request)
     }
 
     /// Run `serve_one` in a loop forever
@@ -252,10 +1164,124 @@ impl<L: Listen, H: HttpRequestHandler<L::Stream>> HttpServer<L, H> {
     pub fn serve_forever(&mut self) -> ! {
         loop {
             if let Err(e) = self.serve_one() {
-                println!("Error {:?}", e)
+                if let Some(on_error) = self.on_error {
+                    on_error(&e);
+                }
+            }
+        }
+    }
+
+    /// Run `serve_one` `count` times, stopping at the first error.
+    pub fn serve_n(&mut self, count: usize) -> error::Result<()> {
+        for _ in 0..count {
+            self.serve_one()?;
+        }
+        Ok(())
+    }
+
+    /// Run `serve_one` until the given condition says to stop, stopping early at the first error.
+    ///
+    /// The condition is checked before waiting for each new connection, so a server blocked
+    /// waiting on a connection only notices the condition once that connection has been served.
+    pub fn serve_until<C: StopCondition>(&mut self, mut condition: C) -> error::Result<()> {
+        while !condition.should_stop() {
+            self.serve_one()?;
+        }
+        Ok(())
+    }
+}
+
+/// *These functions are available if http_io is built with the `"http2"` feature.*
+#[cfg(feature = "http2")]
+impl<L, H> HttpServer<L, H>
+where
+    L: Listen,
+    H: HttpResponder<L::Stream> + HttpResponder<BufferedRequest>,
+{
+    /// Serve one request like `serve_one`, but also accept HTTP/2 on new connections. HTTP/2 is
+    /// used when it is negotiated with ALPN, when the client starts with the HTTP/2 connection
+    /// preface, or when the client's first request asks to upgrade to `h2c`.
+    ///
+    /// An HTTP/2 connection is served until the client closes it, and the summary is of the last
+    /// response sent on it. Its streams are read concurrently but handled one at a time.
+    ///
+    /// To negotiate HTTP/2 over TLS, offer `h2` with ALPN: with rustls, set
+    /// `ServerConfig::alpn_protocols` to `h2` and `http/1.1`; with openssl, use
+    /// `SslAcceptorBuilder::set_alpn_select_callback`.
+    pub fn serve_one_http2(&mut self) -> error::Result<ServeSummary> {
+        if let Some(mut stream) = self.connection.take() {
+            if let Some(head) = self.wait_for_next_request(&mut stream) {
+                return self.serve_stream(stream, Some(head));
+            }
+        }
+        let mut stream = self.connection_stream.accept()?;
+        self.requests_on_connection = 0;
+        let info = self.connection_stream.connection_info(&stream);
+        match http2::detect(&info, &mut stream)? {
+            http2::Opening::Http2(start) => self.serve_http2(stream, info, start),
+            http2::Opening::Http1(head) => self.serve_stream(stream, Some(head)),
+        }
+    }
+
+    /// Run `serve_one_http2` in a loop forever
+    pub fn serve_forever_http2(&mut self) -> ! {
+        loop {
+            if let Err(e) = self.serve_one_http2() {
+                if let Some(on_error) = self.on_error {
+                    on_error(&e);
+                }
             }
         }
     }
+
+    fn serve_http2(
+        &mut self,
+        mut stream: L::Stream,
+        info: ConnectionInfo,
+        start: http2::Start,
+    ) -> error::Result<ServeSummary> {
+        if let http2::Start::Upgrade { .. } = start {
+            let mut response = HttpResponse::new(HttpStatus::SwitchingProtocols, io::empty());
+            response.add_header("Connection", "Upgrade");
+            response.add_header("Upgrade", "h2c");
+            write_response(&mut response, None, false, &mut stream)?;
+        }
+
+        let options = self.options.clone();
+        let mut connection = http2::Http2Connection::new(stream, info);
+        connection.serve(&mut self.request_handler, start, |headers| {
+            options.add_default_headers(headers)
+        })
+    }
+}
+
+/// Something `HttpServer::serve_until` can use to decide when to stop serving.
+pub trait StopCondition {
+    fn should_stop(&mut self) -> bool;
+}
+
+/// Stop once the flag is set.
+impl StopCondition for &AtomicBool {
+    fn should_stop(&mut self) -> bool {
+        self.load(Ordering::SeqCst)
+    }
+}
+
+/// Stop once the flag is set.
+impl StopCondition for Arc<AtomicBool> {
+    fn should_stop(&mut self) -> bool {
+        self.load(Ordering::SeqCst)
+    }
+}
+
+/// Stop once the deadline has passed.
+///
+/// *This impl is available if http_io is built with the `"std"` feature.*
+#[cfg(feature = "std")]
+impl StopCondition for std::time::Instant {
+    fn should_stop(&mut self) -> bool {
+        std::time::Instant::now() >= *self
+    }
 }
 
 #[cfg(test)]
@@ -288,6 +1314,20 @@ use std::core2::io::Read;
 impl<I: core2::io::Read> HttpRequestHandler<I> for TestRequestHandler {
     type Error = HttpResponse<Box<dyn core2::io::Read>>;
 
+    fn delete(
+        &mut self,
+        uri: String,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        let request = self.script.remove(0);
+        assert_eq!(request.expected_method, HttpMethod::Delete);
+        assert_eq!(request.expected_uri, uri);
+
+        Ok(HttpResponse::from_string(
+            request.response_status,
+            request.response_body,
+        ))
+    }
+
     fn get(&mut self, uri: String) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
         let request = self.script.remove(0);
         assert_eq!(request.expected_method, HttpMethod::Get);
@@ -299,6 +1339,17 @@ impl<I: core2::io::Read> HttpRequestHandler<I> for TestRequestHandler {
         ))
     }
 
+    fn head(&mut self, uri: String) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        let request = self.script.remove(0);
+        assert_eq!(request.expected_method, HttpMethod::Head);
+        assert_eq!(request.expected_uri, uri);
+
+        Ok(HttpResponse::from_string(
+            request.response_status,
+            request.response_body,
+        ))
+    }
+
     fn put(
         &mut self,
         uri: String,
@@ -317,6 +1368,25 @@ impl<I: core2::io::Read> HttpRequestHandler<I> for TestRequestHandler {
             request.response_body,
         ))
     }
+
+    fn post(
+        &mut self,
+        uri: String,
+        mut stream: HttpBody<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn core2::io::Read>>, Self::Error> {
+        let request = self.script.remove(0);
+        assert_eq!(request.expected_method, HttpMethod::Post);
+        assert_eq!(request.expected_uri, uri);
+
+        let mut body_string = String::new();
+        stream.read_to_string(&mut body_string).unwrap();
+        assert_eq!(request.expected_body, body_string);
+
+        Ok(HttpResponse::from_string(
+            request.response_status,
+            request.response_body,
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -344,6 +1414,18 @@ pub fn test_ssl_server(
 ) -> core2::Result<(
     u16,
     HttpServer<SslListener<std::net::TcpListener>, TestRequestHandler>,
+)> {
+    test_ssl_server_with_extra_chain(script, vec![])
+}
+
+/// Like `test_ssl_server`, but presenting the given certificates after its own.
+#[cfg(test)]
+pub fn test_ssl_server_with_extra_chain(
+    script: Vec<ExpectedRequest>,
+    extra_chain: Vec<openssl::x509::X509>,
+) -> core2::Result<(
+    u16,
+    HttpServer<SslListener<std::net::TcpListener>, TestRequestHandler>,
 )> {
     use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
 
@@ -359,6 +1441,9 @@ pub fn test_ssl_server(
     acceptor
         .set_certificate_chain_file(manifest_dir.join("test_cert.pem"))
         .unwrap();
+    for certificate in extra_chain {
+        acceptor.add_extra_chain_cert(certificate).unwrap();
+    }
     acceptor.check_private_key().unwrap();
 
     let stream = SslListener::new(server_socket, acceptor.build());
@@ -366,3 +1451,453 @@ pub fn test_ssl_server(
 
     Ok((server_address.port(), server))
 }
+
+#[cfg(test)]
+pub fn test_sni_server(
+    script: Vec<ExpectedRequest>,
+) -> error::Result<(
+    u16,
+    HttpServer<SslListener<std::net::TcpListener>, TestRequestHandler>,
+)> {
+    use openssl::ssl::{SslAcceptor, SslMethod};
+
+    let server_socket = std::net::TcpListener::bind("localhost:0")?;
+    let server_address = server_socket.local_addr()?;
+    let handler = TestRequestHandler::new(script);
+
+    let manifest_dir = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let mut certificates = SniCertificates::new();
+    certificates.add(
+        "localhost",
+        manifest_dir.join("test_cert.pem"),
+        manifest_dir.join("test_key.pem"),
+    )?;
+
+    // The default certificate is never presented since the client always asks for localhost.
+    let acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+    let stream = SslListener::with_sni(server_socket, acceptor, certificates);
+    let server = HttpServer::new(stream, handler);
+
+    Ok((server_address.port(), server))
+}
+
+#[cfg(all(test, feature = "rustls"))]
+pub fn test_rustls_server(
+    script: Vec<ExpectedRequest>,
+) -> error::Result<(
+    u16,
+    HttpServer<RustlsListener<std::net::TcpListener>, TestRequestHandler>,
+)> {
+    let server_socket = std::net::TcpListener::bind("localhost:0")?;
+    let server_address = server_socket.local_addr()?;
+    let handler = TestRequestHandler::new(script);
+
+    let manifest_dir = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let read_pem = |name: &str| -> std::io::Result<std::io::BufReader<std::fs::File>> {
+        Ok(std::io::BufReader::new(std::fs::File::open(
+            manifest_dir.join(name),
+        )?))
+    };
+    let certificates = rustls_pemfile::certs(&mut read_pem("test_cert.pem")?)?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    let key = rustls_pemfile::rsa_private_keys(&mut read_pem("test_key.pem")?)?.remove(0);
+
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certificates, rustls::PrivateKey(key))?;
+    let stream = RustlsListener::new(server_socket, std::sync::Arc::new(config));
+    let server = HttpServer::new(stream, handler);
+
+    Ok((server_address.port(), server))
+}
+
+#[cfg(test)]
+mod virtual_hosts_tests {
+    use super::{HttpRequestHandler, VirtualHosts};
+    use crate::protocol::{HttpRequest, HttpResponse, HttpStatus};
+    use core2::io::{self, Read};
+
+    struct NamedHandler(&'static str);
+
+    impl<I: Read> HttpRequestHandler<I> for NamedHandler {
+        type Error = HttpResponse<Box<dyn Read>>;
+
+        fn get(&mut self, _uri: String) -> Result<HttpResponse<Box<dyn Read>>, Self::Error> {
+            Ok(HttpResponse::from_string(HttpStatus::OK, self.0))
+        }
+    }
+
+    fn response_body(hosts: &mut VirtualHosts<NamedHandler>, request: &str) -> String {
+        let mut input = io::BufReader::<_, 1024>::new(request.as_bytes());
+        let request = HttpRequest::deserialize(&mut input).unwrap();
+        let mut response = hosts.handle(request).unwrap_or_else(|e| e);
+        let mut body = String::new();
+        response.body.read_to_string(&mut body).unwrap();
+        body
+    }
+
+    #[test]
+    fn dispatch_on_host() {
+        let mut hosts = VirtualHosts::new(NamedHandler("default"));
+        hosts.add_host("a.example.com", NamedHandler("a"));
+        hosts.add_host("B.example.com", NamedHandler("b"));
+
+        assert_eq!(
+            response_body(&mut hosts, "GET / HTTP/1.1\r\nHost: a.example.com\r\n\r\n"),
+            "a"
+        );
+        assert_eq!(
+            response_body(
+                &mut hosts,
+                "GET / HTTP/1.1\r\nHost: b.example.com:8080\r\n\r\n"
+            ),
+            "b"
+        );
+        assert_eq!(
+            response_body(&mut hosts, "GET / HTTP/1.1\r\nHost: c.example.com\r\n\r\n"),
+            "default"
+        );
+        assert_eq!(
+            response_body(&mut hosts, "GET / HTTP/1.1\r\n\r\n"),
+            "default"
+        );
+    }
+}
+
+#[cfg(test)]
+mod serve_tests {
+    use super::{
+        test_server, write_response, ExpectedRequest, HttpResponder, HttpServer, TestRequestHandler,
+    };
+    use crate::error::{Error, Result};
+    use crate::protocol::{HttpMethod, HttpRequest, HttpResponse, HttpStatus};
+    use core::sync::atomic::AtomicBool;
+    use core2::io::Read;
+
+    fn expected_get() -> ExpectedRequest {
+        ExpectedRequest {
+            expected_method: HttpMethod::Get,
+            expected_uri: "/".into(),
+            expected_body: "".into(),
+            response_status: HttpStatus::OK,
+            response_body: "hello from server".into(),
+        }
+    }
+
+    #[test]
+    fn serve_n() -> Result<()> {
+        let (port, mut server) = test_server(vec![expected_get(), expected_get()])?;
+        let handle = std::thread::spawn(move || server.serve_n(2));
+
+        for _ in 0..2 {
+            let mut body = crate::client::get(format!("http://localhost:{}/", port).as_ref())?;
+            let mut body_str = String::new();
+            body.read_to_string(&mut body_str)?;
+            assert_eq!(body_str, "hello from server");
+        }
+
+        handle.join().unwrap()
+    }
+
+    #[test]
+    fn serve_one_summary() -> Result<()> {
+        let (port, mut server) = test_server(vec![expected_get()])?;
+        let handle = std::thread::spawn(move || server.serve_one());
+
+        let mut body = crate::client::get(format!("http://localhost:{}/", port).as_ref())?;
+        let mut body_str = String::new();
+        body.read_to_string(&mut body_str)?;
+
+        let summary = handle.join().unwrap()?;
+        assert_eq!(summary.method, Some(HttpMethod::Get));
+        assert_eq!(summary.uri.as_deref(), Some("/"));
+        assert_eq!(summary.status, HttpStatus::OK);
+        assert!(summary.bytes_written > body_str.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn serve_one_summary_bad_request() -> Result<()> {
+        use std::io::Write as _;
+
+        let (port, mut server) = test_server(vec![])?;
+        let handle = std::thread::spawn(move || server.serve_one());
+
+        let mut stream = std::net::TcpStream::connect(("localhost", port))?;
+        stream.write_all(b"BLARG / HTTP/1.1\r\n\r\n")?;
+
+        let summary = handle.join().unwrap()?;
+        assert_eq!(summary.method, None);
+        assert_eq!(summary.uri, None);
+        assert_ne!(summary.status, HttpStatus::OK);
+        Ok(())
+    }
+
+    #[test]
+    fn serve_pipelined_requests() -> Result<()> {
+        use std::io::{Read as _, Write as _};
+
+        let (port, mut server) = test_server(vec![expected_get(), expected_get()])?;
+        server.set_keep_alive_timeout(Some(std::time::Duration::from_secs(5)));
+        let handle = std::thread::spawn(move || server.serve_n(2));
+
+        let mut stream = std::net::TcpStream::connect(("localhost", port))?;
+        stream.write_all(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n\
+              GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )?;
+        handle.join().unwrap()?;
+
+        let mut output = String::new();
+        stream.read_to_string(&mut output)?;
+        assert_eq!(output.matches("hello from server").count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn no_keep_alive_without_timeout() -> Result<()> {
+        let output = raw_request_test(
+            "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            vec![expected_get()],
+            |server| server.set_keep_alive_timeout(None),
+        )?;
+        assert!(output.contains("Connection: close\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn keep_alive_timeout() -> Result<()> {
+        use std::io::Write as _;
+
+        let (port, mut server) = test_server(vec![expected_get(), expected_get()])?;
+        server.set_keep_alive_timeout(Some(std::time::Duration::from_millis(100)));
+        let handle = std::thread::spawn(move || server.serve_n(2));
+
+        // The first client keeps its connection open without sending another request, so the
+        // second is only served once the first times out.
+        let mut idle = std::net::TcpStream::connect(("localhost", port))?;
+        idle.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+        let mut stream = std::net::TcpStream::connect(("localhost", port))?;
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+        handle.join().unwrap()?;
+        Ok(())
+    }
+
+    #[test]
+    fn idle_client_under_default_timeout() -> Result<()> {
+        use std::io::{Read as _, Write as _};
+
+        let (port, mut server) = test_server(vec![expected_get(), expected_get()])?;
+        let handle = std::thread::spawn(move || server.serve_n(2));
+
+        let mut idle = std::net::TcpStream::connect(("localhost", port))?;
+        idle.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+        let mut stream = std::net::TcpStream::connect(("localhost", port))?;
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(30)))?;
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+
+        // The idle connection is given up on once the default timeout passes.
+        let mut output = String::new();
+        stream.read_to_string(&mut output)?;
+        assert!(output.contains("hello from server"));
+        handle.join().unwrap()?;
+
+        let mut output = String::new();
+        idle.read_to_string(&mut output)?;
+        assert_eq!(output.matches("hello from server").count(), 1);
+        Ok(())
+    }
+
+    /// Send the given raw request to a test server expecting the requests of `script`, and return
+    /// the raw response.
+    fn raw_request_test<F>(
+        request: &str,
+        script: Vec<ExpectedRequest>,
+        configure: F,
+    ) -> Result<String>
+    where
+        F: FnOnce(&mut HttpServer<std::net::TcpListener, TestRequestHandler>),
+    {
+        use std::io::{Read as _, Write as _};
+
+        let (port, mut server) = test_server(script)?;
+        configure(&mut server);
+        let handle = std::thread::spawn(move || server.serve_one());
+
+        let mut stream = std::net::TcpStream::connect(("localhost", port))?;
+        stream.write_all(request.as_bytes())?;
+        handle.join().unwrap()?;
+
+        let mut output = String::new();
+        stream.read_to_string(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn http_0_9() -> Result<()> {
+        let output = raw_request_test("GET /\r\n", vec![expected_get()], |_| {})?;
+        assert_eq!(output, "hello from server");
+        Ok(())
+    }
+
+    #[test]
+    fn max_requests_per_connection() -> Result<()> {
+        let output = raw_request_test(
+            "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            vec![expected_get()],
+            |server| {
+                server.set_keep_alive_timeout(Some(std::time::Duration::from_secs(5)));
+                server.set_max_requests_per_connection(Some(1));
+            },
+        )?;
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output.contains("Connection: close\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn strict_mode_accepts_host() -> Result<()> {
+        let output = raw_request_test(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            vec![expected_get()],
+            |server| server.set_strict_mode(true),
+        )?;
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn strict_mode_rejects_missing_host() -> Result<()> {
+        let output = raw_request_test("GET / HTTP/1.1\r\n\r\n", vec![], |server| {
+            server.set_strict_mode(true)
+        })?;
+        assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn serve_http_1_0() -> Result<()> {
+        let output = raw_request_test("GET / HTTP/1.0\r\n\r\n", vec![expected_get()], |_| ())?;
+        assert!(output.contains("Connection: close\r\n"));
+        assert!(!output.contains("Transfer-Encoding"));
+        assert!(output.ends_with("\r\n\r\nhello from server"));
+        Ok(())
+    }
+
+    #[test]
+    fn bodiless_statuses() -> Result<()> {
+        for status in &[
+            HttpStatus::Continue,
+            HttpStatus::EarlyHints,
+            HttpStatus::NoContent,
+            HttpStatus::NotModified,
+        ] {
+            let mut response = HttpResponse::from_string(*status, "ignored");
+            let mut output = Vec::new();
+            write_response(&mut response, Some(HttpMethod::Get), true, &mut output)?;
+            let output = String::from_utf8(output).unwrap();
+            assert!(!output.contains("Transfer-Encoding"));
+            assert!(output.ends_with("\r\n\r\n"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn trace_enabled() -> Result<()> {
+        let output = raw_request_test(
+            "TRACE /a HTTP/1.1\r\nHost: localhost\r\nCookie: secret\r\nConnection: close\r\n\r\n",
+            vec![],
+            |server| server.set_trace_enabled(true),
+        )?;
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output.contains("\r\nContent-Type: message/http\r\n"));
+        assert!(output.ends_with(
+            "\r\n\r\nTRACE /a HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+        ));
+        assert!(!output.contains("secret"));
+        Ok(())
+    }
+
+    #[test]
+    fn trace_disabled() -> Result<()> {
+        let output = raw_request_test("TRACE / HTTP/1.0\r\n\r\n", vec![], |_| ())?;
+        assert!(output.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn default_headers() -> Result<()> {
+        let output = raw_request_test("GET / HTTP/1.0\r\n\r\n", vec![expected_get()], |_| ())?;
+        assert!(output.contains("\r\nServer: http_io\r\n"));
+        assert!(output.contains("\r\nDate: "));
+        Ok(())
+    }
+
+    #[test]
+    fn default_headers_disabled() -> Result<()> {
+        let output = raw_request_test("GET / HTTP/1.0\r\n\r\n", vec![expected_get()], |server| {
+            server.set_server_header(None::<String>);
+            server.set_clock(None);
+        })?;
+        assert!(!output.contains("\r\nServer: "));
+        assert!(!output.contains("\r\nDate: "));
+        Ok(())
+    }
+
+    #[test]
+    fn clock() -> Result<()> {
+        let output = raw_request_test("GET / HTTP/1.0\r\n\r\n", vec![expected_get()], |server| {
+            server.set_clock(Some(|| 784_111_777));
+        })?;
+        assert!(output.contains("\r\nDate: Sun, 06 Nov 1994 08:49:37 GMT\r\n"));
+        Ok(())
+    }
+
+    struct StaticResponder;
+
+    impl HttpResponder<std::net::TcpStream> for StaticResponder {
+        type Body = &'static [u8];
+        type Error = Error;
+
+        fn respond(
+            &mut self,
+            _request: HttpRequest<&mut std::net::TcpStream>,
+        ) -> Result<HttpResponse<&'static [u8]>> {
+            let mut response = HttpResponse::new(HttpStatus::OK, &b"static body"[..]);
+            response.add_header("Content-Length", "11");
+            Ok(response)
+        }
+    }
+
+    #[test]
+    fn serve_typed_body() -> Result<()> {
+        let server_socket = std::net::TcpListener::bind("localhost:0")?;
+        let port = server_socket.local_addr()?.port();
+        let mut server = HttpServer::new(server_socket, StaticResponder);
+        let handle = std::thread::spawn(move || server.serve_one());
+
+        let mut body = crate::client::get(format!("http://localhost:{}/", port).as_ref())?;
+        handle.join().unwrap()?;
+
+        let mut body_str = String::new();
+        body.read_to_string(&mut body_str)?;
+        assert_eq!(body_str, "static body");
+        Ok(())
+    }
+
+    #[test]
+    fn serve_until_flag_set() -> Result<()> {
+        let (_, mut server) = test_server(vec![])?;
+        let stop = AtomicBool::new(true);
+        server.serve_until(&stop)
+    }
+
+    #[test]
+    fn serve_until_deadline_passed() -> Result<()> {
+        let (_, mut server) = test_server(vec![])?;
+        server.serve_until(std::time::Instant::now())
+    }
+}
@@ -0,0 +1,402 @@
+//! A request handler which caches the responses of another handler.
+
+use super::HttpRequestHandler;
+use crate::date::Clock;
+use crate::protocol::{HttpHeaders, HttpMethod, HttpRequest, HttpResponse, HttpStatus};
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, string::String, string::ToString, vec::Vec};
+use core2::io::{self, Read};
+use hashbrown::HashMap;
+
+struct CachedResponse {
+    status: HttpStatus,
+    headers: HttpHeaders,
+    body: Vec<u8>,
+    stored_at: u64,
+    expires_at: u64,
+    /// The request headers named by the response's `Vary` header, with their values in the
+    /// request the response was for.
+    varied: Vec<(String, Option<String>)>,
+    /// Whether the response says `public`, so it may answer requests with credentials.
+    public: bool,
+}
+
+impl CachedResponse {
+    /// Whether this response may answer a request with the given headers.
+    fn matches(&self, request: &HttpHeaders) -> bool {
+        if request.get("Authorization").is_some() && !self.public {
+            return false;
+        }
+        self.varied
+            .iter()
+            .all(|(name, value)| request.get(name) == value.as_deref())
+    }
+
+    fn to_response(&self, now: u64) -> HttpResponse<Box<dyn Read>> {
+        let body = Box::new(io::Cursor::new(self.body.clone())) as Box<dyn Read>;
+        let mut response = HttpResponse::new(self.status, body);
+        response.headers = self.headers.clone();
+        response.add_header("Age", (now - self.stored_at).to_string());
+        response
+    }
+}
+
+/// The directives of all the `Cache-Control` headers, as lowercase names and optional values.
+fn cache_directives(headers: &HttpHeaders) -> impl Iterator<Item = (String, Option<&str>)> {
+    headers
+        .get_all("Cache-Control")
+        .flat_map(|value| value.split(','))
+        .map(|directive| {
+            let mut parts = directive.trim().splitn(2, '=');
+            let name = parts.next().unwrap_or("").to_ascii_lowercase();
+            (name, parts.next().map(|v| v.trim_matches('"')))
+        })
+}
+
+/// How long a response may be cached for according to its `Cache-Control` headers, falling back
+/// to `default_ttl`. `None` if it may not be cached.
+fn cache_lifetime<B: Read>(response: &HttpResponse<B>, default_ttl: u64) -> Option<u64> {
+    if response.status != HttpStatus::OK {
+        return None;
+    }
+
+    let mut max_age = None;
+    let mut shared_max_age = None;
+    for (name, value) in cache_directives(&response.headers) {
+        match name.as_ref() {
+            "no-store" | "no-cache" | "private" => return None,
+            "max-age" => max_age = value.and_then(|v| v.parse().ok()),
+            "s-maxage" => shared_max_age = value.and_then(|v| v.parse().ok()),
+            _ => {}
+        }
+    }
+    Some(shared_max_age.or(max_age).unwrap_or(default_ttl))
+}
+
+/// The values of the request headers named by the response's `Vary` headers. `None` if the
+/// response varies on something other than request headers, so it can't be reused.
+fn varied_headers(
+    response: &HttpHeaders,
+    request: &HttpHeaders,
+) -> Option<Vec<(String, Option<String>)>> {
+    let mut varied = Vec::new();
+    for name in response.get_all("Vary").flat_map(|value| value.split(',')) {
+        let name = name.trim();
+        if name == "*" {
+            return None;
+        }
+        if !name.is_empty() {
+            varied.push((name.into(), request.get(name).map(Into::into)));
+        }
+    }
+    Some(varied)
+}
+
+/// Caches the responses of the wrapped handler to GET requests in memory, keyed by uri.
+///
+/// Successful responses are kept for the lifetime given by their `Cache-Control` header, or the
+/// configured TTL if it doesn't give one. Responses marked `no-store`, `no-cache` or `private`
+/// aren't cached. A cached response only answers requests with the same values for the headers
+/// named by its `Vary` header, and only answers requests with an `Authorization` header if it is
+/// marked `public`. A PUT, POST or DELETE to a uri removes its cached response. When the cache is
+/// full the response closest to expiring is removed to make room.
+///
+/// Cached response bodies are read into memory, so this is best suited to small responses.
+pub struct CachingHandler<H, C = Clock> {
+    handler: H,
+    entries: HashMap<String, CachedResponse>,
+    capacity: usize,
+    ttl: u64,
+    clock: C,
+}
+
+impl<H, C: Fn() -> u64> CachingHandler<H, C> {
+    /// Create a `CachingHandler` holding at most `capacity` responses, keeping responses for
+    /// `ttl` seconds unless they say otherwise. `clock` gives the current time in seconds, e.g.
+    /// `date::system_clock`, or a closure over a time the caller controls.
+    pub fn new(handler: H, capacity: usize, ttl: u64, clock: C) -> Self {
+        CachingHandler {
+            handler,
+            entries: HashMap::new(),
+            capacity,
+            ttl,
+            clock,
+        }
+    }
+
+    /// Remove all cached responses.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn make_room(&mut self, now: u64) {
+        self.entries.retain(|_, entry| entry.expires_at > now);
+        while !self.entries.is_empty() && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires_at)
+                .map(|(uri, _)| uri.clone())
+                .unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+impl<I, H, C> HttpRequestHandler<I> for CachingHandler<H, C>
+where
+    I: Read,
+    H: HttpRequestHandler<I>,
+    C: Fn() -> u64,
+{
+    type Error = H::Error;
+
+    fn handle(
+        &mut self,
+        request: HttpRequest<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn Read>>, Self::Error> {
+        let now = (self.clock)();
+        match request.method {
+            HttpMethod::Get => {}
+            HttpMethod::Put | HttpMethod::Post | HttpMethod::Patch | HttpMethod::Delete => {
+                self.entries.remove(&request.uri);
+                return self.handler.handle(request);
+            }
+            _ => return self.handler.handle(request),
+        }
+
+        let uri = request.uri.clone();
+        if let Some(entry) = self.entries.get(&uri) {
+            if entry.expires_at > now && entry.matches(&request.headers) {
+                return Ok(entry.to_response(now));
+            }
+        }
+
+        let request_headers = request.headers.clone();
+        let mut response = self.handler.handle(request)?;
+        let lifetime = match cache_lifetime(&response, self.ttl) {
+            Some(lifetime) if lifetime > 0 && self.capacity > 0 => lifetime,
+            _ => return Ok(response),
+        };
+        let public = cache_directives(&response.headers).any(|(name, _)| name == "public");
+        if request_headers.get("Authorization").is_some() && !public {
+            return Ok(response);
+        }
+        let varied = match varied_headers(&response.headers, &request_headers) {
+            Some(varied) => varied,
+            None => return Ok(response),
+        };
+
+        let mut body = Vec::new();
+        if let Err(e) = response.body.read_to_end(&mut body) {
+            let error = HttpResponse::from_string(HttpStatus::InternalServerError, e.to_string());
+            return Ok(error);
+        }
+        let entry = CachedResponse {
+            status: response.status,
+            headers: response.headers,
+            body,
+            stored_at: now,
+            expires_at: now.saturating_add(lifetime),
+            varied,
+            public,
+        };
+        let response = entry.to_response(now);
+
+        self.make_room(now);
+        self.entries.insert(uri, entry);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CachingHandler;
+    use crate::protocol::{HttpBody, HttpRequest, HttpResponse, HttpStatus};
+    use crate::server::HttpRequestHandler;
+    use core2::io::{self, Read};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Counts the requests it gets, responding with the count.
+    struct CountingHandler {
+        count: usize,
+        cache_control: &'static [&'static str],
+        vary: Option<&'static str>,
+    }
+
+    impl<I: Read> HttpRequestHandler<I> for CountingHandler {
+        type Error = HttpResponse<Box<dyn Read>>;
+
+        fn get(&mut self, _uri: String) -> Result<HttpResponse<Box<dyn Read>>, Self::Error> {
+            self.count += 1;
+            let mut response = HttpResponse::from_string(HttpStatus::OK, self.count.to_string());
+            for cache_control in self.cache_control {
+                response.add_header("Cache-Control", *cache_control);
+            }
+            if let Some(vary) = self.vary {
+                response.add_header("Vary", vary);
+            }
+            Ok(response)
+        }
+
+        fn put(
+            &mut self,
+            _uri: String,
+            _stream: HttpBody<&mut I>,
+        ) -> Result<HttpResponse<Box<dyn Read>>, Self::Error> {
+            Ok(HttpResponse::from_string(HttpStatus::OK, ""))
+        }
+    }
+
+    type TestCache = CachingHandler<CountingHandler, Box<dyn Fn() -> u64>>;
+
+    fn request(handler: &mut TestCache, method: &str, uri: &str) -> String {
+        request_with_headers(handler, method, uri, "")
+    }
+
+    fn request_with_headers(
+        handler: &mut TestCache,
+        method: &str,
+        uri: &str,
+        headers: &str,
+    ) -> String {
+        let request = format!(
+            "{} {} HTTP/1.1\r\nContent-Length: 0\r\n{}\r\n",
+            method, uri, headers
+        );
+        let mut input = io::BufReader::<_, 1024>::new(request.as_bytes());
+        let request = HttpRequest::deserialize(&mut input).unwrap();
+        let mut response = handler.handle(request).unwrap_or_else(|e| e);
+        let mut body = String::new();
+        response.body.read_to_string(&mut body).unwrap();
+        body
+    }
+
+    fn caching_handler(cache_control: &'static [&'static str]) -> TestCache {
+        varying_handler(cache_control, None)
+    }
+
+    fn varying_handler(
+        cache_control: &'static [&'static str],
+        vary: Option<&'static str>,
+    ) -> TestCache {
+        clocked_handler(cache_control, vary, Rc::new(Cell::new(1000)))
+    }
+
+    fn clocked_handler(
+        cache_control: &'static [&'static str],
+        vary: Option<&'static str>,
+        now: Rc<Cell<u64>>,
+    ) -> TestCache {
+        let handler = CountingHandler {
+            count: 0,
+            cache_control,
+            vary,
+        };
+        CachingHandler::new(handler, 2, 60, Box::new(move || now.get()))
+    }
+
+    #[test]
+    fn caches_get() {
+        let mut handler = caching_handler(&[]);
+        assert_eq!(request(&mut handler, "GET", "/a"), "1");
+        assert_eq!(request(&mut handler, "GET", "/a"), "1");
+        assert_eq!(request(&mut handler, "GET", "/b"), "2");
+    }
+
+    #[test]
+    fn no_store() {
+        let mut handler = caching_handler(&["no-store"]);
+        assert_eq!(request(&mut handler, "GET", "/a"), "1");
+        assert_eq!(request(&mut handler, "GET", "/a"), "2");
+    }
+
+    #[test]
+    fn expires() {
+        let now = Rc::new(Cell::new(1000));
+        let mut handler = clocked_handler(&["public, max-age=10"], None, now.clone());
+        assert_eq!(request(&mut handler, "GET", "/expires"), "1");
+        assert_eq!(request(&mut handler, "GET", "/expires"), "1");
+        now.set(1010);
+        assert_eq!(request(&mut handler, "GET", "/expires"), "2");
+    }
+
+    #[test]
+    fn no_store_in_second_header() {
+        let mut handler = caching_handler(&["max-age=10", "no-store"]);
+        assert_eq!(request(&mut handler, "GET", "/a"), "1");
+        assert_eq!(request(&mut handler, "GET", "/a"), "2");
+    }
+
+    #[test]
+    fn vary() {
+        let mut handler = varying_handler(&[], Some("Accept-Language"));
+        let english = "Accept-Language: en\r\n";
+        let french = "Accept-Language: fr\r\n";
+        assert_eq!(
+            request_with_headers(&mut handler, "GET", "/a", english),
+            "1"
+        );
+        assert_eq!(
+            request_with_headers(&mut handler, "GET", "/a", english),
+            "1"
+        );
+        assert_eq!(request_with_headers(&mut handler, "GET", "/a", french), "2");
+        assert_eq!(request(&mut handler, "GET", "/a"), "3");
+    }
+
+    #[test]
+    fn vary_star() {
+        let mut handler = varying_handler(&[], Some("*"));
+        assert_eq!(request(&mut handler, "GET", "/a"), "1");
+        assert_eq!(request(&mut handler, "GET", "/a"), "2");
+    }
+
+    #[test]
+    fn authorization() {
+        let credentials = "Authorization: Basic dXNlcjpwYXNz\r\n";
+        let mut handler = caching_handler(&[]);
+        assert_eq!(
+            request_with_headers(&mut handler, "GET", "/a", credentials),
+            "1"
+        );
+        assert_eq!(request(&mut handler, "GET", "/a"), "2");
+        assert_eq!(
+            request_with_headers(&mut handler, "GET", "/a", credentials),
+            "3"
+        );
+    }
+
+    #[test]
+    fn public_authorization() {
+        let credentials = "Authorization: Basic dXNlcjpwYXNz\r\n";
+        let mut handler = caching_handler(&["public"]);
+        assert_eq!(
+            request_with_headers(&mut handler, "GET", "/a", credentials),
+            "1"
+        );
+        assert_eq!(
+            request_with_headers(&mut handler, "GET", "/a", credentials),
+            "1"
+        );
+        assert_eq!(request(&mut handler, "GET", "/a"), "1");
+    }
+
+    #[test]
+    fn put_invalidates() {
+        let mut handler = caching_handler(&[]);
+        assert_eq!(request(&mut handler, "GET", "/a"), "1");
+        request(&mut handler, "PUT", "/a");
+        assert_eq!(request(&mut handler, "GET", "/a"), "2");
+    }
+
+    #[test]
+    fn capacity() {
+        let mut handler = caching_handler(&[]);
+        assert_eq!(request(&mut handler, "GET", "/a"), "1");
+        assert_eq!(request(&mut handler, "GET", "/b"), "2");
+        assert_eq!(request(&mut handler, "GET", "/c"), "3");
+        assert_eq!(handler.entries.len(), 2);
+    }
+}
@@ -0,0 +1,1151 @@
+//! HTTP/2 support for `HttpServer`. See `HttpServer::serve_one_http2`.
+//!
+//! Streams are read concurrently, but their requests are handed to the handler one at a time, in
+//! the order they complete. Each request body is read into memory before the handler sees it.
+//! Header blocks longer than `DEFAULT_HEADER_LIMIT` are answered with 431 and end the connection,
+//! and bodies longer than `DEFAULT_BODY_LIMIT` are answered with 413. Server push isn't supported.
+
+use super::{
+    BufferedRequest, Connection, HttpResponder, HttpResult, ServeSummary, DEFAULT_BODY_LIMIT,
+    DEFAULT_HEADER_LIMIT,
+};
+use crate::error::{self, Error};
+use crate::protocol::{
+    response_has_body, ConnectionInfo, CrLfStream, HttpBody, HttpHeaders, HttpMethod, HttpRequest,
+    HttpResponse, HttpStatus,
+};
+use core2::io::{self, ErrorKind, Read, Write};
+use hashbrown::HashMap;
+use std::collections::VecDeque;
+use std::{cmp, mem};
+
+/// What an HTTP/2 client sends before anything else.
+pub(crate) const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+pub(crate) const DATA: u8 = 0x0;
+pub(crate) const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+pub(crate) const RST_STREAM: u8 = 0x3;
+pub(crate) const SETTINGS: u8 = 0x4;
+pub(crate) const PUSH_PROMISE: u8 = 0x5;
+pub(crate) const PING: u8 = 0x6;
+pub(crate) const GOAWAY: u8 = 0x7;
+pub(crate) const WINDOW_UPDATE: u8 = 0x8;
+pub(crate) const CONTINUATION: u8 = 0x9;
+
+pub(crate) const END_STREAM: u8 = 0x1;
+pub(crate) const ACK: u8 = 0x1;
+pub(crate) const END_HEADERS: u8 = 0x4;
+pub(crate) const PADDED: u8 = 0x8;
+pub(crate) const PRIORITY_FLAG: u8 = 0x20;
+
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+pub(crate) const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+pub(crate) const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+const NO_ERROR: u32 = 0x0;
+const PROTOCOL_ERROR: u32 = 0x1;
+const INTERNAL_ERROR: u32 = 0x2;
+const FLOW_CONTROL_ERROR: u32 = 0x3;
+const STREAM_CLOSED: u32 = 0x5;
+const FRAME_SIZE_ERROR: u32 = 0x6;
+const REFUSED_STREAM: u32 = 0x7;
+const COMPRESSION_ERROR: u32 = 0x9;
+
+pub(crate) const DEFAULT_WINDOW_SIZE: i64 = 65_535;
+pub(crate) const MAX_WINDOW_SIZE: i64 = 0x7fff_ffff;
+pub(crate) const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+/// How many streams a client may have open at once.
+const MAX_CONCURRENT_STREAMS: u32 = 100;
+
+/// Headers which only apply to an HTTP/1.1 connection, so aren't allowed in HTTP/2.
+const CONNECTION_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// How a connection came to speak HTTP/2.
+pub(super) enum Start {
+    /// The client started speaking HTTP/2 straight away, either because it was negotiated with
+    /// ALPN or because the client knew the server supports it. `preface_read` is how much of the
+    /// connection preface was read to notice that.
+    PriorKnowledge { preface_read: usize },
+    /// The client asked to upgrade with its first HTTP/1.1 request.
+    Upgrade {
+        request: BufferedRequest,
+        settings: Vec<u8>,
+    },
+}
+
+/// What a new connection starts with.
+pub(super) enum Opening {
+    Http2(Start),
+    /// An HTTP/1 request whose head has been read, or the error reading it. Its body is still to
+    /// be read from the connection.
+    Http1(error::Result<HttpRequest<io::Empty>>),
+}
+
+/// Work out whether a new connection wants HTTP/2. Unless it was negotiated with ALPN, the head
+/// of the first request is read to find out.
+pub(super) fn detect<S: Read>(info: &ConnectionInfo, stream: &mut S) -> error::Result<Opening> {
+    if info.alpn_protocol.as_deref() == Some("h2") {
+        return Ok(Opening::Http2(Start::PriorKnowledge { preface_read: 0 }));
+    }
+
+    // The preface starts like a request line, but with a method nothing else uses.
+    let first_line = CrLfStream::new(&mut *stream).expect_next()?;
+    if PREFACE.starts_with(format!("{}\r\n", first_line).as_bytes()) {
+        return Ok(Opening::Http2(Start::PriorKnowledge {
+            preface_read: first_line.len() + 2,
+        }));
+    }
+
+    let request = match HttpRequest::deserialize_after_line(&first_line, &mut *stream) {
+        Ok(request) => request,
+        Err(e) => return Ok(Opening::Http1(Err(e))),
+    };
+    let settings = upgrade_settings(&request);
+    let head = request.with_body(io::empty());
+    let settings = match settings {
+        Some(settings) => settings,
+        None => return Ok(Opening::Http1(Ok(head))),
+    };
+
+    let mut request = Vec::new();
+    head.serialize_head(&mut request)?;
+    Ok(Opening::Http2(Start::Upgrade {
+        request: io::Cursor::new(request),
+        settings,
+    }))
+}
+
+/// The settings from the `HTTP2-Settings` header of a request asking to upgrade to HTTP/2, or
+/// `None` if it isn't one. Requests with bodies aren't upgraded.
+fn upgrade_settings<B: Read>(head: &HttpRequest<B>) -> Option<Vec<u8>> {
+    let upgrade = head.headers.get("Upgrade")?;
+    if !upgrade
+        .split(',')
+        .any(|t| t.trim().eq_ignore_ascii_case("h2c"))
+    {
+        return None;
+    }
+    match &head.body {
+        HttpBody::ReadTilClose(_) => {}
+        HttpBody::Limited(body) if body.limit() == 0 => {}
+        _ => return None,
+    }
+    decode_base64url(head.headers.get("HTTP2-Settings")?.trim())
+}
+
+fn decode_base64url(s: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for c in s.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | u32::from(value);
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            output.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(output)
+}
+
+/// Turn an HTTP/2 header name, which is lower case, into the usual HTTP/1.1 form.
+pub(crate) fn canonical_name(name: &str) -> String {
+    let words: Vec<String> = name
+        .split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    words.join("-")
+}
+
+/// Write the request described by HTTP/2 headers and a body as HTTP/1.1, so it can be read like
+/// any other request.
+fn build_request(headers: &[(String, String)], body: Vec<u8>) -> error::Result<BufferedRequest> {
+    let malformed = |reason: &str| Err(Error::Http2Error(reason.into()));
+
+    let mut method = None;
+    let mut path = None;
+    let mut authority = None;
+    let mut cookies = Vec::new();
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for (name, value) in headers {
+        if name.contains(|c| c == '\r' || c == '\n') || value.contains(|c| c == '\r' || c == '\n') {
+            return malformed("line break in header");
+        }
+        match name.as_str() {
+            ":method" => method = Some(value),
+            ":path" => path = Some(value),
+            ":authority" => authority = Some(value),
+            ":scheme" => {}
+            n if n.starts_with(':') => return malformed("unknown pseudo-header"),
+            n if CONNECTION_HEADERS.contains(&n) => return malformed("connection-specific header"),
+            "cookie" => cookies.push(value.as_str()),
+            "host" if authority.is_none() => authority = Some(value),
+            "host" | "content-length" | "te" => {}
+            n => {
+                let n = canonical_name(n);
+                match fields.iter_mut().find(|(key, _)| *key == n) {
+                    Some((_, existing)) => {
+                        existing.push_str(", ");
+                        existing.push_str(value);
+                    }
+                    None => fields.push((n, value.clone())),
+                }
+            }
+        }
+    }
+
+    let method = match method {
+        Some(method) => method,
+        None => return malformed("missing :method"),
+    };
+    let target = match (method.as_str(), path, authority) {
+        ("CONNECT", _, Some(authority)) => authority,
+        (_, Some(path), _) => path,
+        _ => return malformed("missing :path"),
+    };
+
+    let mut request = format!("{} {} HTTP/1.1\r\n", method, target);
+    if let Some(authority) = authority {
+        request.push_str(&format!("Host: {}\r\n", authority));
+    }
+    for (name, value) in &fields {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    if !cookies.is_empty() {
+        request.push_str(&format!("Cookie: {}\r\n", cookies.join("; ")));
+    }
+    request.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+
+    let mut request = request.into_bytes();
+    request.extend(body);
+    Ok(io::Cursor::new(request))
+}
+
+/// Parse a request and get the handler's response to it.
+fn handle<H: HttpResponder<BufferedRequest>>(
+    handler: &mut H,
+    mut request: BufferedRequest,
+    info: &ConnectionInfo,
+) -> HttpResult<(HttpMethod, String, HttpResponse<H::Body>)> {
+    let mut request = HttpRequest::deserialize(&mut request)?;
+    request.connection = info.clone();
+    let method = request.method;
+    let uri = request.uri.clone();
+    let response = handler.respond(request).map_err(|e| e.into())?;
+    Ok((method, uri, response))
+}
+
+pub(crate) fn read_exact_or_eof<R: Read>(r: &mut R, buffer: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match r.read(&mut buffer[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "truncated frame")),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// An error which ends the connection. The code is sent to the client in a GOAWAY frame.
+struct ConnectionError {
+    code: u32,
+    error: Error,
+}
+
+impl From<Error> for ConnectionError {
+    fn from(error: Error) -> Self {
+        ConnectionError {
+            code: INTERNAL_ERROR,
+            error,
+        }
+    }
+}
+
+impl From<io::Error> for ConnectionError {
+    fn from(error: io::Error) -> Self {
+        Error::from(error).into()
+    }
+}
+
+fn connection_error(code: u32, reason: &str) -> ConnectionError {
+    ConnectionError {
+        code,
+        error: Error::Http2Error(reason.into()),
+    }
+}
+
+type Result<T> = core::result::Result<T, ConnectionError>;
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    /// The payload without any padding.
+    fn unpadded(&self) -> Result<&[u8]> {
+        if self.flags & PADDED == 0 {
+            return Ok(&self.payload);
+        }
+        let (&padding, rest) = self
+            .payload
+            .split_first()
+            .ok_or_else(|| connection_error(PROTOCOL_ERROR, "missing padding length"))?;
+        rest.len()
+            .checked_sub(padding as usize)
+            .map(|len| &rest[..len])
+            .ok_or_else(|| connection_error(PROTOCOL_ERROR, "too much padding"))
+    }
+}
+
+#[derive(Default)]
+struct Stream {
+    headers: Option<Vec<(String, String)>>,
+    body: Vec<u8>,
+    /// Whether the client has sent the whole request.
+    request_complete: bool,
+    /// How much DATA may be sent on the stream.
+    send_window: i64,
+    /// How much DATA the client may send on the stream.
+    receive_window: i64,
+}
+
+impl Stream {
+    fn new(send_window: i64) -> Self {
+        Stream {
+            send_window,
+            receive_window: DEFAULT_WINDOW_SIZE,
+            ..Stream::default()
+        }
+    }
+}
+
+/// One HTTP/2 connection, served until the client closes it.
+pub(super) struct Http2Connection<S> {
+    connection: Connection<S>,
+    info: ConnectionInfo,
+    decoder: hpack::Decoder<'static>,
+    encoder: hpack::Encoder<'static>,
+    streams: HashMap<u32, Stream>,
+    /// Complete requests waiting to be handled, in the order they completed.
+    ready: VecDeque<(u32, BufferedRequest)>,
+    /// The stream whose header block is being continued, if one is.
+    continuing: Option<u32>,
+    /// The header block being received, until the last piece of it arrives. It is decoded even
+    /// if its stream was closed, to keep the decoder in step with the client's encoder.
+    header_block: Vec<u8>,
+    /// Whether the client ends the stream once the header block is complete.
+    ends_after_headers: bool,
+    last_stream_id: u32,
+    send_window: i64,
+    peer_initial_window: i64,
+    peer_max_frame_size: usize,
+    closing: bool,
+}
+
+impl<S: Read + Write> Http2Connection<S> {
+    pub(super) fn new(stream: S, info: ConnectionInfo) -> Self {
+        Http2Connection {
+            connection: io::BufReader::new(stream),
+            info,
+            decoder: hpack::Decoder::new(),
+            encoder: hpack::Encoder::new(),
+            streams: HashMap::new(),
+            ready: VecDeque::new(),
+            continuing: None,
+            header_block: Vec::new(),
+            ends_after_headers: false,
+            last_stream_id: 0,
+            send_window: DEFAULT_WINDOW_SIZE,
+            peer_initial_window: DEFAULT_WINDOW_SIZE,
+            peer_max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            closing: false,
+        }
+    }
+
+    /// Serve requests until the client closes the connection, passing the headers of each
+    /// response to `prepare` before it is sent. Returns a summary of the last response.
+    pub(super) fn serve<H, F>(
+        &mut self,
+        handler: &mut H,
+        start: Start,
+        mut prepare: F,
+    ) -> error::Result<ServeSummary>
+    where
+        H: HttpResponder<BufferedRequest>,
+        F: FnMut(&mut HttpHeaders),
+    {
+        match self.run(handler, start, &mut prepare) {
+            Ok(summary) => {
+                let _ = self.write_goaway(NO_ERROR);
+                summary.ok_or_else(|| {
+                    Error::UnexpectedEof("connection closed without a request".into())
+                })
+            }
+            Err(e) => {
+                let _ = self.write_goaway(e.code);
+                Err(e.error)
+            }
+        }
+    }
+
+    fn run<H, F>(
+        &mut self,
+        handler: &mut H,
+        start: Start,
+        prepare: &mut F,
+    ) -> Result<Option<ServeSummary>>
+    where
+        H: HttpResponder<BufferedRequest>,
+        F: FnMut(&mut HttpHeaders),
+    {
+        let mut settings = Vec::new();
+        for (id, value) in &[
+            (SETTINGS_MAX_CONCURRENT_STREAMS, MAX_CONCURRENT_STREAMS),
+            (SETTINGS_INITIAL_WINDOW_SIZE, DEFAULT_WINDOW_SIZE as u32),
+        ] {
+            settings.extend_from_slice(&id.to_be_bytes());
+            settings.extend_from_slice(&value.to_be_bytes());
+        }
+        self.write_frame(SETTINGS, 0, 0, &settings)?;
+
+        let preface_read = match start {
+            Start::PriorKnowledge { preface_read } => preface_read,
+            Start::Upgrade { .. } => 0,
+        };
+        if let Start::Upgrade { request, settings } = start {
+            // The request the connection was upgraded with is answered on stream 1.
+            self.apply_settings(&settings)?;
+            self.streams.insert(
+                1,
+                Stream {
+                    request_complete: true,
+                    ..Stream::new(self.peer_initial_window)
+                },
+            );
+            self.last_stream_id = 1;
+            self.ready.push_back((1, request));
+        }
+
+        let mut preface = vec![0; PREFACE.len() - preface_read];
+        self.connection.read_exact(&mut preface)?;
+        if preface[..] != PREFACE[preface_read..] {
+            return Err(connection_error(
+                PROTOCOL_ERROR,
+                "missing connection preface",
+            ));
+        }
+
+        let mut summary = None;
+        loop {
+            while let Some((id, request)) = self.ready.pop_front() {
+                summary = Some(self.respond(handler, id, request, prepare)?);
+            }
+            if self.closing {
+                return Ok(summary);
+            }
+            match self.read_frame()? {
+                Some(frame) => self.process_frame(frame)?,
+                None => return Ok(summary),
+            }
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame>> {
+        let mut header = [0; 9];
+        if !read_exact_or_eof(&mut self.connection, &mut header)? {
+            return Ok(None);
+        }
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        if length > DEFAULT_MAX_FRAME_SIZE {
+            return Err(connection_error(FRAME_SIZE_ERROR, "frame too large"));
+        }
+        let mut payload = vec![0; length];
+        self.connection.read_exact(&mut payload)?;
+        Ok(Some(Frame {
+            kind: header[3],
+            flags: header[4],
+            stream_id: u32::from_be_bytes([header[5], header[6], header[7], header[8]])
+                & 0x7fff_ffff,
+            payload,
+        }))
+    }
+
+    fn write_frame(
+        &mut self,
+        kind: u8,
+        flags: u8,
+        stream_id: u32,
+        payload: &[u8],
+    ) -> io::Result<u64> {
+        let mut header = [0; 9];
+        header[..3].copy_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+        header[3] = kind;
+        header[4] = flags;
+        header[5..].copy_from_slice(&stream_id.to_be_bytes());
+
+        let stream = self.connection.get_mut();
+        stream.write_all(&header)?;
+        stream.write_all(payload)?;
+        stream.flush()?;
+        Ok((header.len() + payload.len()) as u64)
+    }
+
+    fn write_goaway(&mut self, code: u32) -> io::Result<u64> {
+        let mut payload = self.last_stream_id.to_be_bytes().to_vec();
+        payload.extend_from_slice(&code.to_be_bytes());
+        self.write_frame(GOAWAY, 0, 0, &payload)
+    }
+
+    fn reset_stream(&mut self, id: u32, code: u32) -> io::Result<u64> {
+        self.streams.remove(&id);
+        self.write_frame(RST_STREAM, 0, id, &code.to_be_bytes())
+    }
+
+    fn process_frame(&mut self, frame: Frame) -> Result<()> {
+        if let Some(id) = self.continuing {
+            if frame.kind != CONTINUATION || frame.stream_id != id {
+                return Err(connection_error(PROTOCOL_ERROR, "expected CONTINUATION"));
+            }
+        }
+        let on_stream = matches!(
+            frame.kind,
+            DATA | HEADERS | PRIORITY | RST_STREAM | CONTINUATION
+        );
+        let on_connection = matches!(frame.kind, SETTINGS | PING | GOAWAY);
+        if (on_stream && frame.stream_id == 0) || (on_connection && frame.stream_id != 0) {
+            return Err(connection_error(
+                PROTOCOL_ERROR,
+                "frame on the wrong stream",
+            ));
+        }
+
+        match frame.kind {
+            DATA => self.receive_data(frame),
+            HEADERS => self.receive_headers(frame),
+            CONTINUATION => self.receive_continuation(frame),
+            RST_STREAM => {
+                self.streams.remove(&frame.stream_id);
+                self.ready.retain(|(id, _)| *id != frame.stream_id);
+                Ok(())
+            }
+            SETTINGS if frame.flags & ACK == 0 => {
+                if frame.payload.len() % 6 != 0 {
+                    return Err(connection_error(FRAME_SIZE_ERROR, "bad SETTINGS length"));
+                }
+                self.apply_settings(&frame.payload)?;
+                self.write_frame(SETTINGS, ACK, 0, &[])?;
+                Ok(())
+            }
+            PING if frame.flags & ACK == 0 => {
+                if frame.payload.len() != 8 {
+                    return Err(connection_error(FRAME_SIZE_ERROR, "bad PING length"));
+                }
+                self.write_frame(PING, ACK, 0, &frame.payload)?;
+                Ok(())
+            }
+            GOAWAY => {
+                self.closing = true;
+                Ok(())
+            }
+            WINDOW_UPDATE => self.receive_window_update(frame),
+            PUSH_PROMISE => Err(connection_error(PROTOCOL_ERROR, "clients can't push")),
+            // PRIORITY, acknowledgements and unknown frame types need nothing doing.
+            _ => Ok(()),
+        }
+    }
+
+    fn apply_settings(&mut self, payload: &[u8]) -> Result<()> {
+        for setting in payload.chunks_exact(6) {
+            let id = u16::from_be_bytes([setting[0], setting[1]]);
+            let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+            match id {
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    let value = i64::from(value);
+                    if value > MAX_WINDOW_SIZE {
+                        return Err(connection_error(FLOW_CONTROL_ERROR, "window too large"));
+                    }
+                    let delta = value - self.peer_initial_window;
+                    for stream in self.streams.values_mut() {
+                        stream.send_window += delta;
+                    }
+                    self.peer_initial_window = value;
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(16_384..=16_777_215).contains(&value) {
+                        return Err(connection_error(PROTOCOL_ERROR, "bad max frame size"));
+                    }
+                    self.peer_max_frame_size = value as usize;
+                }
+                // The encoder never adds to the dynamic table, so the table size doesn't matter.
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn receive_window_update(&mut self, frame: Frame) -> Result<()> {
+        if frame.payload.len() != 4 {
+            return Err(connection_error(
+                FRAME_SIZE_ERROR,
+                "bad WINDOW_UPDATE length",
+            ));
+        }
+        let p = &frame.payload;
+        let increment = i64::from(u32::from_be_bytes([p[0], p[1], p[2], p[3]]) & 0x7fff_ffff);
+        if increment == 0 {
+            return Err(connection_error(PROTOCOL_ERROR, "empty WINDOW_UPDATE"));
+        }
+        let window = if frame.stream_id == 0 {
+            &mut self.send_window
+        } else {
+            match self.streams.get_mut(&frame.stream_id) {
+                Some(stream) => &mut stream.send_window,
+                None => return Ok(()),
+            }
+        };
+        *window += increment;
+        if *window > MAX_WINDOW_SIZE {
+            return Err(connection_error(FLOW_CONTROL_ERROR, "window too large"));
+        }
+        Ok(())
+    }
+
+    fn receive_headers(&mut self, frame: Frame) -> Result<()> {
+        let id = frame.stream_id;
+        let mut block = frame.unpadded()?;
+        if frame.flags & PRIORITY_FLAG != 0 {
+            if block.len() < 5 {
+                return Err(connection_error(FRAME_SIZE_ERROR, "bad HEADERS length"));
+            }
+            block = &block[5..];
+        }
+
+        match self.streams.get(&id) {
+            Some(stream) if stream.request_complete => {
+                return Err(connection_error(
+                    STREAM_CLOSED,
+                    "HEADERS on a closed stream",
+                ));
+            }
+            Some(_) => {}
+            None if id % 2 == 0 => {
+                return Err(connection_error(PROTOCOL_ERROR, "bad stream id"));
+            }
+            None if id > self.last_stream_id => {
+                self.last_stream_id = id;
+                if self.streams.len() >= MAX_CONCURRENT_STREAMS as usize {
+                    // The header block is still decoded once it is complete, then dropped.
+                    self.write_frame(RST_STREAM, 0, id, &REFUSED_STREAM.to_be_bytes())?;
+                } else {
+                    self.streams
+                        .insert(id, Stream::new(self.peer_initial_window));
+                }
+            }
+            // Trailers of a stream the server reset, which the client sent before it knew.
+            None => {}
+        }
+        self.header_block.extend_from_slice(block);
+        self.ends_after_headers = frame.flags & END_STREAM != 0;
+        self.check_header_block(id)?;
+
+        if frame.flags & END_HEADERS != 0 {
+            self.finish_header_block(id)
+        } else {
+            self.continuing = Some(id);
+            Ok(())
+        }
+    }
+
+    fn receive_continuation(&mut self, frame: Frame) -> Result<()> {
+        let id = frame.stream_id;
+        if self.continuing != Some(id) {
+            return Err(connection_error(PROTOCOL_ERROR, "unexpected CONTINUATION"));
+        }
+        self.header_block.extend_from_slice(&frame.payload);
+        self.check_header_block(id)?;
+        if frame.flags & END_HEADERS != 0 {
+            self.continuing = None;
+            self.finish_header_block(id)?;
+        }
+        Ok(())
+    }
+
+    /// Answer the stream with 431 and end the connection if the header block being received is
+    /// too long. The block can't be skipped without leaving the decoder out of step, so the
+    /// connection can't be used any more.
+    fn check_header_block(&mut self, id: u32) -> Result<()> {
+        if self.header_block.len() <= DEFAULT_HEADER_LIMIT {
+            return Ok(());
+        }
+        if self.streams.remove(&id).is_some() {
+            self.write_status(id, HttpStatus::RequestHeaderFieldsTooLarge)?;
+        }
+        Err(ConnectionError {
+            code: COMPRESSION_ERROR,
+            error: Error::HeadersTooLarge(DEFAULT_HEADER_LIMIT),
+        })
+    }
+
+    fn finish_header_block(&mut self, id: u32) -> Result<()> {
+        let block = mem::take(&mut self.header_block);
+        // The block has to be decoded even if it isn't used, to keep the decoder's state in step.
+        let fields = self
+            .decoder
+            .decode(&block)
+            .map_err(|_| connection_error(COMPRESSION_ERROR, "bad header block"))?;
+
+        let stream = match self.streams.get_mut(&id) {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+        // Trailers are dropped, since requests can't carry them to the handler.
+        if stream.headers.is_none() {
+            let headers: Option<Vec<(String, String)>> = fields
+                .into_iter()
+                .map(|(name, value)| {
+                    Some((
+                        String::from_utf8(name).ok()?,
+                        String::from_utf8(value).ok()?,
+                    ))
+                })
+                .collect();
+            if headers.is_none() {
+                self.reset_stream(id, PROTOCOL_ERROR)?;
+                return Ok(());
+            }
+            stream.headers = headers;
+        }
+
+        if self.ends_after_headers {
+            self.complete_request(id)?;
+        }
+        Ok(())
+    }
+
+    fn receive_data(&mut self, frame: Frame) -> Result<()> {
+        let id = frame.stream_id;
+        let data = frame.unpadded()?;
+        let end_stream = frame.flags & END_STREAM != 0;
+        let open = match self.streams.get(&id) {
+            Some(stream) => stream.headers.is_some() && !stream.request_complete,
+            // DATA the client sent before it knew the server reset the stream is dropped.
+            None => id % 2 == 1 && id <= self.last_stream_id,
+        };
+        if !open {
+            return Err(connection_error(STREAM_CLOSED, "DATA on a closed stream"));
+        }
+
+        // Flow control counts the whole payload, including padding. What the client may send on
+        // the connection is limited by the windows of its streams, so the connection's window is
+        // always given back.
+        let len = frame.payload.len() as u32;
+        if len > 0 {
+            self.write_frame(WINDOW_UPDATE, 0, 0, &len.to_be_bytes())?;
+        }
+        let stream = match self.streams.get_mut(&id) {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+        stream.receive_window -= i64::from(len);
+        if stream.receive_window < 0 {
+            return Err(connection_error(
+                FLOW_CONTROL_ERROR,
+                "stream window exceeded",
+            ));
+        }
+        if stream.body.len() + data.len() > DEFAULT_BODY_LIMIT {
+            self.write_status(id, HttpStatus::RequestEntityTooLarge)?;
+            // The response doesn't need the rest of the body, so the client can stop sending it.
+            self.reset_stream(id, NO_ERROR)?;
+            return Ok(());
+        }
+        stream.body.extend_from_slice(data);
+        if end_stream {
+            return self.complete_request(id);
+        }
+
+        // The window is only given back up to one byte past the body limit, so the client can't
+        // send much more than that, and a body which is too long is still noticed.
+        let left = (DEFAULT_BODY_LIMIT + 1 - stream.body.len()) as i64;
+        let increment = cmp::min(DEFAULT_WINDOW_SIZE, left) - stream.receive_window;
+        if increment > 0 {
+            stream.receive_window += increment;
+            self.write_frame(WINDOW_UPDATE, 0, id, &(increment as u32).to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn complete_request(&mut self, id: u32) -> Result<()> {
+        let stream = self.streams.get_mut(&id).unwrap();
+        stream.request_complete = true;
+        let headers = stream.headers.take().unwrap_or_default();
+        let body = mem::take(&mut stream.body);
+        match build_request(&headers, body) {
+            Ok(request) => self.ready.push_back((id, request)),
+            Err(_) => {
+                self.reset_stream(id, PROTOCOL_ERROR)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn respond<H, F>(
+        &mut self,
+        handler: &mut H,
+        id: u32,
+        request: BufferedRequest,
+        prepare: &mut F,
+    ) -> Result<ServeSummary>
+    where
+        H: HttpResponder<BufferedRequest>,
+        F: FnMut(&mut HttpHeaders),
+    {
+        let summary = match handle(handler, request, &self.info) {
+            Ok((method, uri, mut response)) => {
+                prepare(&mut response.headers);
+                let bytes_written = self.send_response(id, Some(method), &mut response)?;
+                ServeSummary {
+                    method: Some(method),
+                    uri: Some(uri),
+                    status: response.status,
+                    bytes_written,
+                }
+            }
+            Err(mut response) => {
+                prepare(&mut response.headers);
+                let bytes_written = self.send_response(id, None, &mut response)?;
+                ServeSummary {
+                    method: None,
+                    uri: None,
+                    status: response.status,
+                    bytes_written,
+                }
+            }
+        };
+        self.streams.remove(&id);
+        Ok(summary)
+    }
+
+    fn send_response<B: Read>(
+        &mut self,
+        id: u32,
+        method: Option<HttpMethod>,
+        response: &mut HttpResponse<B>,
+    ) -> Result<u64> {
+        let has_body = response_has_body(response.status, method);
+
+        let status = response.status.as_u16().to_string();
+        let mut fields = vec![(b":status".to_vec(), status.as_bytes().to_vec())];
+        fields.extend(header_fields(&response.headers));
+        let mut written = self.write_header_block(id, &fields, !has_body)?;
+        if !has_body {
+            return Ok(written);
+        }
+
+        let mut buffer = vec![0; DEFAULT_MAX_FRAME_SIZE];
+        loop {
+            let read = match response.body.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let mut sent = 0;
+            while sent < read {
+                let window = match self.wait_for_window(id)? {
+                    Some(window) => window,
+                    // The client reset the stream.
+                    None => return Ok(written),
+                };
+                let len = cmp::min(cmp::min(window, read - sent), self.peer_max_frame_size);
+                written += self.write_frame(DATA, 0, id, &buffer[sent..sent + len])?;
+                self.send_window -= len as i64;
+                if let Some(stream) = self.streams.get_mut(&id) {
+                    stream.send_window -= len as i64;
+                }
+                sent += len;
+            }
+        }
+
+        if response.trailers.is_empty() {
+            written += self.write_frame(DATA, END_STREAM, id, &[])?;
+        } else {
+            let trailers: Vec<_> = header_fields(&response.trailers).collect();
+            written += self.write_header_block(id, &trailers, true)?;
+        }
+        Ok(written)
+    }
+
+    /// Wait until DATA can be sent on the stream, processing frames from the client meanwhile.
+    /// Returns how much can be sent, or `None` if the stream was reset.
+    fn wait_for_window(&mut self, id: u32) -> Result<Option<usize>> {
+        loop {
+            let stream_window = match self.streams.get(&id) {
+                Some(stream) => stream.send_window,
+                None => return Ok(None),
+            };
+            let window = cmp::min(self.send_window, stream_window);
+            if window > 0 {
+                return Ok(Some(window as usize));
+            }
+            match self.read_frame()? {
+                Some(frame) => self.process_frame(frame)?,
+                None => {
+                    let error = Error::UnexpectedEof("connection closed mid-response".into());
+                    return Err(error.into());
+                }
+            }
+        }
+    }
+
+    /// Answer the stream with a response which is only a status.
+    fn write_status(&mut self, id: u32, status: HttpStatus) -> io::Result<u64> {
+        let status = status.as_u16().to_string();
+        self.write_header_block(id, &[(b":status".to_vec(), status.into_bytes())], true)
+    }
+
+    fn write_header_block(
+        &mut self,
+        id: u32,
+        fields: &[(Vec<u8>, Vec<u8>)],
+        end_stream: bool,
+    ) -> io::Result<u64> {
+        let block = self
+            .encoder
+            .encode(fields.iter().map(|(n, v)| (&n[..], &v[..])));
+        let mut pieces = block.chunks(self.peer_max_frame_size).peekable();
+        let mut kind = HEADERS;
+        let mut written = 0;
+        loop {
+            let piece = pieces.next().unwrap_or(&[][..]);
+            let mut flags = if pieces.peek().is_none() {
+                END_HEADERS
+            } else {
+                0
+            };
+            if kind == HEADERS && end_stream {
+                flags |= END_STREAM;
+            }
+            written += self.write_frame(kind, flags, id, piece)?;
+            if flags & END_HEADERS != 0 {
+                return Ok(written);
+            }
+            kind = CONTINUATION;
+        }
+    }
+}
+
+/// The fields to send for the given headers: lower case, without connection-specific headers.
+pub(crate) fn header_fields(
+    headers: &HttpHeaders,
+) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_ {
+    headers.iter().filter_map(|(key, value)| {
+        let name = key.to_ascii_lowercase();
+        if CONNECTION_HEADERS.contains(&name.as_str()) {
+            return None;
+        }
+        Some((name.into_bytes(), value.as_bytes().to_vec()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_request, canonical_name, decode_base64url, DATA, END_HEADERS, END_STREAM, GOAWAY,
+        HEADERS, MAX_CONCURRENT_STREAMS, PREFACE, REFUSED_STREAM, RST_STREAM, SETTINGS,
+    };
+    use crate::error::Result;
+    use crate::protocol::{HttpMethod, HttpStatus};
+    use crate::server::{test_server, ExpectedRequest};
+    use core2::io::Read;
+
+    #[test]
+    fn base64url() {
+        assert_eq!(
+            decode_base64url("AAMAAABkAAQAAP__"),
+            Some(vec![0, 3, 0, 0, 0, 100, 0, 4, 0, 0, 255, 255])
+        );
+        assert_eq!(decode_base64url("aGk="), Some(b"hi".to_vec()));
+        assert_eq!(decode_base64url("a+b/"), None);
+    }
+
+    #[test]
+    fn canonical_names() {
+        assert_eq!(canonical_name("content-type"), "Content-Type");
+        assert_eq!(canonical_name("x-forwarded-for"), "X-Forwarded-For");
+        assert_eq!(canonical_name("accept"), "Accept");
+    }
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn request_from_headers() {
+        let headers = fields(&[
+            (":method", "POST"),
+            (":scheme", "https"),
+            (":authority", "example.com"),
+            (":path", "/upload"),
+            ("content-type", "text/plain"),
+            ("cookie", "a=1"),
+            ("cookie", "b=2"),
+            ("accept", "text/html"),
+            ("accept", "text/plain"),
+        ]);
+        let mut request = build_request(&headers, b"hello".to_vec()).unwrap();
+        let mut text = String::new();
+        request.read_to_string(&mut text).unwrap();
+        assert_eq!(
+            text,
+            "POST /upload HTTP/1.1\r\n\
+             Host: example.com\r\n\
+             Content-Type: text/plain\r\n\
+             Accept: text/html, text/plain\r\n\
+             Cookie: a=1; b=2\r\n\
+             Content-Length: 5\r\n\r\n\
+             hello"
+        );
+    }
+
+    #[test]
+    fn malformed_requests() {
+        assert!(build_request(&fields(&[(":path", "/")]), vec![]).is_err());
+        assert!(build_request(&fields(&[(":method", "GET")]), vec![]).is_err());
+        let headers = fields(&[(":method", "GET"), (":path", "/"), ("connection", "close")]);
+        assert!(build_request(&headers, vec![]).is_err());
+        let headers = fields(&[(":method", "GET"), (":path", "/"), ("x", "a\r\nb: c")]);
+        assert!(build_request(&headers, vec![]).is_err());
+    }
+
+    fn frame(kind: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend_from_slice(&[kind, flags]);
+        frame.extend_from_slice(&stream_id.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn serve_prior_knowledge() -> Result<()> {
+        use std::io::{Read as _, Write as _};
+
+        let (port, mut server) = test_server(vec![ExpectedRequest {
+            expected_method: HttpMethod::Get,
+            expected_uri: "/".into(),
+            expected_body: "".into(),
+            response_status: HttpStatus::OK,
+            response_body: "hello from server".into(),
+        }])?;
+        let handle = std::thread::spawn(move || server.serve_one_http2());
+
+        let block = hpack::Encoder::new().encode(vec![
+            (&b":method"[..], &b"GET"[..]),
+            (&b":scheme"[..], &b"http"[..]),
+            (&b":authority"[..], &b"localhost"[..]),
+            (&b":path"[..], &b"/"[..]),
+        ]);
+        let mut request = PREFACE.to_vec();
+        request.extend(frame(SETTINGS, 0, 0, &[]));
+        request.extend(frame(HEADERS, END_HEADERS | END_STREAM, 1, &block));
+        request.extend(frame(GOAWAY, 0, 0, &[0; 8]));
+        let mut stream = std::net::TcpStream::connect(("localhost", port))?;
+        stream.write_all(&request)?;
+
+        let summary = handle.join().unwrap()?;
+        assert_eq!(summary.status, HttpStatus::OK);
+
+        let mut output = vec![];
+        stream.read_to_end(&mut output)?;
+        let mut decoder = hpack::Decoder::new();
+        let mut headers = vec![];
+        let mut body = vec![];
+        let mut rest = &output[..];
+        while rest.len() >= 9 {
+            let length = u32::from_be_bytes([0, rest[0], rest[1], rest[2]]) as usize;
+            let stream_id = u32::from_be_bytes([rest[5], rest[6], rest[7], rest[8]]);
+            let payload = &rest[9..9 + length];
+            match rest[3] {
+                HEADERS if stream_id == 1 => headers = decoder.decode(payload).unwrap(),
+                DATA if stream_id == 1 => body.extend_from_slice(payload),
+                _ => {}
+            }
+            rest = &rest[9 + length..];
+        }
+        assert!(headers.contains(&(b":status".to_vec(), b"200".to_vec())));
+        assert_eq!(body, b"hello from server");
+        Ok(())
+    }
+
+    #[test]
+    fn refuse_streams_past_limit() -> Result<()> {
+        use std::io::{Read as _, Write as _};
+
+        let (port, mut server) = test_server(vec![])?;
+        let handle = std::thread::spawn(move || server.serve_one_http2());
+
+        let mut encoder = hpack::Encoder::new();
+        let mut request = PREFACE.to_vec();
+        request.extend(frame(SETTINGS, 0, 0, &[]));
+        // None of the requests end, so each stream stays open.
+        for i in 0..=MAX_CONCURRENT_STREAMS {
+            let block = encoder.encode(vec![
+                (&b":method"[..], &b"POST"[..]),
+                (&b":scheme"[..], &b"http"[..]),
+                (&b":path"[..], &b"/"[..]),
+            ]);
+            request.extend(frame(HEADERS, END_HEADERS, 2 * i + 1, &block));
+        }
+        request.extend(frame(GOAWAY, 0, 0, &[0; 8]));
+        let mut stream = std::net::TcpStream::connect(("localhost", port))?;
+        stream.write_all(&request)?;
+        assert!(handle.join().unwrap().is_err());
+
+        let mut output = vec![];
+        stream.read_to_end(&mut output)?;
+        let mut resets = vec![];
+        let mut rest = &output[..];
+        while rest.len() >= 9 {
+            let length = u32::from_be_bytes([0, rest[0], rest[1], rest[2]]) as usize;
+            let stream_id = u32::from_be_bytes([rest[5], rest[6], rest[7], rest[8]]);
+            let payload = &rest[9..9 + length];
+            if rest[3] == RST_STREAM {
+                resets.push((stream_id, payload.to_vec()));
+            }
+            rest = &rest[9 + length..];
+        }
+        let last = 2 * MAX_CONCURRENT_STREAMS + 1;
+        assert_eq!(resets, [(last, REFUSED_STREAM.to_be_bytes().to_vec())]);
+        Ok(())
+    }
+}
@@ -0,0 +1,652 @@
+//! A server for use with non-blocking streams, where the caller drives progress when a stream is
+//! ready.
+
+use super::{respond_to, trace_response, write_response, HttpResponder, ServeOptions};
+use crate::date::Clock;
+use crate::error;
+use crate::protocol::push::Parser;
+use crate::protocol::HttpRequest;
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+use core::mem;
+use core2::io::{self, ErrorKind};
+
+/// The stream request bodies are read from when served by a `NonBlockingConnection`: the whole
+/// request, read into memory.
+pub type BufferedRequest = io::Cursor<Vec<u8>>;
+
+/// The longest request head a `NonBlockingConnection` accepts by default, in bytes.
+pub const DEFAULT_HEADER_LIMIT: usize = 8 * 1024;
+
+/// The longest request body a `NonBlockingConnection` accepts by default, in bytes.
+pub const DEFAULT_BODY_LIMIT: usize = 1024 * 1024;
+
+/// Whether a `NonBlockingConnection` is still in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Open,
+    Closed,
+}
+
+/// An HTTP connection on a non-blocking stream. Reads and writes which would block are retried
+/// the next time `poll` is called, which should be when the stream is ready.
+///
+/// Each request is read into memory before it is handed to the handler, and each response is
+/// written into memory before it is sent, so this is best suited to small requests and responses.
+/// Requests with a head longer than `DEFAULT_HEADER_LIMIT` or a body longer than
+/// `DEFAULT_BODY_LIMIT` are refused, unless other limits are set. Requests and responses are
+/// otherwise treated as `HttpServer` treats them, with the same defaults.
+pub struct NonBlockingConnection<S> {
+    stream: S,
+    /// What was read from the stream and not yet given to the parser.
+    input: Vec<u8>,
+    parser: Parser,
+    options: ServeOptions,
+    output: Vec<u8>,
+    closing: bool,
+    peer_closed: bool,
+}
+
+impl<S: core2::io::Read + core2::io::Write> NonBlockingConnection<S> {
+    pub fn new(stream: S) -> Self {
+        NonBlockingConnection {
+            stream,
+            input: Vec::new(),
+            parser: Parser::request()
+                .header_limit(DEFAULT_HEADER_LIMIT)
+                .body_limit(DEFAULT_BODY_LIMIT),
+            options: ServeOptions::default(),
+            output: Vec::new(),
+            closing: false,
+            peer_closed: false,
+        }
+    }
+
+    /// Answer requests with a head longer than `limit` bytes with 431 Request Header Fields Too
+    /// Large, and close the connection.
+    pub fn header_limit(mut self, limit: usize) -> Self {
+        self.parser = self.parser.header_limit(limit);
+        self
+    }
+
+    /// Answer requests with a body longer than `limit` bytes with 413 Request Entity Too Large,
+    /// and close the connection. A chunked body counts with its chunk sizes and trailers.
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.parser = self.parser.body_limit(limit);
+        self
+    }
+
+    /// Turn strict compliance checking on or off, as `HttpServer::set_strict_mode` does.
+    pub fn strict_mode(mut self, strict: bool) -> Self {
+        self.options.strict = strict;
+        self
+    }
+
+    /// Turn answering TRACE requests on or off, as `HttpServer::set_trace_enabled` does.
+    pub fn trace_enabled(mut self, trace: bool) -> Self {
+        self.options.trace = trace;
+        self
+    }
+
+    /// Set the value of the `Server` header added to responses, as
+    /// `HttpServer::set_server_header` does.
+    pub fn server_header<T: Into<String>>(mut self, server: Option<T>) -> Self {
+        self.options.server_header = server.map(Into::into);
+        self
+    }
+
+    /// Set the clock used for the `Date` header added to responses, as `HttpServer::set_clock`
+    /// does.
+    pub fn clock(mut self, clock: Option<Clock>) -> Self {
+        self.options.clock = clock;
+        self
+    }
+
+    #[cfg(feature = "mio")]
+    fn with_options(mut self, options: ServeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Whether there is response data waiting for the stream to become writable.
+    pub fn wants_write(&self) -> bool {
+        !self.output.is_empty()
+    }
+
+    /// Make as much progress as possible without blocking: read what is available, serve any
+    /// complete requests with the given handler, and write as much of the responses as possible.
+    ///
+    /// Returns `ConnectionState::Closed` once the connection is finished with, after which the
+    /// stream can be dropped.
+    pub fn poll<H: HttpResponder<BufferedRequest>>(
+        &mut self,
+        handler: &mut H,
+    ) -> error::Result<ConnectionState> {
+        if !self.closing {
+            self.fill_input()?;
+            self.serve_buffered(handler)?;
+        }
+        self.flush_output()?;
+
+        let finished = self.closing || self.peer_closed;
+        Ok(if finished && self.output.is_empty() {
+            ConnectionState::Closed
+        } else {
+            ConnectionState::Open
+        })
+    }
+
+    fn fill_input(&mut self) -> error::Result<()> {
+        let mut buffer = [0; 1024];
+        while !self.peer_closed {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.peer_closed = true,
+                Ok(read) => self.input.extend_from_slice(&buffer[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_buffered<H: HttpResponder<BufferedRequest>>(
+        &mut self,
+        handler: &mut H,
+    ) -> error::Result<()> {
+        let mut next = self.parser.next_message(&mem::take(&mut self.input));
+        while !self.closing {
+            let mut buffered: BufferedRequest;
+            let request = match next {
+                Ok(Some(request)) => {
+                    buffered = io::Cursor::new(request);
+                    HttpRequest::deserialize(&mut buffered)
+                }
+                Ok(None) => break,
+                Err(e) => Err(e),
+            };
+
+            // The whole request has been read, so the connection can be kept open whether or not
+            // the handler reads the body.
+            let options = &self.options;
+            let (_, close) = match options.check(request) {
+                Ok(request) if options.answers(&request) => {
+                    let served = respond_to(request, false, trace_response);
+                    options.send_served(served, &mut self.output)?
+                }
+                Ok(request) => {
+                    let served =
+                        respond_to(request, false, |r| handler.respond(r).map_err(|e| e.into()));
+                    options.send_served(served, &mut self.output)?
+                }
+                Err(response) => {
+                    options.send_served::<H::Body, _>(Err(response), &mut self.output)?
+                }
+            };
+            self.closing = close;
+            next = self.parser.next_message(&[]);
+        }
+        Ok(())
+    }
+
+    fn flush_output(&mut self) -> error::Result<()> {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => {
+                    return Err(
+                        io::Error::new(ErrorKind::WriteZero, "failed to write response").into(),
+                    )
+                }
+                Ok(written) => {
+                    self.output.drain(..written);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        match self.stream.flush() {
+            Err(e) if e.kind() != ErrorKind::WouldBlock => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A single-threaded server for many connections, using mio to wait until they are ready.
+///
+/// By default there is no limit on how many connections are open at once. On a small device use
+/// `set_max_connections` to bound the memory they take; connections past the limit are answered
+/// with 503 Service Unavailable and closed. Connections which see no activity for the keep-alive
+/// timeout are closed too.
+///
+/// *This struct is available if http_io is built with the `"mio"` feature.*
+#[cfg(feature = "mio")]
+pub struct MioServer<H> {
+    listener: mio::net::TcpListener,
+    poll: mio::Poll,
+    events: mio::Events,
+    connections: hashbrown::HashMap<mio::Token, Tracked>,
+    next_token: usize,
+    max_connections: Option<usize>,
+    keep_alive_timeout: Option<std::time::Duration>,
+    options: ServeOptions,
+    on_error: Option<super::ErrorCallback>,
+    handler: H,
+}
+
+/// An open connection of a `MioServer`, and when it was last ready.
+#[cfg(feature = "mio")]
+struct Tracked {
+    connection: NonBlockingConnection<mio::net::TcpStream>,
+    last_active: std::time::Instant,
+}
+
+#[cfg(feature = "mio")]
+const LISTENER: mio::Token = mio::Token(0);
+
+#[cfg(feature = "mio")]
+impl<H: HttpResponder<BufferedRequest>> MioServer<H> {
+    pub fn new(mut listener: mio::net::TcpListener, handler: H) -> error::Result<Self> {
+        let poll = mio::Poll::new()?;
+        poll.registry()
+            .register(&mut listener, LISTENER, mio::Interest::READABLE)?;
+        Ok(MioServer {
+            listener,
+            poll,
+            events: mio::Events::with_capacity(128),
+            connections: hashbrown::HashMap::new(),
+            next_token: LISTENER.0 + 1,
+            max_connections: None,
+            keep_alive_timeout: Some(std::time::Duration::from_secs(5)),
+            options: ServeOptions::default(),
+            on_error: None,
+            handler,
+        })
+    }
+
+    /// Set the maximum number of connections open at once, or `None` for no limit. It is `None` by
+    /// default.
+    pub fn set_max_connections(&mut self, max: Option<usize>) {
+        self.max_connections = max;
+    }
+
+    /// Set how long a connection may go without becoming ready before it is closed, or `None` to
+    /// keep connections open until the client closes them. It is 5 seconds by default.
+    pub fn set_keep_alive_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.keep_alive_timeout = timeout;
+    }
+
+    /// Turn strict compliance checking on or off, as `HttpServer::set_strict_mode` does.
+    pub fn set_strict_mode(&mut self, strict: bool) {
+        self.options.strict = strict;
+    }
+
+    /// Turn answering TRACE requests on or off, as `HttpServer::set_trace_enabled` does.
+    pub fn set_trace_enabled(&mut self, trace: bool) {
+        self.options.trace = trace;
+    }
+
+    /// Set the value of the `Server` header added to responses, as
+    /// `HttpServer::set_server_header` does.
+    pub fn set_server_header<S: Into<String>>(&mut self, server: Option<S>) {
+        self.options.server_header = server.map(Into::into);
+    }
+
+    /// Set the clock used for the `Date` header added to responses, as `HttpServer::set_clock`
+    /// does.
+    pub fn set_clock(&mut self, clock: Option<Clock>) {
+        self.options.clock = clock;
+    }
+
+    /// Set the function called with the errors `serve_forever` continues past, or `None` to ignore
+    /// them. It is `None` by default.
+    pub fn set_error_callback(&mut self, callback: Option<super::ErrorCallback>) {
+        self.on_error = callback;
+    }
+
+    /// Wait up to `timeout` for connections to become ready, then make progress on those that are.
+    /// With open connections and a keep-alive timeout, this waits no longer than the keep-alive
+    /// timeout, so that idle connections are closed in time.
+    pub fn poll_once(&mut self, timeout: Option<std::time::Duration>) -> error::Result<()> {
+        let timeout = match self.keep_alive_timeout {
+            Some(idle) if !self.connections.is_empty() => {
+                Some(timeout.map_or(idle, |timeout| timeout.min(idle)))
+            }
+            _ => timeout,
+        };
+        self.poll.poll(&mut self.events, timeout)?;
+        let tokens: Vec<mio::Token> = self.events.iter().map(|e| e.token()).collect();
+        for token in tokens {
+            if token == LISTENER {
+                self.accept()?;
+            } else {
+                self.poll_connection(token);
+            }
+        }
+        self.close_idle();
+        Ok(())
+    }
+
+    /// Run `poll_once` in a loop forever
+    pub fn serve_forever(&mut self) -> ! {
+        loop {
+            if let Err(e) = self.poll_once(None) {
+                if let Some(on_error) = self.on_error {
+                    on_error(&e);
+                }
+            }
+        }
+    }
+
+    fn accept(&mut self) -> error::Result<()> {
+        loop {
+            let mut stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            if self.at_capacity() {
+                refuse(stream);
+                continue;
+            }
+            let token = mio::Token(self.next_token);
+            self.next_token += 1;
+            self.poll.registry().register(
+                &mut stream,
+                token,
+                mio::Interest::READABLE | mio::Interest::WRITABLE,
+            )?;
+            let connection = NonBlockingConnection::new(stream).with_options(self.options.clone());
+            self.connections.insert(
+                token,
+                Tracked {
+                    connection,
+                    last_active: std::time::Instant::now(),
+                },
+            );
+        }
+    }
+
+    fn at_capacity(&self) -> bool {
+        match self.max_connections {
+            Some(max) => self.connections.len() >= max,
+            None => false,
+        }
+    }
+
+    fn poll_connection(&mut self, token: mio::Token) {
+        let state = match self.connections.get_mut(&token) {
+            Some(tracked) => {
+                tracked.last_active = std::time::Instant::now();
+                tracked.connection.poll(&mut self.handler)
+            }
+            None => return,
+        };
+        if state.unwrap_or(ConnectionState::Closed) == ConnectionState::Closed {
+            self.close(token);
+        }
+    }
+
+    fn close_idle(&mut self) {
+        let timeout = match self.keep_alive_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let idle: Vec<mio::Token> = self
+            .connections
+            .iter()
+            .filter(|(_, tracked)| tracked.last_active.elapsed() >= timeout)
+            .map(|(token, _)| *token)
+            .collect();
+        for token in idle {
+            self.close(token);
+        }
+    }
+
+    fn close(&mut self, token: mio::Token) {
+        if let Some(mut tracked) = self.connections.remove(&token) {
+            let _ = self
+                .poll
+                .registry()
+                .deregister(tracked.connection.get_mut());
+        }
+    }
+}
+
+/// Answer a connection accepted past the limit with 503 Service Unavailable and close it. The
+/// stream is fresh, so the response fits in its send buffer; if it doesn't, the client just sees
+/// the connection close.
+#[cfg(feature = "mio")]
+fn refuse(mut stream: mio::net::TcpStream) {
+    let mut response = crate::protocol::HttpResponse::from_string(
+        crate::protocol::HttpStatus::ServiceUnavailable,
+        "too many connections",
+    );
+    response.add_header("Connection", "close");
+    let _ = write_response(&mut response, None, false, &mut stream);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConnectionState, NonBlockingConnection};
+    use crate::protocol::{HttpMethod, HttpStatus};
+    use crate::server::{ExpectedRequest, TestRequestHandler};
+    use core2::io::{self, ErrorKind};
+    use std::collections::VecDeque;
+
+    /// A stream which has the given input available in pieces. Reading past the end of a piece
+    /// would block.
+    struct PiecewiseStream {
+        input: VecDeque<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl io::Read for PiecewiseStream {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            match self.input.front_mut() {
+                Some(piece) if piece.is_empty() => {
+                    self.input.pop_front();
+                    Err(io::Error::new(ErrorKind::WouldBlock, "would block"))
+                }
+                Some(piece) => {
+                    let len = std::cmp::min(buffer.len(), piece.len());
+                    buffer[..len].copy_from_slice(&piece[..len]);
+                    piece.drain(..len);
+                    Ok(len)
+                }
+                None => Err(io::Error::new(ErrorKind::WouldBlock, "would block")),
+            }
+        }
+    }
+
+    impl io::Write for PiecewiseStream {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice(buffer);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn expected_get() -> ExpectedRequest {
+        ExpectedRequest {
+            expected_method: HttpMethod::Get,
+            expected_uri: "/".into(),
+            expected_body: "".into(),
+            response_status: HttpStatus::OK,
+            response_body: "hello from server".into(),
+        }
+    }
+
+    fn connection(pieces: &[&str]) -> NonBlockingConnection<PiecewiseStream> {
+        NonBlockingConnection::new(PiecewiseStream {
+            input: pieces.iter().map(|p| p.as_bytes().to_vec()).collect(),
+            output: vec![],
+        })
+    }
+
+    fn output(connection: &NonBlockingConnection<PiecewiseStream>) -> String {
+        String::from_utf8(connection.get_ref().output.clone()).unwrap()
+    }
+
+    #[test]
+    fn request_in_pieces() {
+        let mut handler = TestRequestHandler::new(vec![expected_get()]);
+        let mut connection = connection(&["GET / HTTP/1.1\r\nHo", "st: a\r\n\r\n"]);
+
+        assert_eq!(
+            connection.poll(&mut handler).unwrap(),
+            ConnectionState::Open
+        );
+        assert_eq!(output(&connection), "");
+
+        assert_eq!(
+            connection.poll(&mut handler).unwrap(),
+            ConnectionState::Open
+        );
+        assert!(output(&connection).starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output(&connection).contains("hello from server"));
+    }
+
+    #[test]
+    fn pipelined_requests() {
+        let mut handler = TestRequestHandler::new(vec![expected_get(), expected_get()]);
+        let mut connection = connection(&[
+            "GET / HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+        ]);
+
+        assert_eq!(
+            connection.poll(&mut handler).unwrap(),
+            ConnectionState::Closed
+        );
+        assert_eq!(output(&connection).matches("hello from server").count(), 2);
+    }
+
+    #[test]
+    fn chunked_request_body_in_pieces() {
+        let mut handler = TestRequestHandler::new(vec![ExpectedRequest {
+            expected_method: HttpMethod::Put,
+            expected_uri: "/".into(),
+            expected_body: "hello".into(),
+            response_status: HttpStatus::OK,
+            response_body: "hello from server".into(),
+        }]);
+        let mut connection = connection(&[
+            "PUT / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel",
+            "lo\r\n0\r\n\r\n",
+        ]);
+
+        connection.poll(&mut handler).unwrap();
+        assert_eq!(output(&connection), "");
+        connection.poll(&mut handler).unwrap();
+        assert!(output(&connection).contains("hello from server"));
+    }
+
+    #[test]
+    fn bad_request_closes() {
+        let mut handler = TestRequestHandler::new(vec![]);
+        let mut connection = connection(&["BLARG / HTTP/1.1\r\n\r\n"]);
+        assert_eq!(
+            connection.poll(&mut handler).unwrap(),
+            ConnectionState::Closed
+        );
+        assert!(output(&connection).contains("Connection: close\r\n"));
+    }
+
+    #[test]
+    fn default_headers() {
+        let mut handler = TestRequestHandler::new(vec![expected_get()]);
+        let mut connection =
+            connection(&["GET / HTTP/1.1\r\nHost: a\r\n\r\n"]).clock(Some(|| 784_111_777));
+        connection.poll(&mut handler).unwrap();
+        assert!(output(&connection).contains("Server: http_io\r\n"));
+        assert!(output(&connection).contains("Date: Sun, 06 Nov 1994 08:49:37 GMT\r\n"));
+    }
+
+    #[test]
+    fn strict_mode() {
+        let mut handler = TestRequestHandler::new(vec![]);
+        let mut connection = connection(&["GET / HTTP/1.1\r\n\r\n"]).strict_mode(true);
+        assert_eq!(
+            connection.poll(&mut handler).unwrap(),
+            ConnectionState::Closed
+        );
+        assert!(output(&connection).starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn trace_enabled() {
+        let mut handler = TestRequestHandler::new(vec![]);
+        let mut connection =
+            connection(&["TRACE / HTTP/1.1\r\nHost: a\r\nCookie: b\r\n\r\n"]).trace_enabled(true);
+        connection.poll(&mut handler).unwrap();
+        assert!(output(&connection).contains("TRACE / HTTP/1.1\r\n"));
+        assert!(!output(&connection).contains("Cookie"));
+    }
+
+    #[cfg(feature = "mio")]
+    #[test]
+    fn close_idle_connections() {
+        use std::io::Read as _;
+
+        let listener = mio::net::TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut server = super::MioServer::new(listener, TestRequestHandler::new(vec![])).unwrap();
+        server.set_keep_alive_timeout(Some(std::time::Duration::from_millis(100)));
+
+        let mut idle = std::net::TcpStream::connect(address).unwrap();
+        while server.connections.is_empty() {
+            server
+                .poll_once(Some(std::time::Duration::from_millis(100)))
+                .unwrap();
+        }
+        while !server.connections.is_empty() {
+            server.poll_once(None).unwrap();
+        }
+
+        let mut output = vec![];
+        idle.read_to_end(&mut output).unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[cfg(feature = "mio")]
+    #[test]
+    fn refuse_past_max_connections() {
+        use std::io::Read as _;
+
+        let listener = mio::net::TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut server = super::MioServer::new(listener, TestRequestHandler::new(vec![])).unwrap();
+        server.set_max_connections(Some(1));
+
+        let _first = std::net::TcpStream::connect(address).unwrap();
+        let mut second = std::net::TcpStream::connect(address).unwrap();
+        while server.connections.is_empty() {
+            server
+                .poll_once(Some(std::time::Duration::from_millis(100)))
+                .unwrap();
+        }
+        server
+            .poll_once(Some(std::time::Duration::from_millis(100)))
+            .unwrap();
+
+        let mut output = String::new();
+        second.read_to_string(&mut output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert_eq!(server.connections.len(), 1);
+    }
+}
@@ -0,0 +1,505 @@
+//! A request handler which forwards requests to another HTTP server.
+
+use super::{write_response, Connection, HttpRequestHandler};
+use crate::error::{Error, Result};
+use crate::protocol::{
+    HttpHeaders, HttpMethod, HttpRequest, HttpResponse, HttpStatus, OutgoingBody,
+};
+use crate::url::{Scheme, Url};
+use core::convert::TryInto;
+use core::fmt::Display;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+use core2::io::{self, Read};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::Arc;
+
+/// Headers which only apply to a single connection, and so aren't forwarded.
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+fn is_hop_by_hop(headers: &HttpHeaders, key: &str) -> bool {
+    let listed_in_connection = headers
+        .get("Connection")
+        .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(key)))
+        .unwrap_or(false);
+    listed_in_connection
+        || HOP_BY_HOP_HEADERS
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(key))
+}
+
+/// Copy the end-to-end headers from one set of headers to another.
+fn copy_headers(from: &HttpHeaders, to: &mut HttpHeaders) {
+    for (key, value) in from.iter() {
+        if !is_hop_by_hop(from, key) {
+            to.append(key, value);
+        }
+    }
+}
+
+/// A reverse proxy. It forwards each request it is given to an upstream server using a new
+/// connection, and streams the upstream server's response back.
+///
+/// Hop-by-hop headers are removed in both directions, and the address of the client is appended
+/// to the `X-Forwarded-For` header.
+///
+/// Only plain HTTP upstreams are supported.
+///
+/// *This struct is available if http_io is built with the `"std"` feature.*
+pub struct ProxyHandler {
+    upstream: Url,
+}
+
+impl ProxyHandler {
+    /// Create a `ProxyHandler` forwarding to the given url. The path of each request is appended
+    /// to the path of the url. May fail if the given url does not parse.
+    pub fn new<U: TryInto<Url>>(upstream: U) -> Result<Self>
+    where
+        <U as TryInto<Url>>::Error: Display,
+    {
+        let upstream = upstream
+            .try_into()
+            .map_err(|e| Error::ParseError(e.to_string()))?;
+        Ok(ProxyHandler { upstream })
+    }
+}
+
+/// Send the request to `uri` on the upstream server and return its response.
+fn forward<I: Read>(
+    upstream: &Url,
+    uri: String,
+    mut request: HttpRequest<&mut I>,
+) -> Result<HttpResponse<Box<dyn Read>>> {
+    if upstream.scheme != Scheme::Http {
+        return Err(Error::UnexpectedScheme(upstream.scheme.to_string()));
+    }
+
+    let mut upstream_request = HttpRequest::new(request.method, uri);
+    copy_headers(&request.headers, &mut upstream_request.headers);
+    upstream_request.add_header("Host", upstream.authority.clone());
+    upstream_request.add_header("Connection", "close");
+    // Frame the body like the client did: chunked if it was, otherwise with its length. A request
+    // with neither has no body.
+    upstream_request.headers.remove("Content-Length");
+    let chunked = request.headers.is_chunked();
+    let length = request.headers.content_length()?;
+    if chunked {
+        upstream_request.add_header("Transfer-Encoding", "chunked");
+    } else if let Some(length) = length {
+        upstream_request.headers.set_content_length(length);
+    }
+
+    if let Some(address) = &request.connection.peer_address {
+        let forwarded_for = match request.headers.get("X-Forwarded-For") {
+            Some(existing) => format!("{}, {}", existing, address),
+            None => address.clone(),
+        };
+        upstream_request.add_header("X-Forwarded-For", forwarded_for);
+    }
+
+    let stream = TcpStream::connect((upstream.host(), upstream.port()?))?;
+    let mut writer = io::BufWriter::new(stream);
+    upstream_request.serialize_head(&mut writer)?;
+    let body_length = if chunked {
+        None
+    } else {
+        Some(length.unwrap_or(0))
+    };
+    let mut body = OutgoingBody::new(writer, body_length);
+    io::copy(&mut request.body, &mut body)?;
+    let upstream_response = body.finish()?;
+    let reason = upstream_response.reason_phrase().map(String::from);
+
+    let mut response = HttpResponse::new(
+        upstream_response.status,
+        Box::new(upstream_response.body) as Box<dyn Read>,
+    );
+    if let Some(reason) = reason {
+        response.set_reason_phrase(reason);
+    }
+    copy_headers(&upstream_response.headers, &mut response.headers);
+    Ok(response)
+}
+
+impl<I: Read> HttpRequestHandler<I> for ProxyHandler {
+    type Error = Error;
+
+    fn handle(
+        &mut self,
+        request: HttpRequest<&mut I>,
+    ) -> core::result::Result<HttpResponse<Box<dyn Read>>, Error> {
+        let prefix = self.upstream.path.to_string();
+        let uri = format!("{}{}", prefix.trim_end_matches('/'), request.uri);
+        forward(&self.upstream, uri, request)
+    }
+}
+
+/// Counts a tunnel as active until it is dropped.
+struct ActiveTunnel(Arc<AtomicUsize>);
+
+impl ActiveTunnel {
+    fn new(active: Arc<AtomicUsize>) -> Self {
+        active.fetch_add(1, Ordering::SeqCst);
+        ActiveTunnel(active)
+    }
+}
+
+impl Drop for ActiveTunnel {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Copy bytes in both directions between the client and the upstream server on background threads
+/// until each side closes its half of the connection, or goes quiet for longer than the read
+/// timeout set on its stream.
+fn tunnel(client: Connection<TcpStream>, upstream: TcpStream, active: ActiveTunnel) -> Result<()> {
+    let mut client_reader = client;
+    let mut client_writer = client_reader.get_ref().try_clone()?;
+    let mut upstream_reader = upstream.try_clone()?;
+    let mut upstream_writer = upstream;
+    let active = Arc::new(active);
+    let other_active = active.clone();
+
+    // The client may have sent data right after the CONNECT request, in which case it is in the
+    // buffer of `client_reader`, so that is what we read from.
+    std::thread::spawn(move || -> Result<()> {
+        let _active = other_active;
+        io::copy(&mut client_reader, &mut upstream_writer)?;
+        upstream_writer.shutdown(Shutdown::Write)?;
+        Ok(())
+    });
+    std::thread::spawn(move || -> Result<()> {
+        let _active = active;
+        io::copy(&mut upstream_reader, &mut client_writer)?;
+        client_writer.shutdown(Shutdown::Write)?;
+        Ok(())
+    });
+    Ok(())
+}
+
+/// A forward proxy server. CONNECT requests are answered by connecting to the requested authority
+/// and tunneling bytes between the client and it until either side closes. Requests using an
+/// absolute url are forwarded like `ProxyHandler` does.
+///
+/// Each connection is used for one request. Tunnels run on their own threads, so they don't block
+/// the serving of other requests. Use `set_max_connections` to limit how many run at once.
+///
+/// *This struct is available if http_io is built with the `"std"` feature.*
+pub struct ForwardProxy {
+    listener: TcpListener,
+    active: Arc<AtomicUsize>,
+    max_connections: Option<usize>,
+    read_timeout: Option<Duration>,
+}
+
+impl ForwardProxy {
+    pub fn new(listener: TcpListener) -> Self {
+        ForwardProxy {
+            listener,
+            active: Arc::new(AtomicUsize::new(0)),
+            max_connections: None,
+            read_timeout: Some(Duration::from_secs(60)),
+        }
+    }
+
+    /// Limit the number of connections open at once. Connections are served one at a time, so this
+    /// is the number of open tunnels. Connections arriving at the limit are answered with 503
+    /// Service Unavailable.
+    pub fn set_max_connections(&mut self, max_connections: usize) {
+        self.max_connections = Some(max_connections);
+    }
+
+    /// Set how long reads from the client, and from the server at the other end of a tunnel, wait
+    /// for data before the connection is given up, or `None` to wait forever. It is 60 seconds by
+    /// default, so idle tunnels don't hold their threads forever.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    fn at_capacity(&self) -> bool {
+        match self.max_connections {
+            Some(max) => self.active.load(Ordering::SeqCst) >= max,
+            None => false,
+        }
+    }
+
+    /// Accept one connection and serve the request on it.
+    pub fn serve_one(&mut self) -> Result<()> {
+        let (stream, address) = self.listener.accept()?;
+        stream.set_read_timeout(self.read_timeout)?;
+        let mut connection = Connection::new(stream);
+
+        let mut request = match HttpRequest::deserialize(&mut connection) {
+            Ok(request) => request,
+            Err(e) => return respond(connection.get_mut(), None, e.into()),
+        };
+        let method = request.method;
+
+        if self.at_capacity() {
+            let response =
+                HttpResponse::from_string(HttpStatus::ServiceUnavailable, "too many connections");
+            return respond(connection.get_mut(), Some(method), response);
+        }
+
+        if method == HttpMethod::Connect {
+            return match TcpStream::connect(request.uri.as_str()) {
+                Ok(upstream) => {
+                    upstream.set_read_timeout(self.read_timeout)?;
+                    HttpResponse::new(HttpStatus::OK, io::empty())
+                        .serialize(connection.get_mut())?;
+                    tunnel(connection, upstream, ActiveTunnel::new(self.active.clone()))
+                }
+                Err(e) => {
+                    let response = HttpResponse::from_string(HttpStatus::BadGateway, e.to_string());
+                    respond(connection.get_mut(), Some(method), response)
+                }
+            };
+        }
+
+        let upstream: Url = match request.uri.parse() {
+            Ok(url) => url,
+            Err(e) => {
+                let response = HttpResponse::from_string(
+                    HttpStatus::BadRequest,
+                    format!("expected an absolute url: {}", e),
+                );
+                return respond(connection.get_mut(), Some(method), response);
+            }
+        };
+        request.connection.peer_address = Some(address.ip().to_string());
+        let response = match forward(&upstream, upstream.path(), request) {
+            Ok(response) => response,
+            Err(e) => HttpResponse::from_string(HttpStatus::BadGateway, e.to_string()),
+        };
+        respond(connection.get_mut(), Some(method), response)
+    }
+
+    /// Run `serve_one` in a loop forever
+    pub fn serve_forever(&mut self) -> ! {
+        loop {
+            if let Err(e) = self.serve_one() {
+                println!("Error {:?}", e)
+            }
+        }
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    method: Option<HttpMethod>,
+    mut response: HttpResponse<Box<dyn Read>>,
+) -> Result<()> {
+    response.add_header("Connection", "close");
+    write_response(&mut response, method, false, stream)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ForwardProxy, ProxyHandler};
+    use crate::error::Result;
+    use crate::protocol::{HttpMethod, HttpStatus};
+    use crate::server::{test_server, ExpectedRequest, HttpServer};
+    use core2::io::{Read, Write};
+
+    fn expected_get() -> ExpectedRequest {
+        ExpectedRequest {
+            expected_method: HttpMethod::Get,
+            expected_uri: "/".into(),
+            expected_body: "".into(),
+            response_status: HttpStatus::OK,
+            response_body: "hello from upstream".into(),
+        }
+    }
+
+    fn forward_proxy_test(request: String, upstream_port: u16) -> Result<String> {
+        let proxy_socket = std::net::TcpListener::bind("localhost:0")?;
+        let proxy_port = proxy_socket.local_addr()?.port();
+        let mut proxy = ForwardProxy::new(proxy_socket);
+        let proxy_handle = std::thread::spawn(move || proxy.serve_one());
+
+        let mut stream = std::net::TcpStream::connect(("localhost", proxy_port))?;
+        let request = request.replace("{port}", &upstream_port.to_string());
+        stream.write_all(request.as_bytes())?;
+        proxy_handle.join().unwrap()?;
+
+        let mut output = String::new();
+        stream.read_to_string(&mut output)?;
+        Ok(output)
+    }
+
+    /// An upstream server which reads one request until it ends with `end`, answers it with 204 No
+    /// Content, and returns the request as it arrived.
+    fn raw_upstream(end: &'static str) -> Result<(u16, std::thread::JoinHandle<Result<String>>)> {
+        let listener = std::net::TcpListener::bind("localhost:0")?;
+        let port = listener.local_addr()?.port();
+        let handle = std::thread::spawn(move || -> Result<String> {
+            let (mut stream, _) = listener.accept()?;
+            let mut received = Vec::new();
+            let mut buffer = [0; 1024];
+            while !received.ends_with(end.as_bytes()) {
+                let read = stream.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buffer[..read]);
+            }
+            stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")?;
+            Ok(String::from_utf8(received).unwrap())
+        });
+        Ok((port, handle))
+    }
+
+    #[test]
+    fn forward_proxy_connect() -> Result<()> {
+        let (upstream_port, mut upstream) = test_server(vec![expected_get()])?;
+        let upstream_handle = std::thread::spawn(move || upstream.serve_one());
+
+        let output = forward_proxy_test(
+            "CONNECT localhost:{port} HTTP/1.1\r\nHost: localhost:{port}\r\n\r\n\
+             GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+                .into(),
+            upstream_port,
+        )?;
+        upstream_handle.join().unwrap()?;
+
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n\r\nHTTP/1.1 200 OK\r\n"));
+        assert!(output.contains("hello from upstream"));
+        Ok(())
+    }
+
+    #[test]
+    fn forward_proxy_connection_limit() -> Result<()> {
+        let upstream = std::net::TcpListener::bind("localhost:0")?;
+        let upstream_port = upstream.local_addr()?.port();
+        let connect = format!("CONNECT localhost:{} HTTP/1.1\r\n\r\n", upstream_port);
+
+        let proxy_socket = std::net::TcpListener::bind("localhost:0")?;
+        let proxy_port = proxy_socket.local_addr()?.port();
+        let mut proxy = ForwardProxy::new(proxy_socket);
+        proxy.set_max_connections(1);
+        let proxy_handle = std::thread::spawn(move || -> Result<()> {
+            proxy.serve_one()?;
+            proxy.serve_one()
+        });
+
+        let mut first = std::net::TcpStream::connect(("localhost", proxy_port))?;
+        first.write_all(connect.as_bytes())?;
+        // Keep the tunnel open.
+        let _upstream_connection = upstream.accept()?;
+
+        let mut second = std::net::TcpStream::connect(("localhost", proxy_port))?;
+        second.write_all(connect.as_bytes())?;
+        proxy_handle.join().unwrap()?;
+
+        let mut output = String::new();
+        second.read_to_string(&mut output)?;
+        assert!(output.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn forward_proxy_tunnel_timeout() -> Result<()> {
+        let upstream = std::net::TcpListener::bind("localhost:0")?;
+        let upstream_port = upstream.local_addr()?.port();
+        let connect = format!("CONNECT localhost:{} HTTP/1.1\r\n\r\n", upstream_port);
+
+        let proxy_socket = std::net::TcpListener::bind("localhost:0")?;
+        let proxy_port = proxy_socket.local_addr()?.port();
+        let mut proxy = ForwardProxy::new(proxy_socket);
+        proxy.set_read_timeout(Some(std::time::Duration::from_millis(100)));
+        let proxy_handle = std::thread::spawn(move || proxy.serve_one());
+
+        let mut client = std::net::TcpStream::connect(("localhost", proxy_port))?;
+        client.write_all(connect.as_bytes())?;
+        let (mut upstream_connection, _) = upstream.accept()?;
+        proxy_handle.join().unwrap()?;
+
+        // Neither side sends anything, so the tunnel gives up and the upstream server sees it
+        // close.
+        let mut output = Vec::new();
+        upstream_connection.read_to_end(&mut output)?;
+        assert!(output.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn forward_proxy_absolute_url() -> Result<()> {
+        let (upstream_port, mut upstream) = test_server(vec![expected_get()])?;
+        let upstream_handle = std::thread::spawn(move || upstream.serve_one());
+
+        let output = forward_proxy_test(
+            "GET http://localhost:{port}/ HTTP/1.1\r\nHost: localhost:{port}\r\n\r\n".into(),
+            upstream_port,
+        )?;
+        upstream_handle.join().unwrap()?;
+
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output.contains("hello from upstream"));
+        Ok(())
+    }
+
+    #[test]
+    fn forward_keeps_framing() -> Result<()> {
+        let (upstream_port, upstream) = raw_upstream("\r\n\r\nhello")?;
+        let output = forward_proxy_test(
+            "POST http://localhost:{port}/ HTTP/1.1\r\nHost: localhost:{port}\r\n\
+             Content-Length: 5\r\n\r\nhello"
+                .into(),
+            upstream_port,
+        )?;
+        let received = upstream.join().unwrap()?;
+        assert!(output.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(received.contains("Content-Length: 5\r\n"));
+        assert!(!received.contains("Transfer-Encoding"));
+
+        let (upstream_port, upstream) = raw_upstream("\r\n\r\n")?;
+        forward_proxy_test(
+            "GET http://localhost:{port}/ HTTP/1.1\r\nHost: localhost:{port}\r\n\r\n".into(),
+            upstream_port,
+        )?;
+        let received = upstream.join().unwrap()?;
+        assert!(!received.contains("Content-Length"));
+        assert!(!received.contains("Transfer-Encoding"));
+        Ok(())
+    }
+
+    #[test]
+    fn forward_get() -> Result<()> {
+        let (upstream_port, mut upstream) = test_server(vec![ExpectedRequest {
+            expected_method: HttpMethod::Get,
+            expected_uri: "/a/b".into(),
+            expected_body: "".into(),
+            response_status: HttpStatus::OK,
+            response_body: "hello from upstream".into(),
+        }])?;
+        let upstream_handle = std::thread::spawn(move || upstream.serve_one());
+
+        let proxy_socket = std::net::TcpListener::bind("localhost:0")?;
+        let proxy_port = proxy_socket.local_addr()?.port();
+        let handler = ProxyHandler::new(format!("http://localhost:{}/", upstream_port).as_ref())?;
+        let mut proxy = HttpServer::new(proxy_socket, handler);
+        let proxy_handle = std::thread::spawn(move || proxy.serve_one());
+
+        let mut body = crate::client::get(format!("http://localhost:{}/a/b", proxy_port).as_ref())?;
+        let mut body_str = String::new();
+        body.read_to_string(&mut body_str)?;
+        assert_eq!(body_str, "hello from upstream");
+
+        upstream_handle.join().unwrap()?;
+        let summary = proxy_handle.join().unwrap()?;
+        assert_eq!(summary.uri.as_deref(), Some("/a/b"));
+        assert_eq!(summary.status, HttpStatus::OK);
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+//! A request handler which sends requests to other handlers by method and path.
+
+use super::HttpRequestHandler;
+use crate::protocol::{HttpMethod, HttpRequest, HttpResponse, HttpStatus};
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, format, string::String, string::ToString, vec::Vec};
+use core2::io::Read;
+
+struct Route<H> {
+    method: HttpMethod,
+    pattern: String,
+    handler: H,
+}
+
+/// Whether the path matches the route pattern. Patterns ending in `/*` match any path under
+/// them, other patterns must match exactly.
+fn path_matches(pattern: &str, path: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) if prefix.ends_with('/') => {
+            path.starts_with(prefix) || path == &prefix[..prefix.len() - 1]
+        }
+        _ => pattern == path,
+    }
+}
+
+/// Sends each request to the handler registered for its method and path.
+///
+/// Requests for a path with no routes are answered with 404 Not Found. Requests for a path with
+/// routes, but none for the request's method, are answered with 405 Method Not Allowed and an
+/// `Allow` header listing the methods which have routes for the path.
+pub struct Router<H> {
+    routes: Vec<Route<H>>,
+}
+
+impl<H> Default for Router<H> {
+    fn default() -> Self {
+        Self { routes: Vec::new() }
+    }
+}
+
+impl<H> Router<H> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send requests with the given method for paths matching `pattern` to `handler`. The pattern
+    /// is a path like `/users`, or a path ending in `/*` like `/static/*` to match everything
+    /// under it. The query string isn't part of the path. When several routes match, the first
+    /// added is used.
+    pub fn add_route<S: Into<String>>(&mut self, method: HttpMethod, pattern: S, handler: H) {
+        self.routes.push(Route {
+            method,
+            pattern: pattern.into(),
+            handler,
+        });
+    }
+
+    /// The methods with routes matching the path, in the order they were added.
+    fn allowed_methods(&self, path: &str) -> Vec<HttpMethod> {
+        let mut methods = Vec::new();
+        for route in self
+            .routes
+            .iter()
+            .filter(|r| path_matches(&r.pattern, path))
+        {
+            if !methods.contains(&route.method) {
+                methods.push(route.method);
+            }
+        }
+        methods
+    }
+}
+
+impl<I: Read, H: HttpRequestHandler<I>> HttpRequestHandler<I> for Router<H> {
+    type Error = H::Error;
+
+    fn handle(
+        &mut self,
+        request: HttpRequest<&mut I>,
+    ) -> Result<HttpResponse<Box<dyn Read>>, Self::Error> {
+        let path = request.uri.split('?').next().unwrap_or("");
+        let method = request.method;
+        let position = self
+            .routes
+            .iter()
+            .position(|r| r.method == method && path_matches(&r.pattern, path));
+        if let Some(position) = position {
+            return self.routes[position].handler.handle(request);
+        }
+
+        let allowed = self.allowed_methods(path);
+        if allowed.is_empty() {
+            return Ok(HttpResponse::from_string(HttpStatus::NotFound, "not found"));
+        }
+        let allowed: Vec<String> = allowed.iter().map(|m| m.to_string()).collect();
+        let mut response = HttpResponse::from_string(
+            HttpStatus::MethodNotAllowed,
+            format!("{} not allowed", method),
+        );
+        response.add_header("Allow", allowed.join(", "));
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{path_matches, Router};
+    use crate::protocol::{HttpMethod, HttpRequest, HttpResponse, HttpStatus};
+    use crate::server::HttpRequestHandler;
+    use core2::io::{self, Read};
+
+    struct NamedHandler(&'static str);
+
+    impl<I: Read> HttpRequestHandler<I> for NamedHandler {
+        type Error = HttpResponse<Box<dyn Read>>;
+
+        fn handle(
+            &mut self,
+            _request: HttpRequest<&mut I>,
+        ) -> Result<HttpResponse<Box<dyn Read>>, Self::Error> {
+            Ok(HttpResponse::from_string(HttpStatus::OK, self.0))
+        }
+    }
+
+    fn router() -> Router<NamedHandler> {
+        let mut router = Router::new();
+        router.add_route(HttpMethod::Get, "/users", NamedHandler("list"));
+        router.add_route(HttpMethod::Post, "/users", NamedHandler("create"));
+        router.add_route(HttpMethod::Get, "/static/*", NamedHandler("static"));
+        router
+    }
+
+    fn response(router: &mut Router<NamedHandler>, request: &str) -> HttpResponse<Box<dyn Read>> {
+        let mut input = io::BufReader::<_, 1024>::new(request.as_bytes());
+        let request = HttpRequest::deserialize(&mut input).unwrap();
+        router.handle(request).unwrap_or_else(|e| e)
+    }
+
+    fn body(mut response: HttpResponse<Box<dyn Read>>) -> String {
+        let mut body = String::new();
+        response.body.read_to_string(&mut body).unwrap();
+        body
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(path_matches("/users", "/users"));
+        assert!(!path_matches("/users", "/users/1"));
+        assert!(path_matches("/static/*", "/static/a/b.css"));
+        assert!(path_matches("/static/*", "/static"));
+        assert!(!path_matches("/static/*", "/statics"));
+    }
+
+    #[test]
+    fn routes_by_method_and_path() {
+        let mut router = router();
+        let get = "GET /users?page=2 HTTP/1.1\r\n\r\n";
+        assert_eq!(body(response(&mut router, get)), "list");
+        let post = "POST /users HTTP/1.1\r\nContent-Length: 0\r\n\r\n";
+        assert_eq!(body(response(&mut router, post)), "create");
+        let get = "GET /static/style.css HTTP/1.1\r\n\r\n";
+        assert_eq!(body(response(&mut router, get)), "static");
+    }
+
+    #[test]
+    fn method_not_allowed() {
+        let mut router = router();
+        let response = response(&mut router, "DELETE /users HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, HttpStatus::MethodNotAllowed);
+        assert_eq!(response.get_header("Allow"), Some("GET, POST"));
+    }
+
+    #[test]
+    fn not_found() {
+        let mut router = router();
+        let response = response(&mut router, "GET /missing HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, HttpStatus::NotFound);
+        assert_eq!(response.get_header("Allow"), None);
+    }
+}
@@ -61,7 +61,7 @@ impl fmt::Display for Scheme {
 fn percent_encode_char(c: char) -> String {
     c.to_string()
         .bytes()
-        .map(|b| format!("%{:x}", b))
+        .map(|b| format!("%{:02x}", b))
         .collect::<Vec<_>>()
         .join("")
 }
@@ -76,7 +76,7 @@ fn is_unreserved_char(c: char) -> bool {
         || c == '~'
 }
 
-fn percent_encode(s: &str) -> String {
+pub(crate) fn percent_encode(s: &str) -> String {
     s.chars()
         .map(|c| {
             if is_unreserved_char(c) {
@@ -89,6 +89,26 @@ fn percent_encode(s: &str) -> String {
         .join("")
 }
 
+/// Encode the pairs as `application/x-www-form-urlencoded`: `key=value` joined by `&`, with
+/// spaces as `+` and other reserved characters percent-encoded.
+pub(crate) fn form_urlencode<K: AsRef<str>, V: AsRef<str>>(pairs: &[(K, V)]) -> String {
+    let encode = |s: &str| percent_encode(s).replace("%20", "+");
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", encode(k.as_ref()), encode(v.as_ref())))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[test]
+fn form_urlencode_pairs() {
+    assert_eq!(form_urlencode::<&str, &str>(&[]), "");
+    assert_eq!(
+        form_urlencode(&[("user name", "a&b=c"), ("line", "1\n2+3")]),
+        "user+name=a%26b%3dc&line=1%0a2%2b3"
+    );
+}
+
 #[test]
 fn percent_encode_unreserved_chars_not_encoded() {
     let s = "abcdefghijklmnopqrstuvqxyzABCDEFGHIJKLMNOPQRSTUVQXYZ0123456789-._~";
@@ -278,6 +298,15 @@ impl Url {
         )
     }
 
+    /// The host to connect to: the authority, without the brackets around an IPv6 address
+    /// literal like `[::1]`. The authority keeps them, as the `Host` header needs them.
+    pub fn host(&self) -> &str {
+        self.authority
+            .strip_prefix('[')
+            .and_then(|a| a.strip_suffix(']'))
+            .unwrap_or(&self.authority)
+    }
+
     pub fn port(&self) -> Result<u16> {
         if let Some(p) = self.port {
             return Ok(p);
@@ -288,6 +317,87 @@ impl Url {
             s => Err(Error::UrlError(format!("port for {} not known", s))),
         }
     }
+
+    /// Append a segment to the path. It is percent-encoded on its own, slashes included, so
+    /// pushing `a/b c` onto `http://example.com/items` gives `http://example.com/items/a%2fb%20c`.
+    pub fn push_segment(&mut self, segment: &str) {
+        self.path.push(segment);
+    }
+
+    /// Resolve a reference, like the `Location` header of a redirect, against the url as its base
+    /// (RFC 3986 section 5.2). It can be a url, like `https://example.com/`, a network-path
+    /// reference like `//example.com/`, an absolute path like `/other/path`, or a path relative to
+    /// the url's directory like `../up`, and may end with a query and a fragment.
+    pub fn join(&self, reference: &str) -> Result<Url> {
+        let scheme_end =
+            reference.find(|c: char| !(c.is_ascii_alphanumeric() || "+-.".contains(c)));
+        if let Some(end) = scheme_end {
+            if end > 0
+                && reference[end..].starts_with(':')
+                && reference.starts_with(|c: char| c.is_ascii_alphabetic())
+            {
+                return reference.parse();
+            }
+        }
+        if reference.starts_with("//") {
+            return format!("{}:{}", self.scheme, reference).parse();
+        }
+
+        let (reference, fragment) = match reference.split_once('#') {
+            Some((reference, fragment)) => (reference, Some(percent_decode(fragment)?)),
+            None => (reference, None),
+        };
+        let (path, query) = match reference.split_once('?') {
+            Some((path, query)) => (path, Some(percent_decode(query)?)),
+            None => (reference, None),
+        };
+
+        let mut url = self.clone();
+        url.fragment = fragment;
+        if path.is_empty() {
+            if query.is_some() {
+                url.query = query;
+            }
+            return Ok(url);
+        }
+        url.query = query;
+        let path = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            // Replace the last segment of the base path, unless it ends with a slash.
+            let mut base = self.path.to_string();
+            match base.rfind('/') {
+                Some(slash) => base.truncate(slash + 1),
+                None => base = "/".into(),
+            }
+            base + path
+        };
+        url.path = remove_dot_segments(&path).parse()?;
+        Ok(url)
+    }
+}
+
+/// Remove the `.` and `..` segments of an absolute path (RFC 3986 section 5.2.4).
+fn remove_dot_segments(path: &str) -> String {
+    let mut output: Vec<&str> = Vec::new();
+    let mut trailing_slash = false;
+    for segment in path.split('/').skip(1) {
+        trailing_slash = false;
+        match segment {
+            "." => trailing_slash = true,
+            ".." => {
+                output.pop();
+                trailing_slash = true;
+            }
+            segment => output.push(segment),
+        }
+    }
+    let trailing_slash = if trailing_slash && !output.is_empty() {
+        "/"
+    } else {
+        ""
+    };
+    format!("/{}{}", output.join("/"), trailing_slash)
 }
 
 impl TryFrom<&str> for Url {
@@ -334,10 +444,20 @@ impl str::FromStr for Url {
             _ => None,
         };
 
-        let authority = parser
-            .parse_until_any(&['/', '?', '#', ':'])
-            .or_else(|_| parser.parse_remaining())?
-            .into();
+        let authority = if parser.expect("[").is_ok() {
+            // An IPv6 address literal, whose colons don't start the port.
+            let address = parser.parse_until("]")?;
+            parser.expect("]")?;
+            address
+                .parse::<core::net::Ipv6Addr>()
+                .map_err(|_| Error::UrlError(format!("invalid IPv6 address '{}'", address)))?;
+            format!("[{}]", address)
+        } else {
+            parser
+                .parse_until_any(&['/', '?', '#', ':'])
+                .or_else(|_| parser.parse_remaining())?
+                .into()
+        };
 
         let port = match parser.expect(":") {
             Ok(_) => Some(
@@ -406,6 +526,8 @@ mod tests {
         round_trip_test("http://www.google.com/?%2fderp%2fface");
         round_trip_test("http://www.google.com/#%2fderp%2fface");
         round_trip_test("http://www.google.com/?#");
+        round_trip_test("http://[::1]/");
+        round_trip_test("http://user@[2001:db8::7]:8080/pie");
     }
 
     fn parse_test(
@@ -432,6 +554,47 @@ mod tests {
         assert_eq!(actual_url, expected_url);
     }
 
+    #[test]
+    fn push_segment() {
+        let mut url: Url = "http://example.com/items?x".parse().unwrap();
+        url.push_segment("a/b c");
+        url.push_segment("%");
+        assert_eq!(url.to_string(), "http://example.com/items/a%2fb%20c/%25?x");
+        assert_eq!(
+            url.path.components().collect::<Vec<_>>(),
+            vec!["items", "a/b c", "%"]
+        );
+    }
+
+    #[test]
+    fn join() {
+        let base: Url = "http://a/b/c/d?q".parse().unwrap();
+        let join = |reference| base.join(reference).unwrap().to_string();
+        assert_eq!(join("g"), "http://a/b/c/g");
+        assert_eq!(join("./g"), "http://a/b/c/g");
+        assert_eq!(join("g/"), "http://a/b/c/g/");
+        assert_eq!(join("/g"), "http://a/g");
+        assert_eq!(join("/other/path"), "http://a/other/path");
+        assert_eq!(join("//g/x"), "http://g/x");
+        assert_eq!(join("?y"), "http://a/b/c/d?y");
+        assert_eq!(join("g?y#s"), "http://a/b/c/g?y#s");
+        assert_eq!(join("#s"), "http://a/b/c/d?q#s");
+        assert_eq!(join(""), "http://a/b/c/d?q");
+        assert_eq!(join("."), "http://a/b/c/");
+        assert_eq!(join(".."), "http://a/b/");
+        assert_eq!(join("../g"), "http://a/b/g");
+        assert_eq!(join("../up"), "http://a/b/up");
+        assert_eq!(join("../../../g"), "http://a/g");
+        assert_eq!(join("g/../h"), "http://a/b/c/h");
+        assert_eq!(
+            join("https://example.com:8443/x"),
+            "https://example.com:8443/x"
+        );
+
+        let base: Url = "http://a:8080".parse().unwrap();
+        assert_eq!(base.join("g").unwrap().to_string(), "http://a:8080/g");
+    }
+
     #[test]
     fn parse_simple() {
         parse_test(
@@ -585,6 +748,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_ipv6() -> Result<()> {
+        parse_test(
+            "http://[::1]:8080/foo",
+            Scheme::Http,
+            "[::1]",
+            Some(8080),
+            &["foo"],
+            false,
+            None,
+            None,
+            None,
+        );
+        let url: Url = "https://[2001:db8::7]".parse()?;
+        assert_eq!(url.authority, "[2001:db8::7]");
+        assert_eq!(url.host(), "2001:db8::7");
+        assert_eq!(url.port()?, 443);
+
+        let url: Url = "http://example.com:8080".parse()?;
+        assert_eq!(url.host(), "example.com");
+
+        assert!("http://[::1/".parse::<Url>().is_err());
+        assert!("http://[example.com]/".parse::<Url>().is_err());
+        Ok(())
+    }
+
     #[test]
     fn scheme_to_port() -> Result<()> {
         let url: Url = "http://google.com".parse()?;
@@ -0,0 +1,499 @@
+//! WebSocket (RFC 6455) connections, over a stream which finished the opening handshake.
+//!
+//! The frame codec is shared by both ends: clients open a `WebSocket` with
+//! `HttpRequestBuilder::websocket`, and servers answer the upgrade request with `accept_key`
+//! before wrapping the stream with `Role::Server`.
+//!
+//! *This module is available if http_io is built with the `"websocket"` feature.*
+use crate::client::Random;
+use crate::error::{Error, Result};
+#[cfg(not(feature = "std"))]
+use alloc::{format, string::String, vec, vec::Vec};
+use core::str;
+use core2::io::{Read, Write};
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+/// The GUID appended to the `Sec-WebSocket-Key` to compute the `Sec-WebSocket-Accept`.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The SHA-1 digest of the data, as needed for `Sec-WebSocket-Accept`.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64).wrapping_mul(8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+/// The `Sec-WebSocket-Accept` a server answers with to the given `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    crate::client::encode_base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+fn protocol_error<S: Into<String>>(message: S) -> Error {
+    Error::WebSocketError(message.into())
+}
+
+/// Which end of the connection a `WebSocket` is. Clients mask the frames they send, and servers
+/// require it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+/// A message sent or received on a `WebSocket`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// The connection is closing, with the status code and reason if given.
+    Close(Option<(u16, String)>),
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// A WebSocket connection over a stream. Read messages with `read_message`, and send them with
+/// `write_message`.
+///
+/// Pings are answered automatically while reading, and a close from the peer is echoed, after
+/// which the stream should be closed.
+pub struct WebSocket<S> {
+    stream: S,
+    role: Role,
+    max_message_size: usize,
+    /// The opcode and payload of a fragmented message being received.
+    partial: Option<(u8, Vec<u8>)>,
+    close_sent: bool,
+    /// Where a client's masking keys come from.
+    random: Option<Random>,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    /// Wrap a stream on which the opening handshake is done.
+    pub fn new(stream: S, role: Role) -> Self {
+        WebSocket {
+            stream,
+            role,
+            max_message_size: 1 << 20,
+            partial: None,
+            close_sent: false,
+            #[cfg(feature = "std")]
+            random: Some(crate::client::secure_random_u64),
+            #[cfg(not(feature = "std"))]
+            random: None,
+        }
+    }
+
+    /// Draw a client's masking keys from `random`. When http_io is built with the `"std"` feature
+    /// the default is the operating system's secure random number generator; otherwise a client
+    /// can't send frames until one is set, since predictable keys defeat the masking.
+    pub fn set_random(&mut self, random: Random) {
+        self.random = Some(random);
+    }
+
+    /// Fail with `Error::BodyTooLarge` when receiving a message longer than `limit` bytes. The
+    /// default is 1 MiB.
+    pub fn set_max_message_size(&mut self, limit: usize) {
+        self.max_message_size = limit;
+    }
+
+    /// Return the stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn read_frame(&mut self, limit: usize) -> Result<Frame> {
+        let mut head = [0; 2];
+        self.stream.read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0f;
+        if head[0] & 0x70 != 0 {
+            return Err(protocol_error("reserved bits set"));
+        }
+        let masked = head[1] & 0x80 != 0;
+        if masked != (self.role == Role::Server) {
+            return Err(protocol_error(match self.role {
+                Role::Server => "unmasked frame from client",
+                Role::Client => "masked frame from server",
+            }));
+        }
+        let length = match head[1] & 0x7f {
+            126 => {
+                let mut length = [0; 2];
+                self.stream.read_exact(&mut length)?;
+                u16::from_be_bytes(length) as u64
+            }
+            127 => {
+                let mut length = [0; 8];
+                self.stream.read_exact(&mut length)?;
+                u64::from_be_bytes(length)
+            }
+            length => length as u64,
+        };
+        if opcode >= OPCODE_CLOSE && (length > 125 || !fin) {
+            return Err(protocol_error("invalid control frame"));
+        }
+        if length > limit as u64 {
+            return Err(Error::BodyTooLarge(self.max_message_size));
+        }
+        let mut mask = [0; 4];
+        if masked {
+            self.stream.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0; length as usize];
+        self.stream.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok(Frame {
+            fin,
+            opcode,
+            payload,
+        })
+    }
+
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        let mut frame = Vec::with_capacity(payload.len() + 14);
+        frame.push(0x80 | opcode);
+        let mask_bit = if self.role == Role::Client { 0x80 } else { 0 };
+        match payload.len() {
+            length @ 0..=125 => frame.push(mask_bit | length as u8),
+            length @ 126..=0xffff => {
+                frame.push(mask_bit | 126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                frame.push(mask_bit | 127);
+                frame.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+        if self.role == Role::Client {
+            let random = self
+                .random
+                .ok_or_else(|| protocol_error("no source of masking keys; see set_random"))?;
+            let mask = (random() as u32).to_be_bytes();
+            frame.extend_from_slice(&mask);
+            frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        } else {
+            frame.extend_from_slice(payload);
+        }
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Send a message. Fails once a close has been sent.
+    pub fn write_message(&mut self, message: &Message) -> Result<()> {
+        if self.close_sent {
+            return Err(protocol_error("connection closed"));
+        }
+        match message {
+            Message::Text(text) => self.write_frame(OPCODE_TEXT, text.as_bytes()),
+            Message::Binary(data) => self.write_frame(OPCODE_BINARY, data),
+            Message::Ping(data) => self.write_frame(OPCODE_PING, data),
+            Message::Pong(data) => self.write_frame(OPCODE_PONG, data),
+            Message::Close(status) => {
+                let mut payload = Vec::new();
+                if let Some((code, reason)) = status {
+                    payload.extend_from_slice(&code.to_be_bytes());
+                    payload.extend_from_slice(reason.as_bytes());
+                }
+                self.close_sent = true;
+                self.write_frame(OPCODE_CLOSE, &payload)
+            }
+        }
+    }
+
+    /// Start closing the connection with the given status code, like 1000 for a normal closure,
+    /// and reason. Keep reading messages until the peer's `Message::Close`.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<()> {
+        self.write_message(&Message::Close(Some((code, reason.into()))))
+    }
+
+    /// Read the next message, joining fragmented messages.
+    pub fn read_message(&mut self) -> Result<Message> {
+        loop {
+            let received = self.partial.as_ref().map_or(0, |(_, data)| data.len());
+            let frame = self.read_frame(self.max_message_size.saturating_sub(received))?;
+            match frame.opcode {
+                OPCODE_PING => {
+                    if !self.close_sent {
+                        self.write_frame(OPCODE_PONG, &frame.payload)?;
+                    }
+                    return Ok(Message::Ping(frame.payload));
+                }
+                OPCODE_PONG => return Ok(Message::Pong(frame.payload)),
+                OPCODE_CLOSE => {
+                    let status = match frame.payload.len() {
+                        0 => None,
+                        1 => return Err(protocol_error("invalid close frame")),
+                        _ => Some((
+                            u16::from_be_bytes([frame.payload[0], frame.payload[1]]),
+                            str::from_utf8(&frame.payload[2..])?.into(),
+                        )),
+                    };
+                    if !self.close_sent {
+                        self.close_sent = true;
+                        self.write_frame(
+                            OPCODE_CLOSE,
+                            &frame.payload[..frame.payload.len().min(2)],
+                        )?;
+                    }
+                    return Ok(Message::Close(status));
+                }
+                OPCODE_TEXT | OPCODE_BINARY if self.partial.is_none() => {
+                    self.partial = Some((frame.opcode, frame.payload));
+                }
+                OPCODE_CONTINUATION if self.partial.is_some() => {
+                    if let Some((_, data)) = &mut self.partial {
+                        data.extend_from_slice(&frame.payload);
+                    }
+                }
+                opcode => {
+                    return Err(protocol_error(format!("unexpected opcode {}", opcode)));
+                }
+            }
+            if frame.fin {
+                let (opcode, data) = self.partial.take().unwrap();
+                return Ok(match opcode {
+                    OPCODE_TEXT => {
+                        Message::Text(String::from_utf8(data).map_err(|e| e.utf8_error())?)
+                    }
+                    _ => Message::Binary(data),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accept_key, sha1, Message, Role, WebSocket};
+    use crate::error::Error;
+    use core2::io::{self, Read, Write};
+
+    /// Reads from `input` and records what is written.
+    struct Duplex {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn socket(role: Role, input: Vec<u8>) -> WebSocket<Duplex> {
+        WebSocket::new(
+            Duplex {
+                input: io::Cursor::new(input),
+                output: Vec::new(),
+            },
+            role,
+        )
+    }
+
+    /// What a client sends as the server receives it.
+    fn client_frames(messages: &[Message]) -> Vec<u8> {
+        let mut client = socket(Role::Client, Vec::new());
+        for message in messages {
+            client.write_message(message).unwrap();
+        }
+        client.into_inner().output
+    }
+
+    #[test]
+    fn sha1_digests() {
+        let hex = |digest: [u8; 20]| {
+            digest
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        };
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn accept_key_example() {
+        // From RFC 6455 section 1.3.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn client_to_server() {
+        let messages = [
+            Message::Text("hello".into()),
+            Message::Binary(vec![7; 300]),
+            Message::Binary(vec![1; 70000]),
+        ];
+        let mut server = socket(Role::Server, client_frames(&messages));
+        for message in &messages {
+            assert_eq!(&server.read_message().unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn client_masks_with_random() {
+        let mut client = socket(Role::Client, Vec::new());
+        client.set_random(|| 0x01020304);
+        client.write_message(&Message::Text("hi".into())).unwrap();
+        assert_eq!(
+            client.into_inner().output,
+            b"\x81\x82\x01\x02\x03\x04\x69\x6b"
+        );
+    }
+
+    #[test]
+    fn server_to_client() {
+        let mut server = socket(Role::Server, Vec::new());
+        server
+            .write_message(&Message::Text("hello".into()))
+            .unwrap();
+        let sent = server.into_inner().output;
+        assert_eq!(sent, b"\x81\x05hello");
+
+        let mut client = socket(Role::Client, sent);
+        assert_eq!(
+            client.read_message().unwrap(),
+            Message::Text("hello".into())
+        );
+    }
+
+    #[test]
+    fn fragmented_with_ping() {
+        // "Hel", a ping, then "lo", unmasked as sent by a server.
+        let input = b"\x01\x03Hel\x89\x02hi\x80\x02lo".to_vec();
+        let mut client = socket(Role::Client, input);
+        assert_eq!(
+            client.read_message().unwrap(),
+            Message::Ping(b"hi".to_vec())
+        );
+        assert_eq!(
+            client.read_message().unwrap(),
+            Message::Text("Hello".into())
+        );
+
+        // The pong echoes the ping's payload, masked.
+        let mut server = socket(Role::Server, client.into_inner().output);
+        assert_eq!(
+            server.read_message().unwrap(),
+            Message::Pong(b"hi".to_vec())
+        );
+    }
+
+    #[test]
+    fn close_echoed() {
+        let mut client = socket(Role::Client, b"\x88\x05\x03\xe8bye".to_vec());
+        assert_eq!(
+            client.read_message().unwrap(),
+            Message::Close(Some((1000, "bye".into())))
+        );
+        assert!(client.write_message(&Message::Text("late".into())).is_err());
+
+        let mut server = socket(Role::Server, client.into_inner().output);
+        assert_eq!(
+            server.read_message().unwrap(),
+            Message::Close(Some((1000, "".into())))
+        );
+    }
+
+    #[test]
+    fn protocol_errors() {
+        // Servers require masked frames, and clients unmasked ones.
+        let mut server = socket(Role::Server, b"\x81\x02hi".to_vec());
+        assert!(matches!(
+            server.read_message(),
+            Err(Error::WebSocketError(_))
+        ));
+        let mut client = socket(Role::Client, client_frames(&[Message::Text("hi".into())]));
+        assert!(matches!(
+            client.read_message(),
+            Err(Error::WebSocketError(_))
+        ));
+
+        let mut client = socket(Role::Client, b"\x82\x7e\x01\x00".to_vec());
+        client.set_max_message_size(255);
+        assert!(matches!(
+            client.read_message(),
+            Err(Error::BodyTooLarge(255))
+        ));
+    }
+}